@@ -0,0 +1,13 @@
+-- Optional hooks run around every prompt. Each one may return nil to keep
+-- the text untouched, a string to replace it, or false plus a reason to
+-- reject the interaction.
+
+function on_prompt(prompt)
+  if prompt:find("forbidden word", 1, true) then
+    return false, "forbidden words aren't allowed"
+  end
+end
+
+function on_response(prompt, response)
+  return nil
+end
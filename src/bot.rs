@@ -1,44 +1,316 @@
+mod accounting;
+mod admin;
+mod ask_many;
+mod capture;
+mod channel_context;
+mod command_set;
+mod compare;
+mod console;
+mod dedup;
+mod degraded;
+mod digest;
+mod edits;
+mod experiment;
+mod flusher;
+mod followups;
+mod guard;
+mod guild_data;
+mod history;
+mod imagine;
+mod in_flight;
+mod janitor;
+mod lanes;
+mod language;
+mod limits;
+mod long_prompt;
+mod macros;
+mod moderation;
+mod pipeline;
+mod preflight;
+mod reactions;
+mod reasoning;
+mod routing;
+mod run_pipeline;
+mod schedule;
+mod search;
+mod sessions;
+mod settings;
+mod share;
+mod snapshot;
+mod stats;
+mod status;
+mod system;
+mod tldr;
+mod tombstones;
+mod transfer;
+#[cfg(feature = "voice")]
+mod voice;
+mod warmup;
+mod webhooks;
+
 use std::{
+    collections::{HashMap, HashSet, VecDeque},
     ops::Deref,
     sync::{
-        atomic::{AtomicBool, AtomicI64, Ordering},
-        Arc,
+        atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering},
+        Arc, OnceLock,
     },
     time::Duration,
 };
 
 use dashmap::DashMap;
-use poise::{serenity_prelude as serenity, ReplyHandle};
-use tokio::sync::{Mutex, RwLock};
+use poise::{
+    serenity_prelude::{self as serenity, Mentionable},
+    ReplyHandle,
+};
+use tokio::sync::{Mutex, Semaphore};
 
-use crate::{chat, config};
+use crate::{
+    chat, config, hooks,
+    messages::{self, plural},
+    report, secrets, spam, throughput, usage,
+};
 
 const ONE_DAY_IN_SECS: Duration = Duration::from_secs(86400);
-const DELETE_MSG_AFTER_SECS: Duration = Duration::from_secs(10);
+const EMBED_FIELD_VALUE_LIMIT: usize = 1024;
+const EMBED_DESCRIPTION_LIMIT: usize = 4096;
+/// Entries remembered by the per-message caches before the expired ones are dropped.
+const PRUNE_ABOVE: usize = 1024;
+const PAGINATION_TIMEOUT: Duration = Duration::from_secs(300);
+const LEADERBOARD_SIZE: usize = 10;
+const MAINTENANCE_POLL: Duration = Duration::from_secs(30);
+/// How long an armed capture waits for the prompt of its user.
+const CAPTURE_TIMEOUT: Duration = Duration::from_secs(3600);
+/// Sessions summarized at once before a flush, also bounded by the lanes when configured.
+const SUMMARIES_AT_ONCE: usize = 4;
+
+const DEFAULT_SESSION_NAME: &str = "default";
 
 type GuildId = u64;
+type ChannelId = u64;
 type UserId = u64;
+type SessionName = String;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum SessionKey {
+    User(UserId, SessionName),
+    Channel(ChannelId),
+}
+
+/// Value stored along the flush epoch it was written in.
+///
+/// Values from a previous epoch count as flushed, even before being pruned.
+#[derive(Clone, Debug)]
+struct Epoched<T> {
+    epoch: u64,
+    value: T,
+}
+
+impl<T> Epoched<T> {
+    /// Whether the value survived the flushes up to the epoch.
+    ///
+    /// Flushes write what they carry over under the next epoch before moving to it, so
+    /// values from a later epoch are live too.
+    fn is_live(&self, epoch: u64) -> bool {
+        self.epoch >= epoch
+    }
+}
+
+#[derive(Debug)]
+enum SessionPin {
+    Pinned,
+    AlreadyPinned,
+    LimitReached,
+}
+
+#[derive(Debug)]
+enum SessionCreation {
+    Created,
+    AlreadyExists,
+    LimitReached,
+}
 
-type GuildSessions = Arc<DashMap<UserId, ChatSession>>;
+/// Place taken in the queue of a session, freed once dropped.
+#[derive(Debug)]
+struct QueueSlot {
+    queue: SessionQueue,
+    prompt: u64,
+}
+
+impl Drop for QueueSlot {
+    fn drop(&mut self) {
+        let mut queue = self.queue.lock().unwrap();
+        if let Some(position) = queue.iter().position(|prompt| *prompt == self.prompt) {
+            queue.remove(position);
+        }
+    }
+}
+
+/// Prompts being answered or waiting for a session, in arrival order.
+type SessionQueue = Arc<std::sync::Mutex<VecDeque<u64>>>;
+
+/// What the next exchange of a session does besides answering the prompt.
+#[derive(Debug, Default)]
+struct Extras {
+    /// Captures the request and reply, see [`chat::Session::set_capture`].
+    capture: bool,
+    stream: Option<chat::Stream>,
+    target: Option<chat::Target>,
+}
 
 #[derive(Clone, Debug)]
 struct ChatSession {
     session: Arc<Mutex<chat::Session>>,
+    title: Arc<OnceLock<String>>,
+    queue: SessionQueue,
+    arm: experiment::Arm,
 }
 
 impl ChatSession {
-    fn new(session: chat::Session) -> Self {
+    fn new(session: chat::Session, arm: experiment::Arm) -> Self {
         Self {
             session: Arc::new(Mutex::new(session)),
+            title: Arc::new(OnceLock::new()),
+            queue: SessionQueue::default(),
+            arm,
         }
     }
 
-    async fn send_message(&self, content: String) -> Result<String, genai::Error> {
-        self.session.lock().await.send_message(content).await
+    /// Takes a place in the queue, unless `depth` prompts already wait behind the one in progress.
+    fn enqueue(&self, prompt: u64, depth: u8) -> Option<QueueSlot> {
+        let mut queue = self.queue.lock().unwrap();
+        if queue.len() > depth as usize {
+            return None;
+        }
+        queue.push_back(prompt);
+
+        Some(QueueSlot {
+            queue: self.queue.clone(),
+            prompt,
+        })
     }
 
-    async fn remove_last_interaction(&self) {
-        self.session.lock().await.pop_last_interaction();
+    /// Prompts ahead of the given one, which is being answered when there are none.
+    ///
+    /// Waiting prompts take the session in arrival order, as its lock is fair.
+    fn queue_position(&self, prompt: u64) -> Option<usize> {
+        let queue = self.queue.lock().unwrap();
+
+        queue.iter().position(|queued| *queued == prompt)
+    }
+
+    fn title(&self) -> Option<&str> {
+        self.title.get().map(String::as_str)
+    }
+
+    /// Generates the session title once enough interactions took place, without holding the
+    /// session meanwhile.
+    async fn entitle(&self, after: usize) {
+        let request = {
+            let session = self.session.lock().await;
+
+            if self.title().is_some() || session.exchanged() < after {
+                return;
+            }
+
+            session.title_request()
+        };
+
+        match request.generate().await {
+            Ok(title) if !title.is_empty() => {
+                let _ = self.title.set(title);
+            }
+            Ok(_) => log::warn!("model replied with an empty session title"),
+            Err(err) => log::warn!("failed to generate session title: {err}"),
+        }
+    }
+
+    /// Folds the interactions evicted from history into the summary, without holding the
+    /// session meanwhile, counting the tokens it took.
+    async fn fold_summary(&self, data: &BotDataInner, guild: GuildId) {
+        loop {
+            let Some(fold) = self.session.lock().await.take_fold() else {
+                return;
+            };
+
+            let summary = match fold.summarize().await {
+                Ok(response) => {
+                    data.usage.record_tokens(
+                        guild,
+                        &response.model,
+                        Some(self.arm),
+                        response.usage,
+                    );
+
+                    let summary = response.content.trim();
+                    (!summary.is_empty()).then(|| summary.to_string())
+                }
+                Err(err) => {
+                    log::warn!("failed to fold evicted interactions into summary: {err}");
+
+                    None
+                }
+            };
+            self.session.lock().await.finish_fold(summary);
+        }
+    }
+
+    async fn send_message(
+        &self,
+        content: String,
+        policy: Option<String>,
+        model: Option<&str>,
+        max_tokens: Option<u32>,
+        extras: Extras,
+    ) -> Result<(chat::Response, usize), chat::Error> {
+        let mut session = self.session.lock().await;
+        session.set_policy(policy);
+        session.set_max_tokens(max_tokens);
+        session.set_capture(extras.capture);
+        session.set_stream(extras.stream);
+        session.set_target(extras.target);
+
+        let response = session.send_message(content, model).await;
+        // Left set when the prompt was vetoed before reaching the model, keeping the stream open.
+        session.set_stream(None);
+        session.set_target(None);
+
+        Ok((response?, session.exchanged()))
+    }
+
+    async fn branch(
+        &self,
+        id: u64,
+        content: String,
+        policy: Option<String>,
+        model: Option<&str>,
+        max_tokens: Option<u32>,
+        extras: Extras,
+    ) -> Result<Option<(chat::Response, usize)>, chat::Error> {
+        let mut session = self.session.lock().await;
+        session.set_policy(policy);
+        session.set_max_tokens(max_tokens);
+        session.set_capture(extras.capture);
+
+        let Some(index) = session.position(id) else {
+            return Ok(None);
+        };
+        session.set_stream(extras.stream);
+        session.set_target(extras.target);
+        let response = session.branch(index, content, model).await;
+        session.set_stream(None);
+        session.set_target(None);
+        let response = response?;
+
+        Ok(response.map(|response| (response, session.exchanged())))
+    }
+
+    /// Undoes the interaction that got the session to `exchanged`, see
+    /// [`chat::Session::undo_last_interaction`].
+    async fn undo_last_interaction(&self, exchanged: usize) {
+        if !self.session.lock().await.undo_last_interaction(exchanged) {
+            log::warn!("reply wasn't delivered but the session moved on, keeping it");
+        }
     }
 }
 
@@ -46,32 +318,582 @@ struct BotDataInner {
     next_flush: AtomicI64,
     flush_timeout: Duration,
     flushing: AtomicBool,
+    maintenance: AtomicBool,
+    /// Requests being answered, which flushes wait for.
+    in_flight: Arc<in_flight::InFlight>,
     sbuilder: chat::SessionBuilder,
-    sessions: RwLock<DashMap<GuildId, GuildSessions>>,
-    conf: config::App,
+    /// Bumped by each flush, see [`Epoched`].
+    epoch: AtomicU64,
+    sessions: DashMap<(GuildId, SessionKey), Epoched<ChatSession>>,
+    /// Names of the user sessions by owner, so they're found without going through every
+    /// session. Might still name flushed sessions.
+    owned: DashMap<(GuildId, UserId), HashSet<SessionName>>,
+    /// Held while a session is created, so concurrent ones can't go past the limit.
+    creating: std::sync::Mutex<()>,
+    selected: DashMap<(GuildId, UserId), Epoched<SessionName>>,
+    replies: reactions::Replies,
+    /// Pinned user sessions and the flushes they still survive.
+    pins: DashMap<(GuildId, UserId, SessionName), u8>,
+    followups: DashMap<u64, followups::Record>,
+    /// Thinking behind replies of reasoning models, by reply.
+    reasoning: DashMap<u64, reasoning::Record>,
+    /// Comparisons waiting for a pick, by reply.
+    comparisons: DashMap<u64, compare::Record>,
+    /// Prompt messages whose reply is regenerated once edited.
+    answered_prompts: edits::Prompts,
+    /// Prompts waiting for or being answered by a session, by invocation.
+    prompts: DashMap<u64, status::Prompt>,
+    /// Reply languages picked by members, kept across flushes.
+    languages: DashMap<(GuildId, UserId), whatlang::Lang>,
+    scheduler: schedule::Scheduler,
+    /// Prompt snippets saved by members, kept across flushes.
+    macros: DashMap<(GuildId, UserId, String), String>,
+    /// Held while a macro is saved, so concurrent ones can't go past the limit.
+    saving_macro: std::sync::Mutex<()>,
+    /// Runtime settings of guilds, kept across flushes.
+    settings: DashMap<GuildId, settings::Settings>,
+    /// Held while writing the settings file, so writes don't interleave.
+    settings_saving: tokio::sync::Mutex<()>,
+    /// Sizes bot owners granted guilds at runtime, kept across flushes.
+    limits: DashMap<GuildId, limits::Limits>,
+    /// Webhooks replies are posted with, by channel.
+    webhooks: DashMap<ChannelId, serenity::Webhook>,
+    /// Members whose next prompt is captured, with the owner who asked and until when.
+    captures: DashMap<UserId, (UserId, chrono::DateTime<chrono::Utc>)>,
+    usage: usage::Tracker,
+    degraded: degraded::Tracker,
+    experiment: experiment::Tracker,
+    spam: spam::Detector,
+    throughput: throughput::Monitor,
+    flusher: flusher::Health,
+    /// Sessions deleted recently, until their grace period is over.
+    tombstones: tombstones::Graveyard,
+    invocations: dedup::Invocations,
+    /// Bounds the calls to the default provider account, see [`lanes::Paced`].
+    lanes: Arc<lanes::Lanes>,
+    pipeline: pipeline::Pipeline,
+    /// Deletes temporary replies, set once the bot is up.
+    janitor: OnceLock<janitor::Janitor>,
+    stt: OnceLock<crate::stt::Stt>,
+    images: OnceLock<crate::image::Generator>,
+    /// Routes of the config, set once their keys are resolved.
+    routes: OnceLock<routing::Routes>,
+    #[cfg(feature = "voice")]
+    tts: OnceLock<crate::tts::Tts>,
+    /// Replaced as a whole on reload-config, see [`Self::conf`].
+    conf: std::sync::RwLock<Arc<config::App>>,
+    /// Replaced as a whole on refresh, see [`Self::stats`].
+    stats: std::sync::RwLock<Arc<stats::BotStats>>,
 }
 
 impl BotDataInner {
-    async fn session(&self, guild: GuildId, user: UserId) -> ChatSession {
-        let sessions = self.sessions.read().await;
-
-        let guild_sessions = {
-            sessions
-                .entry(guild)
-                .or_insert_with(|| Arc::new(DashMap::new()))
-                .clone()
-        };
+    /// Current config, which stays the same for as long as it's held.
+    fn conf(&self) -> Arc<config::App> {
+        self.conf.read().unwrap().clone()
+    }
+
+    fn set_conf(&self, conf: config::App) {
+        *self.conf.write().unwrap() = Arc::new(conf);
+    }
+
+    /// Latest stats, possibly a few seconds old.
+    fn stats(&self) -> Arc<stats::BotStats> {
+        self.stats.read().unwrap().clone()
+    }
+
+    /// Current flush epoch, see [`Epoched`].
+    fn epoch(&self) -> u64 {
+        self.epoch.load(Ordering::Acquire)
+    }
+
+    fn settings(&self, guild: GuildId) -> settings::Settings {
+        self.settings
+            .get(&guild)
+            .map(|settings| settings.clone())
+            .unwrap_or_default()
+    }
+
+    /// Changes the settings of the guild and writes them down, if there's somewhere to.
+    async fn update_settings(&self, guild: GuildId, update: impl FnOnce(&mut settings::Settings)) {
+        update(&mut self.settings.entry(guild).or_default());
 
-        let session = {
-            guild_sessions
-                .entry(user)
-                .or_insert_with(|| ChatSession::new(self.sbuilder.create_chat()))
-                .clone()
+        settings::persist(self).await;
+    }
+
+    fn limits(&self, guild: GuildId) -> limits::Limits {
+        self.limits
+            .get(&guild)
+            .map(|limits| *limits)
+            .unwrap_or_default()
+    }
+
+    /// Longest prompt of the guild, granted at runtime or configured.
+    fn prompt_size(&self, guild: GuildId) -> u16 {
+        self.limits(guild)
+            .prompt_size
+            .unwrap_or_else(|| self.conf().prompt_size(guild))
+    }
+
+    /// Most interactions sessions of the guild may keep, granted at runtime or configured.
+    fn max_history_size(&self, guild: GuildId) -> u8 {
+        self.limits(guild)
+            .history_size
+            .unwrap_or_else(|| self.conf().history_size(guild))
+    }
+
+    /// Interactions new sessions of the guild keep, capped by its runtime settings.
+    fn history_size(&self, guild: GuildId) -> u8 {
+        let max = self.max_history_size(guild);
+        self.settings(guild)
+            .history_size
+            .map_or(max, |size| size.min(max))
+    }
+
+    /// Picks the experiment arm of a new session.
+    fn draw_arm(&self) -> experiment::Arm {
+        let arm = experiment::draw(self.conf().experiment.share);
+        self.experiment.record_session(arm);
+
+        arm
+    }
+
+    /// Model replacing the default one for sessions in the arm, if any.
+    fn arm_model(&self, arm: experiment::Arm) -> Option<String> {
+        match arm {
+            experiment::Arm::Control => None,
+            experiment::Arm::Variant => self.conf().experiment.model.clone(),
+        }
+    }
+
+    /// Creates a session with the runtime settings of the guild and the arm applied.
+    fn new_chat(&self, guild: GuildId, arm: experiment::Arm) -> chat::Session {
+        let mut session = self.sbuilder.create_chat(self.history_size(guild) as usize);
+
+        // A persona picked by the guild wins over the one of the variant.
+        let settings = self.settings(guild);
+        if settings.persona.is_some() {
+            session.set_examples(settings.examples);
+        }
+        let persona = match arm {
+            experiment::Arm::Control => settings.persona,
+            experiment::Arm::Variant => settings
+                .persona
+                .or_else(|| self.conf().experiment.persona.clone()),
         };
+        session.set_instructions(persona);
+        session.set_model(self.arm_model(arm));
 
         session
     }
 
+    fn new_session(&self, guild: GuildId) -> ChatSession {
+        let arm = self.draw_arm();
+
+        ChatSession::new(self.new_chat(guild, arm), arm)
+    }
+
+    /// Returns the session stored under the key, unless a flush dropped it.
+    fn stored_session(&self, guild: GuildId, key: SessionKey) -> Option<ChatSession> {
+        let epoch = self.epoch();
+
+        self.sessions
+            .get(&(guild, key))
+            .filter(|stored| stored.is_live(epoch))
+            .map(|stored| stored.value.clone())
+    }
+
+    /// Stores the session under the key, returning the one it replaced.
+    fn store_session(
+        &self,
+        guild: GuildId,
+        key: SessionKey,
+        stored: Epoched<ChatSession>,
+    ) -> Option<Epoched<ChatSession>> {
+        self.index_session(guild, &key);
+
+        self.sessions.insert((guild, key), stored)
+    }
+
+    /// Lists a user session under its owner, see [`Self::owned_sessions`].
+    fn index_session(&self, guild: GuildId, key: &SessionKey) {
+        if let SessionKey::User(user, name) = key {
+            self.owned
+                .entry((guild, *user))
+                .or_default()
+                .insert(name.clone());
+        }
+    }
+
+    /// Returns the session stored under the key, creating it when missing or flushed.
+    fn stored_session_or_new(&self, guild: GuildId, key: SessionKey) -> ChatSession {
+        if let Some(session) = self.stored_session(guild, key.clone()) {
+            return session;
+        }
+
+        let epoch = self.epoch();
+        self.index_session(guild, &key);
+        let mut stored = self
+            .sessions
+            .entry((guild, key))
+            .or_insert_with(|| Epoched {
+                epoch,
+                value: self.new_session(guild),
+            });
+        if !stored.is_live(epoch) {
+            *stored = Epoched {
+                epoch,
+                value: self.new_session(guild),
+            };
+        }
+
+        stored.value.clone()
+    }
+
+    fn selected_session_name(&self, guild: GuildId, user: UserId) -> SessionName {
+        let epoch = self.epoch();
+
+        self.selected
+            .get(&(guild, user))
+            .filter(|selected| selected.is_live(epoch))
+            .map(|selected| selected.value.clone())
+            .unwrap_or_else(|| DEFAULT_SESSION_NAME.to_string())
+    }
+
+    fn select_session(&self, guild: GuildId, user: UserId, name: SessionName) {
+        let selected = Epoched {
+            epoch: self.epoch(),
+            value: name,
+        };
+        self.selected.insert((guild, user), selected);
+    }
+
+    /// Iterates over the sessions that weren't flushed, along with their keys.
+    fn live_sessions(&self) -> impl Iterator<Item = (GuildId, SessionKey, ChatSession)> + '_ {
+        let epoch = self.epoch();
+
+        self.sessions
+            .iter()
+            .filter(move |stored| stored.is_live(epoch))
+            .map(|stored| {
+                let (guild, key) = stored.key().clone();
+                (guild, key, stored.value.clone())
+            })
+    }
+
+    /// Returns the names and sessions owned by the user in the guild.
+    fn owned_sessions(&self, guild: GuildId, user: UserId) -> Vec<(SessionName, ChatSession)> {
+        // Names are copied first, so no shard of the index is held while looking them up.
+        let names: Vec<_> = self
+            .owned
+            .get(&(guild, user))
+            .map(|names| names.iter().cloned().collect())
+            .unwrap_or_default();
+
+        names
+            .into_iter()
+            .filter_map(|name| {
+                let session = self.stored_session(guild, SessionKey::User(user, name.clone()))?;
+
+                Some((name, session))
+            })
+            .collect()
+    }
+
+    fn session(&self, guild: GuildId, user: UserId) -> ChatSession {
+        let name = self.selected_session_name(guild, user);
+
+        self.stored_session_or_new(guild, SessionKey::User(user, name))
+    }
+
+    /// Returns the session shared by everyone talking in the given channel.
+    fn channel_session(&self, guild: GuildId, channel: ChannelId) -> ChatSession {
+        self.stored_session_or_new(guild, SessionKey::Channel(channel))
+    }
+
+    fn is_shared_channel(&self, guild: GuildId, channel: ChannelId) -> bool {
+        self.conf()
+            .guilds
+            .get(&guild)
+            .is_some_and(|guild_conf| guild_conf.shared_channels.contains(&channel))
+    }
+
+    fn create_session(&self, guild: GuildId, user: UserId, name: SessionName) -> SessionCreation {
+        let _creating = self.creating.lock().unwrap();
+
+        let owned = self.owned_sessions(guild, user).len();
+        if owned >= self.conf().chat.max_sessions as usize {
+            return SessionCreation::LimitReached;
+        }
+
+        let epoch = self.epoch();
+        let stored = Epoched {
+            epoch,
+            value: self.new_session(guild),
+        };
+        let key = SessionKey::User(user, name.clone());
+        self.index_session(guild, &key);
+        match self.sessions.entry((guild, key)) {
+            dashmap::Entry::Occupied(entry) if entry.get().is_live(epoch) => {
+                return SessionCreation::AlreadyExists
+            }
+            dashmap::Entry::Occupied(mut entry) => {
+                entry.insert(stored);
+            }
+            dashmap::Entry::Vacant(entry) => {
+                entry.insert(stored);
+            }
+        }
+        self.select_session(guild, user, name);
+
+        SessionCreation::Created
+    }
+
+    /// Saves the macro, replacing the one of the same name, unless the member already has as
+    /// many as allowed.
+    fn save_macro(&self, key: (GuildId, UserId, String), text: String) -> bool {
+        let _saving = self.saving_macro.lock().unwrap();
+
+        let saved = self
+            .macros
+            .iter()
+            .filter(|entry| (entry.key().0, entry.key().1) == (key.0, key.1))
+            .count();
+        if !self.macros.contains_key(&key) && saved >= self.conf().chat.max_macros as usize {
+            return false;
+        }
+        self.macros.insert(key, text);
+
+        true
+    }
+
+    fn switch_session(&self, guild: GuildId, user: UserId, name: SessionName) -> bool {
+        let exists = name == DEFAULT_SESSION_NAME
+            || self
+                .stored_session(guild, SessionKey::User(user, name.clone()))
+                .is_some();
+        if exists {
+            self.select_session(guild, user, name);
+        }
+
+        exists
+    }
+
+    /// Returns the names and titles of the user sessions along with the currently selected one.
+    fn user_sessions(
+        &self,
+        guild: GuildId,
+        user: UserId,
+    ) -> (Vec<(SessionName, Option<String>)>, SessionName) {
+        let mut sessions: Vec<_> = self
+            .owned_sessions(guild, user)
+            .into_iter()
+            .map(|(name, session)| (name, session.title().map(str::to_string)))
+            .collect();
+        if !sessions
+            .iter()
+            .any(|(name, _)| name == DEFAULT_SESSION_NAME)
+        {
+            sessions.push((DEFAULT_SESSION_NAME.to_string(), None));
+        }
+        sessions.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+
+        (sessions, self.selected_session_name(guild, user))
+    }
+
+    /// How long deleted sessions may still be restored.
+    fn undo_grace(&self) -> Duration {
+        Duration::from_secs(self.conf().chat.undo_grace_secs as u64)
+    }
+
+    /// Deletes a user session, falling back to the default one if it was selected.
+    ///
+    /// The session is kept in a tombstone for a while, see [`Self::undo_deletion`].
+    fn delete_session(&self, guild: GuildId, user: UserId, name: SessionName) -> bool {
+        let epoch = self.epoch();
+
+        let deleted = self
+            .sessions
+            .remove(&(guild, SessionKey::User(user, name.clone())))
+            .filter(|(_, stored)| stored.is_live(epoch));
+        if let Some(mut names) = self.owned.get_mut(&(guild, user)) {
+            names.remove(&name);
+        }
+        let selected = self
+            .selected
+            .remove_if(&(guild, user), |_, selected| selected.value == name);
+        let pin = self.pins.remove(&(guild, user, name.clone()));
+
+        let Some((_, stored)) = deleted else {
+            return false;
+        };
+        let mut tombstone = tombstones::Tombstone::new(epoch, self.undo_grace());
+        tombstone.selected = selected.map(|(_, selected)| selected.value);
+        tombstone
+            .pins
+            .extend(pin.map(|(_, cycles)| (name.clone(), cycles)));
+        tombstone.sessions.push((name, stored.value));
+        self.tombstones
+            .bury(guild, user, tombstones::Cause::Reset, tombstone);
+
+        true
+    }
+
+    /// Deletes every session of the user in the guild, returning how many there were.
+    ///
+    /// The sessions are kept in a tombstone for a while, see [`Self::undo_deletion`].
+    fn purge_sessions(&self, guild: GuildId, user: UserId) -> usize {
+        let epoch = self.epoch();
+        let mut tombstone = tombstones::Tombstone::new(epoch, self.undo_grace());
+
+        let owned = self
+            .owned
+            .remove(&(guild, user))
+            .map(|(_, names)| names)
+            .unwrap_or_default();
+        for name in owned {
+            let removed = self
+                .sessions
+                .remove(&(guild, SessionKey::User(user, name.clone())))
+                .filter(|(_, stored)| stored.is_live(epoch));
+            if let Some((_, stored)) = removed {
+                tombstone.sessions.push((name, stored.value));
+            }
+        }
+        tombstone.selected = self
+            .selected
+            .remove(&(guild, user))
+            .filter(|(_, selected)| selected.is_live(epoch))
+            .map(|(_, selected)| selected.value);
+
+        let pinned: Vec<_> = self
+            .pins
+            .iter()
+            .map(|pin| pin.key().clone())
+            .filter(|(pin_guild, pin_user, _)| (*pin_guild, *pin_user) == (guild, user))
+            .collect();
+        for key in pinned {
+            if let Some(((_, _, name), cycles)) = self.pins.remove(&key) {
+                tombstone.pins.push((name, cycles));
+            }
+        }
+
+        // Records of replies hold on to their session, and to what the member said.
+        let purged = |session: &ChatSession| {
+            tombstone
+                .sessions
+                .iter()
+                .any(|(_, purged)| Arc::ptr_eq(&purged.session, &session.session))
+        };
+        self.replies.forget(|record| purged(&record.session));
+        self.followups.retain(|_, record| !purged(&record.session));
+        self.answered_prompts
+            .forget(|prompt| purged(&prompt.session));
+        self.reasoning
+            .retain(|_, record| (record.guild, record.author) != (guild, user));
+
+        let purged = tombstone.sessions.len();
+        self.tombstones
+            .bury(guild, user, tombstones::Cause::Purge, tombstone);
+
+        purged
+    }
+
+    /// Restores the sessions last deleted for the given cause, returning how many there were.
+    ///
+    /// Sessions created since under the same names take their place in the tombstone, so
+    /// undoing again brings them back.
+    fn undo_deletion(
+        &self,
+        guild: GuildId,
+        user: UserId,
+        cause: tombstones::Cause,
+    ) -> Option<usize> {
+        let epoch = self.epoch();
+        let tombstone = self.tombstones.dig_up(guild, user, cause, epoch)?;
+        let mut displaced = tombstones::Tombstone::new(epoch, self.undo_grace());
+
+        let restored = tombstone.sessions.len();
+        for (name, session) in tombstone.sessions {
+            if let Some((_, cycles)) = self.pins.remove(&(guild, user, name.clone())) {
+                displaced.pins.push((name.clone(), cycles));
+            }
+
+            let stored = Epoched {
+                epoch,
+                value: session,
+            };
+            let previous = self
+                .store_session(guild, SessionKey::User(user, name.clone()), stored)
+                .filter(|previous| previous.is_live(epoch));
+            if let Some(previous) = previous {
+                displaced.sessions.push((name, previous.value));
+            }
+        }
+        for (name, cycles) in tombstone.pins {
+            self.pins.insert((guild, user, name), cycles);
+        }
+        if let Some(name) = tombstone.selected {
+            self.select_session(guild, user, name);
+        }
+        self.tombstones.bury(guild, user, cause, displaced);
+
+        Some(restored)
+    }
+
+    /// Captures the next prompt of the user for the owner, for a limited time.
+    fn arm_capture(&self, user: UserId, owner: UserId) {
+        let now = chrono::Utc::now();
+        // Captures of members who never prompted again are dropped once expired.
+        self.captures.retain(|_, (_, expires)| *expires > now);
+        self.captures.insert(user, (owner, now + CAPTURE_TIMEOUT));
+    }
+
+    /// Takes the capture armed for the user, returning the owner who asked for it.
+    fn take_capture(&self, user: UserId) -> Option<UserId> {
+        let (_, (owner, expires)) = self.captures.remove(&user)?;
+
+        (chrono::Utc::now() < expires).then_some(owner)
+    }
+
+    /// Pins the session selected by the user, so it survives the next flushes.
+    fn pin_session(&self, guild: GuildId, user: UserId) -> (SessionName, SessionPin) {
+        let conf = self.conf();
+        let name = self.selected_session_name(guild, user);
+
+        // The default session only exists once used, which pinning counts as.
+        let _ = self.session(guild, user);
+
+        let key = (guild, user, name.clone());
+        if self.pins.contains_key(&key) {
+            return (name, SessionPin::AlreadyPinned);
+        }
+
+        let pinned = self.pins.iter().filter(|pin| pin.key().0 == guild).count();
+        if pinned >= conf.chat.max_pins as usize {
+            return (name, SessionPin::LimitReached);
+        }
+
+        self.pins.insert(key, conf.chat.pin_cycles);
+
+        (name, SessionPin::Pinned)
+    }
+
+    fn unpin_session(&self, guild: GuildId, user: UserId) -> (SessionName, bool) {
+        let name = self.selected_session_name(guild, user);
+        let unpinned = self.pins.remove(&(guild, user, name.clone())).is_some();
+
+        (name, unpinned)
+    }
+
+    fn active_sessions(&self) -> HashMap<GuildId, usize> {
+        let mut active = HashMap::new();
+        for (guild, _, _) in self.live_sessions() {
+            *active.entry(guild).or_default() += 1;
+        }
+
+        active
+    }
+
     fn schedule_next_flush(&self) {
         let next_flush = (chrono::Local::now() + self.flush_timeout).timestamp();
         self.next_flush.store(next_flush, Ordering::Release);
@@ -82,18 +904,160 @@ impl BotDataInner {
         chrono::DateTime::from_timestamp(timestamp, 0).unwrap()
     }
 
+    fn last_flush(&self) -> chrono::DateTime<chrono::Utc> {
+        self.next_flush() - self.flush_timeout
+    }
+
+    /// Whether a flush started, checked by requests once in flight, see [`in_flight::InFlight`].
     fn is_flushing(&self) -> bool {
-        self.flushing.load(Ordering::Acquire)
+        self.flushing.load(Ordering::SeqCst)
     }
 
     fn flushing(&self, yes: bool) {
-        self.flushing.store(yes, Ordering::Release);
+        self.flushing.store(yes, Ordering::SeqCst);
+    }
+
+    fn is_under_maintenance(&self) -> bool {
+        self.maintenance.load(Ordering::Acquire)
+    }
+
+    fn maintenance(&self, yes: bool) {
+        self.maintenance.store(yes, Ordering::Release);
+    }
+
+    /// Summarizes every session with history, so they can be seeded after a flush.
+    async fn summarize_sessions(&self) -> Vec<(GuildId, SessionKey, String)> {
+        let mut tasks = tokio::task::JoinSet::new();
+        let permits = Arc::new(Semaphore::new(SUMMARIES_AT_ONCE));
+
+        for (guild, key, session) in self.live_sessions() {
+            // Pinned sessions are kept as they are.
+            if let SessionKey::User(user, name) = &key {
+                if self.pins.contains_key(&(guild, *user, name.clone())) {
+                    continue;
+                }
+            }
+
+            let permits = permits.clone();
+            tasks.spawn(async move {
+                let _permit = permits.acquire().await;
+                let summary = session.session.lock().await.summarize().await;
+                (guild, key, summary)
+            });
+        }
+
+        let mut summaries = Vec::with_capacity(tasks.len());
+        while let Some(task) = tasks.join_next().await {
+            match task {
+                Ok((guild, key, Ok(Some(summary)))) => summaries.push((guild, key, summary)),
+                Ok((_, _, Ok(None))) => (),
+                Ok((_, _, Err(err))) => log::warn!("failed to summarize session: {err}"),
+                Err(err) => log::warn!("session summary task failed: {err}"),
+            }
+        }
+
+        summaries
     }
 
     async fn flush(&self) {
         self.flushing(true);
-        self.sessions.write().await.clear();
+        self.in_flight.drained().await;
+
+        if let Some(dir) = &self.conf().persistence.snapshot_dir {
+            if let Err(err) = snapshot::save(self, dir).await {
+                log::error!("failed to snapshot sessions before flush: {err}");
+                report::error(report::Context::default(), &err);
+            }
+        }
+
+        let summaries = if self.conf().chat.carry_summary {
+            self.summarize_sessions().await
+        } else {
+            Vec::new()
+        };
+
+        let pinned: Vec<_> = self
+            .pins
+            .iter()
+            .filter_map(|pin| {
+                let (guild, user, name) = pin.key().clone();
+                let key = SessionKey::User(user, name);
+                let session = self.stored_session(guild, key.clone())?;
+
+                Some((guild, key, session))
+            })
+            .collect();
+
+        // Carried sessions are stored under the next epoch before moving to it, so they
+        // are live all along and nothing created in between replaces them.
+        let previous = self.epoch();
+        let epoch = previous + 1;
+
+        let seeded = summaries.into_iter().map(|(guild, key, summary)| {
+            let arm = self.draw_arm();
+            let mut session = self.new_chat(guild, arm);
+            session.seed_summary(summary);
+            (guild, key, ChatSession::new(session, arm))
+        });
+        for (guild, key, session) in seeded.chain(pinned) {
+            let stored = Epoched {
+                epoch,
+                value: session,
+            };
+            self.store_session(guild, key, stored);
+        }
+
+        // Everything else stored so far becomes stale at once, without locking the whole map.
+        self.epoch.store(epoch, Ordering::Release);
+
+        // Selections carry over along with the session they point to.
+        self.selected.retain(|(guild, user), selected| {
+            if selected.is_live(epoch) {
+                return true;
+            }
+
+            let carried = selected.epoch == previous
+                && self
+                    .stored_session(*guild, SessionKey::User(*user, selected.value.clone()))
+                    .is_some();
+            selected.epoch = epoch;
+
+            carried
+        });
+        self.sessions.retain(|_, stored| stored.is_live(epoch));
+
+        let live: HashSet<_> = self
+            .sessions
+            .iter()
+            .filter_map(|stored| match stored.key() {
+                (guild, SessionKey::User(user, name)) => Some((*guild, *user, name.clone())),
+                _ => None,
+            })
+            .collect();
+        self.owned.retain(|(guild, user), names| {
+            names.retain(|name| live.contains(&(*guild, *user, name.clone())));
+            !names.is_empty()
+        });
+
+        // Pins expire once their session went through the configured flushes.
+        self.pins.retain(|_, cycles| {
+            *cycles = cycles.saturating_sub(1);
+            *cycles > 0
+        });
+
+        self.replies.clear();
+        self.followups.clear();
+        self.reasoning.clear();
+        self.comparisons.clear();
+        self.answered_prompts.clear();
+        self.tombstones.clear();
+        self.usage.reset();
+        accounting::save(self).await;
+        self.degraded.reset();
+        self.spam.prune(&self.conf().spam);
         self.flushing(false);
+
+        stats::refresh(self).await;
     }
 }
 
@@ -103,15 +1067,54 @@ struct BotData {
 }
 
 impl BotData {
-    fn new(sbuilder: chat::SessionBuilder, conf: config::App) -> Self {
+    fn new(sbuilder: chat::SessionBuilder, lanes: Arc<lanes::Lanes>, conf: config::App) -> Self {
         Self {
             inner: Arc::new(BotDataInner {
                 flush_timeout: ONE_DAY_IN_SECS * conf.chat.flush_days as u32,
                 next_flush: AtomicI64::new(0),
                 flushing: AtomicBool::new(false),
+                maintenance: AtomicBool::new(conf.bot.maintenance),
+                in_flight: Arc::default(),
                 sbuilder,
-                sessions: RwLock::new(DashMap::new()),
-                conf,
+                epoch: AtomicU64::new(0),
+                sessions: DashMap::new(),
+                owned: DashMap::new(),
+                creating: std::sync::Mutex::new(()),
+                selected: DashMap::new(),
+                replies: reactions::Replies::default(),
+                pins: DashMap::new(),
+                followups: DashMap::new(),
+                reasoning: DashMap::new(),
+                comparisons: DashMap::new(),
+                answered_prompts: edits::Prompts::default(),
+                prompts: DashMap::new(),
+                languages: DashMap::new(),
+                macros: DashMap::new(),
+                saving_macro: std::sync::Mutex::new(()),
+                settings: DashMap::new(),
+                settings_saving: tokio::sync::Mutex::new(()),
+                limits: DashMap::new(),
+                webhooks: DashMap::new(),
+                captures: DashMap::new(),
+                scheduler: schedule::Scheduler::default(),
+                usage: usage::Tracker::default(),
+                degraded: degraded::Tracker::default(),
+                experiment: experiment::Tracker::default(),
+                spam: spam::Detector::default(),
+                throughput: throughput::Monitor::default(),
+                flusher: flusher::Health::default(),
+                tombstones: tombstones::Graveyard::default(),
+                invocations: dedup::Invocations::default(),
+                lanes,
+                pipeline: pipeline::Pipeline::new(),
+                janitor: OnceLock::new(),
+                stt: OnceLock::new(),
+                images: OnceLock::new(),
+                routes: OnceLock::new(),
+                #[cfg(feature = "voice")]
+                tts: OnceLock::new(),
+                conf: std::sync::RwLock::new(Arc::new(conf)),
+                stats: std::sync::RwLock::default(),
             }),
         }
     }
@@ -134,139 +1137,663 @@ pub enum Error {
     Creation(#[source] serenity::Error),
     #[error("failed to initialize bot")]
     Initialization(#[source] serenity::Error),
+    #[error("failed startup checks")]
+    Preflight(#[source] preflight::Error),
+    #[error("failed to resolve secret")]
+    Secret(#[source] secrets::Error),
+    #[error("failed to load hooks script")]
+    Script(#[source] hooks::Error),
+    #[error("failed to restore sessions snapshot")]
+    Restore(#[source] snapshot::Error),
+    #[error("failed to load settings")]
+    Settings(#[source] settings::Error),
+    #[error("failed to load scheduled prompts")]
+    Schedule(#[source] schedule::Error),
+    #[error("failed to load usage")]
+    Usage(#[source] usage::Error),
+    #[error("failed to warm up")]
+    Warmup(#[source] warmup::Error),
+    #[error("failed to listen for shutdown signal")]
+    Signal(#[source] std::io::Error),
+}
+
+fn apply_theme(
+    appearance: &config::Appearance,
+    embed: serenity::CreateEmbed,
+) -> serenity::CreateEmbed {
+    let mut embed = embed;
+
+    if let Some(color) = appearance.color {
+        embed = embed.color(color);
+    }
+
+    if let Some(text) = &appearance.footer_text {
+        let mut footer = serenity::CreateEmbedFooter::new(text);
+        if let Some(icon_url) = &appearance.footer_icon_url {
+            footer = footer.icon_url(icon_url);
+        }
+
+        embed = embed.footer(footer);
+    }
+
+    embed
+}
+
+/// Whether the command channel, or the parent of its thread, is age-restricted.
+async fn is_age_restricted(ctx: Context<'_>) -> bool {
+    let Some(channel) = ctx.guild_channel().await else {
+        return false;
+    };
+
+    if channel.nsfw {
+        return true;
+    }
+
+    let Some(parent) = channel.thread_metadata.and(channel.parent_id) else {
+        return false;
+    };
+
+    ctx.guild()
+        .and_then(|guild| guild.channels.get(&parent).map(|parent| parent.nsfw))
+        .unwrap_or(false)
+}
+
+fn mentions_policy(mentions: config::Mentions) -> serenity::CreateAllowedMentions {
+    let allowed = serenity::CreateAllowedMentions::new().replied_user(true);
+
+    match mentions {
+        config::Mentions::None => allowed,
+        config::Mentions::Users => allowed.all_users(true),
+        config::Mentions::Roles => allowed.all_users(true).all_roles(true),
+    }
+}
+
+/// Mentions that may ping in the messages sent for the command.
+fn allowed_mentions(ctx: Context<'_>) -> serenity::CreateAllowedMentions {
+    let conf = ctx.data().conf();
+    let mentions = match ctx.guild_id() {
+        Some(guild) => conf.mentions(guild.get()),
+        None => conf.bot.mentions,
+    };
+
+    mentions_policy(mentions)
 }
 
 async fn send_embedded_reply(
     ctx: Context<'_>,
     embed: serenity::CreateEmbed,
 ) -> Result<ReplyHandle<'_>, serenity::Error> {
-    let message = poise::CreateReply::default().embed(embed).reply(true);
+    let embed = apply_theme(&ctx.data().conf().appearance, embed);
+    let message = poise::CreateReply::default()
+        .embed(embed)
+        .reply(true)
+        .allowed_mentions(allowed_mentions(ctx));
+    ctx.send(message).await
+}
+
+async fn send_ephemeral_embedded_reply(
+    ctx: Context<'_>,
+    embed: serenity::CreateEmbed,
+) -> Result<ReplyHandle<'_>, serenity::Error> {
+    let embed = apply_theme(&ctx.data().conf().appearance, embed);
+    let message = poise::CreateReply::default()
+        .embed(embed)
+        .reply(true)
+        .ephemeral(true)
+        .allowed_mentions(allowed_mentions(ctx));
     ctx.send(message).await
 }
 
+/// Answers a button press with an alert only its presser sees.
+async fn send_ephemeral_alert(
+    ctx: &serenity::Context,
+    data: &BotData,
+    press: &serenity::ComponentInteraction,
+    title: &str,
+) -> Result<(), serenity::Error> {
+    let embed = apply_theme(
+        &data.conf().appearance,
+        serenity::CreateEmbed::new().title(title),
+    );
+    let response = serenity::CreateInteractionResponse::Message(
+        serenity::CreateInteractionResponseMessage::new()
+            .embed(embed)
+            .ephemeral(true),
+    );
+
+    press.create_response(ctx, response).await
+}
+
 async fn send_temporary_embedded_reply(
     ctx: Context<'_>,
     embed: serenity::CreateEmbed,
 ) -> Result<(), serenity::Error> {
-    let http = ctx.serenity_context().http.clone();
+    let lifetime = Duration::from_secs(ctx.data().conf().appearance.alert_lifetime_secs);
     let message = send_embedded_reply(ctx, embed)
         .await?
         .into_message()
         .await?;
 
-    tokio::spawn(async move {
-        tokio::time::sleep(DELETE_MSG_AFTER_SECS).await;
+    if let Some(janitor) = ctx.data().janitor.get() {
+        janitor.schedule(
+            message.channel_id,
+            message.id,
+            tokio::time::Instant::now() + lifetime,
+        );
+    }
+
+    Ok(())
+}
+
+fn pagination_buttons(
+    prev_button_id: &str,
+    next_button_id: &str,
+    current: usize,
+    total: usize,
+) -> Vec<serenity::CreateActionRow> {
+    vec![serenity::CreateActionRow::Buttons(vec![
+        serenity::CreateButton::new(prev_button_id).emoji('◀'),
+        serenity::CreateButton::new(format!("{prev_button_id}page"))
+            .label(format!("{}/{}", current + 1, total))
+            .style(serenity::ButtonStyle::Secondary)
+            .disabled(true),
+        serenity::CreateButton::new(next_button_id).emoji('▶'),
+    ])]
+}
+
+async fn send_paginated_embeds(
+    ctx: Context<'_>,
+    pages: Vec<serenity::CreateEmbed>,
+    ephemeral: bool,
+) -> Result<(), serenity::Error> {
+    let appearance = &ctx.data().conf().appearance;
+    let pages: Vec<_> = pages
+        .into_iter()
+        .map(|page| apply_theme(appearance, page))
+        .collect();
+    let total = pages.len();
+
+    let Some(first) = pages.first() else {
+        return Ok(());
+    };
+
+    let reply = poise::CreateReply::default()
+        .embed(first.clone())
+        .ephemeral(ephemeral)
+        .allowed_mentions(allowed_mentions(ctx));
+
+    if total == 1 {
+        ctx.send(reply).await?;
+
+        return Ok(());
+    }
+
+    let ctx_id = ctx.id();
+    let prev_button_id = format!("{ctx_id}prev");
+    let next_button_id = format!("{ctx_id}next");
+    let buttons = pagination_buttons(&prev_button_id, &next_button_id, 0, total);
+    let handle = ctx.send(reply.components(buttons)).await?;
+
+    let mut current = 0;
+    while let Some(press) = serenity::ComponentInteractionCollector::new(ctx)
+        .author_id(ctx.author().id)
+        .filter(move |press| press.data.custom_id.starts_with(&ctx_id.to_string()))
+        .timeout(PAGINATION_TIMEOUT)
+        .await
+    {
+        if press.data.custom_id == next_button_id {
+            current = (current + 1) % total;
+        } else if press.data.custom_id == prev_button_id {
+            current = current.checked_sub(1).unwrap_or(total - 1);
+        } else {
+            continue;
+        }
+
+        let buttons = pagination_buttons(&prev_button_id, &next_button_id, current, total);
+        let response = serenity::CreateInteractionResponse::UpdateMessage(
+            serenity::CreateInteractionResponseMessage::new()
+                .embed(pages[current].clone())
+                .components(buttons),
+        );
+        press.create_response(ctx, response).await?;
+    }
+
+    let reply = poise::CreateReply::default()
+        .embed(pages[current].clone())
+        .components(vec![]);
+    handle.edit(ctx, reply).await
+}
+
+/// Cuts the value down to the given characters, ellipsis included.
+fn truncate_chars(value: &str, limit: usize) -> String {
+    if value.chars().count() <= limit {
+        return value.to_string();
+    }
+
+    let mut truncated: String = value.chars().take(limit.saturating_sub(3)).collect();
+    truncated.push_str("...");
+
+    truncated
+}
+
+fn truncate_field_value(value: &str) -> String {
+    truncate_chars(value, EMBED_FIELD_VALUE_LIMIT)
+}
+
+fn format_command_argument(value: &serenity::ResolvedValue<'_>) -> String {
+    match value {
+        serenity::ResolvedValue::String(value) => truncate_field_value(value),
+        serenity::ResolvedValue::Integer(value) => value.to_string(),
+        serenity::ResolvedValue::Number(value) => value.to_string(),
+        serenity::ResolvedValue::Boolean(value) => value.to_string(),
+        serenity::ResolvedValue::User(user, _) => user.mention().to_string(),
+        serenity::ResolvedValue::Role(role) => role.mention().to_string(),
+        serenity::ResolvedValue::Channel(channel) => channel.id.mention().to_string(),
+        _ => "(unsupported)".to_string(),
+    }
+}
+
+/// Rejects members whose account is too recent or who lack the required roles.
+///
+/// Bot owners are always let through.
+async fn check_access(ctx: Context<'_>) -> Result<bool, InternalError> {
+    // Discord may deliver an interaction again, which must not be answered twice.
+    if !dedup::admit(ctx).await {
+        log::info!("ignoring invocation {} delivered again", ctx.id());
+
+        return Ok(false);
+    }
+
+    if ctx.framework().options().owners.contains(&ctx.author().id) {
+        return Ok(true);
+    }
+
+    // Commands are guild only, which poise checks first.
+    let Some(guild) = ctx.guild_id() else {
+        return Ok(true);
+    };
+
+    let qualified_name = &ctx.command().qualified_name;
+    let command = qualified_name.split(' ').next().unwrap_or(qualified_name);
+    let channel = ctx.channel_id();
+    let prompter = guard::Prompter {
+        guild: guild.get(),
+        user: ctx.author().id,
+        roles: ctx.author_member().await.map(|member| member.roles.clone()),
+        channel,
+        parent: ctx
+            .guild()
+            .and_then(|guild| guard::thread_parent(&guild, channel)),
+        command,
+    };
+
+    let reasons = guard::access_reasons(ctx.data(), &prompter);
+    if reasons.is_empty() {
+        return Ok(true);
+    }
+
+    let embed = serenity::CreateEmbed::new()
+        .title(&ctx.data().conf().messages.alerts.access_denied)
+        .description(reasons.join("\n"));
+    send_ephemeral_embedded_reply(ctx, embed).await?;
+
+    Ok(false)
+}
+
+async fn log_command_invocation(ctx: Context<'_>) {
+    let Some(guild) = ctx.guild_id() else {
+        return;
+    };
+
+    let conf = ctx.data().conf();
+    let Some(guild_conf) = conf.guilds.get(&guild.get()) else {
+        return;
+    };
+
+    let Some(log_channel) = guild_conf.log_channel else {
+        return;
+    };
 
-        let _ = message.delete(http).await;
+    let mut embed = serenity::CreateEmbed::new()
+        .title(":scroll: Command Invoked")
+        .field(
+            ":bust_in_silhouette: | User:",
+            ctx.author().mention().to_string(),
+            true,
+        )
+        .field(
+            ":hash: | Channel:",
+            ctx.channel_id().mention().to_string(),
+            true,
+        )
+        .field(
+            ":gear: | Command:",
+            format!("{}{}", ctx.prefix(), ctx.command().qualified_name),
+            true,
+        )
+        .timestamp(serenity::Timestamp::now());
+
+    if guild_conf.log_arguments {
+        match ctx {
+            poise::Context::Application(actx) => {
+                for option in actx.args {
+                    embed = embed.field(
+                        format!("`{}`", option.name),
+                        format_command_argument(&option.value),
+                        false,
+                    );
+                }
+            }
+            poise::Context::Prefix(pctx) if !pctx.args.is_empty() => {
+                embed = embed.field("`arguments`", truncate_field_value(pctx.args), false);
+            }
+            poise::Context::Prefix(_) => (),
+        }
+    }
+
+    let http = ctx.serenity_context().http.clone();
+    let embed = apply_theme(&ctx.data().conf().appearance, embed);
+    let message = serenity::CreateMessage::new().embed(embed);
+
+    tokio::spawn(async move {
+        let channel = serenity::ChannelId::new(log_channel);
+        if let Err(err) = channel.send_message(http, message).await {
+            log::warn!("failed to post command invocation in log channel {channel}: {err}");
+        }
     });
+}
 
-    Ok(())
+fn report_context<'a>(ctx: &'a Context<'_>) -> report::Context<'a> {
+    report::Context::new(
+        ctx.guild_id().map(|guild| guild.get()),
+        Some(&ctx.command().qualified_name),
+    )
+}
+
+fn report_framework_error(err: &poise::FrameworkError<'_, BotData, InternalError>) {
+    let ctx = err.ctx();
+    let context = ctx.as_ref().map(report_context).unwrap_or_default();
+
+    report::message(context, &err.to_string());
 }
 
 async fn send_cooldown_alert(ctx: Context<'_>) {
-    let embed = serenity::CreateEmbed::new().title(":hotsprings: Hold on, I'm not that fast!");
+    let alerts = &ctx.data().conf().messages.alerts;
+    let embed = serenity::CreateEmbed::new().title(&alerts.cooldown);
     if let Err(err) = send_temporary_embedded_reply(ctx, embed).await {
         log::warn!("failed to send cooldown alert: {err}");
     }
 }
 
-async fn send_alert_on_info_error(ctx: Context<'_>) {
-    let embed =
-        serenity::CreateEmbed::new().title(":man_shrugging: Something went wrong and Idk why...");
+async fn send_alert_on_error(ctx: Context<'_>) {
+    let alerts = &ctx.data().conf().messages.alerts;
+    let embed = serenity::CreateEmbed::new().title(&alerts.unexpected_error);
     if let Err(err) = send_temporary_embedded_reply(ctx, embed).await {
-        log::warn!("failed to send alert on error in 'info' command: {err}",);
+        log::warn!(
+            "failed to send alert on error in '{}' command: {err}",
+            ctx.command().qualified_name
+        );
     }
 }
 
-async fn handle_info_error(err: poise::FrameworkError<'_, BotData, InternalError>) {
+async fn handle_command_error(err: poise::FrameworkError<'_, BotData, InternalError>) {
     match err {
         poise::FrameworkError::Command { ctx, ref error, .. } => {
-            log::error!("unexpected error while executing 'info' command: {error}");
+            log::error!(
+                "unexpected error while executing '{}' command: {error}",
+                ctx.command().qualified_name
+            );
+            report::error(report_context(&ctx), error.as_ref());
 
-            send_alert_on_info_error(ctx).await;
+            send_alert_on_error(ctx).await;
         }
         poise::FrameworkError::CommandPanic { ctx, payload, .. } => {
             log::error!(
-                "info command was abruptly stopped (i.e., panicked): {}",
+                "{} command was abruptly stopped (i.e., panicked): {}",
+                ctx.command().qualified_name,
                 payload.as_deref().unwrap_or("unknown reason")
             );
+            report::panic(report_context(&ctx), payload.as_deref());
 
-            send_alert_on_info_error(ctx).await;
+            send_alert_on_error(ctx).await;
         }
         poise::FrameworkError::CooldownHit { ctx, .. } => {
             send_cooldown_alert(ctx).await;
         }
-        poise::FrameworkError::MissingBotPermissions { .. } => (),
-        err => log::error!("scary error on 'info' command: {err}"),
+        poise::FrameworkError::MissingBotPermissions {
+            missing_permissions,
+            ctx,
+            ..
+        } => {
+            degraded::handle_missing_permissions(ctx, missing_permissions).await;
+        }
+        // The access check already told the member why.
+        poise::FrameworkError::CommandCheckFailed { error: None, .. } => (),
+        err => {
+            log::error!("scary error on command: {err}");
+            report_framework_error(&err);
+        }
     }
 }
 
 /// Displays information about the model and prompt characteristics
 #[poise::command(
     slash_command,
+    prefix_command,
     guild_only,
     user_cooldown = 2,
     required_permissions = "SEND_MESSAGES",
-    on_error = "handle_info_error"
+    on_error = "handle_command_error"
 )]
 async fn info(ctx: Context<'_>) -> Result<(), InternalError> {
     let data = ctx.data();
-    let reset_date = data.next_flush().format("%v, %R");
-    let conf = &data.conf;
-    let history_size = conf.chat.history_size;
-    let model = &conf.ai_provider.model;
+    let stats = data.stats();
+    let reset_date = stats.next_flush.format("%v, %R");
+    let conf = data.conf();
+    let guild = ctx.guild_id().unwrap().get();
+    let history_size = data.history_size(guild);
+    let model = &stats.model;
+    let info = &conf.messages.info;
 
     let embed = serenity::CreateEmbed::new()
-        .title("Characteristics")
-        .description(
-            "**Note:** older interactions are removed
-            when session limit is reached",
-        )
+        .title(&info.title)
+        .description(&info.description)
+        .field(&info.reset_date, format!("{}", reset_date), false)
         .field(
-            ":wastebasket: | Sessions Reset Date:",
-            format!("{}", reset_date),
+            &info.history_size,
+            messages::render(
+                &info.history_size_value,
+                &[
+                    ("count", &history_size),
+                    ("plural", &plural(history_size as u64)),
+                ],
+            ),
             false,
         )
         .field(
-            ":notepad_spiral: | Session History Size:",
-            format!(
-                "{} interaction{} per user",
-                history_size,
-                if history_size > 1 { "s" } else { "" }
+            &info.sessions_limit,
+            messages::render(
+                &info.sessions_limit_value,
+                &[("count", &conf.chat.max_sessions)],
             ),
             false,
         )
-        .field(":brain: | LLM's Name:", model, false)
+        .field(&info.model, model, false)
         .field(
-            ":pencil: | Prompt Message Size Limit:",
-            format!("{} tokens (aka characters)", data.conf.chat.prompt_size),
+            &info.prompt_size,
+            messages::render(
+                &info.prompt_size_value,
+                &[("count", &data.prompt_size(guild))],
+            ),
             false,
         );
+
+    let embed = match &conf.pricing {
+        Some(pricing) => {
+            let guild = ctx.guild_id().unwrap().get();
+            let usage = stats.guild_usage.get(&guild).copied().unwrap_or_default();
+            let spend = pricing.estimate(&data.usage.guild_spent(guild));
+            let per_prompt = if usage.prompts > 0 {
+                spend / usage.prompts as f64
+            } else {
+                0.
+            };
+
+            embed.field(
+                &info.cost,
+                messages::render(
+                    &info.cost_value,
+                    &[
+                        ("per_prompt", &format!("{per_prompt:.4}")),
+                        ("spend", &format!("{spend:.2}")),
+                        ("currency", &pricing.currency),
+                    ],
+                ),
+                false,
+            )
+        }
+        None => embed,
+    };
     send_embedded_reply(ctx, embed).await?;
 
     Ok(())
 }
 
+/// Shows who sent the most prompts in this server since the last reset
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    user_cooldown = 2,
+    required_permissions = "SEND_MESSAGES",
+    on_error = "handle_command_error"
+)]
+async fn leaderboard(ctx: Context<'_>) -> Result<(), InternalError> {
+    let data = ctx.data();
+    let guild = ctx.guild_id().unwrap().get();
+    let top_users = data.usage.top_users(guild, LEADERBOARD_SIZE);
+
+    let messages = &data.conf().messages.leaderboard;
+
+    let description = if top_users.is_empty() {
+        messages.empty.clone()
+    } else {
+        top_users
+            .iter()
+            .enumerate()
+            .map(|(i, (user, prompts))| {
+                let medal = match i {
+                    0 => ":first_place:",
+                    1 => ":second_place:",
+                    2 => ":third_place:",
+                    _ => ":medal:",
+                };
+
+                messages::render(
+                    &messages.entry,
+                    &[
+                        ("medal", &medal),
+                        ("user", &serenity::UserId::new(*user).mention()),
+                        ("count", prompts),
+                        ("plural", &plural(*prompts)),
+                    ],
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    let resets = messages::render(
+        &messages.resets,
+        &[("date", &data.next_flush().format("%v, %R"))],
+    );
+    let embed = serenity::CreateEmbed::new()
+        .title(&messages.title)
+        .description(format!("{description}\n\n{resets}"));
+    send_embedded_reply(ctx, embed).await?;
+
+    Ok(())
+}
+
+/// Alert telling why the prompt couldn't be answered.
+fn failure_alert<'a>(
+    alerts: &'a messages::Alerts,
+    error: &(dyn std::error::Error + Send + Sync + 'static),
+) -> &'a str {
+    let failure = error
+        .downcast_ref::<chat::Error>()
+        .map_or(chat::Failure::Internal, chat::Error::failure);
+
+    match failure {
+        chat::Failure::RateLimited => &alerts.rate_limited,
+        chat::Failure::Outage => &alerts.provider_outage,
+        chat::Failure::ContentFiltered => &alerts.content_filtered,
+        chat::Failure::ContextExceeded => &alerts.context_exceeded,
+        chat::Failure::Timeout => &alerts.prompt_timeout,
+        chat::Failure::EmptyResponse => &alerts.empty_response,
+        chat::Failure::Internal => &alerts.prompt_failure,
+    }
+}
+
 async fn handle_prompt_error(err: poise::FrameworkError<'_, BotData, InternalError>) {
     match err {
         poise::FrameworkError::Command { ctx, ref error, .. } => {
             log::error!("unexpected error while executing 'prompt' command: {error}");
+            report::error(report_context(&ctx), error.as_ref());
+
+            let alerts = &ctx.data().conf().messages.alerts;
+            let embed = serenity::CreateEmbed::new().title(failure_alert(alerts, error.as_ref()));
+            let _ = send_embedded_reply(ctx, embed).await;
+        }
+        poise::FrameworkError::CommandPanic { ctx, payload, .. } => {
+            log::error!(
+                "prompt command was abruptly stopped (i.e., panicked): {}",
+                payload.as_deref().unwrap_or("unknown reason")
+            );
+            report::panic(report_context(&ctx), payload.as_deref());
 
-            let embed = serenity::CreateEmbed::new()
-                .title(":skull: Failed to send message. Something went realy bad...");
+            let alerts = &ctx.data().conf().messages.alerts;
+            let embed = serenity::CreateEmbed::new().title(&alerts.prompt_failure);
             let _ = send_embedded_reply(ctx, embed).await;
         }
         poise::FrameworkError::CooldownHit { ctx, .. } => {
             send_cooldown_alert(ctx).await;
         }
-        poise::FrameworkError::MissingBotPermissions { .. } => (),
-        err => log::error!("scary error on 'prompt' command: {err}"),
+        poise::FrameworkError::MissingBotPermissions {
+            missing_permissions,
+            ctx,
+            ..
+        } => {
+            degraded::handle_missing_permissions(ctx, missing_permissions).await;
+        }
+        poise::FrameworkError::CommandCheckFailed { error: None, .. } => (),
+        err => {
+            log::error!("scary error on 'prompt' command: {err}");
+            report_framework_error(&err);
+        }
     }
 }
 
+async fn autocomplete_override_model(
+    ctx: Context<'_>,
+    partial: &str,
+) -> Vec<serenity::AutocompleteChoice> {
+    ctx.data()
+        .conf()
+        .ai_provider
+        .override_models
+        .iter()
+        .filter(|model| model.contains(partial))
+        .map(|model| serenity::AutocompleteChoice::new(model.as_str(), model.as_str()))
+        .collect()
+}
+
 /// Sends a message and waits for the model's response
 #[poise::command(
     slash_command,
+    prefix_command,
+    aliases("ask"),
+    invoke_on_edit,
     guild_only,
     user_cooldown = 4,
     required_permissions = "SEND_MESSAGES",
@@ -274,99 +1801,268 @@ async fn handle_prompt_error(err: poise::FrameworkError<'_, BotData, InternalErr
 )]
 async fn prompt(
     ctx: Context<'_>,
-    #[description = "message to send"] content: String,
+    #[description = "voice message to transcribe and send"] voice: Option<serenity::Attachment>,
+    #[description = "model to use for this message only"]
+    #[autocomplete = "autocomplete_override_model"]
+    model: Option<pipeline::ModelChoice>,
+    #[description = "sends the recent messages of the channel along"]
+    #[flag]
+    channel_context: bool,
+    #[description = "message to send"]
+    #[rest]
+    content: Option<String>,
 ) -> Result<(), InternalError> {
-    let data = ctx.data();
-    let conf = &data.conf;
+    let mut exchange = pipeline::Exchange::new(ctx, content.unwrap_or_default());
+    exchange.voice = voice;
+    exchange.model = model.map(|model| model.0);
+    exchange.channel_context = channel_context;
 
-    if content.len() > conf.chat.prompt_size as usize {
-        let embed = serenity::CreateEmbed::new().title(format!(
-            ":red_circle: Message must be {} tokens max",
-            conf.chat.prompt_size
-        ));
-        send_embedded_reply(ctx, embed).await?;
-
-        return Ok(());
-    }
-
-    if data.is_flushing() {
-        let embed = serenity::CreateEmbed::new()
-            .title(":yellow_circle: History is being flushed, wait a little more");
-        send_embedded_reply(ctx, embed).await?;
+    ctx.data().pipeline.run(ctx, &mut exchange).await
+}
 
+/// Posts a quick-start guide in the system channel of a newly joined guild.
+async fn send_welcome_message(
+    ctx: &serenity::Context,
+    data: &BotData,
+    guild: &serenity::Guild,
+) -> Result<(), serenity::Error> {
+    let Some(channel) = guild.system_channel_id else {
         return Ok(());
-    }
-
-    let guild = ctx.guild_id().unwrap().get();
-    let user = ctx.author().id.get();
-
-    let session = data.session(guild, user).await;
-    let response = session.send_message(content).await?;
-
-    match ctx.reply(response).await {
-        Ok(_) => Ok(()),
-        Err(err) => {
-            session.remove_last_interaction().await;
-
-            Err(Box::from(err))
-        }
-    }
-}
+    };
 
-fn start_sessions_flusher(data: BotData) {
-    tokio::spawn(async move {
-        loop {
-            data.schedule_next_flush();
+    let conf = data.conf();
+    let welcome = &conf.messages.welcome;
+    let history_size = data.history_size(guild.id.get());
+    let limits = messages::render(
+        &welcome.limits_value,
+        &[
+            ("prompt_size", &data.prompt_size(guild.id.get())),
+            ("history_size", &history_size),
+            ("plural", &plural(history_size as u64)),
+            ("flush_days", &conf.chat.flush_days),
+        ],
+    );
 
-            tokio::time::sleep(data.flush_timeout).await;
+    let embed = serenity::CreateEmbed::new()
+        .title(&welcome.title)
+        .description(&welcome.description)
+        .field(&welcome.prompt, &welcome.prompt_value, false)
+        .field(&welcome.info, &welcome.info_value, false)
+        .field(&welcome.limits, limits, false)
+        .field(&welcome.admin, &welcome.admin_value, false);
+    let embed = apply_theme(&conf.appearance, embed);
+
+    channel
+        .send_message(ctx, serenity::CreateMessage::new().embed(embed))
+        .await?;
 
-            data.flush().await;
-        }
-    });
+    Ok(())
 }
 
 async fn event_handler(
-    _ctx: &serenity::Context,
+    ctx: &serenity::Context,
     event: &serenity::FullEvent,
-    _framework: poise::FrameworkContext<'_, BotData, InternalError>,
-    _data: &BotData,
+    framework: poise::FrameworkContext<'_, BotData, InternalError>,
+    data: &BotData,
 ) -> Result<(), InternalError> {
     match event {
         serenity::FullEvent::Ready { data_about_bot } => {
             let servers = data_about_bot.guilds.len();
             let session = data_about_bot.session_id.as_str();
             log::info!(
-                "bot has been connected to discord on {} server{} (session '{}')",
+                "[shard {}] bot has been connected to discord on {} server{} (session '{}')",
+                ctx.shard_id,
                 servers,
                 if servers != 1 { "s" } else { "" },
                 session
             );
         }
         serenity::FullEvent::Resume { .. } => {
-            log::info!("bot was reconnected to discord");
+            log::info!("[shard {}] bot was reconnected to discord", ctx.shard_id);
         }
         serenity::FullEvent::ShardsReady { total_shards } => {
             let shards = total_shards;
             log::info!("bot shards are ready (loaded {})", shards);
         }
+        serenity::FullEvent::GuildCreate { guild, is_new } => {
+            let commands = &framework.options().commands;
+            if let Err(err) = command_set::register(&ctx.http, commands, data, guild.id.get()).await
+            {
+                log::warn!(
+                    "[shard {}] failed to register the commands of guild {}: {err}",
+                    ctx.shard_id,
+                    guild.id
+                );
+            }
+
+            let welcome = *is_new == Some(true) && data.conf().appearance.welcome_message;
+            if welcome {
+                if let Err(err) = send_welcome_message(ctx, data, guild).await {
+                    log::warn!(
+                        "[shard {}] failed to send welcome message to guild {}: {err}",
+                        ctx.shard_id,
+                        guild.id
+                    );
+                }
+            }
+        }
+        serenity::FullEvent::MessageDelete {
+            deleted_message_id, ..
+        } => {
+            if let Some(janitor) = data.janitor.get() {
+                janitor.cancel(*deleted_message_id);
+            }
+        }
+        serenity::FullEvent::MessageDeleteBulk {
+            multiple_deleted_messages_ids,
+            ..
+        } => {
+            if let Some(janitor) = data.janitor.get() {
+                multiple_deleted_messages_ids
+                    .iter()
+                    .for_each(|message| janitor.cancel(*message));
+            }
+        }
+        serenity::FullEvent::ReactionAdd { add_reaction } if data.conf().reactions.enabled => {
+            reactions::handle_reaction(ctx, data, add_reaction).await?;
+        }
+        serenity::FullEvent::InteractionCreate {
+            interaction: serenity::Interaction::Component(press),
+        } if press
+            .data
+            .custom_id
+            .starts_with(followups::CUSTOM_ID_PREFIX) =>
+        {
+            followups::handle_press(ctx, data, press).await?;
+        }
+        serenity::FullEvent::InteractionCreate {
+            interaction: serenity::Interaction::Component(press),
+        } if press.data.custom_id == reasoning::CUSTOM_ID => {
+            reasoning::handle_press(ctx, data, press).await?;
+        }
+        serenity::FullEvent::InteractionCreate {
+            interaction: serenity::Interaction::Component(press),
+        } if press.data.custom_id.starts_with(compare::CUSTOM_ID_PREFIX) => {
+            compare::handle_press(ctx, data, press).await?;
+        }
         _ => (),
     }
 
     Ok(())
 }
 
-fn build_framework(conf: &config::App) -> poise::Framework<BotData, InternalError> {
+async fn handle_framework_error(err: poise::FrameworkError<'_, BotData, InternalError>) {
+    match err {
+        poise::FrameworkError::EventHandler {
+            ref error,
+            ctx,
+            event,
+            ..
+        } => {
+            log::error!(
+                "[shard {}] unexpected error while handling '{}' event: {error}",
+                ctx.shard_id,
+                event.snake_case_name()
+            );
+            report::error(report::Context::default(), error.as_ref());
+        }
+        poise::FrameworkError::Setup { ref error, .. } => {
+            log::error!("unexpected error while setting up the bot: {error}");
+            report::error(report::Context::default(), error.as_ref());
+        }
+        err => {
+            log::error!("scary framework error: {err}");
+            report_framework_error(&err);
+        }
+    }
+}
+
+fn build_data(
+    conf: &config::App,
+    api_key: secrets::Secret,
+    script: Option<hooks::Script>,
+) -> BotData {
+    let lanes = Arc::new(lanes::Lanes::new(&conf.lanes));
+    let provider = chat::provider(conf.ai_provider.provider, api_key);
     let sbuilder = chat::SessionBuilder::new(
-        conf.ai_provider.api_key.clone(),
+        Arc::new(lanes::Paced::new(provider, lanes.clone())),
         conf.ai_provider.model.clone(),
-        conf.chat.history_size as usize,
+        conf.ai_provider
+            .title_model
+            .clone()
+            .unwrap_or_else(|| conf.ai_provider.model.clone()),
+        script,
+        conf.postprocess.clone(),
+        chat::history_policy(&conf.chat),
     );
 
-    let data = BotData::new(sbuilder, conf.clone());
+    BotData::new(sbuilder, lanes, conf.clone())
+}
+
+fn build_framework(conf: &config::App, data: BotData) -> poise::Framework<BotData, InternalError> {
+    let mut commands = vec![
+        info(),
+        prompt(),
+        long_prompt::prompt_long(),
+        ask_many::ask_many(),
+        run_pipeline::run_pipeline(),
+        compare::compare(),
+        leaderboard(),
+        sessions::sessions(),
+        sessions::pin_session(),
+        sessions::unpin_session(),
+        sessions::undo_reset(),
+        status::status(),
+        system::system(),
+        transfer::export(),
+        transfer::import(),
+        schedule::schedule(),
+        schedule::schedules(),
+        schedule::unschedule(),
+        search::search(),
+        share::share(),
+        settings::guild_config(),
+        tldr::tldr(),
+        history::history(),
+        history::branch(),
+        language::language(),
+        macros::prompt_macro(),
+        moderation::moderation(),
+        admin::admin(),
+    ];
+
+    if conf.imagine.enabled {
+        commands.push(imagine::imagine());
+    }
+
+    #[cfg(feature = "voice")]
+    if conf.voice.enabled {
+        commands.push(voice::speak());
+    }
 
     poise::Framework::builder()
         .options(poise::FrameworkOptions {
-            commands: vec![info(), prompt()],
+            commands,
+            owners: conf
+                .bot
+                .owners
+                .iter()
+                .map(|&owner| serenity::UserId::new(owner))
+                .collect(),
+            on_error: |err| Box::pin(handle_framework_error(err)),
+            command_check: Some(|ctx| Box::pin(check_access(ctx))),
+            allowed_mentions: Some(mentions_policy(conf.bot.mentions)),
+            prefix_options: poise::PrefixFrameworkOptions {
+                prefix: conf.bot.prefix.clone(),
+                mention_as_prefix: conf.bot.prefix.is_some(),
+                edit_tracker: conf.bot.regenerate_on_edit.then(edits::tracker),
+                // Edits re-run the prompts tracked since the start, which then ignore the ones
+                // not answered yet. Replies posted through webhooks aren't tracked as responses.
+                execute_untracked_edits: false,
+                ignore_edits_if_not_yet_responded: false,
+                ..Default::default()
+            },
+            pre_command: |ctx| Box::pin(log_command_invocation(ctx)),
             event_handler: |ctx, event, framework, data| {
                 Box::pin(event_handler(ctx, event, framework, data))
             },
@@ -374,11 +2070,21 @@ fn build_framework(conf: &config::App) -> poise::Framework<BotData, InternalErro
         })
         .setup(|ctx, _ready, framework| {
             Box::pin(async move {
-                let commands = &framework.options().commands;
-                let create_commands = poise::builtins::create_application_commands(commands);
+                // Guilds get their own commands once they're available, see `command_set`.
+                let create_commands = if data.conf().bot.guild_commands {
+                    Vec::new()
+                } else {
+                    poise::builtins::create_application_commands(&framework.options().commands)
+                };
                 serenity::Command::set_global_commands(ctx, create_commands).await?;
 
-                start_sessions_flusher(data.clone());
+                flusher::start(data.clone());
+                accounting::start(data.clone());
+                tombstones::start(data.clone());
+                stats::start(data.clone());
+                janitor::start(ctx.http.clone(), data.clone());
+                digest::start(ctx.clone(), data.clone());
+                schedule::start(ctx.clone(), data.clone());
 
                 Ok(data)
             })
@@ -386,11 +2092,54 @@ fn build_framework(conf: &config::App) -> poise::Framework<BotData, InternalErro
         .build()
 }
 
+fn gateway_intent(intent: config::Intent) -> serenity::GatewayIntents {
+    match intent {
+        config::Intent::Guilds => serenity::GatewayIntents::GUILDS,
+        config::Intent::GuildMembers => serenity::GatewayIntents::GUILD_MEMBERS,
+        config::Intent::GuildModeration => serenity::GatewayIntents::GUILD_MODERATION,
+        config::Intent::GuildPresences => serenity::GatewayIntents::GUILD_PRESENCES,
+        config::Intent::GuildMessages => serenity::GatewayIntents::GUILD_MESSAGES,
+        config::Intent::GuildMessageReactions => serenity::GatewayIntents::GUILD_MESSAGE_REACTIONS,
+        config::Intent::MessageContent => serenity::GatewayIntents::MESSAGE_CONTENT,
+        config::Intent::GuildVoiceStates => serenity::GatewayIntents::GUILD_VOICE_STATES,
+        config::Intent::DirectMessages => serenity::GatewayIntents::DIRECT_MESSAGES,
+        config::Intent::DirectMessageReactions => {
+            serenity::GatewayIntents::DIRECT_MESSAGE_REACTIONS
+        }
+    }
+}
+
+fn gateway_intents(conf: &config::App) -> serenity::GatewayIntents {
+    if let Some(intents) = &conf.bot.intents {
+        return intents
+            .iter()
+            .fold(serenity::GatewayIntents::empty(), |all, &intent| {
+                all | gateway_intent(intent)
+            });
+    }
+
+    let mut intents = serenity::GatewayIntents::GUILDS | serenity::GatewayIntents::GUILD_MESSAGES;
+
+    if conf.reactions.enabled {
+        intents |= serenity::GatewayIntents::GUILD_MESSAGE_REACTIONS;
+    }
+
+    if conf.bot.prefix.is_some() {
+        intents |= serenity::GatewayIntents::MESSAGE_CONTENT;
+    }
+
+    if conf.voice.enabled {
+        intents |= serenity::GatewayIntents::GUILD_VOICE_STATES;
+    }
+
+    intents
+}
+
 async fn build_client(
-    bot: config::Bot,
+    discord_token: String,
+    intents: serenity::GatewayIntents,
     framework: poise::Framework<BotData, InternalError>,
 ) -> Result<serenity::Client, serenity::Error> {
-    let intents = serenity::GatewayIntents::GUILD_MESSAGES;
     let activity = serenity::ActivityData {
         name: "Stealing LLM's access for my own benefit".to_string(),
         kind: serenity::ActivityType::Playing,
@@ -399,19 +2148,203 @@ async fn build_client(
     };
     let status = serenity::OnlineStatus::Online;
 
-    serenity::ClientBuilder::new(bot.discord_token, intents)
+    let builder = serenity::ClientBuilder::new(discord_token, intents)
         .framework(framework)
         .activity(activity)
-        .status(status)
-        .await
+        .status(status);
+
+    #[cfg(feature = "voice")]
+    let builder = songbird::SerenityInit::register_songbird(builder);
+
+    builder.await
 }
 
-pub async fn run(config: config::App) -> Result<(), Error> {
-    let framework = build_framework(&config);
+/// Waits for ctrl-c or, on unix, for SIGTERM, which containers are stopped with.
+async fn shutdown_signal() -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        let mut terminate =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+
+        tokio::select! {
+            signal = tokio::signal::ctrl_c() => signal,
+            _ = terminate.recv() => Ok(()),
+        }
+    }
+
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await
+}
+
+pub async fn run(config: config::App, restore: bool) -> Result<(), Error> {
+    let script = config
+        .hooks
+        .script
+        .as_deref()
+        .map(hooks::Script::load)
+        .transpose()
+        .map_err(Error::Script)?;
+
+    let resolvers = Arc::new(secrets::Resolvers::new(&config.secrets));
+    let discord_token = resolvers
+        .resolve(&config.bot.discord_token)
+        .await
+        .map_err(Error::Secret)?;
+    #[cfg(feature = "voice")]
+    let tts_key = if config.voice.enabled {
+        let key = resolvers
+            .resolve(&config.voice.tts_api_key)
+            .await
+            .map_err(Error::Secret)?;
+
+        Some(secrets::Secret::new(key))
+    } else {
+        None
+    };
+
+    let stt_key = if config.transcription.enabled {
+        let key = resolvers
+            .resolve(&config.transcription.stt_api_key)
+            .await
+            .map_err(Error::Secret)?;
+
+        Some(secrets::Secret::new(key))
+    } else {
+        None
+    };
+
+    let image_key = if config.imagine.enabled {
+        let key = resolvers
+            .resolve(&config.imagine.image_api_key)
+            .await
+            .map_err(Error::Secret)?;
+
+        Some(secrets::Secret::new(key))
+    } else {
+        None
+    };
+
+    // Mock replies don't need a key, which may not even be stored yet.
+    let api_key = match config.ai_provider.provider {
+        config::Provider::Genai => resolvers
+            .resolve(&config.ai_provider.api_key)
+            .await
+            .map(secrets::Secret::new)
+            .map_err(Error::Secret)?,
+        config::Provider::Mock => secrets::Secret::new(String::new()),
+    };
+
+    if config.secrets.refresh_secs > 0 && config.ai_provider.provider == config::Provider::Genai {
+        secrets::spawn_refresher(
+            resolvers.clone(),
+            config.ai_provider.api_key.clone(),
+            api_key.clone(),
+            Duration::from_secs(config.secrets.refresh_secs),
+        );
+    }
+
+    let data = build_data(&config, api_key.clone(), script);
+    let routes = routing::Routes::build(&config, &resolvers, &api_key, &data.lanes)
+        .await
+        .map_err(Error::Secret)?;
+    let _ = data.routes.set(routes);
+
+    if let Some(key) = stt_key {
+        let _ = data
+            .stt
+            .set(crate::stt::Stt::new(&config.transcription, key));
+    }
+
+    if let Some(key) = image_key {
+        let _ = data
+            .images
+            .set(crate::image::Generator::new(&config.imagine, key));
+    }
 
-    let mut client = build_client(config.bot, framework)
+    #[cfg(feature = "voice")]
+    if let Some(key) = tts_key {
+        let _ = data.tts.set(crate::tts::Tts::new(&config.voice, key));
+    }
+
+    if restore {
+        match &config.persistence.snapshot_dir {
+            Some(dir) => {
+                let restored = snapshot::restore(&data, dir)
+                    .await
+                    .map_err(Error::Restore)?;
+                log::info!("restored {restored} session(s) from snapshot");
+            }
+            None => log::warn!("nothing to restore, snapshot directory isn't configured"),
+        }
+    }
+
+    if let Some(dir) = &config.persistence.snapshot_dir {
+        let loaded = settings::load(&data, dir).await.map_err(Error::Settings)?;
+        log::info!("loaded the settings of {loaded} guild(s)");
+
+        let loaded = schedule::load(&data, dir).await.map_err(Error::Schedule)?;
+        log::info!("loaded {loaded} scheduled prompt(s)");
+
+        let usage_file = config.bot.shard_file(usage::USAGE_FILE);
+        let loaded = data
+            .usage
+            .load(dir, &usage_file)
+            .await
+            .map_err(Error::Usage)?;
+        log::info!("loaded {loaded} day(s) of usage");
+    }
+
+    if config.bot.warmup {
+        warmup::run(&data).await.map_err(Error::Warmup)?;
+    }
+
+    console::spawn(&data);
+
+    let framework = build_framework(&config, data.clone());
+    let intents = gateway_intents(&config);
+
+    let mut client = build_client(discord_token, intents, framework)
         .await
         .map_err(Error::Creation)?;
+    preflight::check(&client.http, intents, config.bot.startup_checks)
+        .await
+        .map_err(Error::Preflight)?;
+
+    let shard_manager = client.shard_manager.clone();
+    let shards = config.bot.shards;
+    let start = async {
+        match shards {
+            None => client.start().await,
+            Some(config::Shards::Auto(_)) => client.start_autosharded().await,
+            Some(config::Shards::Count(total)) => client.start_shards(total).await,
+            // Serenity takes the range end as the last shard to start.
+            Some(config::Shards::Range { first, last, total }) => {
+                client.start_shard_range(first..last, total).await
+            }
+        }
+    };
+
+    tokio::select! {
+        result = start => result.map_err(|err| {
+            report::error(report::Context::default(), &err);
+
+            Error::Initialization(err)
+        }),
+        signal = shutdown_signal() => {
+            signal.map_err(Error::Signal)?;
+            log::info!("shutting down bot");
+
+            shard_manager.shutdown_all().await;
+            accounting::save(&data).await;
 
-    client.start().await.map_err(Error::Initialization)
+            if let Some(dir) = &data.conf().persistence.snapshot_dir {
+                if let Err(err) = snapshot::save(&data, dir).await {
+                    log::error!("failed to snapshot sessions on shutdown: {err}");
+                    report::error(report::Context::default(), &err);
+                }
+            }
+
+            Ok(())
+        }
+    }
 }
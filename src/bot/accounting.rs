@@ -0,0 +1,35 @@
+use std::time::Duration;
+
+use crate::{report, usage};
+
+use super::{BotData, BotDataInner};
+
+/// Usage counted since the last save is lost if the bot stops abruptly.
+const SAVE_POLL: Duration = Duration::from_secs(5);
+
+/// Writes the usage counters down, if there's somewhere to keep them.
+pub(super) async fn save(data: &BotDataInner) {
+    let conf = data.conf();
+    let Some(dir) = &conf.persistence.snapshot_dir else {
+        return;
+    };
+
+    let file = conf.bot.shard_file(usage::USAGE_FILE);
+    if let Err(err) = data.usage.save(dir, &file).await {
+        log::error!("failed to save usage: {err}");
+        report::error(report::Context::default(), &err);
+    }
+}
+
+/// Saves the usage counters shortly after they change, so restarts keep quotas and reports.
+pub(super) fn start(data: BotData) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(SAVE_POLL).await;
+
+            if data.usage.changed() {
+                save(&data).await;
+            }
+        }
+    });
+}
@@ -0,0 +1,585 @@
+use std::collections::HashSet;
+
+use poise::serenity_prelude::{self as serenity, Mentionable};
+
+use crate::{config, usage};
+
+use super::{
+    apply_theme, command_set,
+    experiment::{Arm, Summary},
+    guild_data, handle_command_error,
+    limits::Limits,
+    send_embedded_reply, send_ephemeral_embedded_reply, send_paginated_embeds,
+    send_temporary_embedded_reply, settings, BotData, Context, InternalError,
+};
+
+const GUILDS_PER_PAGE: usize = 10;
+const DEGRADED_SHOWN: usize = 5;
+const ARCHIVE_MAX_KIB: u32 = 1024;
+
+async fn handle_admin_error(err: poise::FrameworkError<'_, BotData, InternalError>) {
+    match err {
+        poise::FrameworkError::NotAnOwner { ctx, .. } => {
+            let alerts = &ctx.data().conf().messages.alerts;
+            let embed = serenity::CreateEmbed::new().title(&alerts.owner_only);
+            if let Err(err) = send_temporary_embedded_reply(ctx, embed).await {
+                log::warn!("failed to send owner-only alert: {err}");
+            }
+        }
+        err => handle_command_error(err).await,
+    }
+}
+
+/// Bot owner tools
+#[poise::command(
+    slash_command,
+    owners_only,
+    default_member_permissions = "ADMINISTRATOR",
+    subcommands(
+        "stats",
+        "usage_report",
+        "maintenance",
+        "capture",
+        "limits",
+        "experiment",
+        "export_guild_data",
+        "import_guild_data"
+    ),
+    subcommand_required,
+    on_error = "handle_admin_error"
+)]
+pub async fn admin(_ctx: Context<'_>) -> Result<(), InternalError> {
+    Ok(())
+}
+
+/// Line telling whether the sessions flusher runs and when it last flushed.
+fn flusher_summary(ctx: Context<'_>) -> String {
+    let health = &ctx.data().flusher;
+    let state = if health.is_alive() {
+        ":green_circle: alive"
+    } else {
+        ":red_circle: down"
+    };
+    let last_run = health.last_run().map_or_else(
+        || "never".to_string(),
+        |at| format!("<t:{}:R>", at.timestamp()),
+    );
+
+    format!(
+        "\nFlusher {state}, last run {last_run}, {} restart(s)",
+        health.restarts()
+    )
+}
+
+/// Lines listing the most recently degraded guilds, if any.
+fn degraded_summary(ctx: Context<'_>) -> String {
+    let degraded = ctx.data().degraded.guilds();
+    if degraded.is_empty() {
+        return String::new();
+    }
+
+    let mut summary = format!("\n\n:warning: **{}** degraded:", degraded.len());
+    for (guild, entry) in degraded.iter().take(DEGRADED_SHOWN) {
+        let name = serenity::GuildId::new(*guild)
+            .name(ctx.cache())
+            .unwrap_or_else(|| "Unknown".to_string());
+        summary.push_str(&format!(
+            "\n- {} ({}): {} <t:{}:R>",
+            name,
+            guild,
+            entry.reason,
+            entry.at.timestamp()
+        ));
+    }
+
+    summary
+}
+
+/// Lists the guilds with the highest prompt volume since the last flush
+#[poise::command(
+    slash_command,
+    owners_only,
+    user_cooldown = 2,
+    on_error = "handle_admin_error"
+)]
+async fn stats(ctx: Context<'_>) -> Result<(), InternalError> {
+    let data = ctx.data();
+    let guilds = data.usage.guilds();
+    let stats = data.stats();
+    let since = data.last_flush().format("%v, %R");
+
+    if guilds.is_empty() {
+        let embed = serenity::CreateEmbed::new()
+            .title(":bar_chart: Guild Statistics")
+            .description(format!(
+                "No prompts were sent since {since}{}{}",
+                flusher_summary(ctx),
+                degraded_summary(ctx)
+            ));
+        send_embedded_reply(ctx, embed).await?;
+
+        return Ok(());
+    }
+
+    let total_prompts: u64 = guilds.iter().map(|(_, usage)| usage.prompts).sum();
+    let total_tokens: u64 = guilds.iter().map(|(_, usage)| usage.total_tokens()).sum();
+    let mut description = format!(
+        "**{}** guild{}, **{}** prompts and **{}** tokens since {}",
+        guilds.len(),
+        if guilds.len() > 1 { "s" } else { "" },
+        total_prompts,
+        total_tokens,
+        since
+    );
+    if let Some(rate) = data.throughput.last_rate() {
+        description.push_str(&format!("\nLast reply generated at **{rate:.1}** tokens/s"));
+    }
+    description.push_str(&flusher_summary(ctx));
+    description.push_str(&degraded_summary(ctx));
+
+    let pages = guilds
+        .chunks(GUILDS_PER_PAGE)
+        .enumerate()
+        .map(|(page, chunk)| {
+            let fields = chunk.iter().enumerate().map(|(i, (guild, usage))| {
+                let name = serenity::GuildId::new(*guild)
+                    .name(ctx.cache())
+                    .unwrap_or_else(|| "Unknown".to_string());
+                let sessions = stats.active_sessions.get(guild).copied().unwrap_or(0);
+
+                (
+                    format!("{}. {} ({})", page * GUILDS_PER_PAGE + i + 1, name, guild),
+                    format!(
+                        "prompts: {} | sessions: {} | errors: {} ({:.1}%) | tokens: {} in, {} out",
+                        usage.prompts,
+                        sessions,
+                        usage.errors,
+                        usage.error_rate() * 100.,
+                        usage.input_tokens,
+                        usage.output_tokens
+                    ),
+                    false,
+                )
+            });
+
+            serenity::CreateEmbed::new()
+                .title(":bar_chart: Guild Statistics")
+                .description(&description)
+                .fields(fields)
+        })
+        .collect();
+
+    send_paginated_embeds(ctx, pages, true).await?;
+
+    Ok(())
+}
+
+fn usage_field(
+    pricing: Option<&config::Pricing>,
+    period: &str,
+    usage: usage::Summary,
+    spent: usage::Spent,
+) -> (String, String, bool) {
+    let mut value = format!(
+        "prompts: {} | images: {} | errors: {} | tokens: {} in, {} out",
+        usage.prompts, usage.images, usage.errors, usage.input_tokens, usage.output_tokens
+    );
+    if let Some(pricing) = pricing {
+        let spend = pricing.estimate(&spent);
+        value.push_str(&format!(" | spend: ~{:.2} {}", spend, pricing.currency));
+    }
+
+    (period.to_string(), value, false)
+}
+
+/// Shows the provider usage and estimated spend across every guild
+#[poise::command(
+    slash_command,
+    owners_only,
+    rename = "usage-report",
+    user_cooldown = 2,
+    on_error = "handle_admin_error"
+)]
+async fn usage_report(ctx: Context<'_>) -> Result<(), InternalError> {
+    let data = ctx.data();
+    let conf = data.conf();
+    let pricing = conf.pricing.as_ref();
+    let spent = |day| data.usage.spent_since(day, None);
+
+    let embed = serenity::CreateEmbed::new()
+        .title(":money_with_wings: Usage Report")
+        .fields([
+            usage_field(
+                pricing,
+                ":calendar: | Today:",
+                data.usage.today(),
+                spent(usage::today()),
+            ),
+            usage_field(
+                pricing,
+                ":date: | This Week:",
+                data.usage.this_week(),
+                spent(usage::monday()),
+            ),
+            usage_field(
+                pricing,
+                ":spiral_calendar: | This Month:",
+                data.usage.this_month(),
+                spent(usage::month_start()),
+            ),
+        ]);
+    send_ephemeral_embedded_reply(ctx, embed).await?;
+
+    Ok(())
+}
+
+/// Pauses prompts and background tasks, or resumes them
+#[poise::command(
+    slash_command,
+    owners_only,
+    user_cooldown = 2,
+    on_error = "handle_admin_error"
+)]
+async fn maintenance(
+    ctx: Context<'_>,
+    #[description = "whether the bot is under maintenance"] enabled: bool,
+) -> Result<(), InternalError> {
+    ctx.data().maintenance(enabled);
+
+    let title = if enabled {
+        ":construction: Maintenance mode is on"
+    } else {
+        ":white_check_mark: Maintenance mode is off"
+    };
+    log::info!(
+        "maintenance mode was turned {}",
+        if enabled { "on" } else { "off" }
+    );
+
+    let embed = serenity::CreateEmbed::new().title(title);
+    send_ephemeral_embedded_reply(ctx, embed).await?;
+
+    Ok(())
+}
+
+/// Writes the full request and provider response of a member's next prompt to a file
+#[poise::command(
+    slash_command,
+    owners_only,
+    user_cooldown = 2,
+    on_error = "handle_admin_error"
+)]
+async fn capture(
+    ctx: Context<'_>,
+    #[description = "member whose next prompt is captured"] user: serenity::User,
+) -> Result<(), InternalError> {
+    let data = ctx.data();
+
+    let embed = if data.conf().observability.capture_dir.is_none() {
+        serenity::CreateEmbed::new()
+            .title(":no_entry: Captures are disabled")
+            .description("Set `observability.capture_dir` to enable them")
+    } else {
+        data.arm_capture(user.id.get(), ctx.author().id.get());
+        log::info!(
+            "{} armed a capture of the next prompt of {}",
+            ctx.author().id,
+            user.id
+        );
+
+        serenity::CreateEmbed::new()
+            .title(":microscope: Capture armed")
+            .description(format!(
+                "The next prompt of {} within the hour is written to a file, you'll get its path in DMs",
+                user.mention()
+            ))
+    };
+    send_ephemeral_embedded_reply(ctx, embed).await?;
+
+    Ok(())
+}
+
+/// Grants a guild other prompt and history sizes, or resets them when both are left out
+#[poise::command(
+    slash_command,
+    owners_only,
+    user_cooldown = 2,
+    on_error = "handle_admin_error"
+)]
+async fn limits(
+    ctx: Context<'_>,
+    #[description = "id of the guild"] guild: String,
+    #[description = "longest prompt, in characters"] prompt_size: Option<u16>,
+    #[description = "interactions sessions keep"] history_size: Option<u8>,
+) -> Result<(), InternalError> {
+    let data = ctx.data();
+
+    let Ok(guild) = guild.trim().parse::<u64>() else {
+        let embed = serenity::CreateEmbed::new().title(":red_circle: Invalid guild id");
+        send_ephemeral_embedded_reply(ctx, embed).await?;
+
+        return Ok(());
+    };
+
+    let range = config::PROMPT_SIZE_RANGE;
+    if prompt_size.is_some_and(|size| !range.contains(&size)) || history_size == Some(0) {
+        let embed = serenity::CreateEmbed::new()
+            .title(":red_circle: Invalid limits")
+            .description(format!(
+                "Prompt size must be between {} and {}, history size greater than zero",
+                range.start(),
+                range.end()
+            ));
+        send_ephemeral_embedded_reply(ctx, embed).await?;
+
+        return Ok(());
+    }
+
+    let limits = Limits {
+        prompt_size,
+        history_size,
+    };
+    if limits.is_empty() {
+        data.limits.remove(&guild);
+    } else {
+        data.limits.insert(guild, limits);
+    }
+    log::info!(
+        "{} set the limits of guild {guild} to {limits:?}",
+        ctx.author().id
+    );
+
+    // Sessions already started keep their history size until flushed.
+    let embed = serenity::CreateEmbed::new()
+        .title(":straight_ruler: Limits updated")
+        .description(format!(
+            "Guild {} now takes prompts up to **{}** characters, new sessions keep **{}** interactions at most",
+            guild,
+            data.prompt_size(guild),
+            data.max_history_size(guild)
+        ));
+    send_ephemeral_embedded_reply(ctx, embed).await?;
+
+    Ok(())
+}
+
+fn arm_field(
+    pricing: Option<&config::Pricing>,
+    name: &str,
+    model: &str,
+    summary: Summary,
+    spent: usage::Spent,
+) -> (String, String, bool) {
+    let mut value = format!(
+        "model: {} | sessions: {} | prompts: {} | errors: {:.1}% | tokens per prompt: {:.0}\n\
+        regenerated: {:.1}% | deleted: {:.1}% | picked in /compare: {}",
+        model,
+        summary.sessions,
+        summary.prompts,
+        summary.rate(summary.errors) * 100.,
+        summary.tokens_per_prompt(),
+        summary.rate(summary.regenerations) * 100.,
+        summary.rate(summary.deletions) * 100.,
+        summary.picks
+    );
+    if let Some(pricing) = pricing {
+        let spend = pricing.estimate(&spent);
+        value.push_str(&format!(
+            " | spend this month: ~{:.2} {}",
+            spend, pricing.currency
+        ));
+    }
+
+    (name.to_string(), value, false)
+}
+
+/// Compares the usage and feedback of the default and variant sessions
+#[poise::command(
+    slash_command,
+    owners_only,
+    user_cooldown = 2,
+    on_error = "handle_admin_error"
+)]
+async fn experiment(ctx: Context<'_>) -> Result<(), InternalError> {
+    let data = ctx.data();
+    let conf = data.conf();
+    let experiment = &conf.experiment;
+    let pricing = conf.pricing.as_ref();
+    let model = data.sbuilder.model();
+    let variant_model = experiment.model.as_deref().unwrap_or(&model);
+    // Tokens of each arm are tagged in the usage, which keeps them across restarts.
+    let month = usage::month_start();
+
+    let description = if experiment.share == 0 {
+        "No experiment is running, counts since startup are kept".to_string()
+    } else {
+        format!(
+            "**{}%** of new sessions are created with the variant, counted since startup",
+            experiment.share
+        )
+    };
+    let embed = serenity::CreateEmbed::new()
+        .title(":test_tube: Experiment")
+        .description(description)
+        .fields([
+            arm_field(
+                pricing,
+                ":a: | Control:",
+                &model,
+                data.experiment.summary(Arm::Control),
+                data.usage.spent_since(month, Some(Arm::Control)),
+            ),
+            arm_field(
+                pricing,
+                ":b: | Variant:",
+                variant_model,
+                data.experiment.summary(Arm::Variant),
+                data.usage.spent_since(month, Some(Arm::Variant)),
+            ),
+        ]);
+    send_ephemeral_embedded_reply(ctx, embed).await?;
+
+    Ok(())
+}
+
+/// Exports the settings, limits and macros of a guild, to move them to another instance
+#[poise::command(
+    slash_command,
+    rename = "export-guild-data",
+    owners_only,
+    user_cooldown = 10,
+    on_error = "handle_admin_error"
+)]
+async fn export_guild_data(
+    ctx: Context<'_>,
+    #[description = "id of the guild"] guild: String,
+    #[description = "also include its usage since the last flush"] audit: Option<bool>,
+) -> Result<(), InternalError> {
+    let data = ctx.data();
+
+    let Ok(guild) = guild.trim().parse::<u64>() else {
+        let embed = serenity::CreateEmbed::new().title(":red_circle: Invalid guild id");
+        send_ephemeral_embedded_reply(ctx, embed).await?;
+
+        return Ok(());
+    };
+
+    let archive = guild_data::export(data, guild, audit.unwrap_or_default());
+    let description = format!(
+        "Settings: **{}** | Limits: **{}** | Macros: **{}** | Config overrides: **{}** | Audit: **{}**",
+        yes_no(archive.settings.is_some()),
+        yes_no(archive.limits.is_some()),
+        archive.macros.len(),
+        yes_no(archive.config.is_some()),
+        yes_no(archive.audit.is_some()),
+    );
+    let contents = serde_json::to_vec_pretty(&archive)?;
+    log::info!("{} exported the data of guild {guild}", ctx.author().id);
+
+    let embed = serenity::CreateEmbed::new()
+        .title(":package: Guild data exported")
+        .description(description);
+    let reply = poise::CreateReply::default()
+        .embed(apply_theme(&data.conf().appearance, embed))
+        .attachment(serenity::CreateAttachment::bytes(
+            contents,
+            format!("guild-{guild}.json"),
+        ))
+        .ephemeral(true);
+    ctx.send(reply).await?;
+
+    Ok(())
+}
+
+/// Imports the settings, limits and macros exported from a guild, replacing the current ones
+#[poise::command(
+    slash_command,
+    rename = "import-guild-data",
+    owners_only,
+    user_cooldown = 10,
+    on_error = "handle_admin_error"
+)]
+async fn import_guild_data(
+    ctx: Context<'_>,
+    #[description = "id of the guild, which may differ from the exported one"] guild: String,
+    #[description = "file sent by /admin export-guild-data"] file: serenity::Attachment,
+) -> Result<(), InternalError> {
+    let data = ctx.data();
+
+    let Ok(guild) = guild.trim().parse::<u64>() else {
+        let embed = serenity::CreateEmbed::new().title(":red_circle: Invalid guild id");
+        send_ephemeral_embedded_reply(ctx, embed).await?;
+
+        return Ok(());
+    };
+
+    if file.size > ARCHIVE_MAX_KIB * 1024 {
+        let embed = serenity::CreateEmbed::new()
+            .title(":red_circle: Archive too large")
+            .description(format!("Archives must be {ARCHIVE_MAX_KIB} KiB at most"));
+        send_ephemeral_embedded_reply(ctx, embed).await?;
+
+        return Ok(());
+    }
+
+    ctx.defer_ephemeral().await?;
+
+    let channels = ctx.cache().guild(guild).map(|cached| {
+        cached
+            .channels
+            .keys()
+            .map(|channel| channel.get())
+            .collect::<HashSet<_>>()
+    });
+    let archive = serde_json::from_slice::<guild_data::Archive>(&file.download().await?)
+        .ok()
+        .filter(|archive| archive.is_valid(&data.conf(), guild, channels.as_ref()));
+    let Some(archive) = archive else {
+        let embed = serenity::CreateEmbed::new()
+            .title(":red_circle: Invalid archive")
+            .description(
+                "Only files sent by /admin export-guild-data of this version, with settings and \
+                 macros within the limits of the guild, can be imported",
+            );
+        send_ephemeral_embedded_reply(ctx, embed).await?;
+
+        return Ok(());
+    };
+
+    let source = archive.guild;
+    let imported = guild_data::import(data, guild, archive);
+    settings::persist(data).await;
+    command_set::refresh(ctx, guild).await;
+    log::info!(
+        "{} imported the data of guild {source} into guild {guild}",
+        ctx.author().id
+    );
+
+    let mut description = format!(
+        "Settings: **{}** | Limits: **{}** | Macros: **{}**",
+        yes_no(imported.settings),
+        yes_no(imported.limits),
+        imported.macros
+    );
+    if imported.config {
+        description.push_str(
+            "\n\nThe archive has config file overrides, which must be copied to the config file",
+        );
+    }
+    let embed = serenity::CreateEmbed::new()
+        .title(format!(
+            ":inbox_tray: Data of guild {source} imported into {guild}"
+        ))
+        .description(description);
+    send_ephemeral_embedded_reply(ctx, embed).await?;
+
+    Ok(())
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
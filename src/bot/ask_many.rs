@@ -0,0 +1,198 @@
+use std::sync::Arc;
+
+use poise::serenity_prelude as serenity;
+use tokio::{sync::Semaphore, task::JoinSet};
+
+use crate::{chat, code, config, messages};
+
+use super::{
+    allowed_mentions, experiment, handle_command_error, long_prompt, pipeline,
+    send_ephemeral_embedded_reply, truncate_chars, Context, InternalError,
+};
+
+/// Longest question shown above its answer, in characters.
+const QUESTION_HEADING_LIMIT: usize = 200;
+
+/// Numbered answers, packed in as few messages as they fit.
+fn pack_answers(blocks: Vec<String>) -> Vec<String> {
+    let limit = config::DISCORD_MESSAGE_LIMIT as usize;
+    let mut messages: Vec<String> = Vec::new();
+
+    for block in blocks {
+        let block = truncate_chars(&block, limit);
+        match messages.last_mut() {
+            Some(last) if last.chars().count() + block.chars().count() + 2 <= limit => {
+                last.push_str("\n\n");
+                last.push_str(&block);
+            }
+            _ => messages.push(block),
+        }
+    }
+
+    messages
+}
+
+/// Asks several questions at once, each answered on its own outside your conversation
+#[poise::command(
+    slash_command,
+    prefix_command,
+    rename = "ask-many",
+    guild_only,
+    user_cooldown = 30,
+    required_permissions = "SEND_MESSAGES",
+    on_error = "handle_command_error"
+)]
+pub async fn ask_many(
+    ctx: Context<'_>,
+    #[description = "one question per line, opens a form when left out"]
+    #[rest]
+    questions: Option<String>,
+) -> Result<(), InternalError> {
+    let data = ctx.data();
+    let conf = data.conf();
+    let messages = &conf.messages.ask_many;
+    let guild = ctx.guild_id().unwrap().get();
+    let user = ctx.author().id.get();
+
+    let questions = match (questions, ctx) {
+        (Some(questions), _) => questions,
+        (None, poise::Context::Application(ctx)) => {
+            let form = long_prompt::open_form(
+                ctx,
+                &messages.title,
+                &messages.label,
+                &messages.placeholder,
+                u16::MAX,
+            );
+            let Some(questions) = form.await? else {
+                return Ok(());
+            };
+
+            questions
+        }
+        (None, poise::Context::Prefix(_)) => String::new(),
+    };
+    let questions: Vec<String> = questions
+        .lines()
+        .map(str::trim)
+        .filter(|question| !question.is_empty())
+        .map(str::to_string)
+        .collect();
+
+    let max_questions = conf.ask_many.max_questions as usize;
+    let prompt_size = data.prompt_size(guild) as usize;
+    let title = if questions.is_empty() {
+        Some(messages.empty.clone())
+    } else if questions.len() > max_questions {
+        Some(messages::render(
+            &messages.too_many,
+            &[("max", &max_questions)],
+        ))
+    } else {
+        questions
+            .iter()
+            .position(|question| question.chars().count() > prompt_size)
+            .map(|index| {
+                messages::render(
+                    &messages.too_long,
+                    &[("index", &(index + 1)), ("max", &prompt_size)],
+                )
+            })
+    };
+    if let Some(title) = title {
+        let embed = serenity::CreateEmbed::new().title(title);
+        send_ephemeral_embedded_reply(ctx, embed).await?;
+
+        return Ok(());
+    }
+
+    // Held in flight by the exchange until answered.
+    let mut exchange = pipeline::Exchange::new(ctx, questions.join("\n"));
+    if !pipeline::Pipeline::guards()
+        .admit(ctx, &mut exchange)
+        .await?
+    {
+        return Ok(());
+    }
+    let policy = exchange.policy.take();
+
+    ctx.defer().await?;
+
+    // Each question gets a session of its own, dropped once answered.
+    let (max_chars, _) = conf.response_limit(guild);
+    let semaphore = Arc::new(Semaphore::new(conf.ask_many.concurrency as usize));
+    let mut tasks = JoinSet::new();
+    for (index, question) in questions.iter().enumerate() {
+        let mut session = data.new_chat(guild, experiment::Arm::Control);
+        session.set_policy(policy.clone());
+        session.set_max_tokens(Some(chat::max_tokens_for(max_chars)));
+
+        data.usage.record_prompt(guild, user);
+        let semaphore = semaphore.clone();
+        let question = question.clone();
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await;
+
+            (index, session.send_message(question, None).await)
+        });
+    }
+
+    let mut answers = vec![None; questions.len()];
+    while let Some(joined) = tasks.join_next().await {
+        let (index, sent) = match joined {
+            Ok(joined) => joined,
+            Err(err) => {
+                log::error!("question task failed: {err}");
+                data.usage.record_error(guild);
+
+                continue;
+            }
+        };
+
+        match sent {
+            Ok(response) => {
+                data.usage
+                    .record_tokens(guild, &response.model, None, response.usage);
+                answers[index] = Some(response.content);
+            }
+            Err(chat::Error::Vetoed(reason)) => {
+                log::info!(
+                    "answer to question {} was rejected by script: {reason}",
+                    index + 1
+                );
+            }
+            Err(err) => {
+                log::warn!("failed to answer question {}: {err}", index + 1);
+                data.usage.record_error(guild);
+            }
+        }
+    }
+
+    let blocks = questions
+        .iter()
+        .zip(answers)
+        .enumerate()
+        .map(|(index, (question, answer))| {
+            let answer = match answer {
+                Some(answer) if conf.code.format_fences => code::format_fences(&answer),
+                Some(answer) => answer,
+                None => messages.failed.clone(),
+            };
+
+            format!(
+                "**{}. {}**\n{}",
+                index + 1,
+                truncate_chars(question, QUESTION_HEADING_LIMIT),
+                answer
+            )
+        })
+        .collect();
+    for message in pack_answers(blocks) {
+        let reply = poise::CreateReply::default()
+            .content(message)
+            .allowed_mentions(allowed_mentions(ctx));
+        ctx.send(reply).await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,81 @@
+use std::path::{Path, PathBuf};
+
+use poise::serenity_prelude as serenity;
+use tokio::io::AsyncWriteExt;
+
+use crate::chat;
+
+use super::{Context, GuildId, UserId};
+
+/// Writes a captured prompt to its own file in the directory, returning its path.
+async fn write(
+    dir: &Path,
+    guild: GuildId,
+    user: UserId,
+    prompt: &str,
+    capture: &chat::Capture,
+) -> std::io::Result<PathBuf> {
+    let now = chrono::Utc::now();
+    let contents = format!(
+        "guild: {guild}\nuser: {user}\ncaptured: {}\nmodel: {}\n\n\
+         ## Prompt\n\n{prompt}\n\n## Request\n\n{}\n\n## Response\n\n{}\n",
+        now.to_rfc3339(),
+        capture.model,
+        capture.request,
+        capture.response
+    );
+
+    tokio::fs::create_dir_all(dir).await?;
+    let path = dir.join(format!(
+        "capture-{guild}-{user}-{}.txt",
+        now.format("%Y%m%dT%H%M%S")
+    ));
+    let mut options = tokio::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    // Captures hold what members sent, so only the user running the bot may read them.
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(&path).await?;
+    file.write_all(contents.as_bytes()).await?;
+
+    Ok(path)
+}
+
+/// Saves the capture of a prompt and lets the owner who armed it know where it is.
+pub(super) async fn save(
+    ctx: Context<'_>,
+    owner: UserId,
+    guild: GuildId,
+    user: UserId,
+    prompt: &str,
+    capture: &chat::Capture,
+) {
+    // The directory may have been unset since the capture was armed.
+    let Some(dir) = ctx.data().conf().observability.capture_dir.clone() else {
+        log::warn!("dropped capture of user {user}, captures were disabled");
+        return;
+    };
+
+    let description = match write(&dir, guild, user, prompt, capture).await {
+        Ok(path) => {
+            log::info!("captured prompt of user {user} to {}", path.display());
+            format!("Written to `{}`", path.display())
+        }
+        Err(err) => {
+            log::error!("failed to write capture of user {user}: {err}");
+            format!("Failed to write it: {err}")
+        }
+    };
+
+    let embed = serenity::CreateEmbed::new()
+        .title(format!(":microscope: Captured a prompt of user {user}"))
+        .description(description)
+        .timestamp(serenity::Timestamp::now());
+    let message = serenity::CreateMessage::new().embed(embed);
+    if let Err(err) = serenity::UserId::new(owner)
+        .direct_message(ctx, message)
+        .await
+    {
+        log::warn!("failed to tell owner {owner} about capture: {err}");
+    }
+}
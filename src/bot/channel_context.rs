@@ -0,0 +1,87 @@
+use poise::serenity_prelude as serenity;
+
+use crate::config;
+
+use super::Context;
+
+/// Whether the author may read the history of the channel the command was invoked in.
+///
+/// Threads take the permissions of their parent channel.
+pub(super) async fn can_read_history(ctx: Context<'_>) -> bool {
+    let Some(member) = ctx.author_member().await else {
+        return false;
+    };
+    let Some(guild) = ctx.guild() else {
+        return false;
+    };
+
+    let channel = ctx.channel_id();
+    let channel = guild.channels.get(&channel).or_else(|| {
+        guild
+            .threads
+            .iter()
+            .find(|thread| thread.id == channel)
+            .and_then(|thread| thread.parent_id)
+            .and_then(|parent| guild.channels.get(&parent))
+    });
+
+    channel.is_some_and(|channel| {
+        guild
+            .user_permissions_in(channel, &member)
+            .read_message_history()
+    })
+}
+
+/// Whether the message was written by a member, other than to invoke a command.
+pub(super) fn is_conversation(message: &serenity::Message, prefix: Option<&str>) -> bool {
+    let content = message.content.trim();
+
+    !message.author.bot
+        && matches!(
+            message.kind,
+            serenity::MessageType::Regular | serenity::MessageType::InlineReply
+        )
+        && !content.is_empty()
+        && !prefix.is_some_and(|prefix| content.starts_with(prefix))
+}
+
+/// Recent messages of the channel, oldest first, as `name: content` lines.
+///
+/// Prefix commands leave out their own message. Returns `None` when no message is left.
+pub(super) async fn transcript(
+    ctx: Context<'_>,
+    limits: &config::ChannelContext,
+) -> Result<Option<String>, serenity::Error> {
+    let mut request = serenity::GetMessages::new().limit(limits.messages);
+    if let poise::Context::Prefix(prefix_ctx) = ctx {
+        request = request.before(prefix_ctx.msg.id);
+    }
+    let messages = ctx.channel_id().messages(ctx, request).await?;
+
+    let prefix = ctx.data().conf().bot.prefix.clone();
+    let mut lines = Vec::new();
+    let mut chars = 0;
+    // Newest first, so the oldest are the ones left out.
+    for message in messages
+        .iter()
+        .filter(|message| is_conversation(message, prefix.as_deref()))
+    {
+        let line = format!(
+            "{}: {}",
+            message.author.display_name(),
+            message.content.trim()
+        );
+        chars += line.chars().count() + 1;
+        if chars > limits.max_chars {
+            break;
+        }
+        lines.push(line);
+    }
+
+    if lines.is_empty() {
+        return Ok(None);
+    }
+    lines.reverse();
+
+    Ok(Some(lines.join("\n")))
+}
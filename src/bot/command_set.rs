@@ -0,0 +1,66 @@
+use poise::serenity_prelude as serenity;
+
+use super::{BotData, BotDataInner, Context, GuildId, InternalError};
+
+/// Commands guilds can't turn off, so their admins can always turn the others back on.
+pub(super) const LOCKED: [&str; 2] = ["config", "admin"];
+
+/// Whether the top-level command is on in the guild, as turned on or off at runtime or configured.
+pub(super) fn is_enabled(data: &BotDataInner, guild: GuildId, command: &str) -> bool {
+    if LOCKED.contains(&command) {
+        return true;
+    }
+
+    if let Some(&enabled) = data.settings(guild).commands.get(command) {
+        return enabled;
+    }
+
+    !data.conf().guilds.get(&guild).is_some_and(|guild_conf| {
+        guild_conf
+            .disabled_commands
+            .iter()
+            .any(|disabled| disabled == command)
+    })
+}
+
+/// Names of the top-level commands guilds may turn off.
+pub(super) fn toggleable(commands: &[poise::Command<BotData, InternalError>]) -> Vec<&str> {
+    commands
+        .iter()
+        .map(|command| command.name.as_str())
+        .filter(|name| !LOCKED.contains(name))
+        .collect()
+}
+
+/// Registers the commands the guild has on as its own, when commands are registered per guild.
+pub(super) async fn register(
+    http: &serenity::Http,
+    commands: &[poise::Command<BotData, InternalError>],
+    data: &BotDataInner,
+    guild: GuildId,
+) -> Result<(), serenity::Error> {
+    if !data.conf().bot.guild_commands {
+        return Ok(());
+    }
+
+    let enabled: Vec<_> = commands
+        .iter()
+        .filter(|command| is_enabled(data, guild, &command.name))
+        .flat_map(|command| {
+            poise::builtins::create_application_commands(std::slice::from_ref(command))
+        })
+        .collect();
+    serenity::GuildId::new(guild)
+        .set_commands(http, enabled)
+        .await?;
+
+    Ok(())
+}
+
+/// Registers the commands of the guild again after they were turned on or off.
+pub(super) async fn refresh(ctx: Context<'_>, guild: GuildId) {
+    let commands = &ctx.framework().options().commands;
+    if let Err(err) = register(ctx.http(), commands, ctx.data(), guild).await {
+        log::warn!("failed to register the commands of guild {guild}: {err}");
+    }
+}
@@ -0,0 +1,248 @@
+use poise::serenity_prelude as serenity;
+
+use crate::{chat, code, messages};
+
+use super::{
+    apply_theme,
+    experiment::{self, Arm},
+    handle_command_error, pipeline, send_ephemeral_alert, send_ephemeral_embedded_reply,
+    truncate_chars, BotData, Context, InternalError, UserId, EMBED_DESCRIPTION_LIMIT,
+};
+
+pub(super) const CUSTOM_ID_PREFIX: &str = "compare:";
+
+/// Comparison waiting for its author to pick the better answer.
+#[derive(Clone, Debug)]
+pub(super) struct Record {
+    pub author: UserId,
+    pub picked: bool,
+    /// Whether picks count for the experiment, as it runs with the compared models.
+    pub counted: bool,
+}
+
+fn arm_custom_id(arm: Arm) -> String {
+    match arm {
+        Arm::Control => format!("{CUSTOM_ID_PREFIX}control"),
+        Arm::Variant => format!("{CUSTOM_ID_PREFIX}variant"),
+    }
+}
+
+/// Whether the author may compare models, as bot owners or with a compare role of the guild.
+async fn is_allowed(ctx: Context<'_>, guild: u64) -> bool {
+    if ctx.framework().options().owners.contains(&ctx.author().id) {
+        return true;
+    }
+
+    let conf = ctx.data().conf();
+    let allowed_roles = conf
+        .guilds
+        .get(&guild)
+        .map(|guild_conf| guild_conf.compare_roles.as_slice())
+        .unwrap_or_default();
+
+    ctx.author_member().await.is_some_and(|member| {
+        member
+            .roles
+            .iter()
+            .any(|role| allowed_roles.contains(&role.get()))
+    })
+}
+
+/// Answers the same message with two models, side by side, to pick the better answer
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    user_cooldown = 30,
+    required_permissions = "SEND_MESSAGES",
+    on_error = "handle_command_error"
+)]
+pub async fn compare(
+    ctx: Context<'_>,
+    #[description = "message both models answer"]
+    #[rest]
+    content: String,
+) -> Result<(), InternalError> {
+    let data = ctx.data();
+    let conf = data.conf();
+    let messages = &conf.messages.compare;
+    let guild = ctx.guild_id().unwrap().get();
+    let user = ctx.author().id.get();
+
+    let control_model = conf
+        .compare
+        .control
+        .clone()
+        .unwrap_or_else(|| data.sbuilder.model());
+    let Some(variant_model) = conf
+        .compare
+        .variant
+        .clone()
+        .or_else(|| conf.experiment.model.clone())
+    else {
+        let embed = serenity::CreateEmbed::new().title(&messages.unavailable);
+        send_ephemeral_embedded_reply(ctx, embed).await?;
+
+        return Ok(());
+    };
+
+    if !is_allowed(ctx, guild).await {
+        let embed = serenity::CreateEmbed::new().title(&messages.denied);
+        send_ephemeral_embedded_reply(ctx, embed).await?;
+
+        return Ok(());
+    }
+
+    let content = content.trim();
+    let max = data.prompt_size(guild);
+    if content.chars().count() > max as usize {
+        let embed = serenity::CreateEmbed::new()
+            .title(messages::render(&messages.too_long, &[("max", &max)]));
+        send_ephemeral_embedded_reply(ctx, embed).await?;
+
+        return Ok(());
+    }
+
+    // Held in flight by the exchange until answered.
+    let mut exchange = pipeline::Exchange::new(ctx, content.to_string());
+    if !pipeline::Pipeline::guards()
+        .admit(ctx, &mut exchange)
+        .await?
+    {
+        return Ok(());
+    }
+    let policy = exchange.policy.take();
+
+    ctx.defer().await?;
+
+    // Answers are shown in a random order, so their position doesn't sway the pick.
+    let arms = match experiment::draw(50) {
+        Arm::Control => [Arm::Control, Arm::Variant],
+        Arm::Variant => [Arm::Variant, Arm::Control],
+    };
+    let model_of = |arm| match arm {
+        Arm::Control => control_model.as_str(),
+        Arm::Variant => variant_model.as_str(),
+    };
+
+    // Picks only tell the arms apart when their models are the compared ones.
+    let default_model = data.sbuilder.model();
+    let counted = conf.experiment.share > 0
+        && control_model == default_model
+        && variant_model == *conf.experiment.model.as_ref().unwrap_or(&default_model);
+
+    // Both answers are billed as the single prompt the member sent.
+    data.usage.record_prompt(guild, user);
+
+    let (max_chars, _) = conf.response_limit(guild);
+    let ask = |arm| {
+        let mut session = data.sbuilder.create_chat(1);
+        session.set_policy(policy.clone());
+        session.set_max_tokens(Some(chat::max_tokens_for(max_chars)));
+
+        async move {
+            session
+                .send_message(content.to_string(), Some(model_of(arm)))
+                .await
+        }
+    };
+    let answers = tokio::join!(ask(arms[0]), ask(arms[1]));
+
+    let mut embeds = Vec::with_capacity(arms.len());
+    let mut buttons = Vec::with_capacity(arms.len());
+    for ((label, arm), answer) in ["A", "B"].into_iter().zip(arms).zip([answers.0, answers.1]) {
+        let description = match answer {
+            Ok(response) => {
+                data.usage
+                    .record_tokens(guild, &response.model, Some(arm), response.usage);
+
+                if conf.code.format_fences {
+                    code::format_fences(&response.content)
+                } else {
+                    response.content
+                }
+            }
+            Err(chat::Error::Vetoed(reason)) => {
+                log::info!("compared answer was rejected by script: {reason}");
+
+                messages.failed.clone()
+            }
+            Err(err) => {
+                log::warn!(
+                    "failed to answer compared prompt with {}: {err}",
+                    model_of(arm)
+                );
+                data.usage.record_error(guild);
+
+                messages.failed.clone()
+            }
+        };
+
+        let title = messages::render(
+            &messages.answer,
+            &[("label", &label), ("model", &model_of(arm))],
+        );
+        let embed = serenity::CreateEmbed::new()
+            .title(title)
+            .description(truncate_chars(&description, EMBED_DESCRIPTION_LIMIT));
+        embeds.push(apply_theme(&conf.appearance, embed));
+
+        let button = serenity::CreateButton::new(arm_custom_id(arm))
+            .label(messages::render(&messages.pick, &[("label", &label)]))
+            .style(serenity::ButtonStyle::Secondary);
+        buttons.push(button);
+    }
+
+    let mut reply =
+        poise::CreateReply::default().components(vec![serenity::CreateActionRow::Buttons(buttons)]);
+    for embed in embeds {
+        reply = reply.embed(embed);
+    }
+    let handle = ctx.send(reply).await?;
+
+    let message = handle.message().await?;
+    let record = Record {
+        author: user,
+        picked: false,
+        counted,
+    };
+    data.comparisons.insert(message.id.get(), record);
+
+    Ok(())
+}
+
+/// Counts the answer picked by the author of the comparison for its arm, once, when the
+/// experiment runs with the compared models.
+pub(super) async fn handle_press(
+    ctx: &serenity::Context,
+    data: &BotData,
+    press: &serenity::ComponentInteraction,
+) -> Result<(), InternalError> {
+    let Some(arm) = [Arm::Control, Arm::Variant]
+        .into_iter()
+        .find(|&arm| press.data.custom_id == arm_custom_id(arm))
+    else {
+        return Ok(());
+    };
+
+    let conf = data.conf();
+    let messages = &conf.messages.compare;
+
+    // Records are dropped on flush and restart.
+    let alert = match data.comparisons.get_mut(&press.message.id.get()) {
+        Some(record) if press.user.id.get() != record.author => &messages.not_author,
+        Some(record) if record.picked => &messages.already_picked,
+        Some(mut record) => {
+            record.picked = true;
+            if record.counted {
+                data.experiment.record_pick(arm);
+            }
+
+            &messages.picked
+        }
+        None => &messages.expired,
+    };
+    send_ephemeral_alert(ctx, data, press, alert).await?;
+
+    Ok(())
+}
@@ -0,0 +1,217 @@
+use std::{io, path::PathBuf};
+
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+
+use crate::config;
+
+use super::{stats, BotData};
+
+const HELP: &str = "commands: flush | stats | set-model <model> | maintenance on|off \
+    | reload-config | help";
+
+/// Starts the configured admin consoles, each taking one command per line.
+pub(super) fn spawn(data: &BotData) {
+    let conf = data.conf();
+
+    if conf.console.stdin {
+        let data = data.clone();
+        tokio::spawn(async move {
+            if let Err(err) = serve(&data, tokio::io::stdin(), tokio::io::stdout()).await {
+                log::warn!("admin console on stdin stopped: {err}");
+            }
+        });
+    }
+
+    if let Some(path) = &conf.console.socket {
+        listen(data.clone(), path.clone());
+    }
+}
+
+#[cfg(unix)]
+fn listen(data: BotData, path: PathBuf) {
+    use std::os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt};
+
+    // A socket left behind by a previous run would make binding fail.
+    if std::fs::symlink_metadata(&path).is_ok_and(|meta| meta.file_type().is_socket()) {
+        let _ = std::fs::remove_file(&path);
+    }
+
+    let listener = match tokio::net::UnixListener::bind(&path) {
+        Ok(listener) => listener,
+        Err(err) => {
+            log::error!(
+                "failed to bind admin console socket {}: {err}",
+                path.display()
+            );
+            return;
+        }
+    };
+    // Only the user running the bot may connect, the socket being created as theirs.
+    let owner = std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))
+        .and_then(|()| std::fs::metadata(&path))
+        .map(|meta| meta.uid());
+    let owner = match owner {
+        Ok(owner) => owner,
+        Err(err) => {
+            log::error!(
+                "failed to restrict admin console socket {}: {err}",
+                path.display()
+            );
+            return;
+        }
+    };
+    log::info!("admin console listening on {}", path.display());
+
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    match stream.peer_cred() {
+                        Ok(peer) if peer.uid() == owner => (),
+                        Ok(peer) => {
+                            log::warn!("refused admin console connection of user {}", peer.uid());
+                            continue;
+                        }
+                        Err(err) => {
+                            log::warn!("failed to identify admin console connection: {err}");
+                            continue;
+                        }
+                    }
+
+                    let data = data.clone();
+                    tokio::spawn(async move {
+                        let (reader, writer) = stream.into_split();
+                        if let Err(err) = serve(&data, reader, writer).await {
+                            log::warn!("admin console connection stopped: {err}");
+                        }
+                    });
+                }
+                Err(err) => log::warn!("failed to accept admin console connection: {err}"),
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+fn listen(_data: BotData, path: PathBuf) {
+    log::warn!(
+        "admin console socket {} ignored, sockets are only supported on Unix",
+        path.display()
+    );
+}
+
+async fn serve<R, W>(data: &BotData, reader: R, mut writer: W) -> io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Some(line) = lines.next_line().await? {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let reply = execute(data, line).await;
+        writer.write_all(format!("{reply}\n").as_bytes()).await?;
+        writer.flush().await?;
+    }
+
+    Ok(())
+}
+
+async fn execute(data: &BotData, line: &str) -> String {
+    let (command, argument) = line
+        .split_once(char::is_whitespace)
+        .map_or((line, ""), |(command, argument)| (command, argument.trim()));
+
+    log::info!("admin console command: {line}");
+
+    match (command, argument) {
+        ("flush", "") => {
+            data.flush().await;
+
+            "sessions flushed".to_string()
+        }
+        ("stats", "") => stats(data).await,
+        ("set-model", "") => "usage: set-model <model>".to_string(),
+        ("set-model", model) => {
+            data.sbuilder.set_model(model.to_string());
+            stats::refresh(data).await;
+
+            format!("model set to {model}")
+        }
+        ("maintenance", "on") => {
+            data.maintenance(true);
+
+            "maintenance enabled".to_string()
+        }
+        ("maintenance", "off") => {
+            data.maintenance(false);
+
+            "maintenance disabled".to_string()
+        }
+        ("maintenance", _) => "usage: maintenance on|off".to_string(),
+        ("reload-config", "") => reload_config(data).await,
+        ("help", "") => HELP.to_string(),
+        _ => format!("unknown command '{line}', {HELP}"),
+    }
+}
+
+async fn stats(data: &BotData) -> String {
+    let guilds = data.usage.guilds();
+    let sessions: usize = data.active_sessions().values().sum();
+    let prompts: u64 = guilds.iter().map(|(_, usage)| usage.prompts).sum();
+    let errors: u64 = guilds.iter().map(|(_, usage)| usage.errors).sum();
+    let input_tokens: u64 = guilds.iter().map(|(_, usage)| usage.input_tokens).sum();
+    let output_tokens: u64 = guilds.iter().map(|(_, usage)| usage.output_tokens).sum();
+
+    format!(
+        "since {}: guilds: {} | sessions: {} | prompts: {} | errors: {} | tokens: {} in, {} out \
+        | model: {} | maintenance: {}",
+        data.last_flush().format("%v, %R"),
+        guilds.len(),
+        sessions,
+        prompts,
+        errors,
+        input_tokens,
+        output_tokens,
+        data.sbuilder.model(),
+        if data.is_under_maintenance() {
+            "on"
+        } else {
+            "off"
+        }
+    )
+}
+
+/// Parses the config file again and swaps it in.
+///
+/// Settings only read on startup keep their previous values until a restart.
+async fn reload_config(data: &BotData) -> String {
+    let current = data.conf();
+    let mut conf = match config::App::parse(&current.path) {
+        Ok(conf) => conf,
+        Err(err) => return format!("failed to reload config: {err}"),
+    };
+
+    // Shards are fixed once the client starts and may come from --shards.
+    conf.bot.shards = current.bot.shards;
+
+    // Keeps a model picked with set-model, unless the config changes it too.
+    if conf.ai_provider.model != current.ai_provider.model {
+        data.sbuilder.set_model(conf.ai_provider.model.clone());
+        stats::refresh(data).await;
+    }
+
+    data.lanes.configure(&conf.lanes);
+    if let Some(routes) = data.routes.get() {
+        routes.configure_lanes(&conf.lanes);
+    }
+    data.set_conf(conf);
+
+    "config reloaded, tokens, secrets, routes, hooks, postprocess rules, consoles and flush_days \
+    apply on restart"
+        .to_string()
+}
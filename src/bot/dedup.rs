@@ -0,0 +1,58 @@
+use std::time::{Duration, Instant};
+
+use dashmap::{DashMap, Entry};
+
+use super::{Context, PRUNE_ABOVE};
+
+/// Redeliveries come within the lifetime of the interaction token.
+const REMEMBERED_FOR: Duration = Duration::from_secs(900);
+
+/// Invocations already handled, so that one delivered twice by Discord runs once.
+#[derive(Debug, Default)]
+pub(super) struct Invocations {
+    seen: DashMap<u64, Instant>,
+}
+
+impl Invocations {
+    /// Whether the invocation is seen for the first time, remembering it if so.
+    pub fn first_delivery(&self, id: u64) -> bool {
+        if self.seen.len() > PRUNE_ABOVE {
+            self.seen.retain(|_, seen| seen.elapsed() < REMEMBERED_FOR);
+        }
+
+        match self.seen.entry(id) {
+            Entry::Occupied(entry) if entry.get().elapsed() < REMEMBERED_FOR => false,
+            Entry::Occupied(mut entry) => {
+                entry.insert(Instant::now());
+                true
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(Instant::now());
+                true
+            }
+        }
+    }
+}
+
+/// Marks an invocation as let through, as checks run again for each parent command.
+struct Admitted;
+
+/// Whether the invocation is delivered for the first time, or was already let through.
+pub(super) async fn admit(ctx: Context<'_>) -> bool {
+    // Each autocompletion comes in an interaction of its own.
+    if let poise::Context::Application(app) = ctx {
+        if app.interaction_type == poise::CommandInteractionType::Autocomplete {
+            return true;
+        }
+    }
+
+    if ctx.invocation_data::<Admitted>().await.is_some() {
+        return true;
+    }
+    if !ctx.data().invocations.first_delivery(ctx.id()) {
+        return false;
+    }
+    ctx.set_invocation_data(Admitted).await;
+
+    true
+}
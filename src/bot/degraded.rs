@@ -0,0 +1,126 @@
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use poise::serenity_prelude::{self as serenity, Mentionable};
+
+use crate::messages;
+
+use super::{Context, GuildId};
+
+/// Least time between two notices sent to the same guild.
+const NOTICE_INTERVAL: Duration = Duration::from_secs(86400);
+
+/// Why a guild can't be fully served.
+#[derive(Clone, Debug)]
+pub(super) struct Entry {
+    pub reason: String,
+    pub at: DateTime<Utc>,
+}
+
+/// Guilds where the bot lacks what it needs to work, since the last flush.
+#[derive(Debug, Default)]
+pub(super) struct Tracker {
+    guilds: DashMap<GuildId, Entry>,
+    /// When each guild was last told how to fix it, kept across flushes.
+    noticed: DashMap<GuildId, Instant>,
+}
+
+impl Tracker {
+    pub fn record(&self, guild: GuildId, reason: String) {
+        log::warn!("guild {guild} is degraded: {reason}");
+
+        let entry = Entry {
+            reason,
+            at: Utc::now(),
+        };
+        self.guilds.insert(guild, entry);
+    }
+
+    /// Degraded guilds, the most recent first.
+    pub fn guilds(&self) -> Vec<(GuildId, Entry)> {
+        let mut guilds: Vec<_> = self
+            .guilds
+            .iter()
+            .map(|entry| (*entry.key(), entry.value().clone()))
+            .collect();
+        guilds.sort_unstable_by_key(|(_, entry)| std::cmp::Reverse(entry.at));
+
+        guilds
+    }
+
+    pub fn reset(&self) {
+        self.guilds.clear();
+    }
+
+    /// Whether the guild may be sent a notice now, taking the slot if so.
+    fn take_notice(&self, guild: GuildId) -> bool {
+        let now = Instant::now();
+        let mut allowed = false;
+        self.noticed
+            .entry(guild)
+            .and_modify(|last| {
+                if now.duration_since(*last) >= NOTICE_INTERVAL {
+                    *last = now;
+                    allowed = true;
+                }
+            })
+            .or_insert_with(|| {
+                allowed = true;
+                now
+            });
+
+        allowed
+    }
+}
+
+/// Records the guild as degraded and tells its owner which permissions to grant.
+///
+/// The notice goes by DM, or to the system channel when the owner doesn't take DMs.
+pub(super) async fn handle_missing_permissions(ctx: Context<'_>, missing: serenity::Permissions) {
+    let Some(guild_id) = ctx.guild_id() else {
+        return;
+    };
+    let data = ctx.data();
+    let guild = guild_id.get();
+    let channel = ctx.channel_id();
+    let command = ctx.command().qualified_name.clone();
+
+    data.degraded.record(
+        guild,
+        format!("missing {missing} in channel {channel} for /{command}"),
+    );
+    if !data.degraded.take_notice(guild) {
+        return;
+    }
+
+    let Some((owner, system_channel, name)) = ctx
+        .guild()
+        .map(|guild| (guild.owner_id, guild.system_channel_id, guild.name.clone()))
+    else {
+        return;
+    };
+
+    let notice = messages::render(
+        &data.conf().messages.degraded.missing_permissions,
+        &[
+            ("permissions", &missing),
+            ("channel", &channel.mention()),
+            ("guild", &name),
+            ("command", &command),
+        ],
+    );
+    let message = serenity::CreateMessage::new().content(notice);
+
+    let Err(err) = owner.direct_message(ctx, message.clone()).await else {
+        return;
+    };
+    log::debug!("failed to tell the owner of guild {guild} about missing permissions: {err}");
+
+    let Some(system_channel) = system_channel else {
+        return;
+    };
+    if let Err(err) = system_channel.send_message(ctx, message).await {
+        log::warn!("failed to post missing permissions in guild {guild}: {err}");
+    }
+}
@@ -0,0 +1,109 @@
+use chrono::{DateTime, Datelike, Days, TimeDelta, Utc, Weekday};
+use poise::serenity_prelude as serenity;
+
+use crate::usage;
+
+use super::{apply_theme, BotData};
+
+const TOP_GUILDS: usize = 5;
+
+/// Next time the digest is due, strictly after now.
+fn next_digest(now: DateTime<Utc>, weekday: Weekday, hour: u32) -> DateTime<Utc> {
+    let days_ahead =
+        (7 + weekday.num_days_from_monday() - now.weekday().num_days_from_monday()) % 7;
+    let next = (now.date_naive() + Days::new(days_ahead as u64))
+        .and_hms_opt(hour, 0, 0)
+        .unwrap()
+        .and_utc();
+
+    if next <= now {
+        next + TimeDelta::weeks(1)
+    } else {
+        next
+    }
+}
+
+fn digest_embed(ctx: &serenity::Context, data: &BotData) -> serenity::CreateEmbed {
+    let conf = data.conf();
+    let since = usage::week_start();
+    let week = data.usage.last_week();
+
+    let mut tokens = format!("{} in, {} out", week.input_tokens, week.output_tokens);
+    if let Some(pricing) = &conf.pricing {
+        let spend = pricing.estimate(&data.usage.spent_since(since, None));
+        tokens.push_str(&format!(" (~{:.2} {})", spend, pricing.currency));
+    }
+
+    let top_guilds = data.usage.top_guilds_since(since, TOP_GUILDS);
+    let top_guilds = if top_guilds.is_empty() {
+        "None".to_string()
+    } else {
+        top_guilds
+            .iter()
+            .enumerate()
+            .map(|(i, (guild, prompts))| {
+                let name = serenity::GuildId::new(*guild)
+                    .name(&ctx.cache)
+                    .unwrap_or_else(|| "Unknown".to_string());
+
+                format!("{}. {} ({}): {} prompts", i + 1, name, guild, prompts)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    let embed = serenity::CreateEmbed::new()
+        .title(":calendar_spiral: Weekly Digest")
+        .description(format!("Usage since {}", since.format("%v")))
+        .field(
+            ":speech_balloon: | Prompts:",
+            week.prompts.to_string(),
+            true,
+        )
+        .field(
+            ":warning: | Errors:",
+            format!("{} ({:.1}%)", week.errors, week.error_rate() * 100.),
+            true,
+        )
+        .field(":coin: | Tokens:", tokens, false)
+        .field(":trophy: | Top Guilds:", top_guilds, false)
+        .timestamp(serenity::Timestamp::now());
+
+    apply_theme(&conf.appearance, embed)
+}
+
+async fn send_digest(ctx: &serenity::Context, data: &BotData) {
+    let embed = digest_embed(ctx, data);
+
+    for &owner in &data.conf().bot.owners {
+        let message = serenity::CreateMessage::new().embed(embed.clone());
+        if let Err(err) = serenity::UserId::new(owner)
+            .direct_message(ctx, message)
+            .await
+        {
+            log::warn!("failed to send weekly digest to owner {owner}: {err}");
+        }
+    }
+}
+
+/// Sends the owners a usage digest every week, while enabled in config.
+pub(super) fn start(ctx: serenity::Context, data: BotData) {
+    tokio::spawn(async move {
+        loop {
+            let (weekday, hour) = {
+                let conf = data.conf();
+                (conf.digest.weekday, conf.digest.hour)
+            };
+            let now = Utc::now();
+            let wait = (next_digest(now, weekday, hour) - now)
+                .to_std()
+                .unwrap_or_default();
+
+            tokio::time::sleep(wait).await;
+
+            if data.conf().digest.enabled {
+                send_digest(&ctx, &data).await;
+            }
+        }
+    });
+}
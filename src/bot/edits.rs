@@ -0,0 +1,106 @@
+use std::{
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use dashmap::DashMap;
+use poise::serenity_prelude as serenity;
+
+use super::{ChatSession, Context, PRUNE_ABOVE};
+
+/// How long after being sent or last edited a prompt message is followed for edits.
+pub(super) const EDIT_WINDOW: Duration = Duration::from_secs(600);
+
+/// Prompt message answered by the bot, whose reply is regenerated once edited.
+#[derive(Clone, Debug)]
+pub(super) struct Prompt {
+    pub session: ChatSession,
+    /// Id of the interaction the prompt got in the session.
+    pub interaction: u64,
+    pub reply: serenity::MessageId,
+    /// Messages the rest of a split reply was posted in.
+    pub parts: Vec<serenity::MessageId>,
+    pub webhook: Option<serenity::Webhook>,
+}
+
+/// Answered prompt messages, by their id, forgotten once no longer followed for edits.
+#[derive(Debug, Default)]
+pub(super) struct Prompts {
+    answered: DashMap<u64, (Instant, Prompt)>,
+}
+
+impl Prompts {
+    pub fn remember(&self, message: u64, prompt: Prompt) {
+        if self.answered.len() > PRUNE_ABOVE {
+            self.answered
+                .retain(|_, (answered, _)| answered.elapsed() < EDIT_WINDOW);
+        }
+
+        self.answered.insert(message, (Instant::now(), prompt));
+    }
+
+    pub fn get(&self, message: u64) -> Option<Prompt> {
+        self.answered
+            .get(&message)
+            .filter(|entry| entry.0.elapsed() < EDIT_WINDOW)
+            .map(|entry| entry.1.clone())
+    }
+
+    /// Forgets the prompts the predicate holds for, e.g. those of purged sessions.
+    pub fn forget(&self, mut forgotten: impl FnMut(&Prompt) -> bool) {
+        self.answered.retain(|_, (_, prompt)| !forgotten(prompt));
+    }
+
+    pub fn clear(&self) {
+        self.answered.clear();
+    }
+}
+
+/// Follows the edits of prefix commands, only re-running those of answered prompts.
+pub(super) fn tracker() -> Arc<RwLock<poise::EditTracker>> {
+    Arc::new(poise::EditTracker::for_timespan(EDIT_WINDOW))
+}
+
+/// Id of the prompt message of the command, when followed for edits.
+///
+/// Edits keep the id of the message, unlike the one of the command they re-run.
+pub(super) fn followed(ctx: Context<'_>) -> Option<u64> {
+    match ctx {
+        poise::Context::Prefix(prefix)
+            if ctx
+                .framework()
+                .options()
+                .prefix_options
+                .edit_tracker
+                .is_some() =>
+        {
+            Some(prefix.msg.id.get())
+        }
+        _ => None,
+    }
+}
+
+/// Prompt message the command re-runs for, when triggered by its edit.
+pub(super) fn edited_message(ctx: Context<'_>) -> Option<u64> {
+    match ctx {
+        poise::Context::Prefix(ctx)
+            if ctx.trigger == poise::MessageDispatchTrigger::MessageEdit =>
+        {
+            Some(ctx.msg.id.get())
+        }
+        _ => None,
+    }
+}
+
+/// Deletes the rest of the previous reply, as the regenerated one is split anew.
+pub(super) async fn delete_parts(ctx: Context<'_>, prompt: &Prompt) {
+    for &part in &prompt.parts {
+        let deleted = match &prompt.webhook {
+            Some(webhook) => webhook.delete_message(ctx, None, part).await,
+            None => ctx.channel_id().delete_message(ctx, part).await,
+        };
+        if let Err(err) = deleted {
+            log::warn!("failed to delete part of the reply to an edited prompt: {err}");
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        OnceLock,
+    },
+    time::SystemTime,
+};
+
+use crate::chat;
+
+/// Group a session was put in when created, tagging the tokens it spends.
+pub(super) use crate::usage::Arm;
+
+/// Draws taken so far, each one rolling the next number of the sequence.
+static DRAWS: AtomicU64 = AtomicU64::new(0);
+/// Start of the sequence, picked from the clock by the first draw.
+static SEED: OnceLock<u64> = OnceLock::new();
+
+/// Next number of a SplitMix64 sequence, uniform enough for a split.
+fn roll() -> u64 {
+    let seed = *SEED.get_or_init(|| {
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |since| since.as_nanos() as u64)
+    });
+    let draw = DRAWS.fetch_add(1, Ordering::Relaxed);
+
+    let mut z = seed.wrapping_add(draw.wrapping_add(1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+
+    z ^ (z >> 31)
+}
+
+/// Picks the variant arm for the given percentage of draws.
+pub(super) fn draw(share: u8) -> Arm {
+    if share == 0 {
+        return Arm::Control;
+    }
+
+    if roll() % 100 < share as u64 {
+        Arm::Variant
+    } else {
+        Arm::Control
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    sessions: AtomicU64,
+    prompts: AtomicU64,
+    errors: AtomicU64,
+    input_tokens: AtomicU64,
+    output_tokens: AtomicU64,
+    regenerations: AtomicU64,
+    deletions: AtomicU64,
+    picks: AtomicU64,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub(super) struct Summary {
+    pub sessions: u64,
+    pub prompts: u64,
+    pub errors: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub regenerations: u64,
+    pub deletions: u64,
+    /// Times its answer was picked as the better one with /compare.
+    pub picks: u64,
+}
+
+impl Summary {
+    /// Share of the prompts where the given count happened, zero without prompts.
+    pub fn rate(&self, count: u64) -> f64 {
+        if self.prompts == 0 {
+            return 0.;
+        }
+
+        count as f64 / self.prompts as f64
+    }
+
+    pub fn tokens_per_prompt(&self) -> f64 {
+        self.rate(self.input_tokens + self.output_tokens)
+    }
+}
+
+/// Counts usage and feedback of each arm since the bot started, flushes included.
+///
+/// Regenerated and deleted replies stand for answers members weren't happy with.
+#[derive(Debug, Default)]
+pub(super) struct Tracker {
+    control: Counters,
+    variant: Counters,
+}
+
+fn bump(counter: &AtomicU64) {
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+impl Tracker {
+    fn counters(&self, arm: Arm) -> &Counters {
+        match arm {
+            Arm::Control => &self.control,
+            Arm::Variant => &self.variant,
+        }
+    }
+
+    pub fn record_session(&self, arm: Arm) {
+        bump(&self.counters(arm).sessions);
+    }
+
+    pub fn record_prompt(&self, arm: Arm) {
+        bump(&self.counters(arm).prompts);
+    }
+
+    pub fn record_error(&self, arm: Arm) {
+        bump(&self.counters(arm).errors);
+    }
+
+    pub fn record_tokens(&self, arm: Arm, usage: chat::Usage) {
+        let counters = self.counters(arm);
+        counters
+            .input_tokens
+            .fetch_add(usage.input_tokens, Ordering::Relaxed);
+        counters
+            .output_tokens
+            .fetch_add(usage.output_tokens, Ordering::Relaxed);
+    }
+
+    pub fn record_regeneration(&self, arm: Arm) {
+        bump(&self.counters(arm).regenerations);
+    }
+
+    pub fn record_deletion(&self, arm: Arm) {
+        bump(&self.counters(arm).deletions);
+    }
+
+    pub fn record_pick(&self, arm: Arm) {
+        bump(&self.counters(arm).picks);
+    }
+
+    pub fn summary(&self, arm: Arm) -> Summary {
+        let counters = self.counters(arm);
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+
+        Summary {
+            sessions: load(&counters.sessions),
+            prompts: load(&counters.prompts),
+            errors: load(&counters.errors),
+            input_tokens: load(&counters.input_tokens),
+            output_tokens: load(&counters.output_tokens),
+            regenerations: load(&counters.regenerations),
+            deletions: load(&counters.deletions),
+            picks: load(&counters.picks),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draws_split_by_share() {
+        assert!((0..1000).all(|_| draw(0) == Arm::Control));
+        assert!((0..1000).all(|_| draw(100) == Arm::Variant));
+
+        let variants = (0..10_000).filter(|_| draw(30) == Arm::Variant).count();
+        assert!((2_500..3_500).contains(&variants), "{variants} variants");
+    }
+}
@@ -0,0 +1,114 @@
+use std::{
+    any::Any,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+
+use crate::report;
+
+use super::{stats, BotData, MAINTENANCE_POLL};
+
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+/// Flushes running longer than this are logged as stalled, once.
+const STALL_WARNING: Duration = Duration::from_secs(300);
+
+/// Whether the flusher runs and how it went so far.
+#[derive(Debug, Default)]
+pub(super) struct Health {
+    alive: AtomicBool,
+    restarts: AtomicU32,
+    last_run: Mutex<Option<DateTime<Utc>>>,
+}
+
+impl Health {
+    pub fn is_alive(&self) -> bool {
+        self.alive.load(Ordering::Acquire)
+    }
+
+    /// Times the flusher was restarted after stopping unexpectedly.
+    pub fn restarts(&self) -> u32 {
+        self.restarts.load(Ordering::Relaxed)
+    }
+
+    /// When the last flush finished.
+    pub fn last_run(&self) -> Option<DateTime<Utc>> {
+        *self.last_run.lock().unwrap()
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> Option<&str> {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+}
+
+/// Flushes the sessions, logging when it takes unusually long.
+async fn flush_watched(data: &BotData) {
+    let flush = data.flush();
+    tokio::pin!(flush);
+
+    tokio::select! {
+        _ = &mut flush => return,
+        _ = tokio::time::sleep(STALL_WARNING) => {
+            log::warn!(
+                "sessions flush is stalled, still running after {}s",
+                STALL_WARNING.as_secs()
+            );
+        }
+    }
+
+    flush.await;
+}
+
+async fn run(data: BotData) {
+    loop {
+        data.schedule_next_flush();
+        stats::refresh(&data).await;
+
+        tokio::time::sleep(data.flush_timeout).await;
+
+        // Flushes are held back until maintenance is over.
+        while data.is_under_maintenance() {
+            tokio::time::sleep(MAINTENANCE_POLL).await;
+        }
+
+        flush_watched(&data).await;
+        *data.flusher.last_run.lock().unwrap() = Some(Utc::now());
+    }
+}
+
+/// Flushes the sessions periodically, restarting the flusher whenever it stops.
+pub(super) fn start(data: BotData) {
+    tokio::spawn(async move {
+        loop {
+            data.flusher.alive.store(true, Ordering::Release);
+            let stopped = tokio::spawn(run(data.clone())).await;
+            data.flusher.alive.store(false, Ordering::Release);
+
+            match stopped {
+                Err(err) if err.is_panic() => {
+                    let payload = err.into_panic();
+                    let reason = panic_message(payload.as_ref());
+                    log::error!(
+                        "sessions flusher panicked, restarting it: {}",
+                        reason.unwrap_or("unknown reason")
+                    );
+                    report::panic(report::Context::new(None, None), reason);
+                }
+                _ => log::error!("sessions flusher stopped, restarting it"),
+            }
+
+            // A flush cut short would hold prompts back for good.
+            data.flushing(false);
+            data.flusher.restarts.fetch_add(1, Ordering::Relaxed);
+
+            tokio::time::sleep(RESTART_DELAY).await;
+        }
+    });
+}
@@ -0,0 +1,261 @@
+use std::sync::Arc;
+
+use poise::serenity_prelude as serenity;
+
+use crate::{chat, messages};
+
+use super::{
+    guard, reasoning, send_ephemeral_alert, truncate_chars, BotData, ChatSession, Extras, GuildId,
+    InternalError, UserId,
+};
+
+pub(super) const CUSTOM_ID_PREFIX: &str = "followup:";
+
+#[derive(Clone, Debug)]
+pub(super) struct Record {
+    pub guild: GuildId,
+    pub author: UserId,
+    pub session: ChatSession,
+    pub questions: Vec<String>,
+}
+
+fn question_buttons(questions: &[String]) -> Vec<serenity::CreateActionRow> {
+    let buttons = questions
+        .iter()
+        .enumerate()
+        .map(|(index, question)| {
+            serenity::CreateButton::new(format!("{CUSTOM_ID_PREFIX}{index}"))
+                .label(question)
+                .style(serenity::ButtonStyle::Secondary)
+        })
+        .collect();
+
+    vec![serenity::CreateActionRow::Buttons(buttons)]
+}
+
+/// Adds buttons with follow-up questions under the reply, once the model suggests them.
+pub(super) fn suggest(
+    http: Arc<serenity::Http>,
+    data: BotData,
+    mut reply: serenity::Message,
+    webhook: Option<serenity::Webhook>,
+    guild: GuildId,
+    author: UserId,
+    session: ChatSession,
+) {
+    let count = data.conf().followups.count as usize;
+
+    tokio::spawn(async move {
+        // Taken under a short lock, so the next prompt of the member doesn't wait on the call.
+        let request = session.session.lock().await.followups_request(count);
+        let questions = match request.suggest().await {
+            Ok((questions, response)) => {
+                data.usage
+                    .record_tokens(guild, &response.model, Some(session.arm), response.usage);
+                if questions.is_empty() {
+                    return;
+                }
+
+                questions
+            }
+            Err(err) => {
+                log::warn!("failed to suggest follow-up questions: {err}");
+
+                return;
+            }
+        };
+
+        let mut buttons = question_buttons(&questions);
+        if data.reasoning.contains_key(&reply.id.get()) {
+            buttons.push(reasoning::button_row(&data));
+        }
+        let edited = match &webhook {
+            Some(webhook) => {
+                let builder = serenity::EditWebhookMessage::new().components(buttons);
+                webhook
+                    .edit_message(&http, reply.id, builder)
+                    .await
+                    .map(drop)
+            }
+            None => {
+                let builder = serenity::EditMessage::new().components(buttons);
+                reply.edit(&http, builder).await
+            }
+        };
+        if let Err(err) = edited {
+            log::warn!("failed to add follow-up questions to reply: {err}");
+
+            return;
+        }
+
+        let record = Record {
+            guild,
+            author,
+            session,
+            questions,
+        };
+        data.followups.insert(reply.id.get(), record);
+    });
+}
+
+/// Sends the follow-up question picked by the author of the prompt as their next prompt.
+pub(super) async fn handle_press(
+    ctx: &serenity::Context,
+    data: &BotData,
+    press: &serenity::ComponentInteraction,
+) -> Result<(), InternalError> {
+    let Some(index) = press
+        .data
+        .custom_id
+        .strip_prefix(CUSTOM_ID_PREFIX)
+        .and_then(|index| index.parse::<usize>().ok())
+    else {
+        return Ok(());
+    };
+
+    let conf = data.conf();
+    let messages = &conf.messages.followups;
+
+    // Records are dropped on flush and restart, along with the sessions they refer to.
+    let Some(record) = data
+        .followups
+        .get(&press.message.id.get())
+        .map(|record| record.clone())
+    else {
+        send_ephemeral_alert(ctx, data, press, &messages.expired).await?;
+
+        return Ok(());
+    };
+
+    if press.user.id.get() != record.author {
+        send_ephemeral_alert(ctx, data, press, &messages.not_author).await?;
+
+        return Ok(());
+    }
+
+    let Some(question) = record.questions.get(index).cloned() else {
+        return Ok(());
+    };
+
+    let prompter = guard::Prompter::cached(
+        &ctx.cache,
+        record.guild,
+        press.user.id,
+        press.channel_id,
+        "prompt",
+    );
+    // Held until the reply is sent, so a flush waits for it.
+    let guard::Admitted {
+        policy,
+        warning,
+        in_flight: _in_flight,
+    } = match guard::admit(ctx, data, &prompter, &question, true).await {
+        Ok(admitted) => admitted,
+        Err(guard::Refusal::Unanswerable) => return Ok(()),
+        Err(guard::Refusal::Alert(alert)) => {
+            send_ephemeral_alert(ctx, data, press, &alert).await?;
+
+            return Ok(());
+        }
+    };
+    let Some(_queue_slot) = record
+        .session
+        .enqueue(press.id.get(), conf.chat.queue_depth)
+    else {
+        send_ephemeral_alert(ctx, data, press, &conf.messages.alerts.request_in_progress).await?;
+
+        return Ok(());
+    };
+
+    // Buttons are removed right away, so each question is only asked once.
+    data.followups.remove(&press.message.id.get());
+    let response = serenity::CreateInteractionResponse::UpdateMessage(
+        serenity::CreateInteractionResponseMessage::new().components(vec![]),
+    );
+    press.create_response(ctx, response).await?;
+
+    if let Some(warning) = warning {
+        let followup = serenity::CreateInteractionResponseFollowup::new()
+            .content(warning)
+            .ephemeral(true);
+        press.create_followup(ctx, followup).await?;
+    }
+
+    data.usage.record_prompt(record.guild, record.author);
+    data.experiment.record_prompt(record.session.arm);
+
+    let (max_chars, _) = conf.response_limit(record.guild);
+    let max_tokens = Some(chat::max_tokens_for(max_chars));
+    let (response, exchanged) = match record
+        .session
+        .send_message(
+            question.clone(),
+            policy,
+            None,
+            max_tokens,
+            Extras::default(),
+        )
+        .await
+    {
+        Ok(sent) => sent,
+        Err(chat::Error::Vetoed(reason)) => {
+            let content =
+                messages::render(&conf.messages.alerts.prompt_vetoed, &[("reason", &reason)]);
+            let followup = serenity::CreateInteractionResponseFollowup::new()
+                .content(content)
+                .ephemeral(true);
+            press.create_followup(ctx, followup).await?;
+
+            return Ok(());
+        }
+        Err(err) => {
+            data.usage.record_error(record.guild);
+            data.experiment.record_error(record.session.arm);
+
+            return Err(Box::from(err));
+        }
+    };
+
+    data.usage.record_tokens(
+        record.guild,
+        &response.model,
+        Some(record.session.arm),
+        response.usage,
+    );
+    data.experiment
+        .record_tokens(record.session.arm, response.usage);
+
+    let asked = messages::render(
+        &messages.asked,
+        &[("user", &press.user.name), ("question", &question)],
+    );
+    let content = format!("{asked}\n\n{}", response.content);
+    let followup = serenity::CreateInteractionResponseFollowup::new()
+        .content(truncate_chars(&content, max_chars));
+
+    let reply = match press.create_followup(ctx, followup).await {
+        Ok(reply) => reply,
+        Err(err) => {
+            record.session.undo_last_interaction(exchanged).await;
+            data.usage.record_error(record.guild);
+            data.experiment.record_error(record.session.arm);
+
+            return Err(Box::from(err));
+        }
+    };
+
+    let (fold_data, session) = (data.clone(), record.session.clone());
+    tokio::spawn(async move { session.fold_summary(&fold_data, record.guild).await });
+
+    suggest(
+        ctx.http.clone(),
+        data.clone(),
+        reply,
+        None,
+        record.guild,
+        record.author,
+        record.session,
+    );
+
+    Ok(())
+}
@@ -0,0 +1,398 @@
+use std::sync::Arc;
+
+use crate::{config, messages, spam};
+use poise::serenity_prelude::{self as serenity, Mentionable};
+
+use super::{command_set, in_flight, language, truncate_field_value, BotDataInner, GuildId};
+
+/// Member sending a prompt, and where.
+pub(super) struct Prompter<'a> {
+    pub guild: GuildId,
+    pub user: serenity::UserId,
+    /// Roles of the member, none when unknown.
+    pub roles: Option<Vec<serenity::RoleId>>,
+    pub channel: serenity::ChannelId,
+    /// Parent of the channel when it's a thread.
+    pub parent: Option<serenity::ChannelId>,
+    /// Top-level command the prompt counts as.
+    pub command: &'a str,
+}
+
+impl<'a> Prompter<'a> {
+    /// Member prompting in the channel, with their roles and the thread parent taken from cache.
+    pub fn cached(
+        cache: &serenity::Cache,
+        guild: GuildId,
+        user: serenity::UserId,
+        channel: serenity::ChannelId,
+        command: &'a str,
+    ) -> Self {
+        let cached = cache.guild(guild);
+        let roles = cached
+            .as_ref()
+            .and_then(|cached| cached.members.get(&user))
+            .map(|member| member.roles.clone());
+        let parent = cached
+            .as_ref()
+            .and_then(|cached| thread_parent(cached, channel));
+
+        Self {
+            guild,
+            user,
+            roles,
+            channel,
+            parent,
+            command,
+        }
+    }
+}
+
+/// Parent of the channel, when it's a cached thread.
+pub(super) fn thread_parent(
+    guild: &serenity::Guild,
+    channel: serenity::ChannelId,
+) -> Option<serenity::ChannelId> {
+    guild
+        .threads
+        .iter()
+        .find(|thread| thread.id == channel)
+        .and_then(|thread| thread.parent_id)
+}
+
+/// Why the member can't use the command in the channel, none when they can.
+///
+/// Bot owners are always let through.
+pub(super) fn access_reasons(data: &BotDataInner, prompter: &Prompter<'_>) -> Vec<String> {
+    let conf = data.conf();
+    if conf.bot.owners.contains(&prompter.user.get()) {
+        return Vec::new();
+    }
+
+    let alerts = &conf.messages.alerts;
+    let mut reasons = Vec::new();
+
+    let min_age = conf.access.min_account_age_days;
+    if min_age > 0 {
+        let age = chrono::Utc::now() - *prompter.user.created_at();
+        if age < chrono::TimeDelta::days(min_age as i64) {
+            reasons.push(messages::render(
+                &alerts.access_account_age,
+                &[("days", &min_age)],
+            ));
+        }
+    }
+
+    let required_roles = conf
+        .guilds
+        .get(&prompter.guild)
+        .map(|guild_conf| guild_conf.required_roles.as_slice())
+        .unwrap_or_default();
+    if !required_roles.is_empty() {
+        let has_role = prompter.roles.as_ref().is_some_and(|roles| {
+            roles
+                .iter()
+                .any(|role| required_roles.contains(&role.get()))
+        });
+        if !has_role {
+            let roles = required_roles
+                .iter()
+                .map(|&role| serenity::RoleId::new(role).mention().to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            reasons.push(messages::render(&alerts.access_roles, &[("roles", &roles)]));
+        }
+    }
+
+    if !command_set::is_enabled(data, prompter.guild, prompter.command) {
+        reasons.push(messages::render(
+            &alerts.access_command,
+            &[("command", &prompter.command)],
+        ));
+    }
+
+    // /config stays usable everywhere, so admins can fix the allowed channels.
+    let settings = data.settings(prompter.guild);
+    let parent = prompter.parent.map(|parent| parent.get());
+    if prompter.command != "config" && !settings.allows(prompter.channel.get(), parent) {
+        let channels = settings
+            .allowed_channels
+            .iter()
+            .map(|&channel| serenity::ChannelId::new(channel).mention().to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        reasons.push(messages::render(
+            &alerts.access_channels,
+            &[("channels", &channels)],
+        ));
+    }
+
+    reasons
+}
+
+/// Why the bot can't reply in the channel, if it can't.
+///
+/// Interaction responses don't depend on the channel permissions, only on the bot
+/// not being timed out. Unknown permissions, like those of an uncached member, don't
+/// block replies.
+pub(super) fn reply_blocker(
+    cache: &serenity::Cache,
+    guild: GuildId,
+    channel_id: serenity::ChannelId,
+    interaction: bool,
+) -> Option<String> {
+    let guild = cache.guild(guild)?;
+    let bot = cache.current_user().id;
+    let member = guild.members.get(&bot)?;
+
+    if member
+        .communication_disabled_until
+        .is_some_and(|until| until > serenity::Timestamp::now())
+    {
+        return Some("bot is timed out".to_string());
+    }
+
+    if interaction {
+        return None;
+    }
+
+    let (channel, required) = match guild.channels.get(&channel_id) {
+        Some(channel) => (channel, serenity::Permissions::SEND_MESSAGES),
+        None => (
+            guild.channels.get(&thread_parent(&guild, channel_id)?)?,
+            serenity::Permissions::SEND_MESSAGES_IN_THREADS,
+        ),
+    };
+    // Replies reference the prompt, which needs reading the history.
+    let required = required
+        | serenity::Permissions::VIEW_CHANNEL
+        | serenity::Permissions::READ_MESSAGE_HISTORY;
+    let missing = required - guild.user_permissions_in(channel, member);
+
+    (!missing.is_empty()).then(|| format!("missing {missing} in channel {channel_id} to reply"))
+}
+
+/// Whether the channel, or the parent of the thread, is age-restricted, as far as cached.
+fn is_cached_age_restricted(cache: &serenity::Cache, prompter: &Prompter<'_>) -> bool {
+    let Some(guild) = cache.guild(prompter.guild) else {
+        return false;
+    };
+
+    [Some(prompter.channel), prompter.parent]
+        .into_iter()
+        .flatten()
+        .any(|channel| {
+            guild
+                .channels
+                .get(&channel)
+                .is_some_and(|channel| channel.nsfw)
+        })
+}
+
+/// Instructions of the content level, or none when the prompt is refused.
+pub(super) fn content_instructions(
+    content: &config::Content,
+    age_restricted: bool,
+    prompt: &str,
+) -> Option<Option<String>> {
+    if !content.enabled {
+        return Some(None);
+    }
+
+    let level = content.level(age_restricted);
+    if level == config::ContentLevel::Strict {
+        let prompt = prompt.to_lowercase();
+        let blocked = content
+            .blocked_terms
+            .iter()
+            .any(|term| prompt.contains(&term.to_lowercase()));
+
+        if blocked {
+            return None;
+        }
+    }
+
+    let instructions = content.instructions(level);
+
+    Some((!instructions.is_empty()).then(|| instructions.to_string()))
+}
+
+/// Hint to reply in the language picked by the member or the one of the prompt, if any.
+pub(super) fn language_hint(
+    data: &BotDataInner,
+    guild: GuildId,
+    user: u64,
+    prompt: &str,
+) -> Option<String> {
+    let picked = data.languages.get(&(guild, user)).map(|lang| *lang);
+    let lang = match picked {
+        Some(lang) => lang,
+        None if data.conf().chat.match_language => language::detect(prompt)?,
+        None => return None,
+    };
+
+    Some(language::hint(lang))
+}
+
+/// Posts a spam block in the guild log channel, if there's one.
+pub(super) fn notify_spam_block(
+    http: Arc<serenity::Http>,
+    data: &BotDataInner,
+    guild: GuildId,
+    user: serenity::UserId,
+    channel: serenity::ChannelId,
+    prompt: &str,
+    until: chrono::DateTime<chrono::Utc>,
+) {
+    let conf = data.conf();
+    let Some(log_channel) = conf
+        .guilds
+        .get(&guild)
+        .and_then(|guild_conf| guild_conf.log_channel)
+    else {
+        return;
+    };
+
+    let embed = serenity::CreateEmbed::new()
+        .title(":rotating_light: Prompt Spam Blocked")
+        .field(
+            ":bust_in_silhouette: | User:",
+            user.mention().to_string(),
+            true,
+        )
+        .field(":hash: | Channel:", channel.mention().to_string(), true)
+        .field(
+            ":hourglass: | Until:",
+            format!("<t:{}:f>", until.timestamp()),
+            true,
+        )
+        .field(
+            ":speech_balloon: | Last Prompt:",
+            truncate_field_value(prompt),
+            false,
+        );
+    let message = serenity::CreateMessage::new().embed(embed);
+
+    tokio::spawn(async move {
+        let channel = serenity::ChannelId::new(log_channel);
+        if let Err(err) = channel.send_message(http, message).await {
+            log::warn!("failed to post spam block in log channel {channel}: {err}");
+        }
+    });
+}
+
+/// Why a prompt sent outside the `prompt` command was turned down.
+pub(super) enum Refusal {
+    /// The bot can't reply, which is recorded instead of told.
+    Unanswerable,
+    Alert(String),
+}
+
+/// Prompt let through the guards, held in flight until answered.
+pub(super) struct Admitted {
+    /// Extra system instructions: the content level of the channel and the language hint.
+    pub policy: Option<String>,
+    /// Spam warning the member should see, if they got one.
+    pub warning: Option<String>,
+    pub in_flight: in_flight::Guard,
+}
+
+/// Runs the guards of the `prompt` command over a prompt sent another way, like by
+/// pressing a followup button.
+pub(super) async fn admit(
+    ctx: &serenity::Context,
+    data: &BotDataInner,
+    prompter: &Prompter<'_>,
+    prompt: &str,
+    interaction: bool,
+) -> Result<Admitted, Refusal> {
+    let conf = data.conf();
+    let alerts = &conf.messages.alerts;
+
+    if data.is_under_maintenance() {
+        return Err(Refusal::Alert(alerts.maintenance.clone()));
+    }
+
+    if let Some(reason) = reply_blocker(&ctx.cache, prompter.guild, prompter.channel, interaction) {
+        data.degraded.record(prompter.guild, reason);
+
+        return Err(Refusal::Unanswerable);
+    }
+
+    let reasons = access_reasons(data, prompter);
+    if !reasons.is_empty() {
+        let reasons = reasons.join("\n");
+
+        return Err(Refusal::Alert(format!(
+            "{}\n{reasons}",
+            alerts.access_denied
+        )));
+    }
+
+    let max = data.prompt_size(prompter.guild);
+    if prompt.chars().count() > max as usize {
+        return Err(Refusal::Alert(messages::render(
+            &alerts.prompt_too_long,
+            &[("max", &max)],
+        )));
+    }
+
+    let mut warning = None;
+    if conf.spam.enabled {
+        let verdict = data
+            .spam
+            .check(&conf.spam, prompter.guild, prompter.user.get(), prompt);
+        match verdict {
+            spam::Verdict::Allowed => (),
+            spam::Verdict::Warned { strikes } => {
+                warning = Some(messages::render(
+                    &alerts.spam_warning,
+                    &[("strikes", &strikes), ("max", &conf.spam.warnings)],
+                ));
+            }
+            spam::Verdict::Blocked { until, new } => {
+                if new {
+                    log::info!(
+                        "blocked user {} in guild {} for prompt spam",
+                        prompter.user,
+                        prompter.guild
+                    );
+                    notify_spam_block(
+                        ctx.http.clone(),
+                        data,
+                        prompter.guild,
+                        prompter.user,
+                        prompter.channel,
+                        prompt,
+                        until,
+                    );
+                }
+
+                return Err(Refusal::Alert(messages::render(
+                    &alerts.spam_blocked,
+                    &[("date", &until.format("%v, %R"))],
+                )));
+            }
+        }
+    }
+
+    let in_flight = data.in_flight.enter();
+    if data.is_flushing() {
+        return Err(Refusal::Alert(alerts.flushing.clone()));
+    }
+
+    let age_restricted = is_cached_age_restricted(&ctx.cache, prompter);
+    let Some(policy) = content_instructions(&conf.content, age_restricted, prompt) else {
+        return Err(Refusal::Alert(alerts.content_refused.clone()));
+    };
+    let hint = language_hint(data, prompter.guild, prompter.user.get(), prompt);
+    let policy = match (policy, hint) {
+        (Some(policy), Some(hint)) => Some(format!("{policy}\n\n{hint}")),
+        (policy, hint) => policy.or(hint),
+    };
+
+    Ok(Admitted {
+        policy,
+        warning,
+        in_flight,
+    })
+}
@@ -0,0 +1,170 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+
+use crate::{config, usage};
+
+use super::{
+    limits, sessions::parse_session_name, settings, BotDataInner, ChannelId, GuildId, UserId,
+};
+
+/// Everything the bot keeps about a guild besides its conversations, as moved between instances.
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub(super) struct Archive {
+    pub version: u32,
+    pub guild: GuildId,
+    pub exported_at: DateTime<Utc>,
+    #[serde(default)]
+    pub settings: Option<settings::Settings>,
+    #[serde(default)]
+    pub limits: Option<limits::Limits>,
+    #[serde(default)]
+    pub macros: Vec<(UserId, String, String)>,
+    /// Overrides from the config file, which imports leave to the operator.
+    #[serde(default)]
+    pub config: Option<config::Guild>,
+    #[serde(default)]
+    pub audit: Option<Audit>,
+}
+
+/// What the guild did since the last flush, kept for the record only.
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub(super) struct Audit {
+    pub usage: usage::Summary,
+    pub prompts_by_user: Vec<(UserId, u64)>,
+    pub degraded: Option<String>,
+}
+
+/// What an import changed.
+pub(super) struct Imported {
+    pub settings: bool,
+    pub limits: bool,
+    pub macros: usize,
+    /// Config file overrides found in the archive, not applied.
+    pub config: bool,
+}
+
+impl Archive {
+    pub const VERSION: u32 = 1;
+
+    /// Whether the archive holds only what the commands setting it would accept in the guild,
+    /// under the limits it brings along.
+    ///
+    /// Allowed channels must be among the channels of the guild, when known.
+    pub fn is_valid(
+        &self,
+        conf: &config::App,
+        guild: GuildId,
+        channels: Option<&HashSet<ChannelId>>,
+    ) -> bool {
+        let valid_limits = self.limits.is_none_or(|limits| {
+            limits
+                .prompt_size
+                .is_none_or(|size| config::PROMPT_SIZE_RANGE.contains(&size))
+                && limits.history_size != Some(0)
+        });
+
+        // Imports replace the limits of the guild with the archived ones.
+        let limits = self.limits.unwrap_or_default();
+        let max_prompt = limits
+            .prompt_size
+            .unwrap_or_else(|| conf.prompt_size(guild)) as usize;
+        let max_history = limits
+            .history_size
+            .unwrap_or_else(|| conf.history_size(guild));
+        let fits = |text: &str| !text.trim().is_empty() && text.chars().count() <= max_prompt;
+
+        let valid_settings = self.settings.as_ref().is_none_or(|settings| {
+            let mut allowed = HashSet::new();
+
+            settings.persona.as_deref().is_none_or(fits)
+                && settings.examples.len() <= settings::MAX_EXAMPLES
+                && settings
+                    .examples
+                    .iter()
+                    .all(|example| fits(&example.prompt) && fits(&example.response))
+                && settings
+                    .history_size
+                    .is_none_or(|size| (1..=max_history).contains(&size))
+                && settings.allowed_channels.iter().all(|&channel| {
+                    allowed.insert(channel)
+                        && channels.is_none_or(|channels| channels.contains(&channel))
+                })
+        });
+        let valid_macros = self.macros.iter().all(|(_, name, text)| {
+            parse_session_name(name).is_some_and(|parsed| parsed == *name) && fits(text)
+        });
+
+        self.version == Self::VERSION && valid_limits && valid_settings && valid_macros
+    }
+}
+
+pub(super) fn export(data: &BotDataInner, guild: GuildId, audit: bool) -> Archive {
+    let mut macros: Vec<_> = data
+        .macros
+        .iter()
+        .filter(|entry| entry.key().0 == guild)
+        .map(|entry| {
+            let (_, user, name) = entry.key().clone();
+            (user, name, entry.value().clone())
+        })
+        .collect();
+    macros.sort_unstable_by(|(a_user, a_name, _), (b_user, b_name, _)| {
+        (a_user, a_name).cmp(&(b_user, b_name))
+    });
+
+    let audit = audit.then(|| Audit {
+        usage: data.usage.guild(guild),
+        prompts_by_user: data.usage.top_users(guild, usize::MAX),
+        degraded: data
+            .degraded
+            .guilds()
+            .into_iter()
+            .find(|(degraded, _)| *degraded == guild)
+            .map(|(_, entry)| entry.reason),
+    });
+
+    Archive {
+        version: Archive::VERSION,
+        guild,
+        exported_at: Utc::now(),
+        settings: data.settings.get(&guild).map(|settings| settings.clone()),
+        limits: data.limits.get(&guild).map(|limits| *limits),
+        macros,
+        config: data.conf().guilds.get(&guild).cloned(),
+        audit,
+    }
+}
+
+/// Replaces the settings and limits of the guild with the archived ones and adds its macros.
+///
+/// Members keep the macros they saved since under other names, up to the configured maximum.
+pub(super) fn import(data: &BotDataInner, guild: GuildId, archive: Archive) -> Imported {
+    let settings = archive.settings.is_some();
+    if let Some(settings) = archive.settings {
+        data.settings.insert(guild, settings);
+    } else {
+        data.settings.remove(&guild);
+    }
+
+    let limits = archive.limits.filter(|limits| !limits.is_empty());
+    if let Some(limits) = limits {
+        data.limits.insert(guild, limits);
+    } else {
+        data.limits.remove(&guild);
+    }
+
+    let mut macros = 0;
+    for (user, name, text) in archive.macros {
+        if data.save_macro((guild, user, name), text) {
+            macros += 1;
+        }
+    }
+
+    Imported {
+        settings,
+        limits: limits.is_some(),
+        macros,
+        config: archive.config.is_some(),
+    }
+}
@@ -0,0 +1,97 @@
+use poise::serenity_prelude as serenity;
+
+use crate::messages;
+
+use super::{
+    handle_command_error, handle_prompt_error, pipeline, send_ephemeral_embedded_reply,
+    send_paginated_embeds, truncate_field_value, Context, InternalError,
+};
+
+const INTERACTIONS_PER_PAGE: usize = 5;
+
+/// Lists the interactions of your current conversation, to pick one for /branch
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    user_cooldown = 4,
+    required_permissions = "SEND_MESSAGES",
+    on_error = "handle_command_error"
+)]
+pub async fn history(ctx: Context<'_>) -> Result<(), InternalError> {
+    let data = ctx.data();
+    let conf = data.conf();
+    let messages = &conf.messages.history;
+    let guild = ctx.guild_id().unwrap().get();
+    let channel = ctx.channel_id().get();
+
+    // Same session picked by the pipeline, so the numbers match /branch.
+    let session = if data.is_shared_channel(guild, channel) {
+        data.channel_session(guild, channel)
+    } else {
+        data.session(guild, ctx.author().id.get())
+    };
+
+    let entries: Vec<_> = {
+        let chat = session.session.lock().await;
+        chat.history()
+            .map(|interaction| {
+                let date = interaction.at.format("%v, %R");
+
+                (
+                    messages::render(
+                        &messages.entry,
+                        &[("index", &interaction.id), ("date", &date)],
+                    ),
+                    truncate_field_value(&interaction.prompt),
+                    false,
+                )
+            })
+            .collect()
+    };
+
+    if entries.is_empty() {
+        let embed = serenity::CreateEmbed::new().title(&messages.empty);
+        send_ephemeral_embedded_reply(ctx, embed).await?;
+
+        return Ok(());
+    }
+
+    let title = messages::render(&messages.title, &[("count", &entries.len())]);
+    let pages = entries
+        .chunks(INTERACTIONS_PER_PAGE)
+        .map(|chunk| {
+            serenity::CreateEmbed::new()
+                .title(&title)
+                .fields(chunk.iter().cloned())
+        })
+        .collect();
+
+    send_paginated_embeds(ctx, pages, true).await?;
+
+    Ok(())
+}
+
+/// Replaces an earlier message of your conversation, forgetting what came after it
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    user_cooldown = 4,
+    required_permissions = "SEND_MESSAGES",
+    on_error = "handle_prompt_error"
+)]
+pub async fn branch(
+    ctx: Context<'_>,
+    #[description = "number of the interaction to replace, as listed by /history"]
+    #[min = 1]
+    interaction: u64,
+    #[description = "message to send instead"]
+    #[rest]
+    content: String,
+) -> Result<(), InternalError> {
+    let mut exchange = pipeline::Exchange::new(ctx, content);
+    exchange.branch = Some(interaction);
+
+    ctx.data().pipeline.run(ctx, &mut exchange).await
+}
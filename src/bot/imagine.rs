@@ -0,0 +1,91 @@
+use poise::serenity_prelude as serenity;
+
+use crate::messages;
+
+use super::{
+    apply_theme, handle_prompt_error, is_age_restricted, pipeline, send_ephemeral_embedded_reply,
+    Context, InternalError,
+};
+
+const IMAGE_FILE: &str = "image.png";
+
+/// Generates an image from your description
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    user_cooldown = 30,
+    required_permissions = "SEND_MESSAGES",
+    on_error = "handle_prompt_error"
+)]
+pub async fn imagine(
+    ctx: Context<'_>,
+    #[description = "image to draw"]
+    #[max_length = 1000]
+    #[rest]
+    prompt: String,
+) -> Result<(), InternalError> {
+    let data = ctx.data();
+    let conf = data.conf();
+    let messages = &conf.messages.imagine;
+    let guild = ctx.guild_id().unwrap().get();
+    let user = ctx.author().id.get();
+
+    let Some(generator) = data.images.get() else {
+        return Ok(());
+    };
+
+    if conf.imagine.nsfw_only && !is_age_restricted(ctx).await {
+        let embed = serenity::CreateEmbed::new().title(&messages.nsfw_only);
+        send_ephemeral_embedded_reply(ctx, embed).await?;
+
+        return Ok(());
+    }
+
+    let quota = conf.imagine.daily_quota;
+    if quota > 0 && data.usage.user_images_today(user) >= quota {
+        let embed = serenity::CreateEmbed::new().title(messages::render(
+            &messages.quota_reached,
+            &[("max", &quota)],
+        ));
+        send_ephemeral_embedded_reply(ctx, embed).await?;
+
+        return Ok(());
+    }
+
+    // Held in flight by the exchange until the image is posted.
+    let mut exchange = pipeline::Exchange::new(ctx, prompt.clone());
+    if !pipeline::Pipeline::guards()
+        .admit(ctx, &mut exchange)
+        .await?
+    {
+        return Ok(());
+    }
+
+    ctx.defer().await?;
+
+    let image = match generator.generate(&prompt).await {
+        Ok(image) => image,
+        Err(err) => {
+            data.usage.record_error(guild);
+
+            return Err(Box::from(err));
+        }
+    };
+    data.usage.record_image(guild, user);
+
+    let embed = serenity::CreateEmbed::new()
+        .title(messages::render(
+            &messages.caption,
+            &[("user", &ctx.author().name)],
+        ))
+        .description(prompt)
+        .attachment(IMAGE_FILE);
+    let reply = poise::CreateReply::default()
+        .reply(true)
+        .attachment(serenity::CreateAttachment::bytes(image, IMAGE_FILE))
+        .embed(apply_theme(&conf.appearance, embed));
+    ctx.send(reply).await?;
+
+    Ok(())
+}
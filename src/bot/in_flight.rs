@@ -0,0 +1,82 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use tokio::sync::Notify;
+
+/// Counts the requests in flight, so a flush waits for their replies.
+///
+/// Requests only bump a counter, instead of sharing a lock that flushes take over.
+#[derive(Debug, Default)]
+pub(super) struct InFlight {
+    count: AtomicUsize,
+    drained: Notify,
+}
+
+/// Keeps a request in flight until dropped.
+#[derive(Debug)]
+pub(super) struct Guard(Arc<InFlight>);
+
+impl InFlight {
+    /// Marks a request in flight.
+    ///
+    /// Callers check whether a flush started afterwards, which a flush waiting in
+    /// [`Self::drained`] is sure to see otherwise.
+    pub fn enter(self: &Arc<Self>) -> Guard {
+        self.count.fetch_add(1, Ordering::SeqCst);
+
+        Guard(self.clone())
+    }
+
+    /// Waits until no request is in flight.
+    pub async fn drained(&self) {
+        loop {
+            // Registered before checking, so a guard dropped in between still wakes it.
+            let drained = self.drained.notified();
+            if self.count.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+
+            drained.await;
+        }
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        if self.0.count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.drained.notify_waiters();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn waits_for_requests_in_flight() {
+        let in_flight = Arc::new(InFlight::default());
+        in_flight.drained().await;
+
+        let first = in_flight.enter();
+        let second = in_flight.enter();
+        let waiting = tokio::spawn({
+            let in_flight = in_flight.clone();
+            async move { in_flight.drained().await }
+        });
+
+        drop(first);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!waiting.is_finished());
+
+        drop(second);
+        tokio::time::timeout(Duration::from_secs(1), waiting)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+}
@@ -0,0 +1,99 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashSet},
+    sync::Arc,
+};
+
+use poise::serenity_prelude as serenity;
+use tokio::{sync::mpsc, time::Instant};
+
+use super::BotData;
+
+enum Command {
+    Schedule {
+        channel: serenity::ChannelId,
+        message: serenity::MessageId,
+        at: Instant,
+    },
+    Cancel(serenity::MessageId),
+}
+
+/// Deletes messages once they're due, all from a single task.
+#[derive(Debug)]
+pub(super) struct Janitor {
+    commands: mpsc::UnboundedSender<Command>,
+}
+
+impl Janitor {
+    /// Deletes the message at the given time, unless it's cancelled before.
+    pub fn schedule(
+        &self,
+        channel: serenity::ChannelId,
+        message: serenity::MessageId,
+        at: Instant,
+    ) {
+        let _ = self.commands.send(Command::Schedule {
+            channel,
+            message,
+            at,
+        });
+    }
+
+    /// Forgets the message, e.g. when someone else already deleted it.
+    pub fn cancel(&self, message: serenity::MessageId) {
+        let _ = self.commands.send(Command::Cancel(message));
+    }
+}
+
+/// Owns the queue of messages to delete, waking up only when the next one is due.
+async fn run(http: Arc<serenity::Http>, mut commands: mpsc::UnboundedReceiver<Command>) {
+    let mut queue: BinaryHeap<Reverse<(Instant, serenity::ChannelId, serenity::MessageId)>> =
+        BinaryHeap::new();
+    // Cancelled messages stay queued, they're skipped once due.
+    let mut pending = HashSet::new();
+
+    loop {
+        let next = queue.peek().map(|Reverse((at, _, _))| *at);
+        let command = match next {
+            Some(at) => tokio::select! {
+                command = commands.recv() => command,
+                _ = tokio::time::sleep_until(at) => {
+                    let Reverse((_, channel, message)) = queue.pop().unwrap();
+                    if pending.remove(&message) {
+                        if let Err(err) = channel.delete_message(&http, message).await {
+                            log::debug!("failed to delete temporary message {message}: {err}");
+                        }
+                    }
+
+                    continue;
+                }
+            },
+            None => commands.recv().await,
+        };
+
+        match command {
+            Some(Command::Schedule {
+                channel,
+                message,
+                at,
+            }) => {
+                queue.push(Reverse((at, channel, message)));
+                pending.insert(message);
+            }
+            Some(Command::Cancel(message)) => {
+                pending.remove(&message);
+            }
+            None => break,
+        }
+    }
+}
+
+/// Starts deleting the messages scheduled from now on.
+pub(super) fn start(http: Arc<serenity::Http>, data: BotData) {
+    let (commands, receiver) = mpsc::unbounded_channel();
+    if data.janitor.set(Janitor { commands }).is_err() {
+        return;
+    }
+
+    tokio::spawn(run(http, receiver));
+}
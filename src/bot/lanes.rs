@@ -0,0 +1,464 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use genai::chat::{ChatOptions, ChatRequest};
+use poise::BoxFuture;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{chat, config};
+
+use super::{Context, GuildId};
+
+/// Span the provider counts requests and tokens over.
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+/// Time every call is held back after going over the provider limits, unless it says how long.
+const RATE_LIMIT_PAUSE: Duration = Duration::from_secs(5);
+
+tokio::task_local! {
+    /// Lane of the prompt being answered, see [`in_lane`].
+    static LANE: Lane;
+}
+
+/// Queue a prompt waits in for the provider, supporters' one being served first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Lane {
+    Priority,
+    Normal,
+}
+
+/// Lane of the command author, supporters being those with a priority role of the guild or,
+/// when enabled, boosting it.
+pub(super) async fn lane_of(ctx: Context<'_>, guild: GuildId) -> Lane {
+    let conf = ctx.data().conf();
+    let priority_roles = conf
+        .guilds
+        .get(&guild)
+        .map(|guild_conf| guild_conf.priority_roles.as_slice())
+        .unwrap_or_default();
+    if priority_roles.is_empty() && !conf.lanes.boosters {
+        return Lane::Normal;
+    }
+
+    let supporter = ctx.author_member().await.is_some_and(|member| {
+        (conf.lanes.boosters && member.premium_since.is_some())
+            || member
+                .roles
+                .iter()
+                .any(|role| priority_roles.contains(&role.get()))
+    });
+
+    if supporter {
+        Lane::Priority
+    } else {
+        Lane::Normal
+    }
+}
+
+/// Runs the future with its provider calls queued in the lane, instead of the normal one.
+pub(super) async fn in_lane<F: std::future::Future>(lane: Lane, future: F) -> F::Output {
+    LANE.scope(lane, future).await
+}
+
+#[derive(Debug, Default)]
+struct State {
+    running: usize,
+    /// Calls running at once, zero when unbounded.
+    max: usize,
+    /// Priority calls let through in a row while normal ones wait.
+    burst: u8,
+    streak: u8,
+    /// Calls and tokens the provider takes per window, zero when unbounded.
+    requests_per_window: u32,
+    tokens_per_window: u32,
+    /// When the calls and their tokens started within the window were let through.
+    requests: VecDeque<Instant>,
+    tokens: VecDeque<(Instant, u64)>,
+    /// Set when the provider asked to wait, e.g. after going over its limits.
+    paused_until: Option<Instant>,
+    /// Time waiting calls are next let through at, once there's room in the window.
+    wake_at: Option<Instant>,
+    priority: VecDeque<oneshot::Sender<()>>,
+    normal: VecDeque<oneshot::Sender<()>>,
+}
+
+impl State {
+    /// Picks the next waiting call, letting a normal one through once the priority ones had
+    /// their burst, so they're never starved.
+    fn next(&mut self) -> Option<oneshot::Sender<()>> {
+        let starving = self.streak >= self.burst && !self.normal.is_empty();
+        if !starving {
+            if let Some(waiter) = self.priority.pop_front() {
+                if !self.normal.is_empty() {
+                    self.streak += 1;
+                }
+
+                return Some(waiter);
+            }
+        }
+
+        self.streak = 0;
+        self.normal.pop_front()
+    }
+
+    /// Time the window has room for another call again, none when it has already.
+    fn blocked_until(&mut self, now: Instant) -> Option<Instant> {
+        while self
+            .requests
+            .front()
+            .is_some_and(|&at| now - at >= RATE_WINDOW)
+        {
+            self.requests.pop_front();
+        }
+        while self
+            .tokens
+            .front()
+            .is_some_and(|&(at, _)| now - at >= RATE_WINDOW)
+        {
+            self.tokens.pop_front();
+        }
+
+        let paused = self.paused_until.filter(|&until| until > now);
+        let requests_full = self.requests_per_window > 0
+            && self.requests.len() >= self.requests_per_window as usize;
+        let tokens_full = self.tokens_per_window > 0
+            && self.tokens.iter().map(|&(_, tokens)| tokens).sum::<u64>()
+                >= self.tokens_per_window as u64;
+
+        [
+            paused,
+            requests_full
+                .then(|| self.requests.front().map(|&at| at + RATE_WINDOW))
+                .flatten(),
+            tokens_full
+                .then(|| self.tokens.front().map(|&(at, _)| at + RATE_WINDOW))
+                .flatten(),
+        ]
+        .into_iter()
+        .flatten()
+        .max()
+    }
+}
+
+/// Lets waiting calls through while there's room, skipping those no longer waiting.
+///
+/// When the window is full, they're let through once it has room again.
+fn drain(shared: &Arc<Mutex<State>>, state: &mut State) {
+    while state.max == 0 || state.running < state.max {
+        let now = Instant::now();
+        if state.priority.is_empty() && state.normal.is_empty() {
+            break;
+        }
+        if let Some(until) = state.blocked_until(now) {
+            if state.wake_at.is_none_or(|wake_at| wake_at > until) {
+                state.wake_at = Some(until);
+                let shared = shared.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep_until(until.into()).await;
+
+                    let mut state = shared.lock().unwrap();
+                    state.wake_at = None;
+                    drain(&shared, &mut state);
+                });
+            }
+
+            break;
+        }
+
+        let Some(waiter) = state.next() else {
+            break;
+        };
+        if waiter.send(()).is_ok() {
+            state.running += 1;
+            state.requests.push_back(now);
+        }
+    }
+}
+
+fn release(shared: &Arc<Mutex<State>>) {
+    let mut state = shared.lock().unwrap();
+    state.running -= 1;
+    drain(shared, &mut state);
+}
+
+/// Bounds the provider calls running at once across every guild, queueing the rest by lane.
+///
+/// Calls are also paced to the requests and tokens the provider takes per minute, and held
+/// back for as long as it asks once over its limits.
+#[derive(Debug, Default)]
+pub(super) struct Lanes {
+    state: Arc<Mutex<State>>,
+}
+
+/// Room taken by a provider call, freed once dropped.
+#[derive(Debug)]
+pub(super) struct Permit {
+    state: Arc<Mutex<State>>,
+}
+
+impl Permit {
+    /// Counts the tokens the call took against the window.
+    pub fn spend(&self, tokens: u64) {
+        self.state
+            .lock()
+            .unwrap()
+            .tokens
+            .push_back((Instant::now(), tokens));
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        release(&self.state);
+    }
+}
+
+/// Call waiting in a lane, which gives back the room it got if dropped right after.
+struct Waiting {
+    state: Arc<Mutex<State>>,
+    granted: Option<oneshot::Receiver<()>>,
+}
+
+impl Drop for Waiting {
+    fn drop(&mut self) {
+        let Some(mut granted) = self.granted.take() else {
+            return;
+        };
+
+        granted.close();
+        if granted.try_recv().is_ok() {
+            release(&self.state);
+        }
+    }
+}
+
+impl Lanes {
+    pub fn new(conf: &config::Lanes) -> Self {
+        let lanes = Self::default();
+        lanes.configure(conf);
+
+        lanes
+    }
+
+    /// Applies the bounds of the config to the calls let through from now on.
+    pub fn configure(&self, conf: &config::Lanes) {
+        let mut state = self.state.lock().unwrap();
+        state.max = conf.max_concurrent as usize;
+        state.burst = conf.priority_burst;
+        state.requests_per_window = conf.requests_per_minute;
+        state.tokens_per_window = conf.tokens_per_minute;
+        drain(&self.state, &mut state);
+    }
+
+    /// Waits for room in the lane.
+    pub async fn acquire(&self, lane: Lane) -> Permit {
+        let (granter, granted) = oneshot::channel();
+        {
+            let mut state = self.state.lock().unwrap();
+            match lane {
+                Lane::Priority => state.priority.push_back(granter),
+                Lane::Normal => state.normal.push_back(granter),
+            }
+            drain(&self.state, &mut state);
+        }
+
+        let mut waiting = Waiting {
+            state: self.state.clone(),
+            granted: Some(granted),
+        };
+        // Senders are only dropped once they granted the room or the receiver was gone.
+        let _ = waiting.granted.as_mut().unwrap().await;
+        waiting.granted = None;
+
+        Permit {
+            state: self.state.clone(),
+        }
+    }
+
+    /// Holds back every call for the given time, e.g. as asked by the provider.
+    pub fn pause(&self, wait: Duration) {
+        let until = Instant::now() + wait;
+        let mut state = self.state.lock().unwrap();
+        if state.paused_until.is_none_or(|paused| paused < until) {
+            state.paused_until = Some(until);
+        }
+    }
+
+    /// Counts the tokens of a finished call, or holds every call back if it went over the
+    /// provider limits.
+    fn settle(&self, permit: &Permit, sent: &Result<chat::Response, chat::Error>) {
+        match sent {
+            Ok(response) => {
+                permit.spend(response.usage.input_tokens + response.usage.output_tokens);
+            }
+            Err(err) if err.failure() == chat::Failure::RateLimited => {
+                self.pause(err.retry_after().unwrap_or(RATE_LIMIT_PAUSE));
+            }
+            Err(_) => (),
+        }
+    }
+}
+
+/// Provider whose every call waits for room in the lanes of its account, titles, summaries and
+/// other commands included.
+pub(super) struct Paced {
+    provider: Arc<dyn chat::ChatProvider>,
+    lanes: Arc<Lanes>,
+}
+
+impl Paced {
+    pub fn new(provider: Arc<dyn chat::ChatProvider>, lanes: Arc<Lanes>) -> Self {
+        Self { provider, lanes }
+    }
+}
+
+/// Lane of the running prompt, the normal one outside of any.
+fn current_lane() -> Lane {
+    LANE.try_with(|lane| *lane).unwrap_or(Lane::Normal)
+}
+
+impl chat::ChatProvider for Paced {
+    fn exec_chat<'a>(
+        &'a self,
+        model: &'a str,
+        request: ChatRequest,
+        options: Option<&'a ChatOptions>,
+    ) -> BoxFuture<'a, Result<chat::Response, chat::Error>> {
+        Box::pin(async move {
+            let permit = self.lanes.acquire(current_lane()).await;
+            let sent = self.provider.exec_chat(model, request, options).await;
+            self.lanes.settle(&permit, &sent);
+
+            sent
+        })
+    }
+
+    fn exec_chat_stream<'a>(
+        &'a self,
+        model: &'a str,
+        request: ChatRequest,
+        options: Option<&'a ChatOptions>,
+        chunks: mpsc::UnboundedSender<String>,
+    ) -> BoxFuture<'a, Result<chat::Response, chat::Error>> {
+        Box::pin(async move {
+            let permit = self.lanes.acquire(current_lane()).await;
+            let sent = self
+                .provider
+                .exec_chat_stream(model, request, options, chunks)
+                .await;
+            self.lanes.settle(&permit, &sent);
+
+            sent
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn waiter(queue: &mut VecDeque<oneshot::Sender<()>>) -> oneshot::Receiver<()> {
+        let (granter, granted) = oneshot::channel();
+        queue.push_back(granter);
+
+        granted
+    }
+
+    #[test]
+    fn next_lets_a_normal_call_through_after_the_burst() {
+        let mut state = State {
+            burst: 2,
+            ..State::default()
+        };
+        let [first, second, third] = [(); 3].map(|()| waiter(&mut state.priority));
+        let normal = waiter(&mut state.normal);
+
+        for mut granted in [first, second, normal, third] {
+            state.next().unwrap().send(()).unwrap();
+            assert!(granted.try_recv().is_ok());
+        }
+        assert!(state.next().is_none());
+    }
+
+    #[test]
+    fn next_keeps_priority_first_without_normal_calls() {
+        let mut state = State {
+            burst: 1,
+            ..State::default()
+        };
+        let _granted: Vec<_> = (0..3).map(|_| waiter(&mut state.priority)).collect();
+
+        for _ in 0..3 {
+            assert!(state.next().is_some());
+        }
+        assert_eq!(state.streak, 0);
+    }
+
+    #[test]
+    fn blocked_until_waits_for_the_oldest_request_to_leave_the_window() {
+        let now = Instant::now();
+        let oldest = now - Duration::from_secs(10);
+        let mut state = State {
+            requests_per_window: 2,
+            requests: VecDeque::from([oldest, now]),
+            ..State::default()
+        };
+
+        assert_eq!(state.blocked_until(now), Some(oldest + RATE_WINDOW));
+        assert_eq!(state.blocked_until(oldest + RATE_WINDOW), None);
+        assert_eq!(state.requests.len(), 1);
+    }
+
+    #[test]
+    fn blocked_until_counts_tokens_and_pauses() {
+        let now = Instant::now();
+        let mut state = State {
+            tokens_per_window: 100,
+            tokens: VecDeque::from([(now, 60), (now, 40)]),
+            ..State::default()
+        };
+        assert_eq!(state.blocked_until(now), Some(now + RATE_WINDOW));
+
+        let mut state = State {
+            paused_until: Some(now + Duration::from_secs(5)),
+            ..State::default()
+        };
+        assert_eq!(state.blocked_until(now), Some(now + Duration::from_secs(5)));
+    }
+
+    #[tokio::test]
+    async fn drain_bounds_calls_running_at_once() {
+        let lanes = Lanes::new(&config::Lanes {
+            max_concurrent: 1,
+            ..config::Lanes::default()
+        });
+
+        let permit = lanes.acquire(Lane::Normal).await;
+        let waiting = lanes.acquire(Lane::Priority);
+        tokio::pin!(waiting);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(20), &mut waiting)
+                .await
+                .is_err()
+        );
+
+        drop(permit);
+        let _permit = waiting.await;
+        assert_eq!(lanes.state.lock().unwrap().running, 1);
+    }
+
+    #[tokio::test]
+    async fn drain_paces_calls_to_the_window() {
+        let lanes = Lanes::new(&config::Lanes {
+            requests_per_minute: 1,
+            ..config::Lanes::default()
+        });
+
+        drop(lanes.acquire(Lane::Normal).await);
+        let next = tokio::time::timeout(Duration::from_millis(20), lanes.acquire(Lane::Normal));
+        assert!(next.await.is_err());
+        assert!(lanes.state.lock().unwrap().wake_at.is_some());
+    }
+}
@@ -0,0 +1,94 @@
+use poise::serenity_prelude as serenity;
+use whatlang::Lang;
+
+use crate::messages;
+
+use super::{handle_command_error, send_ephemeral_embedded_reply, Context, InternalError};
+
+const AUTO_LANGUAGE: &str = "auto";
+const MAX_CHOICES: usize = 25;
+
+/// Finds the language by its ISO 639-3 code or English name, ignoring case.
+fn parse_language(language: &str) -> Option<Lang> {
+    let language = language.trim().to_lowercase();
+
+    Lang::from_code(language.as_str()).or_else(|| {
+        Lang::all()
+            .iter()
+            .copied()
+            .find(|lang| lang.eng_name().to_lowercase() == language)
+    })
+}
+
+/// Hint asking the model to reply in the language.
+pub(super) fn hint(language: Lang) -> String {
+    format!(
+        "Reply in {}, unless the user explicitly asks for another language.",
+        language.eng_name()
+    )
+}
+
+/// Language of the prompt, when it can be told reliably.
+pub(super) fn detect(prompt: &str) -> Option<Lang> {
+    whatlang::detect(prompt)
+        .filter(whatlang::Info::is_reliable)
+        .map(|info| info.lang())
+}
+
+async fn autocomplete_language(
+    _ctx: Context<'_>,
+    partial: &str,
+) -> Vec<serenity::AutocompleteChoice> {
+    let partial = partial.to_lowercase();
+
+    std::iter::once(serenity::AutocompleteChoice::new(
+        "Automatic",
+        AUTO_LANGUAGE,
+    ))
+    .chain(
+        Lang::all()
+            .iter()
+            .filter(|lang| lang.eng_name().to_lowercase().contains(&partial))
+            .map(|lang| serenity::AutocompleteChoice::new(lang.eng_name(), lang.code())),
+    )
+    .take(MAX_CHOICES)
+    .collect()
+}
+
+/// Picks the language of the model replies, or lets it follow your messages
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    user_cooldown = 2,
+    required_permissions = "SEND_MESSAGES",
+    on_error = "handle_command_error"
+)]
+pub async fn language(
+    ctx: Context<'_>,
+    #[description = "language name or ISO 639-3 code, auto to follow your messages"]
+    #[autocomplete = "autocomplete_language"]
+    language: String,
+) -> Result<(), InternalError> {
+    let data = ctx.data();
+    let conf = data.conf();
+    let messages = &conf.messages.language;
+    let key = (ctx.guild_id().unwrap().get(), ctx.author().id.get());
+
+    let title = if language.trim().eq_ignore_ascii_case(AUTO_LANGUAGE) {
+        data.languages.remove(&key);
+
+        messages.automatic.clone()
+    } else if let Some(lang) = parse_language(&language) {
+        data.languages.insert(key, lang);
+
+        messages::render(&messages.set, &[("language", &lang.eng_name())])
+    } else {
+        messages::render(&messages.unknown, &[("language", &language.trim())])
+    };
+
+    let embed = serenity::CreateEmbed::new().title(title);
+    send_ephemeral_embedded_reply(ctx, embed).await?;
+
+    Ok(())
+}
@@ -0,0 +1,15 @@
+/// Sizes bot owners grant a guild at runtime, over the config file.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, Default)]
+#[serde(default)]
+pub(super) struct Limits {
+    /// Longest prompt, in characters.
+    pub prompt_size: Option<u16>,
+    /// Interactions sessions keep.
+    pub history_size: Option<u8>,
+}
+
+impl Limits {
+    pub fn is_empty(&self) -> bool {
+        self.prompt_size.is_none() && self.history_size.is_none()
+    }
+}
@@ -0,0 +1,98 @@
+use std::time::Duration;
+
+use poise::serenity_prelude as serenity;
+
+use super::{handle_prompt_error, pipeline, BotData, InternalError};
+
+/// Longest text Discord accepts in a modal field.
+const MODAL_MAX_LENGTH: u16 = 4000;
+const MODAL_TIMEOUT: Duration = Duration::from_secs(900);
+const CONTENT_ID: &str = "content";
+
+/// Opens a form with a single multi-line field, returning what was submitted.
+///
+/// Nothing is returned when the form times out.
+pub(super) async fn open_form(
+    ctx: poise::ApplicationContext<'_, BotData, InternalError>,
+    title: &str,
+    label: &str,
+    placeholder: &str,
+    max_length: u16,
+) -> Result<Option<String>, InternalError> {
+    let modal_id = ctx.interaction.id.to_string();
+
+    let field =
+        serenity::CreateInputText::new(serenity::InputTextStyle::Paragraph, label, CONTENT_ID)
+            .placeholder(placeholder)
+            .max_length(MODAL_MAX_LENGTH.min(max_length));
+    let modal = serenity::CreateModal::new(&modal_id, title)
+        .components(vec![serenity::CreateActionRow::InputText(field)]);
+    ctx.interaction
+        .create_response(ctx, serenity::CreateInteractionResponse::Modal(modal))
+        .await?;
+    ctx.has_sent_initial_response
+        .store(true, std::sync::atomic::Ordering::SeqCst);
+
+    let author = ctx.author().id;
+    let Some(submit) = serenity::ModalInteractionCollector::new(ctx)
+        .author_id(author)
+        .filter(move |submit| submit.data.custom_id == modal_id)
+        .timeout(MODAL_TIMEOUT)
+        .await
+    else {
+        return Ok(None);
+    };
+
+    // Closes the form, the reply follows up on the command.
+    submit
+        .create_response(ctx, serenity::CreateInteractionResponse::Acknowledge)
+        .await?;
+
+    let content = submit
+        .data
+        .components
+        .iter()
+        .flat_map(|row| &row.components)
+        .find_map(|component| match component {
+            serenity::ActionRowComponent::InputText(text) if text.custom_id == CONTENT_ID => {
+                text.value.clone()
+            }
+            _ => None,
+        })
+        .unwrap_or_default();
+
+    Ok(Some(content))
+}
+
+/// Opens a form to write a long, multi-line message to send
+#[poise::command(
+    slash_command,
+    rename = "prompt-long",
+    guild_only,
+    user_cooldown = 4,
+    required_permissions = "SEND_MESSAGES",
+    on_error = "handle_prompt_error"
+)]
+pub async fn prompt_long(
+    ctx: poise::ApplicationContext<'_, BotData, InternalError>,
+) -> Result<(), InternalError> {
+    let conf = ctx.data().conf();
+    let messages = &conf.messages.long_prompt;
+    let prompt_size = ctx.data().prompt_size(ctx.guild_id().unwrap().get());
+
+    let form = open_form(
+        ctx,
+        &messages.title,
+        &messages.label,
+        &messages.placeholder,
+        prompt_size,
+    );
+    let Some(content) = form.await? else {
+        return Ok(());
+    };
+
+    let ctx = poise::Context::Application(ctx);
+    let mut exchange = pipeline::Exchange::new(ctx, content);
+
+    ctx.data().pipeline.run(ctx, &mut exchange).await
+}
@@ -0,0 +1,238 @@
+use poise::serenity_prelude as serenity;
+
+use crate::messages;
+
+use super::{
+    handle_command_error, handle_prompt_error, pipeline, send_ephemeral_embedded_reply,
+    sessions::{parse_session_name, SESSION_NAME_MAX_LEN},
+    Context, InternalError,
+};
+
+const MAX_CHOICES: usize = 25;
+
+async fn send_invalid_name_alert(ctx: Context<'_>) -> Result<(), serenity::Error> {
+    let embed = serenity::CreateEmbed::new().title(messages::render(
+        &ctx.data().conf().messages.macros.invalid_name,
+        &[("max", &SESSION_NAME_MAX_LEN)],
+    ));
+    send_ephemeral_embedded_reply(ctx, embed).await?;
+
+    Ok(())
+}
+
+async fn autocomplete_macro(ctx: Context<'_>, partial: &str) -> Vec<serenity::AutocompleteChoice> {
+    let Some(guild) = ctx.guild_id() else {
+        return Vec::new();
+    };
+    let (guild, user) = (guild.get(), ctx.author().id.get());
+    let partial = partial.to_lowercase();
+
+    let mut names: Vec<_> = ctx
+        .data()
+        .macros
+        .iter()
+        .filter(|entry| {
+            let (macro_guild, owner, name) = entry.key();
+            *macro_guild == guild && *owner == user && name.contains(&partial)
+        })
+        .map(|entry| entry.key().2.clone())
+        .collect();
+    names.sort_unstable();
+
+    names
+        .into_iter()
+        .take(MAX_CHOICES)
+        .map(|name| serenity::AutocompleteChoice::new(name.clone(), name))
+        .collect()
+}
+
+/// Manages your reusable prompt snippets
+#[poise::command(
+    slash_command,
+    prefix_command,
+    rename = "macro",
+    guild_only,
+    subcommands("save", "run", "list", "delete"),
+    subcommand_required,
+    on_error = "handle_command_error"
+)]
+pub async fn prompt_macro(_ctx: Context<'_>) -> Result<(), InternalError> {
+    Ok(())
+}
+
+/// Saves a snippet to send later with /macro run
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    user_cooldown = 2,
+    required_permissions = "SEND_MESSAGES",
+    on_error = "handle_command_error"
+)]
+async fn save(
+    ctx: Context<'_>,
+    #[description = "name of the macro"] name: String,
+    #[description = "e.g. review this code for bugs and style:"]
+    #[rest]
+    text: String,
+) -> Result<(), InternalError> {
+    let Some(name) = parse_session_name(&name) else {
+        send_invalid_name_alert(ctx).await?;
+
+        return Ok(());
+    };
+
+    let data = ctx.data();
+    let conf = data.conf();
+    let messages = &conf.messages.macros;
+    let guild = ctx.guild_id().unwrap().get();
+    let user = ctx.author().id.get();
+
+    if conf.chat.max_macros == 0 {
+        let embed = serenity::CreateEmbed::new().title(&messages.disabled);
+        send_ephemeral_embedded_reply(ctx, embed).await?;
+
+        return Ok(());
+    }
+
+    let text = text.trim();
+    let max = data.prompt_size(guild);
+    if text.is_empty() || text.chars().count() > max as usize {
+        let embed = serenity::CreateEmbed::new()
+            .title(messages::render(&messages.too_long, &[("max", &max)]));
+        send_ephemeral_embedded_reply(ctx, embed).await?;
+
+        return Ok(());
+    }
+
+    let title = if data.save_macro((guild, user, name.clone()), text.to_string()) {
+        messages::render(&messages.saved, &[("name", &name)])
+    } else {
+        messages::render(&messages.limit_reached, &[("max", &conf.chat.max_macros)])
+    };
+    let embed = serenity::CreateEmbed::new().title(title);
+    send_ephemeral_embedded_reply(ctx, embed).await?;
+
+    Ok(())
+}
+
+/// Sends a saved snippet, followed by anything you add
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    user_cooldown = 4,
+    required_permissions = "SEND_MESSAGES",
+    on_error = "handle_prompt_error"
+)]
+async fn run(
+    ctx: Context<'_>,
+    #[description = "name of the macro"]
+    #[autocomplete = "autocomplete_macro"]
+    name: String,
+    #[description = "text sent after the snippet"]
+    #[rest]
+    extra: Option<String>,
+) -> Result<(), InternalError> {
+    let data = ctx.data();
+    let key = (
+        ctx.guild_id().unwrap().get(),
+        ctx.author().id.get(),
+        name.trim().to_lowercase(),
+    );
+
+    let Some(text) = data.macros.get(&key).map(|text| text.clone()) else {
+        let embed = serenity::CreateEmbed::new().title(messages::render(
+            &data.conf().messages.macros.missing,
+            &[("name", &key.2)],
+        ));
+        send_ephemeral_embedded_reply(ctx, embed).await?;
+
+        return Ok(());
+    };
+
+    let content = match extra.as_deref().map(str::trim) {
+        Some(extra) if !extra.is_empty() => format!("{text}\n\n{extra}"),
+        _ => text,
+    };
+    let mut exchange = pipeline::Exchange::new(ctx, content);
+
+    data.pipeline.run(ctx, &mut exchange).await
+}
+
+/// Lists your saved snippets
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    user_cooldown = 2,
+    required_permissions = "SEND_MESSAGES",
+    on_error = "handle_command_error"
+)]
+async fn list(ctx: Context<'_>) -> Result<(), InternalError> {
+    let data = ctx.data();
+    let conf = data.conf();
+    let messages = &conf.messages.macros;
+    let guild = ctx.guild_id().unwrap().get();
+    let user = ctx.author().id.get();
+
+    let mut macros: Vec<_> = data
+        .macros
+        .iter()
+        .filter(|entry| entry.key().0 == guild && entry.key().1 == user)
+        .map(|entry| (entry.key().2.clone(), entry.value().clone()))
+        .collect();
+
+    if macros.is_empty() {
+        let embed = serenity::CreateEmbed::new().title(&messages.empty);
+        send_ephemeral_embedded_reply(ctx, embed).await?;
+
+        return Ok(());
+    }
+
+    macros.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+    let embed = serenity::CreateEmbed::new()
+        .title(messages::render(
+            &messages.list_title,
+            &[("count", &macros.len()), ("max", &conf.chat.max_macros)],
+        ))
+        .fields(
+            macros
+                .iter()
+                .map(|(name, text)| (name, super::truncate_field_value(text), false)),
+        );
+    send_ephemeral_embedded_reply(ctx, embed).await?;
+
+    Ok(())
+}
+
+/// Deletes a saved snippet
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    user_cooldown = 2,
+    required_permissions = "SEND_MESSAGES",
+    on_error = "handle_command_error"
+)]
+async fn delete(
+    ctx: Context<'_>,
+    #[description = "name of the macro"]
+    #[autocomplete = "autocomplete_macro"]
+    name: String,
+) -> Result<(), InternalError> {
+    let data = ctx.data();
+    let messages = &data.conf().messages.macros;
+    let name = name.trim().to_lowercase();
+    let key = (ctx.guild_id().unwrap().get(), ctx.author().id.get(), name);
+
+    let title = if data.macros.remove(&key).is_some() {
+        messages::render(&messages.deleted, &[("name", &key.2)])
+    } else {
+        messages::render(&messages.missing, &[("name", &key.2)])
+    };
+    let embed = serenity::CreateEmbed::new().title(title);
+    send_ephemeral_embedded_reply(ctx, embed).await?;
+
+    Ok(())
+}
@@ -0,0 +1,235 @@
+use poise::serenity_prelude::{self as serenity, Mentionable};
+
+use crate::messages;
+
+use super::{
+    handle_command_error, send_ephemeral_embedded_reply, send_paginated_embeds, tombstones,
+    Context, InternalError,
+};
+
+const SESSIONS_PER_PAGE: usize = 5;
+
+/// Moderation tools for the conversations members keep with the bot
+#[poise::command(
+    slash_command,
+    prefix_command,
+    rename = "mod",
+    guild_only,
+    default_member_permissions = "MODERATE_MEMBERS",
+    subcommands("history", "purge", "undo_purge"),
+    subcommand_required,
+    on_error = "handle_command_error"
+)]
+pub async fn moderation(_ctx: Context<'_>) -> Result<(), InternalError> {
+    Ok(())
+}
+
+/// Shows what the bot keeps of a member's conversations
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    user_cooldown = 2,
+    required_permissions = "MODERATE_MEMBERS",
+    on_error = "handle_command_error"
+)]
+async fn history(
+    ctx: Context<'_>,
+    #[description = "member to look up"] member: serenity::User,
+) -> Result<(), InternalError> {
+    let data = ctx.data();
+    let conf = data.conf();
+    let messages = &conf.messages.moderation;
+    let guild = ctx.guild_id().unwrap().get();
+    let user = member.id.get();
+
+    let mut sessions = data.owned_sessions(guild, user);
+    sessions.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut entries = Vec::with_capacity(sessions.len());
+    for (name, session) in sessions {
+        let chat = session.session.lock().await;
+        let kept = chat.history().count();
+        let last = chat
+            .history()
+            .last()
+            .map(|interaction| format!("<t:{}:R>", interaction.at.timestamp()))
+            .unwrap_or_else(|| "-".to_string());
+
+        let mut value = messages::render(
+            &messages.session_entry,
+            &[
+                ("kept", &kept),
+                ("exchanged", &chat.exchanged()),
+                ("last", &last),
+            ],
+        );
+        if chat.instructions().is_some() {
+            value.push('\n');
+            value.push_str(&messages.with_instructions);
+        }
+        if data.pins.contains_key(&(guild, user, name.clone())) {
+            value.push('\n');
+            value.push_str(&messages.pinned);
+        }
+
+        let name = match session.title() {
+            Some(title) => format!("`{name}` - {title}"),
+            None => format!("`{name}`"),
+        };
+        entries.push((name, value, false));
+    }
+
+    if entries.is_empty() {
+        let embed = serenity::CreateEmbed::new().title(messages::render(
+            &messages.no_sessions,
+            &[("user", &member.name)],
+        ));
+        send_ephemeral_embedded_reply(ctx, embed).await?;
+
+        return Ok(());
+    }
+
+    let title = messages::render(
+        &messages.history_title,
+        &[("user", &member.name), ("count", &entries.len())],
+    );
+    let pages = entries
+        .chunks(SESSIONS_PER_PAGE)
+        .map(|chunk| {
+            serenity::CreateEmbed::new()
+                .title(&title)
+                .description(member.mention().to_string())
+                .fields(chunk.iter().cloned())
+        })
+        .collect();
+    send_paginated_embeds(ctx, pages, true).await?;
+
+    Ok(())
+}
+
+/// Deletes every conversation a member keeps with the bot
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    user_cooldown = 2,
+    required_permissions = "MODERATE_MEMBERS",
+    on_error = "handle_command_error"
+)]
+async fn purge(
+    ctx: Context<'_>,
+    #[description = "member whose conversations are deleted"] member: serenity::User,
+) -> Result<(), InternalError> {
+    let data = ctx.data();
+    let conf = data.conf();
+    let messages = &conf.messages.moderation;
+    let guild = ctx.guild_id().unwrap().get();
+
+    let purged = data.purge_sessions(guild, member.id.get());
+    let grace = data.undo_grace();
+    let embed = if purged == 0 {
+        serenity::CreateEmbed::new().title(messages::render(
+            &messages.no_sessions,
+            &[("user", &member.name)],
+        ))
+    } else {
+        notify_purge(ctx, &member, purged);
+
+        let embed = serenity::CreateEmbed::new().title(messages::render(
+            &messages.purged,
+            &[
+                ("user", &member.name),
+                ("count", &purged),
+                ("plural", &messages::plural(purged as u64)),
+            ],
+        ));
+        if grace.is_zero() {
+            embed
+        } else {
+            embed.description(messages::render(
+                &messages.undo_hint,
+                &[("minutes", &grace.as_secs().div_ceil(60))],
+            ))
+        }
+    };
+    send_ephemeral_embedded_reply(ctx, embed).await?;
+
+    Ok(())
+}
+
+/// Brings back the conversations of a member purged in the last few minutes
+#[poise::command(
+    slash_command,
+    prefix_command,
+    rename = "undo-purge",
+    guild_only,
+    user_cooldown = 2,
+    required_permissions = "MODERATE_MEMBERS",
+    on_error = "handle_command_error"
+)]
+async fn undo_purge(
+    ctx: Context<'_>,
+    #[description = "member whose conversations are restored"] member: serenity::User,
+) -> Result<(), InternalError> {
+    let data = ctx.data();
+    let conf = data.conf();
+    let messages = &conf.messages.moderation;
+    let guild = ctx.guild_id().unwrap().get();
+
+    let title = match data.undo_deletion(guild, member.id.get(), tombstones::Cause::Purge) {
+        Some(restored) => messages::render(
+            &messages.restored,
+            &[
+                ("user", &member.name),
+                ("count", &restored),
+                ("plural", &messages::plural(restored as u64)),
+            ],
+        ),
+        None => messages::render(&messages.nothing_to_restore, &[("user", &member.name)]),
+    };
+    let embed = serenity::CreateEmbed::new().title(title);
+    send_ephemeral_embedded_reply(ctx, embed).await?;
+
+    Ok(())
+}
+
+/// Posts a purge in the guild log channel, if there's one.
+fn notify_purge(ctx: Context<'_>, member: &serenity::User, purged: usize) {
+    let conf = ctx.data().conf();
+    let Some(log_channel) = ctx
+        .guild_id()
+        .and_then(|guild| conf.guilds.get(&guild.get()))
+        .and_then(|guild_conf| guild_conf.log_channel)
+    else {
+        return;
+    };
+
+    let embed = serenity::CreateEmbed::new()
+        .title(":wastebasket: Sessions Purged")
+        .field(
+            ":shield: | Moderator:",
+            ctx.author().mention().to_string(),
+            true,
+        )
+        .field(
+            ":bust_in_silhouette: | Member:",
+            member.mention().to_string(),
+            true,
+        )
+        .field(
+            ":card_index_dividers: | Sessions:",
+            purged.to_string(),
+            true,
+        )
+        .timestamp(serenity::Timestamp::now());
+    let message = serenity::CreateMessage::new().embed(embed);
+    let http = ctx.serenity_context().http.clone();
+
+    tokio::spawn(async move {
+        let channel = serenity::ChannelId::new(log_channel);
+        if let Err(err) = channel.send_message(http, message).await {
+            log::warn!("failed to post session purge in log channel {channel}: {err}");
+        }
+    });
+}
@@ -0,0 +1,2138 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use poise::{
+    serenity_prelude::{self as serenity, Mentionable},
+    BoxFuture,
+};
+use tokio::sync::mpsc;
+
+use crate::{chat, code, config, messages, report, spam, throughput};
+
+use super::{
+    allowed_mentions, apply_theme, capture, channel_context, command_set, edits, failure_alert,
+    followups, guard, in_flight, is_age_restricted, lanes, reactions, reasoning, report_context,
+    send_embedded_reply, send_ephemeral_embedded_reply, status, truncate_chars, webhooks, BotData,
+    ChannelId, ChatSession, Context, Extras, GuildId, InternalError, QueueSlot, UserId,
+    EMBED_DESCRIPTION_LIMIT,
+};
+
+const MODEL_CHOICE_PREFIX: &str = "model:";
+pub(super) const RESPONSE_FILE: &str = "response.md";
+/// Times longer than a message summarized replies may be before being shortened.
+const SUMMARIZED_REPLY_FACTOR: usize = 4;
+const RETRY_TIMEOUT: Duration = Duration::from_secs(60);
+const CLOSING_FENCE: &str = "\n```";
+const FLUSH_WARNING_WINDOW: chrono::TimeDelta = chrono::TimeDelta::hours(1);
+/// Longest thread name Discord takes.
+const THREAD_NAME_LIMIT: usize = 100;
+/// Interaction tokens last 15 minutes, minus a margin for the request answering it.
+const INTERACTION_TOKEN_LIFETIME: chrono::TimeDelta = chrono::TimeDelta::minutes(14);
+/// Least time between edits of a streamed reply, keeping under the message edit rate limit.
+const STREAM_EDIT_INTERVAL: Duration = Duration::from_millis(1500);
+
+#[derive(thiserror::Error, Debug)]
+#[error("models are picked as {MODEL_CHOICE_PREFIX}<name>")]
+pub(super) struct InvalidModelChoice;
+
+/// Model picked for a single prompt, written as `model:<name>` in prefix commands.
+///
+/// The prefix keeps prefix commands from taking the first word of the prompt as a model, while
+/// slash commands take the bare name picked in the autocomplete.
+#[derive(Debug)]
+pub(super) struct ModelChoice(pub String);
+
+impl std::str::FromStr for ModelChoice {
+    type Err = InvalidModelChoice;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix(MODEL_CHOICE_PREFIX) {
+            Some(model) if !model.is_empty() => Ok(Self(model.to_string())),
+            _ => Err(InvalidModelChoice),
+        }
+    }
+}
+
+impl poise::SlashArgument for ModelChoice {
+    fn extract<'ctx, 'interaction, 'value, 'resolved, 'fut>(
+        _: &'ctx serenity::Context,
+        _: &'interaction serenity::CommandInteraction,
+        value: &'value serenity::ResolvedValue<'resolved>,
+    ) -> BoxFuture<'fut, Result<Self, poise::SlashArgError>>
+    where
+        'ctx: 'fut,
+        'interaction: 'fut,
+        'value: 'fut,
+        'resolved: 'fut,
+    {
+        let choice = match value {
+            serenity::ResolvedValue::String(model) if !model.is_empty() => {
+                Ok(Self(model.to_string()))
+            }
+            _ => Err(poise::SlashArgError::new_command_structure_mismatch(
+                "expected a model name",
+            )),
+        };
+
+        Box::pin(std::future::ready(choice))
+    }
+
+    fn create(builder: serenity::CreateCommandOption) -> serenity::CreateCommandOption {
+        builder.kind(serenity::CommandOptionType::String)
+    }
+}
+
+/// Tells the pipeline whether the next stages should run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Flow {
+    Continue,
+    Halt,
+}
+
+/// Message a slow reply is streamed into, replaced by the delivered one.
+#[derive(Debug)]
+pub(super) struct Placeholder {
+    pub message: serenity::Message,
+    /// Thread started off the message once the reply no longer fits in it.
+    pub thread: Option<ThreadPages>,
+}
+
+/// Pages of a long reply posted in a thread started off its pointer message.
+#[derive(Debug)]
+pub(super) struct ThreadPages {
+    pub thread: serenity::ChannelId,
+    pub pages: Vec<serenity::Message>,
+}
+
+/// State of a prompt while it goes through the pipeline.
+#[derive(Debug)]
+pub(super) struct Exchange {
+    pub guild: GuildId,
+    pub user: UserId,
+    pub channel: ChannelId,
+    pub content: String,
+    /// Voice message whose transcript is sent along the content.
+    pub voice: Option<serenity::Attachment>,
+    pub transcript: Option<String>,
+    /// Extra system instructions, like the content policy of the channel.
+    pub policy: Option<String>,
+    /// Model replacing the default one for this prompt.
+    pub model: Option<String>,
+    /// Id of the interaction replaced by this prompt, dropping the ones after it.
+    pub branch: Option<u64>,
+    /// Provider and model of the route the prompt takes, if any.
+    pub target: Option<chat::Target>,
+    /// Earlier answer to the edited prompt message, whose reply is replaced.
+    pub edited: Option<edits::Prompt>,
+    /// Messages the reply was streamed into, replaced by the delivered ones.
+    pub placeholder: Option<Placeholder>,
+    /// Sends the recent messages of the channel along the prompt.
+    pub channel_context: bool,
+    pub session: Option<ChatSession>,
+    /// Place in the session queue, held until the reply is delivered.
+    pub queue_slot: Option<QueueSlot>,
+    pub response: Option<chat::Response>,
+    /// Interactions the session exchanged once it answered the prompt.
+    pub exchanged: usize,
+    /// Alert offering to retry when the reply couldn't be had or sent, after undoing it in
+    /// the session.
+    pub undelivered: Option<String>,
+    pub in_flight: Option<in_flight::Guard>,
+}
+
+impl Exchange {
+    pub fn new(ctx: Context<'_>, content: String) -> Self {
+        Self {
+            guild: ctx.guild_id().unwrap().get(),
+            user: ctx.author().id.get(),
+            channel: ctx.channel_id().get(),
+            content,
+            voice: None,
+            transcript: None,
+            policy: None,
+            model: None,
+            branch: None,
+            target: None,
+            edited: None,
+            placeholder: None,
+            channel_context: false,
+            session: None,
+            queue_slot: None,
+            response: None,
+            exchanged: 0,
+            undelivered: None,
+            in_flight: None,
+        }
+    }
+}
+
+pub(super) trait Stage: Send + Sync {
+    fn handle<'a>(
+        &'a self,
+        ctx: Context<'a>,
+        exchange: &'a mut Exchange,
+    ) -> BoxFuture<'a, Result<Flow, InternalError>>;
+}
+
+/// Ordered stages run by the `prompt` command.
+#[derive(Default)]
+pub(super) struct Pipeline {
+    stages: Vec<Box<dyn Stage>>,
+    /// Stage undelivered prompts start over from when retried.
+    retry_from: usize,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+            .then(UnansweredEdit)
+            .then(MaintenanceGuard)
+            .then(ReplyGuard)
+            .then(ModelOverride)
+            .then(Transcribe)
+            .then(SizeLimit)
+            .then(SpamGuard)
+            .then(FlushGuard)
+            .then(Sanitize)
+            .then(ContentPolicy)
+            .then(LanguageHint)
+            .then(ChannelContext)
+            .then(Template)
+            .then(SessionQueue)
+            .then(EditedPrompt)
+            .then(Routing)
+            .retry_from_here()
+            .then(ProviderCall)
+            .then(Deliver)
+    }
+
+    /// Guards of the `prompt` command, for commands prompting the model on their own.
+    pub fn guards() -> Self {
+        Self::default()
+            .then(MaintenanceGuard)
+            .then(ReplyGuard)
+            .then(PromptsEnabled)
+            .then(SpamGuard)
+            .then(FlushGuard)
+            .then(ContentPolicy)
+            .then(LanguageHint)
+    }
+
+    pub fn then(mut self, stage: impl Stage + 'static) -> Self {
+        self.stages.push(Box::new(stage));
+
+        self
+    }
+
+    /// Makes retried prompts start over from the next stage.
+    pub fn retry_from_here(mut self) -> Self {
+        self.retry_from = self.stages.len();
+
+        self
+    }
+
+    async fn run_from(
+        &self,
+        ctx: Context<'_>,
+        exchange: &mut Exchange,
+        first: usize,
+    ) -> Result<(), InternalError> {
+        for stage in &self.stages[first..] {
+            if stage.handle(ctx, exchange).await? == Flow::Halt {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Runs the stages, returning whether the exchange went through all of them.
+    pub async fn admit(
+        &self,
+        ctx: Context<'_>,
+        exchange: &mut Exchange,
+    ) -> Result<bool, InternalError> {
+        for stage in &self.stages {
+            if stage.handle(ctx, exchange).await? == Flow::Halt {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    pub async fn run(
+        &self,
+        ctx: Context<'_>,
+        exchange: &mut Exchange,
+    ) -> Result<(), InternalError> {
+        let data = ctx.data();
+
+        let mut result = self.run_from(ctx, exchange, 0).await;
+        while result.is_ok() {
+            let Some(alert) = exchange.undelivered.take() else {
+                break;
+            };
+            exchange.response = None;
+
+            // Flushes don't wait for the author to make up their mind.
+            let held = exchange.in_flight.take().is_some();
+            let epoch = data.epoch();
+            let retry = offer_retry(ctx, &alert).await;
+            if held {
+                exchange.in_flight = Some(data.in_flight.enter());
+            }
+
+            result = match retry {
+                // The session of the prompt is gone once flushed.
+                Ok(true) if data.is_flushing() || data.epoch() != epoch => {
+                    exchange.in_flight = None;
+                    let embed =
+                        serenity::CreateEmbed::new().title(&data.conf().messages.alerts.flushing);
+                    send_embedded_reply(ctx, embed)
+                        .await
+                        .map(drop)
+                        .map_err(Box::from)
+                }
+                Ok(true) => self.run_from(ctx, exchange, self.retry_from).await,
+                Ok(false) => break,
+                Err(err) => Err(Box::from(err)),
+            };
+        }
+
+        // Tracked by SessionQueue, however the prompt ended.
+        ctx.data().prompts.remove(&ctx.id());
+
+        result
+    }
+}
+
+/// Tells the author why they didn't get their reply, returning whether they asked to retry.
+///
+/// The prompt keeps its place in the session queue meanwhile.
+async fn offer_retry(ctx: Context<'_>, alert: &str) -> Result<bool, serenity::Error> {
+    let conf = ctx.data().conf();
+    let retry_button_id = format!("{}retry", ctx.id());
+
+    let embed = serenity::CreateEmbed::new().title(alert);
+    let button = serenity::CreateButton::new(&retry_button_id)
+        .label(&conf.messages.alerts.delivery_retry)
+        .style(serenity::ButtonStyle::Primary);
+    let reply = poise::CreateReply::default()
+        .embed(apply_theme(&conf.appearance, embed))
+        .components(vec![serenity::CreateActionRow::Buttons(vec![button])]);
+    let handle = ctx.send(reply).await?;
+
+    let press = serenity::ComponentInteractionCollector::new(ctx)
+        .author_id(ctx.author().id)
+        .custom_ids(vec![retry_button_id])
+        .timeout(RETRY_TIMEOUT)
+        .await;
+
+    match press {
+        Some(press) => {
+            let response = serenity::CreateInteractionResponse::UpdateMessage(
+                serenity::CreateInteractionResponseMessage::new().components(vec![]),
+            );
+            press.create_response(ctx, response).await?;
+
+            Ok(true)
+        }
+        None => {
+            let reply = poise::CreateReply::default().components(vec![]);
+            handle.edit(ctx, reply).await?;
+
+            Ok(false)
+        }
+    }
+}
+
+/// Ignores edits of prompt messages the bot didn't answer, or no longer follows.
+struct UnansweredEdit;
+
+impl Stage for UnansweredEdit {
+    fn handle<'a>(
+        &'a self,
+        ctx: Context<'a>,
+        _exchange: &'a mut Exchange,
+    ) -> BoxFuture<'a, Result<Flow, InternalError>> {
+        Box::pin(async move {
+            match edits::edited_message(ctx) {
+                Some(message) if ctx.data().answered_prompts.get(message).is_none() => {
+                    Ok(Flow::Halt)
+                }
+                _ => Ok(Flow::Continue),
+            }
+        })
+    }
+}
+
+/// Holds prompts back while the bot is under maintenance.
+struct MaintenanceGuard;
+
+impl Stage for MaintenanceGuard {
+    fn handle<'a>(
+        &'a self,
+        ctx: Context<'a>,
+        _exchange: &'a mut Exchange,
+    ) -> BoxFuture<'a, Result<Flow, InternalError>> {
+        Box::pin(async move {
+            let data = ctx.data();
+
+            if !data.is_under_maintenance() {
+                return Ok(Flow::Continue);
+            }
+
+            let embed =
+                serenity::CreateEmbed::new().title(&data.conf().messages.alerts.maintenance);
+            send_embedded_reply(ctx, embed).await?;
+
+            Ok(Flow::Halt)
+        })
+    }
+}
+
+/// Drops prompts the bot couldn't reply to, before paying for their response.
+struct ReplyGuard;
+
+impl Stage for ReplyGuard {
+    fn handle<'a>(
+        &'a self,
+        ctx: Context<'a>,
+        exchange: &'a mut Exchange,
+    ) -> BoxFuture<'a, Result<Flow, InternalError>> {
+        Box::pin(async move {
+            let interaction = matches!(ctx, poise::Context::Application(_));
+            let Some(reason) =
+                guard::reply_blocker(ctx.cache(), exchange.guild, ctx.channel_id(), interaction)
+            else {
+                return Ok(Flow::Continue);
+            };
+
+            ctx.data().degraded.record(exchange.guild, reason);
+
+            Ok(Flow::Halt)
+        })
+    }
+}
+
+/// Holds back other commands prompting the model while the guild has `prompt` turned off.
+struct PromptsEnabled;
+
+impl Stage for PromptsEnabled {
+    fn handle<'a>(
+        &'a self,
+        ctx: Context<'a>,
+        exchange: &'a mut Exchange,
+    ) -> BoxFuture<'a, Result<Flow, InternalError>> {
+        Box::pin(async move {
+            let data = ctx.data();
+
+            if command_set::is_enabled(data, exchange.guild, "prompt") {
+                return Ok(Flow::Continue);
+            }
+
+            let alerts = &data.conf().messages.alerts;
+            let embed = serenity::CreateEmbed::new()
+                .title(&alerts.access_denied)
+                .description(messages::render(
+                    &alerts.access_command,
+                    &[("command", &"prompt")],
+                ));
+            send_ephemeral_embedded_reply(ctx, embed).await?;
+
+            Ok(Flow::Halt)
+        })
+    }
+}
+
+/// Lets members with a privileged role pick one of the override models.
+struct ModelOverride;
+
+impl Stage for ModelOverride {
+    fn handle<'a>(
+        &'a self,
+        ctx: Context<'a>,
+        exchange: &'a mut Exchange,
+    ) -> BoxFuture<'a, Result<Flow, InternalError>> {
+        Box::pin(async move {
+            let Some(model) = &exchange.model else {
+                return Ok(Flow::Continue);
+            };
+
+            let conf = ctx.data().conf();
+            let alerts = &conf.messages.alerts;
+
+            if !conf.ai_provider.override_models.contains(model) {
+                let embed = serenity::CreateEmbed::new()
+                    .title(messages::render(&alerts.model_unknown, &[("model", model)]));
+                send_ephemeral_embedded_reply(ctx, embed).await?;
+
+                return Ok(Flow::Halt);
+            }
+
+            let allowed_roles = conf
+                .guilds
+                .get(&exchange.guild)
+                .map(|guild_conf| guild_conf.model_override_roles.as_slice())
+                .unwrap_or_default();
+            let allowed = ctx.framework().options().owners.contains(&ctx.author().id)
+                || ctx.author_member().await.is_some_and(|member| {
+                    member
+                        .roles
+                        .iter()
+                        .any(|role| allowed_roles.contains(&role.get()))
+                });
+            if allowed {
+                return Ok(Flow::Continue);
+            }
+
+            let embed = serenity::CreateEmbed::new().title(&alerts.model_override_denied);
+            send_ephemeral_embedded_reply(ctx, embed).await?;
+
+            Ok(Flow::Halt)
+        })
+    }
+}
+
+/// Turns the attached voice message into text, appended to the content.
+struct Transcribe;
+
+impl Stage for Transcribe {
+    fn handle<'a>(
+        &'a self,
+        ctx: Context<'a>,
+        exchange: &'a mut Exchange,
+    ) -> BoxFuture<'a, Result<Flow, InternalError>> {
+        Box::pin(async move {
+            let data = ctx.data();
+            let conf = data.conf();
+            let alerts = &conf.messages.alerts;
+
+            let Some(voice) = &exchange.voice else {
+                if !exchange.content.trim().is_empty() {
+                    return Ok(Flow::Continue);
+                }
+
+                let embed = serenity::CreateEmbed::new().title(&alerts.prompt_empty);
+                send_ephemeral_embedded_reply(ctx, embed).await?;
+
+                return Ok(Flow::Halt);
+            };
+
+            let Some(stt) = data.stt.get() else {
+                let embed = serenity::CreateEmbed::new().title(&alerts.transcription_disabled);
+                send_ephemeral_embedded_reply(ctx, embed).await?;
+
+                return Ok(Flow::Halt);
+            };
+
+            let max_duration = conf.transcription.max_duration_secs;
+            let content_type = voice
+                .content_type
+                .as_deref()
+                .filter(|content_type| content_type.starts_with("audio/"));
+            let valid_duration = voice
+                .duration_secs
+                .is_some_and(|secs| secs <= max_duration as f64);
+            let (Some(content_type), true) = (content_type, valid_duration) else {
+                let embed = serenity::CreateEmbed::new().title(messages::render(
+                    &alerts.voice_invalid,
+                    &[("max", &max_duration)],
+                ));
+                send_ephemeral_embedded_reply(ctx, embed).await?;
+
+                return Ok(Flow::Halt);
+            };
+
+            // Private replies need the deferred response to be private too.
+            if data.settings(exchange.guild).private_replies {
+                ctx.defer_ephemeral().await?;
+            } else {
+                ctx.defer().await?;
+            }
+
+            let audio = voice.download().await?;
+            let transcript = stt
+                .transcribe(audio, voice.filename.clone(), content_type)
+                .await?;
+
+            if transcript.is_empty() && exchange.content.trim().is_empty() {
+                let embed = serenity::CreateEmbed::new().title(&alerts.prompt_empty);
+                send_ephemeral_embedded_reply(ctx, embed).await?;
+
+                return Ok(Flow::Halt);
+            }
+
+            if exchange.content.trim().is_empty() {
+                exchange.content = transcript.clone();
+            } else {
+                exchange.content = format!("{}\n\n{transcript}", exchange.content);
+            }
+            exchange.transcript = Some(transcript);
+
+            Ok(Flow::Continue)
+        })
+    }
+}
+
+/// Rejects prompts bigger than the configured size.
+struct SizeLimit;
+
+impl Stage for SizeLimit {
+    fn handle<'a>(
+        &'a self,
+        ctx: Context<'a>,
+        exchange: &'a mut Exchange,
+    ) -> BoxFuture<'a, Result<Flow, InternalError>> {
+        Box::pin(async move {
+            let data = ctx.data();
+            let max = data.prompt_size(ctx.guild_id().unwrap().get());
+
+            if exchange.content.chars().count() <= max as usize {
+                return Ok(Flow::Continue);
+            }
+
+            let embed = serenity::CreateEmbed::new().title(messages::render(
+                &data.conf().messages.alerts.prompt_too_long,
+                &[("max", &max)],
+            ));
+            send_embedded_reply(ctx, embed).await?;
+
+            Ok(Flow::Halt)
+        })
+    }
+}
+
+/// Warns members flooding near-duplicate prompts and then blocks them for a while.
+struct SpamGuard;
+
+impl Stage for SpamGuard {
+    fn handle<'a>(
+        &'a self,
+        ctx: Context<'a>,
+        exchange: &'a mut Exchange,
+    ) -> BoxFuture<'a, Result<Flow, InternalError>> {
+        Box::pin(async move {
+            let data = ctx.data();
+            let conf = data.conf();
+
+            if !conf.spam.enabled {
+                return Ok(Flow::Continue);
+            }
+
+            let alerts = &conf.messages.alerts;
+            let title =
+                match data
+                    .spam
+                    .check(&conf.spam, exchange.guild, exchange.user, &exchange.content)
+                {
+                    spam::Verdict::Allowed => return Ok(Flow::Continue),
+                    spam::Verdict::Warned { strikes } => {
+                        let title = messages::render(
+                            &alerts.spam_warning,
+                            &[("strikes", &strikes), ("max", &conf.spam.warnings)],
+                        );
+                        let embed = serenity::CreateEmbed::new().title(title);
+                        send_embedded_reply(ctx, embed).await?;
+
+                        return Ok(Flow::Continue);
+                    }
+                    spam::Verdict::Blocked { until, new } => {
+                        if new {
+                            log::info!(
+                                "blocked user {} in guild {} for prompt spam",
+                                exchange.user,
+                                exchange.guild
+                            );
+                            guard::notify_spam_block(
+                                ctx.serenity_context().http.clone(),
+                                data,
+                                exchange.guild,
+                                ctx.author().id,
+                                ctx.channel_id(),
+                                &exchange.content,
+                                until,
+                            );
+                        }
+
+                        messages::render(&alerts.spam_blocked, &[("date", &until.format("%v, %R"))])
+                    }
+                };
+
+            let embed = serenity::CreateEmbed::new().title(title);
+            send_ephemeral_embedded_reply(ctx, embed).await?;
+
+            Ok(Flow::Halt)
+        })
+    }
+}
+
+/// Holds prompts back while sessions are being flushed.
+///
+/// Otherwise, the prompt is marked as in flight until it leaves the pipeline,
+/// so a flush waits for its reply instead of losing it.
+struct FlushGuard;
+
+impl Stage for FlushGuard {
+    fn handle<'a>(
+        &'a self,
+        ctx: Context<'a>,
+        exchange: &'a mut Exchange,
+    ) -> BoxFuture<'a, Result<Flow, InternalError>> {
+        Box::pin(async move {
+            let data = ctx.data();
+
+            exchange.in_flight = Some(data.in_flight.enter());
+
+            if !data.is_flushing() {
+                return Ok(Flow::Continue);
+            }
+
+            exchange.in_flight = None;
+
+            let embed = serenity::CreateEmbed::new().title(&data.conf().messages.alerts.flushing);
+            send_embedded_reply(ctx, embed).await?;
+
+            Ok(Flow::Halt)
+        })
+    }
+}
+
+/// Strips surrounding whitespace from the prompt.
+struct Sanitize;
+
+impl Stage for Sanitize {
+    fn handle<'a>(
+        &'a self,
+        _ctx: Context<'a>,
+        exchange: &'a mut Exchange,
+    ) -> BoxFuture<'a, Result<Flow, InternalError>> {
+        Box::pin(async move {
+            let trimmed = exchange.content.trim();
+            if trimmed.len() != exchange.content.len() {
+                exchange.content = trimmed.to_string();
+            }
+
+            Ok(Flow::Continue)
+        })
+    }
+}
+
+/// Outcome of checking a prompt against the content level of the channel.
+pub(super) enum Policy {
+    /// Sent along with the instructions of the level, if any.
+    Allowed(Option<String>),
+    Refused,
+}
+
+pub(super) async fn content_policy(ctx: Context<'_>, prompt: &str) -> Policy {
+    let content = &ctx.data().conf().content;
+    let age_restricted = content.enabled && is_age_restricted(ctx).await;
+
+    match guard::content_instructions(content, age_restricted, prompt) {
+        Some(instructions) => Policy::Allowed(instructions),
+        None => Policy::Refused,
+    }
+}
+
+/// Applies the content level of the channel, refusing blocked terms in strict ones.
+struct ContentPolicy;
+
+impl Stage for ContentPolicy {
+    fn handle<'a>(
+        &'a self,
+        ctx: Context<'a>,
+        exchange: &'a mut Exchange,
+    ) -> BoxFuture<'a, Result<Flow, InternalError>> {
+        Box::pin(async move {
+            match content_policy(ctx, &exchange.content).await {
+                Policy::Allowed(policy) => {
+                    if policy.is_some() {
+                        exchange.policy = policy;
+                    }
+
+                    Ok(Flow::Continue)
+                }
+                Policy::Refused => {
+                    let embed = serenity::CreateEmbed::new()
+                        .title(&ctx.data().conf().messages.alerts.content_refused);
+                    send_ephemeral_embedded_reply(ctx, embed).await?;
+
+                    Ok(Flow::Halt)
+                }
+            }
+        })
+    }
+}
+
+/// Asks the model to reply in the language picked by the member or used in the prompt.
+struct LanguageHint;
+
+impl Stage for LanguageHint {
+    fn handle<'a>(
+        &'a self,
+        ctx: Context<'a>,
+        exchange: &'a mut Exchange,
+    ) -> BoxFuture<'a, Result<Flow, InternalError>> {
+        Box::pin(async move {
+            let Some(hint) =
+                guard::language_hint(ctx.data(), exchange.guild, exchange.user, &exchange.content)
+            else {
+                return Ok(Flow::Continue);
+            };
+
+            exchange.policy = Some(match exchange.policy.take() {
+                Some(policy) => format!("{policy}\n\n{hint}"),
+                None => hint,
+            });
+
+            Ok(Flow::Continue)
+        })
+    }
+}
+
+/// Adds the recent messages of the channel to the instructions, when asked for.
+struct ChannelContext;
+
+impl Stage for ChannelContext {
+    fn handle<'a>(
+        &'a self,
+        ctx: Context<'a>,
+        exchange: &'a mut Exchange,
+    ) -> BoxFuture<'a, Result<Flow, InternalError>> {
+        Box::pin(async move {
+            if !exchange.channel_context {
+                return Ok(Flow::Continue);
+            }
+
+            let conf = ctx.data().conf();
+            let alerts = &conf.messages.alerts;
+
+            let Some(limits) = conf.channel_context(exchange.guild) else {
+                let embed = serenity::CreateEmbed::new().title(&alerts.channel_context_disabled);
+                send_ephemeral_embedded_reply(ctx, embed).await?;
+
+                return Ok(Flow::Halt);
+            };
+
+            if !channel_context::can_read_history(ctx).await {
+                let embed = serenity::CreateEmbed::new().title(&alerts.channel_context_denied);
+                send_ephemeral_embedded_reply(ctx, embed).await?;
+
+                return Ok(Flow::Halt);
+            }
+
+            let Some(transcript) = channel_context::transcript(ctx, limits).await? else {
+                return Ok(Flow::Continue);
+            };
+
+            let context = format!(
+                "Recent messages of the channel the user is writing from, oldest first:\n\
+                 {transcript}"
+            );
+            exchange.policy = Some(match exchange.policy.take() {
+                Some(policy) => format!("{policy}\n\n{context}"),
+                None => context,
+            });
+
+            Ok(Flow::Continue)
+        })
+    }
+}
+
+/// Picks the session and prefixes the speaker name on shared channels.
+struct Template;
+
+impl Stage for Template {
+    fn handle<'a>(
+        &'a self,
+        ctx: Context<'a>,
+        exchange: &'a mut Exchange,
+    ) -> BoxFuture<'a, Result<Flow, InternalError>> {
+        Box::pin(async move {
+            let data = ctx.data();
+
+            let session = if data.is_shared_channel(exchange.guild, exchange.channel) {
+                let speaker = ctx.author().display_name();
+                exchange.content = format!("{speaker}: {}", exchange.content);
+
+                data.channel_session(exchange.guild, exchange.channel)
+            } else {
+                data.session(exchange.guild, exchange.user)
+            };
+            exchange.session = Some(session);
+
+            Ok(Flow::Continue)
+        })
+    }
+}
+
+/// Bounds the prompts waiting for the same session, refusing those past the queue depth.
+struct SessionQueue;
+
+impl Stage for SessionQueue {
+    fn handle<'a>(
+        &'a self,
+        ctx: Context<'a>,
+        exchange: &'a mut Exchange,
+    ) -> BoxFuture<'a, Result<Flow, InternalError>> {
+        Box::pin(async move {
+            let data = ctx.data();
+            let Some(session) = &exchange.session else {
+                return Ok(Flow::Halt);
+            };
+
+            let conf = data.conf();
+            exchange.queue_slot = session.enqueue(ctx.id(), conf.chat.queue_depth);
+            if exchange.queue_slot.is_some() {
+                let prompt = status::Prompt {
+                    guild: exchange.guild,
+                    user: exchange.user,
+                    content: exchange.content.clone(),
+                    started: chrono::Utc::now(),
+                    session: session.clone(),
+                    cancel: Arc::default(),
+                };
+                data.prompts.insert(ctx.id(), prompt);
+
+                return Ok(Flow::Continue);
+            }
+
+            let embed =
+                serenity::CreateEmbed::new().title(&conf.messages.alerts.request_in_progress);
+            send_ephemeral_embedded_reply(ctx, embed).await?;
+
+            Ok(Flow::Halt)
+        })
+    }
+}
+
+/// Answers an edited prompt message again, replacing its interaction when still in history.
+struct EditedPrompt;
+
+impl Stage for EditedPrompt {
+    fn handle<'a>(
+        &'a self,
+        ctx: Context<'a>,
+        exchange: &'a mut Exchange,
+    ) -> BoxFuture<'a, Result<Flow, InternalError>> {
+        Box::pin(async move {
+            let data = ctx.data();
+            let Some(message) = edits::edited_message(ctx) else {
+                return Ok(Flow::Continue);
+            };
+            let (Some(session), Some(answered)) =
+                (&exchange.session, data.answered_prompts.get(message))
+            else {
+                return Ok(Flow::Continue);
+            };
+
+            // The member may have switched sessions since, which then just gets the prompt.
+            if Arc::ptr_eq(&answered.session.session, &session.session)
+                && session
+                    .session
+                    .lock()
+                    .await
+                    .position(answered.interaction)
+                    .is_some()
+            {
+                exchange.branch = Some(answered.interaction);
+            }
+            exchange.edited = Some(answered);
+
+            Ok(Flow::Continue)
+        })
+    }
+}
+
+/// Picks the provider and model of the prompt from the routes of the config.
+struct Routing;
+
+impl Stage for Routing {
+    fn handle<'a>(
+        &'a self,
+        ctx: Context<'a>,
+        exchange: &'a mut Exchange,
+    ) -> BoxFuture<'a, Result<Flow, InternalError>> {
+        Box::pin(async move {
+            let (Some(routes), Some(session)) = (ctx.data().routes.get(), &exchange.session) else {
+                return Ok(Flow::Continue);
+            };
+            // Prompts picking a model keep it, as do both arms of a running experiment so their
+            // stats stay apart.
+            if exchange.model.is_some() || ctx.data().conf().experiment.share > 0 {
+                return Ok(Flow::Continue);
+            }
+
+            // Sessions with a model of their own, like variant ones of a past experiment, keep it.
+            let instructions = {
+                let session = session.session.lock().await;
+                if session.model().is_some() {
+                    return Ok(Flow::Continue);
+                }
+
+                session.instructions().map(str::to_string)
+            };
+            exchange.target =
+                routes.pick(exchange.guild, instructions.as_deref(), &exchange.content);
+
+            Ok(Flow::Continue)
+        })
+    }
+}
+
+/// Bounds the reply to what its delivery keeps, so no tokens are spent on text that'd be cut.
+fn reply_max_tokens(max_chars: usize, overflow: config::Overflow) -> Option<u32> {
+    match overflow {
+        config::Overflow::Truncate => Some(chat::max_tokens_for(max_chars)),
+        config::Overflow::Summarize => {
+            Some(chat::max_tokens_for(max_chars * SUMMARIZED_REPLY_FACTOR))
+        }
+        // The whole reply is attached or posted, however long it is.
+        config::Overflow::AttachFile | config::Overflow::Thread => None,
+    }
+}
+
+/// Sends the prompt to the model and accounts its usage.
+struct ProviderCall;
+
+impl Stage for ProviderCall {
+    fn handle<'a>(
+        &'a self,
+        ctx: Context<'a>,
+        exchange: &'a mut Exchange,
+    ) -> BoxFuture<'a, Result<Flow, InternalError>> {
+        Box::pin(async move {
+            let data = ctx.data();
+            let Some(session) = &exchange.session else {
+                return Ok(Flow::Halt);
+            };
+
+            data.usage.record_prompt(exchange.guild, exchange.user);
+            data.experiment.record_prompt(session.arm);
+
+            let cancel = data
+                .prompts
+                .get(&ctx.id())
+                .map(|prompt| prompt.cancel.clone())
+                .unwrap_or_default();
+            let (max_chars, overflow) = data.conf().response_limit(exchange.guild);
+            let max_tokens = reply_max_tokens(max_chars, overflow);
+            let content = exchange.content.clone();
+            let policy = exchange.policy.clone();
+            let model = exchange.model.as_deref();
+            let capture = data.take_capture(exchange.user);
+            let lane = lanes::lane_of(ctx, exchange.guild).await;
+            let (stream, chunks) = reply_stream(ctx, exchange).unzip();
+            // Replies overflowing into a thread go on streaming there.
+            let thread_name =
+                matches!(overflow, config::Overflow::Thread).then(|| exchange.content.clone());
+            let extras = Extras {
+                capture: capture.is_some(),
+                stream,
+                target: exchange.target.clone(),
+            };
+            let mut placeholder = None;
+            // Provider calls wait for room in the lane of the author.
+            let sent = lanes::in_lane(lane, async {
+                match exchange.branch {
+                    Some(id) => {
+                        session
+                            .branch(id, content, policy, model, max_tokens, extras)
+                            .await
+                    }
+                    None => session
+                        .send_message(content, policy, model, max_tokens, extras)
+                        .await
+                        .map(Some),
+                }
+            });
+            // The stream closes along the request, once the session drops its sender.
+            let sent = async {
+                let shown = show_stream(ctx, chunks, thread_name.as_deref(), &mut placeholder);
+                let (sent, ()) = tokio::join!(sent, shown);
+
+                sent
+            };
+
+            // Dropping the request leaves the session as it was before the prompt.
+            let sent = tokio::select! {
+                sent = sent => sent,
+                _ = cancel.notified() => {
+                    discard_placeholder(ctx, placeholder).await;
+                    let embed = serenity::CreateEmbed::new()
+                        .title(&data.conf().messages.alerts.prompt_cancelled);
+                    send_embedded_reply(ctx, embed).await?;
+
+                    return Ok(Flow::Halt);
+                }
+            };
+            if sent.as_ref().map_or(true, Option::is_none) {
+                discard_placeholder(ctx, placeholder.take()).await;
+            }
+
+            let (response, exchanged) = match sent {
+                Ok(Some(sent)) => sent,
+                Ok(None) => {
+                    let index = exchange.branch.unwrap_or_default();
+                    let embed = serenity::CreateEmbed::new().title(messages::render(
+                        &data.conf().messages.history.missing,
+                        &[("index", &index)],
+                    ));
+                    send_ephemeral_embedded_reply(ctx, embed).await?;
+
+                    return Ok(Flow::Halt);
+                }
+                Err(chat::Error::Vetoed(reason)) => {
+                    let embed = serenity::CreateEmbed::new().title(messages::render(
+                        &data.conf().messages.alerts.prompt_vetoed,
+                        &[("reason", &reason)],
+                    ));
+                    send_embedded_reply(ctx, embed).await?;
+
+                    return Ok(Flow::Halt);
+                }
+                Err(err) => {
+                    data.usage.record_error(exchange.guild);
+                    data.experiment.record_error(session.arm);
+
+                    // Refused prompts would only be refused again.
+                    if !err.failure().is_retryable() {
+                        return Err(Box::from(err));
+                    }
+
+                    log::error!("failed to get a reply: {err}");
+                    report::error(report_context(&ctx), &err);
+                    let alerts = &data.conf().messages.alerts;
+                    exchange.undelivered = Some(failure_alert(alerts, &err).to_string());
+
+                    return Ok(Flow::Halt);
+                }
+            };
+
+            if let (Some(owner), Some(captured)) = (capture, &response.capture) {
+                capture::save(
+                    ctx,
+                    owner,
+                    exchange.guild,
+                    exchange.user,
+                    &exchange.content,
+                    captured,
+                )
+                .await;
+            }
+
+            data.usage.record_tokens(
+                exchange.guild,
+                &response.model,
+                Some(session.arm),
+                response.usage,
+            );
+            data.experiment.record_tokens(session.arm, response.usage);
+            let alert = data.throughput.record(
+                &data.conf().observability,
+                response.usage.output_tokens,
+                response.elapsed,
+            );
+            if let Some(alert) = alert {
+                notify_throughput(ctx, alert);
+            }
+            exchange.response = Some(response);
+            exchange.exchanged = exchanged;
+            exchange.placeholder = placeholder;
+
+            Ok(Flow::Continue)
+        })
+    }
+}
+
+/// Stream the reply is shown through once the provider takes longer than the latency budget,
+/// unless it's delivered where the bot can't edit it or replaces an earlier one.
+fn reply_stream(
+    ctx: Context<'_>,
+    exchange: &Exchange,
+) -> Option<(chat::Stream, mpsc::UnboundedReceiver<String>)> {
+    let data = ctx.data();
+    let conf = data.conf();
+    let private = matches!(ctx, poise::Context::Application(_))
+        && data.settings(exchange.guild).private_replies;
+    if conf.chat.latency_budget_secs == 0
+        || private
+        || conf.webhook(exchange.guild).is_some()
+        || exchange.edited.is_some()
+    {
+        return None;
+    }
+
+    let (chunks, received) = mpsc::unbounded_channel();
+    let stream = chat::Stream {
+        budget: Duration::from_secs(conf.chat.latency_budget_secs.into()),
+        chunks,
+    };
+
+    Some((stream, received))
+}
+
+/// Posts a placeholder once the reply is asked as a stream, then edits in the answer written so
+/// far until the stream closes.
+///
+/// Given a thread name, an answer longer than the placeholder goes on in a thread started off
+/// it, the placeholder pointing there.
+async fn show_stream(
+    ctx: Context<'_>,
+    chunks: Option<mpsc::UnboundedReceiver<String>>,
+    thread_name: Option<&str>,
+    placeholder: &mut Option<Placeholder>,
+) {
+    let Some(mut chunks) = chunks else {
+        return;
+    };
+    let limit = config::DISCORD_MESSAGE_LIMIT as usize;
+    let mut written = String::new();
+    let mut shown = String::new();
+    let mut shown_at: Option<Instant> = None;
+
+    while let Some(chunk) = chunks.recv().await {
+        written.push_str(&chunk);
+        if shown_at.is_some_and(|at| at.elapsed() < STREAM_EDIT_INTERVAL) {
+            continue;
+        }
+        shown_at = Some(Instant::now());
+
+        let conf = ctx.data().conf();
+        let answer = chat::streamed_answer(&written);
+        let pages = answer
+            .filter(|answer| thread_name.is_some() && answer.chars().count() > limit)
+            .map(|answer| code::split_reply(answer, limit));
+        let content = match (answer, &pages) {
+            (Some(_), Some(_)) => conf.messages.alerts.thread_pointer.clone(),
+            (Some(answer), None) => truncate_reply(answer, limit),
+            (None, _) => conf.messages.alerts.streaming.clone(),
+        };
+
+        match placeholder {
+            None => {
+                let reply = poise::CreateReply::default()
+                    .reply(true)
+                    .content(&content)
+                    .allowed_mentions(allowed_mentions(ctx));
+                let posted = match ctx.send(reply).await {
+                    Ok(handle) => handle.into_message().await,
+                    Err(err) => Err(err),
+                };
+                match posted {
+                    Ok(message) => {
+                        *placeholder = Some(Placeholder {
+                            message,
+                            thread: None,
+                        });
+                    }
+                    Err(err) => {
+                        log::warn!("failed to post placeholder of streamed reply: {err}");
+
+                        return;
+                    }
+                }
+            }
+            Some(placeholder) if content != shown => {
+                let edit = serenity::EditMessage::new()
+                    .content(&content)
+                    .allowed_mentions(allowed_mentions(ctx));
+                if let Err(err) = placeholder.message.edit(ctx, edit).await {
+                    log::warn!("failed to show streamed reply: {err}");
+                }
+            }
+            Some(_) => (),
+        }
+        shown = content;
+
+        if let (Some(pages), Some(name), Some(placeholder)) =
+            (pages, thread_name, placeholder.as_mut())
+        {
+            let posted = post_in_thread(
+                ctx,
+                &placeholder.message,
+                name,
+                &mut placeholder.thread,
+                &pages,
+            );
+            if let Err(err) = posted.await {
+                log::warn!("failed to show streamed reply in its thread: {err}");
+            }
+        }
+    }
+}
+
+/// Deletes the placeholder of a streamed reply that won't be delivered.
+async fn discard_placeholder(ctx: Context<'_>, placeholder: Option<Placeholder>) {
+    if let Some(placeholder) = placeholder {
+        delete_pages(ctx, placeholder.thread).await;
+        if let Err(err) = placeholder.message.delete(ctx).await {
+            log::warn!("failed to delete placeholder of streamed reply: {err}");
+        }
+    }
+}
+
+/// Logs and reports the provider getting slow or fast again.
+fn notify_throughput(ctx: Context<'_>, alert: throughput::Alert) {
+    match alert {
+        throughput::Alert::Degraded { rate, requests } => {
+            let message = format!(
+                "provider is degraded, {requests} replies in a row were generated below the \
+                 configured speed, the last at {rate:.1} tokens/s"
+            );
+            log::warn!("{message}");
+            report::message(report_context(&ctx), &message);
+        }
+        throughput::Alert::Recovered { rate } => {
+            log::info!("provider recovered, last reply generated at {rate:.1} tokens/s");
+        }
+    }
+}
+
+/// Wraps the reply in an embed labelling it as AI-generated, along with what generated it.
+///
+/// The persona is left out, as its instructions may not be meant for everyone to read.
+pub(super) fn reply_embed(
+    conf: &config::App,
+    model: &str,
+    usage: chat::Usage,
+    body: &str,
+) -> serenity::CreateEmbed {
+    let messages = &conf.messages.reply_embed;
+    let tokens = messages::render(
+        &messages.tokens_value,
+        &[
+            ("input", &usage.input_tokens),
+            ("output", &usage.output_tokens),
+        ],
+    );
+
+    let embed = apply_theme(&conf.appearance, serenity::CreateEmbed::new())
+        .description(truncate_chars(body, EMBED_DESCRIPTION_LIMIT))
+        .field(&messages.model, format!("`{model}`"), true)
+        .field(&messages.tokens, tokens, true);
+
+    // Replaces the themed footer, the disclaimer must always show.
+    embed.footer(serenity::CreateEmbedFooter::new(&messages.disclaimer))
+}
+
+/// Truncates the reply, closing the code block it may cut through.
+fn truncate_reply(reply: &str, limit: usize) -> String {
+    let truncated = truncate_chars(reply, limit);
+    if !code::has_open_fence(&truncated) {
+        return truncated;
+    }
+
+    let mut truncated = truncate_chars(reply, limit.saturating_sub(CLOSING_FENCE.len()));
+    truncated.push_str(CLOSING_FENCE);
+
+    truncated
+}
+
+/// Posts the pages of a long reply in a thread started off the message, unless one already was,
+/// replacing the pages posted there earlier.
+///
+/// Each page is tried twice. The pages posted are kept along the thread as they go, so they can
+/// be rolled back if one fails.
+async fn post_in_thread(
+    ctx: Context<'_>,
+    message: &serenity::Message,
+    name: &str,
+    thread: &mut Option<ThreadPages>,
+    pages: &[String],
+) -> Result<(), serenity::Error> {
+    let thread = match thread {
+        Some(thread) => thread,
+        None => {
+            let name = truncate_chars(name.lines().next().unwrap_or_default(), THREAD_NAME_LIMIT);
+            let started = message
+                .channel_id
+                .create_thread_from_message(ctx, message.id, serenity::CreateThread::new(name))
+                .await?;
+
+            thread.insert(ThreadPages {
+                thread: started.id,
+                pages: Vec::new(),
+            })
+        }
+    };
+
+    let channel = thread.thread;
+    for (index, page) in pages.iter().enumerate() {
+        if let Some(posted) = thread.pages.get_mut(index) {
+            if posted.content != *page {
+                let edit = serenity::EditMessage::new()
+                    .content(page)
+                    .allowed_mentions(allowed_mentions(ctx));
+                posted.edit(ctx, edit).await?;
+            }
+            continue;
+        }
+
+        let post = || {
+            let builder = serenity::CreateMessage::new()
+                .content(page)
+                .allowed_mentions(allowed_mentions(ctx));
+            channel.send_message(ctx, builder)
+        };
+        let posted = match post().await {
+            Ok(posted) => posted,
+            Err(err) => {
+                log::warn!("failed to post part of a reply in its thread, retrying: {err}");
+
+                post().await?
+            }
+        };
+        thread.pages.push(posted);
+    }
+
+    // Left over by a streamed reply that ended up shorter.
+    let surplus = thread.pages.split_off(pages.len().min(thread.pages.len()));
+    for page in surplus {
+        if let Err(err) = page.delete(ctx).await {
+            log::warn!("failed to delete leftover part of a reply in its thread: {err}");
+        }
+    }
+
+    Ok(())
+}
+
+/// Deletes the pages posted in the thread of a reply that won't be delivered.
+async fn delete_pages(ctx: Context<'_>, thread: Option<ThreadPages>) {
+    for page in thread.into_iter().flat_map(|thread| thread.pages) {
+        if let Err(err) = page.delete(ctx).await {
+            log::warn!("failed to delete part of an undelivered reply in its thread: {err}");
+        }
+    }
+}
+
+/// Warns that the session of the exchange is flushed within the hour, unless it's pinned.
+fn flush_warning(ctx: Context<'_>, exchange: &Exchange) -> Option<String> {
+    let data = ctx.data();
+    let conf = data.conf();
+    if !conf.chat.flush_warning {
+        return None;
+    }
+
+    let next_flush = data.next_flush();
+    if next_flush - chrono::Utc::now() > FLUSH_WARNING_WINDOW {
+        return None;
+    }
+
+    let pinned = !data.is_shared_channel(exchange.guild, exchange.channel)
+        && data.pins.contains_key(&(
+            exchange.guild,
+            exchange.user,
+            data.selected_session_name(exchange.guild, exchange.user),
+        ));
+    if pinned {
+        return None;
+    }
+
+    let time = format!("<t:{}:R>", next_flush.timestamp());
+
+    Some(messages::render(
+        &conf.messages.alerts.flush_warning,
+        &[("time", &time)],
+    ))
+}
+
+/// How a reply message is posted.
+#[derive(Clone, Copy)]
+enum Poster<'a> {
+    /// Answering the command, visible to its author only when private.
+    Command { private: bool },
+    /// Through the webhook, as the given speaker name and avatar.
+    Webhook(&'a serenity::Webhook, &'a str, Option<&'a str>),
+}
+
+/// Whether the slash command can no longer be answered, as its interaction token expired.
+fn interaction_expired(ctx: Context<'_>) -> bool {
+    matches!(ctx, poise::Context::Application(_))
+        && chrono::Utc::now() - *ctx.created_at() > INTERACTION_TOKEN_LIFETIME
+}
+
+/// Posts a reply in the channel of a slash command that can no longer be answered.
+///
+/// The author is mentioned, as the message isn't tied to their command. Private replies
+/// are sent by DM instead, without the mention.
+async fn send_late_reply(
+    ctx: Context<'_>,
+    private: bool,
+    content: String,
+    embed: Option<serenity::CreateEmbed>,
+    attachment: Option<serenity::CreateAttachment>,
+    components: Vec<serenity::CreateActionRow>,
+) -> Result<serenity::Message, serenity::Error> {
+    let notice = messages::render(
+        &ctx.data().conf().messages.alerts.late_reply,
+        &[("user", &ctx.author().mention())],
+    );
+    let limit = config::DISCORD_MESSAGE_LIMIT as usize;
+
+    let content = if private {
+        content
+    } else if content.chars().count() + notice.chars().count() < limit {
+        format!("{notice}\n{content}")
+    } else {
+        ctx.channel_id()
+            .send_message(
+                ctx,
+                serenity::CreateMessage::new()
+                    .content(notice)
+                    .allowed_mentions(allowed_mentions(ctx)),
+            )
+            .await?;
+
+        content
+    };
+    let mut message = serenity::CreateMessage::new();
+    if !content.is_empty() {
+        message = message.content(content);
+    }
+    if let Some(embed) = embed {
+        message = message.embed(embed);
+    }
+    if let Some(attachment) = attachment {
+        message = message.add_file(attachment);
+    }
+    if !components.is_empty() {
+        message = message.components(components);
+    }
+    message = message.allowed_mentions(allowed_mentions(ctx));
+
+    if private {
+        ctx.author().direct_message(ctx, message).await
+    } else {
+        ctx.channel_id().send_message(ctx, message).await
+    }
+}
+
+/// Posts a reply message.
+///
+/// The message is only returned when tracked, as fetching it may take another request.
+async fn send_reply(
+    ctx: Context<'_>,
+    poster: Poster<'_>,
+    content: String,
+    embed: Option<serenity::CreateEmbed>,
+    attachment: Option<serenity::CreateAttachment>,
+    components: Vec<serenity::CreateActionRow>,
+    track: bool,
+) -> Result<Option<serenity::Message>, serenity::Error> {
+    let Poster::Webhook(webhook, name, avatar_url) = poster else {
+        let private = matches!(poster, Poster::Command { private: true });
+        if interaction_expired(ctx) {
+            log::info!("interaction {} expired, replying in its channel", ctx.id());
+            let message =
+                send_late_reply(ctx, private, content, embed, attachment, components).await?;
+
+            return Ok(track.then_some(message));
+        }
+
+        let mut reply = poise::CreateReply::default()
+            .reply(true)
+            .ephemeral(private)
+            .allowed_mentions(allowed_mentions(ctx));
+        if !content.is_empty() {
+            reply = reply.content(content);
+        }
+        if let Some(embed) = embed {
+            reply = reply.embed(embed);
+        }
+        if let Some(attachment) = attachment {
+            reply = reply.attachment(attachment);
+        }
+        if !components.is_empty() {
+            reply = reply.components(components);
+        }
+
+        let handle = ctx.send(reply).await?;
+        if !track {
+            return Ok(None);
+        }
+
+        return match handle.message().await {
+            Ok(message) => Ok(Some(message.into_owned())),
+            Err(err) => {
+                log::warn!("failed to fetch reply to track it: {err}");
+
+                Ok(None)
+            }
+        };
+    };
+
+    let mut builder = serenity::ExecuteWebhook::new().username(name);
+    if let Some(avatar_url) = avatar_url {
+        builder = builder.avatar_url(avatar_url);
+    }
+    if !content.is_empty() {
+        builder = builder.content(content);
+    }
+    if let Some(embed) = embed {
+        builder = builder.embed(embed);
+    }
+    if let Some(attachment) = attachment {
+        builder = builder.add_file(attachment);
+    }
+    if !components.is_empty() {
+        builder = builder.components(components);
+    }
+    builder = builder.allowed_mentions(allowed_mentions(ctx));
+
+    webhook.execute(ctx, true, builder).await
+}
+
+/// Posts the rest of a split reply, in order, trying each part twice.
+///
+/// The parts posted are pushed as they go, so they can be rolled back if one fails.
+async fn send_parts(
+    ctx: Context<'_>,
+    poster: Poster<'_>,
+    parts: Vec<String>,
+    posted: &mut Vec<serenity::MessageId>,
+) -> Result<(), serenity::Error> {
+    for part in parts {
+        let sent = match send_reply(ctx, poster, part.clone(), None, None, Vec::new(), true).await {
+            Ok(sent) => sent,
+            Err(err) => {
+                log::warn!("failed to deliver part of a split reply, retrying: {err}");
+
+                send_reply(ctx, poster, part, None, None, Vec::new(), true).await?
+            }
+        };
+        posted.extend(sent.map(|sent| sent.id));
+    }
+
+    Ok(())
+}
+
+/// Deletes messages posted for a reply, through the webhook they were posted with if any.
+async fn delete_reply(ctx: Context<'_>, poster: Poster<'_>, messages: &[serenity::MessageId]) {
+    for &message in messages {
+        let deleted = match poster {
+            Poster::Webhook(webhook, ..) => webhook.delete_message(ctx, None, message).await,
+            Poster::Command { .. } => ctx.channel_id().delete_message(ctx, message).await,
+        };
+        if let Err(err) = deleted {
+            log::warn!("failed to delete message {message} of an undelivered reply: {err}");
+        }
+    }
+}
+
+/// Replaces a reply in place, through the webhook it was posted with if any.
+async fn edit_reply(
+    ctx: Context<'_>,
+    reply: serenity::MessageId,
+    webhook: Option<&serenity::Webhook>,
+    content: String,
+    embed: Option<serenity::CreateEmbed>,
+    attachment: Option<serenity::CreateAttachment>,
+    components: Vec<serenity::CreateActionRow>,
+) -> Result<serenity::Message, serenity::Error> {
+    let embeds = embed.into_iter().collect();
+
+    match webhook {
+        Some(webhook) => {
+            let mut builder = serenity::EditWebhookMessage::new()
+                .content(content)
+                .embeds(embeds)
+                .components(components)
+                .clear_attachments();
+            if let Some(attachment) = attachment {
+                builder = builder.new_attachment(attachment);
+            }
+
+            webhook.edit_message(ctx, reply, builder).await
+        }
+        None => {
+            let mut builder = serenity::EditMessage::new()
+                .content(content)
+                .embeds(embeds)
+                .components(components)
+                .remove_all_attachments()
+                .allowed_mentions(allowed_mentions(ctx));
+            if let Some(attachment) = attachment {
+                builder = builder.new_attachment(attachment);
+            }
+
+            ctx.channel_id().edit_message(ctx, reply, builder).await
+        }
+    }
+}
+
+/// Reply shaped to fit the response limit of its guild, see [`fit_reply`].
+pub(super) struct Fitted {
+    pub body: String,
+    /// The whole reply, attached to the cut one.
+    pub attachment: Option<serenity::CreateAttachment>,
+    /// Pages of a reply going on in a thread, the body pointing there.
+    pub thread_parts: Vec<String>,
+}
+
+/// Fits the reply in `budget` characters as the overflow mode says, going on in a thread only
+/// when `threads` allows it.
+pub(super) async fn fit_reply(
+    data: &BotData,
+    guild: GuildId,
+    session: &ChatSession,
+    response: &chat::Response,
+    budget: usize,
+    overflow: config::Overflow,
+    threads: bool,
+) -> Fitted {
+    let conf = data.conf();
+    let content = if conf.code.format_fences {
+        code::format_fences(&response.content)
+    } else {
+        response.content.clone()
+    };
+    let mut fitted = Fitted {
+        body: String::new(),
+        attachment: None,
+        thread_parts: Vec::new(),
+    };
+    if content.chars().count() <= budget {
+        fitted.body = content;
+
+        return fitted;
+    }
+
+    fitted.body = match overflow {
+        config::Overflow::Truncate => truncate_reply(&content, budget),
+        config::Overflow::Summarize => {
+            // Nothing of the session is needed, so it isn't held meanwhile.
+            match data.sbuilder.shorten(&response.content, budget).await {
+                Ok(shortened) => {
+                    data.usage.record_tokens(
+                        guild,
+                        &shortened.model,
+                        Some(session.arm),
+                        shortened.usage,
+                    );
+
+                    let shortened = shortened.content.trim();
+                    if conf.code.format_fences {
+                        truncate_reply(&code::format_fences(shortened), budget)
+                    } else {
+                        truncate_reply(shortened, budget)
+                    }
+                }
+                Err(err) => {
+                    log::warn!("failed to shorten reply, truncating it instead: {err}");
+
+                    truncate_reply(&content, budget)
+                }
+            }
+        }
+        config::Overflow::AttachFile => {
+            fitted.attachment = Some(serenity::CreateAttachment::bytes(
+                response.content.as_bytes(),
+                RESPONSE_FILE,
+            ));
+
+            truncate_reply(&content, budget)
+        }
+        config::Overflow::Thread if threads => {
+            fitted.thread_parts =
+                code::split_reply(&content, config::DISCORD_MESSAGE_LIMIT as usize);
+
+            conf.messages.alerts.thread_pointer.clone()
+        }
+        config::Overflow::Thread => truncate_reply(&content, budget),
+    };
+
+    fitted
+}
+
+/// Lays the fitted body out as the guild wants it, in an embed, split at code blocks or as is,
+/// returning the content, the embed and the messages the rest goes in.
+pub(super) fn compose_reply(
+    conf: &config::App,
+    guild: GuildId,
+    model: &str,
+    usage: chat::Usage,
+    header: String,
+    body: &str,
+    footer: &str,
+) -> (String, Option<serenity::CreateEmbed>, Vec<String>) {
+    if conf.embed_replies(guild) {
+        let embed = reply_embed(conf, model, usage, body);
+        let content = header + footer.trim_start();
+
+        (content.trim_end().to_string(), Some(embed), Vec::new())
+    } else if conf.code.split_replies {
+        let mut parts = code::split_code(body);
+        if let Some(last) = parts.last_mut() {
+            last.push_str(footer);
+        }
+        let mut parts = parts.into_iter();
+        let first = parts.next().unwrap_or_default();
+
+        (header + &first, None, parts.collect())
+    } else {
+        (header + body + footer, None, Vec::new())
+    }
+}
+
+/// Replies with the model response and tracks it for reactions and titles.
+struct Deliver;
+
+impl Stage for Deliver {
+    fn handle<'a>(
+        &'a self,
+        ctx: Context<'a>,
+        exchange: &'a mut Exchange,
+    ) -> BoxFuture<'a, Result<Flow, InternalError>> {
+        Box::pin(async move {
+            let data = ctx.data();
+            let conf = data.conf();
+            let (Some(session), Some(response)) = (&exchange.session, &exchange.response) else {
+                return Ok(Flow::Halt);
+            };
+
+            let (max_chars, overflow) = conf.response_limit(exchange.guild);
+
+            let header = match &exchange.transcript {
+                Some(transcript) => {
+                    let transcript = messages::render(
+                        &conf.messages.alerts.voice_transcript,
+                        &[("transcript", transcript)],
+                    );
+
+                    format!("{}\n\n", truncate_chars(&transcript, max_chars / 2))
+                }
+                None => String::new(),
+            };
+            let footer = flush_warning(ctx, exchange)
+                .map(|warning| format!("\n\n{warning}"))
+                .unwrap_or_default();
+            let budget = max_chars
+                .saturating_sub(header.chars().count())
+                .saturating_sub(footer.chars().count());
+
+            // Only slash commands answer privately, prefix ones reply to a public message.
+            let private = matches!(ctx, poise::Context::Application(_))
+                && data.settings(exchange.guild).private_replies;
+            let Fitted {
+                body,
+                attachment,
+                thread_parts,
+            } = fit_reply(
+                data,
+                exchange.guild,
+                session,
+                response,
+                budget,
+                overflow,
+                !private,
+            )
+            .await;
+
+            let (persona, session_model) = {
+                let session = session.session.lock().await;
+
+                (
+                    session.instructions().map(str::to_string),
+                    session.model().map(str::to_string),
+                )
+            };
+            let model = exchange
+                .model
+                .clone()
+                .or_else(|| exchange.target.as_ref().map(|target| target.model.clone()))
+                .or(session_model)
+                .unwrap_or_else(|| data.sbuilder.model());
+            let (content, embed, mut extra_parts) = compose_reply(
+                &conf,
+                exchange.guild,
+                &model,
+                response.usage,
+                header,
+                &body,
+                &footer,
+            );
+
+            let mut webhook = match conf.webhook(exchange.guild).filter(|_| !private) {
+                Some(webhook_conf) => webhooks::channel_webhook(ctx)
+                    .await
+                    .map(|webhook| (webhook, webhook_conf.speaker(persona.as_deref()))),
+                None => None,
+            };
+            let mut poster = match &webhook {
+                Some((webhook, (name, avatar_url))) => Poster::Webhook(webhook, name, *avatar_url),
+                None => Poster::Command { private },
+            };
+            let reasoning = response
+                .reasoning
+                .as_ref()
+                .filter(|_| conf.chat.show_reasoning);
+            let components = match reasoning {
+                Some(_) => vec![reasoning::button_row(data)],
+                None => Vec::new(),
+            };
+            // Private replies can't be reacted to, nor edited by follow-ups.
+            let track_reactions = conf.reactions.enabled && !private;
+            let suggest_followups = conf.followups.enabled && !private;
+            let track = track_reactions || suggest_followups || reasoning.is_some();
+            let remember = edits::followed(ctx);
+            // Pages streamed into a thread are replaced by the delivered ones.
+            let mut thread = exchange
+                .placeholder
+                .as_mut()
+                .and_then(|placeholder| placeholder.thread.take());
+            // The thread is started off the reply and split ones are deleted if left partial,
+            // so it's needed either way.
+            let fetch = track
+                || remember.is_some()
+                || !thread_parts.is_empty()
+                || thread.is_some()
+                || !extra_parts.is_empty();
+            // Edited prompts replace their earlier reply and streamed ones their placeholder.
+            let replaced = match (&exchange.edited, &exchange.placeholder) {
+                (Some(edited), _) => Some((edited.reply, edited.webhook.as_ref())),
+                (None, Some(placeholder)) => Some((placeholder.message.id, None)),
+                (None, None) => None,
+            };
+            // Kept to post the reply again if its webhook turns out deleted.
+            let resend = webhook.is_some().then(|| {
+                (
+                    content.clone(),
+                    embed.clone(),
+                    attachment.clone(),
+                    components.clone(),
+                )
+            });
+            let mut sent = match replaced {
+                Some((reply, webhook)) => {
+                    let edit = edit_reply(
+                        ctx,
+                        reply,
+                        webhook,
+                        content.clone(),
+                        embed.clone(),
+                        attachment.clone(),
+                        components.clone(),
+                    );
+                    match edit.await {
+                        Ok(message) => Ok(Some(message)),
+                        Err(err) => {
+                            log::warn!("failed to edit reply in place, sending it: {err}");
+                            // Its thread hangs off the message left behind.
+                            delete_pages(ctx, thread.take()).await;
+
+                            send_reply(ctx, poster, content, embed, attachment, components, fetch)
+                                .await
+                        }
+                    }
+                }
+                None => {
+                    send_reply(ctx, poster, content, embed, attachment, components, fetch).await
+                }
+            };
+            if let (Err(err), Some((stale, speaker)), Some(resend)) = (&sent, &webhook, resend) {
+                if webhooks::is_unknown(err) {
+                    log::warn!(
+                        "webhook of channel {} is gone, posting reply again: {err}",
+                        exchange.channel
+                    );
+                    webhooks::forget(ctx, stale);
+                    let speaker = *speaker;
+                    let (content, embed, attachment, components) = resend;
+
+                    // Through a new webhook once, then as a normal reply.
+                    webhook = webhooks::channel_webhook(ctx)
+                        .await
+                        .map(|webhook| (webhook, speaker));
+                    if let Some((fresh, (name, avatar_url))) = &webhook {
+                        let poster = Poster::Webhook(fresh, name, *avatar_url);
+                        sent = send_reply(
+                            ctx,
+                            poster,
+                            content.clone(),
+                            embed.clone(),
+                            attachment.clone(),
+                            components.clone(),
+                            fetch,
+                        )
+                        .await;
+                    }
+                    if let (Err(err), Some(_)) = (&sent, &webhook) {
+                        log::warn!("failed to post reply through a new webhook: {err}");
+                    }
+                    if webhook.is_none() || sent.is_err() {
+                        webhook = None;
+                        let poster = Poster::Command { private };
+                        sent =
+                            send_reply(ctx, poster, content, embed, attachment, components, fetch)
+                                .await;
+                    }
+                }
+            }
+            poster = match &webhook {
+                Some((webhook, (name, avatar_url))) => Poster::Webhook(webhook, name, *avatar_url),
+                None => Poster::Command { private },
+            };
+            if let (Some(edited), Ok(_)) = (&exchange.edited, &sent) {
+                edits::delete_parts(ctx, edited).await;
+            }
+
+            let message = match sent {
+                Ok(message) => message,
+                Err(err) => {
+                    log::error!("failed to deliver reply: {err}");
+                    report::error(report_context(&ctx), &err);
+
+                    delete_pages(ctx, thread).await;
+                    // Branches get back what they dropped, as when the provider call fails.
+                    session.undo_last_interaction(exchange.exchanged).await;
+                    data.usage.record_error(exchange.guild);
+                    data.experiment.record_error(session.arm);
+                    exchange.undelivered = Some(conf.messages.alerts.delivery_failed.clone());
+
+                    return Ok(Flow::Halt);
+                }
+            };
+
+            if !thread_parts.is_empty() || thread.is_some() {
+                let started = match &message {
+                    Some(message) => {
+                        let posted = post_in_thread(
+                            ctx,
+                            message,
+                            &exchange.content,
+                            &mut thread,
+                            &thread_parts,
+                        );
+                        match posted.await {
+                            Ok(()) => true,
+                            Err(err) if thread.is_none() => {
+                                log::warn!("failed to start thread for reply: {err}");
+
+                                false
+                            }
+                            Err(err) => {
+                                log::error!(
+                                    "failed to deliver the rest of a reply in its thread: {err}"
+                                );
+                                report::error(report_context(&ctx), &err);
+
+                                // A partial reply is taken back, like one never sent.
+                                delete_pages(ctx, thread).await;
+                                delete_reply(ctx, poster, &[message.id]).await;
+                                session.undo_last_interaction(exchange.exchanged).await;
+                                data.usage.record_error(exchange.guild);
+                                data.experiment.record_error(session.arm);
+                                exchange.undelivered =
+                                    Some(conf.messages.alerts.delivery_failed.clone());
+
+                                return Ok(Flow::Halt);
+                            }
+                        }
+                    }
+                    None => {
+                        delete_pages(ctx, thread.take()).await;
+
+                        false
+                    }
+                };
+                // Posted below the pointer instead, as split parts.
+                if !started && !thread_parts.is_empty() {
+                    extra_parts = thread_parts;
+                }
+            }
+            let mut parts = Vec::new();
+            if let Err(err) = send_parts(ctx, poster, extra_parts, &mut parts).await {
+                log::error!("failed to deliver the rest of a split reply: {err}");
+                report::error(report_context(&ctx), &err);
+
+                // A partial reply is taken back, like one never sent.
+                parts.extend(message.as_ref().map(|message| message.id));
+                delete_reply(ctx, poster, &parts).await;
+                session.undo_last_interaction(exchange.exchanged).await;
+                data.usage.record_error(exchange.guild);
+                data.experiment.record_error(session.arm);
+                exchange.undelivered = Some(conf.messages.alerts.delivery_failed.clone());
+
+                return Ok(Flow::Halt);
+            }
+
+            // Slash commands still expect an answer of their own.
+            if let (Some((_, (name, _))), Some(message), poise::Context::Application(_)) =
+                (&webhook, &message, ctx)
+            {
+                let embed = serenity::CreateEmbed::new().description(messages::render(
+                    &conf.messages.webhook.delivered,
+                    &[("name", name), ("link", &message.link())],
+                ));
+                if let Err(err) = send_ephemeral_embedded_reply(ctx, embed).await {
+                    log::warn!("failed to acknowledge reply posted through webhook: {err}");
+                }
+            }
+            let webhook = webhook.map(|(webhook, _)| webhook);
+
+            if let (Some(message), Some(prompt)) = (&message, remember) {
+                let answered = edits::Prompt {
+                    session: session.clone(),
+                    interaction: exchange.exchanged as u64,
+                    reply: message.id,
+                    parts: parts.clone(),
+                    webhook: webhook.clone(),
+                };
+                data.answered_prompts.remember(prompt, answered);
+            }
+
+            if let Some(message) = message.filter(|_| track) {
+                if let Some(reasoning) = reasoning {
+                    let record = reasoning::Record {
+                        guild: exchange.guild,
+                        author: exchange.user,
+                        reasoning: reasoning.clone(),
+                    };
+                    data.reasoning.insert(message.id.get(), record);
+                }
+
+                if track_reactions {
+                    let record = reactions::ReplyRecord {
+                        guild: exchange.guild,
+                        author: exchange.user,
+                        session: session.clone(),
+                        exchanged: exchange.exchanged,
+                        prompt: exchange.content.clone(),
+                        response: response.content.clone(),
+                        webhook: webhook.clone(),
+                        parts,
+                    };
+                    data.replies.record(message.id.get(), record);
+                }
+
+                if suggest_followups {
+                    followups::suggest(
+                        ctx.serenity_context().http.clone(),
+                        data.clone(),
+                        message,
+                        webhook,
+                        exchange.guild,
+                        exchange.user,
+                        session.clone(),
+                    );
+                }
+            }
+
+            let title_after = conf.chat.title_after as usize;
+            if title_after > 0 && session.title().is_none() {
+                let session = session.clone();
+                tokio::spawn(async move { session.entitle(title_after).await });
+            }
+
+            let (data, guild, session) = (data.clone(), exchange.guild, session.clone());
+            tokio::spawn(async move { session.fold_summary(&data, guild).await });
+
+            Ok(Flow::Continue)
+        })
+    }
+}
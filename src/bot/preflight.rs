@@ -0,0 +1,73 @@
+use poise::serenity_prelude as serenity;
+
+use crate::config;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("failed to fetch application info, set bot.startup_checks to off to skip the checks")]
+    Fetch(#[source] serenity::Error),
+    #[error("the {0} intent isn't enabled for the application, turn it on under Bot > Privileged Gateway Intents in the Discord developer portal or leave out the features needing it")]
+    Disallowed(&'static str),
+}
+
+/// Privileged intents, the portal name of each and the application flags granting it.
+const PRIVILEGED: [(serenity::GatewayIntents, &str, serenity::ApplicationFlags); 3] = [
+    (
+        serenity::GatewayIntents::MESSAGE_CONTENT,
+        "Message Content",
+        serenity::ApplicationFlags::GATEWAY_MESSAGE_CONTENT
+            .union(serenity::ApplicationFlags::GATEWAY_MESSAGE_CONTENT_LIMITED),
+    ),
+    (
+        serenity::GatewayIntents::GUILD_MEMBERS,
+        "Server Members",
+        serenity::ApplicationFlags::GATEWAY_GUILD_MEMBERS
+            .union(serenity::ApplicationFlags::GATEWAY_GUILD_MEMBERS_LIMITED),
+    ),
+    (
+        serenity::GatewayIntents::GUILD_PRESENCES,
+        "Presence",
+        serenity::ApplicationFlags::GATEWAY_PRESENCE
+            .union(serenity::ApplicationFlags::GATEWAY_PRESENCE_LIMITED),
+    ),
+];
+
+/// Makes sure Discord grants the application the privileged intents requested, as it
+/// refuses the connection otherwise with little hint of which one is missing.
+pub(super) async fn check(
+    http: &serenity::Http,
+    intents: serenity::GatewayIntents,
+    checks: config::StartupChecks,
+) -> Result<(), Error> {
+    if checks == config::StartupChecks::Off || !intents.is_privileged() {
+        return Ok(());
+    }
+
+    let result = match http.get_current_application_info().await {
+        Ok(info) => match missing(intents, info.flags.unwrap_or_default()) {
+            Some(name) => Err(Error::Disallowed(name)),
+            None => Ok(()),
+        },
+        Err(err) => Err(Error::Fetch(err)),
+    };
+
+    match result {
+        Err(err) if checks == config::StartupChecks::Warn => {
+            log::warn!("{err}");
+
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+/// Portal name of the first privileged intent requested without the flags granting it.
+fn missing(
+    intents: serenity::GatewayIntents,
+    flags: serenity::ApplicationFlags,
+) -> Option<&'static str> {
+    PRIVILEGED
+        .into_iter()
+        .find(|&(intent, _, granted_by)| intents.contains(intent) && !flags.intersects(granted_by))
+        .map(|(_, name, _)| name)
+}
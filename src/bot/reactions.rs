@@ -0,0 +1,408 @@
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use poise::serenity_prelude as serenity;
+
+use crate::{chat, config};
+
+use super::{
+    pipeline, reasoning, BotData, ChatSession, GuildId, InternalError, UserId, PRUNE_ABOVE,
+};
+
+/// How long after being posted a reply is acted on through reactions.
+const REACTION_WINDOW: Duration = Duration::from_secs(86400);
+
+#[derive(Clone, Debug)]
+pub(super) struct ReplyRecord {
+    pub guild: GuildId,
+    pub author: UserId,
+    pub session: ChatSession,
+    pub exchanged: usize,
+    pub prompt: String,
+    pub response: String,
+    /// Webhook the reply was posted with, which has to edit or delete it.
+    pub webhook: Option<serenity::Webhook>,
+    /// Messages the rest of a split reply was posted in.
+    pub parts: Vec<serenity::MessageId>,
+}
+
+/// Bot replies reactions act on, by their id, forgotten once past the reaction window.
+#[derive(Debug, Default)]
+pub(super) struct Replies {
+    recorded: DashMap<u64, (Instant, ReplyRecord)>,
+}
+
+impl Replies {
+    pub fn record(&self, message: u64, record: ReplyRecord) {
+        if self.recorded.len() > PRUNE_ABOVE {
+            self.recorded
+                .retain(|_, (recorded, _)| recorded.elapsed() < REACTION_WINDOW);
+        }
+
+        self.recorded.insert(message, (Instant::now(), record));
+    }
+
+    pub fn get(&self, message: u64) -> Option<ReplyRecord> {
+        self.recorded
+            .get(&message)
+            .filter(|entry| entry.0.elapsed() < REACTION_WINDOW)
+            .map(|entry| entry.1.clone())
+    }
+
+    pub fn remove(&self, message: u64) {
+        self.recorded.remove(&message);
+    }
+
+    /// Forgets the replies the predicate holds for, e.g. those of purged sessions.
+    pub fn forget(&self, mut forgotten: impl FnMut(&ReplyRecord) -> bool) {
+        self.recorded.retain(|_, (_, record)| !forgotten(record));
+    }
+
+    pub fn clear(&self) {
+        self.recorded.clear();
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Action {
+    Regenerate,
+    Delete,
+    Export,
+}
+
+fn parse_action(data: &BotData, emoji: &serenity::ReactionType) -> Option<Action> {
+    let serenity::ReactionType::Unicode(emoji) = emoji else {
+        return None;
+    };
+
+    let reactions = &data.conf().reactions;
+    let matches = |configured: &Option<String>| configured.as_deref() == Some(emoji.as_str());
+
+    if matches(&reactions.regenerate) {
+        Some(Action::Regenerate)
+    } else if matches(&reactions.delete) {
+        Some(Action::Delete)
+    } else if matches(&reactions.export) {
+        Some(Action::Export)
+    } else {
+        None
+    }
+}
+
+fn can_manage_messages(ctx: &serenity::Context, reaction: &serenity::Reaction) -> bool {
+    let (Some(guild), Some(member)) = (reaction.guild_id, &reaction.member) else {
+        return false;
+    };
+
+    let Some(guild) = ctx.cache.guild(guild) else {
+        return false;
+    };
+
+    guild
+        .channels
+        .get(&reaction.channel_id)
+        .is_some_and(|channel| guild.user_permissions_in(channel, member).manage_messages())
+}
+
+/// Deletes a message of the reply, through the webhook it was posted with if any.
+async fn delete_message(
+    ctx: &serenity::Context,
+    channel: serenity::ChannelId,
+    record: &ReplyRecord,
+    message: serenity::MessageId,
+) -> Result<(), serenity::Error> {
+    match &record.webhook {
+        Some(webhook) => webhook.delete_message(ctx, None, message).await,
+        None => channel.delete_message(ctx, message).await,
+    }
+}
+
+async fn regenerate(
+    ctx: &serenity::Context,
+    data: &BotData,
+    reaction: &serenity::Reaction,
+    record: ReplyRecord,
+) -> Result<(), InternalError> {
+    let _in_flight = data.in_flight.enter();
+    if data.is_flushing() || data.is_under_maintenance() {
+        return Ok(());
+    }
+
+    // Held until the reply is edited and recorded, so concurrent regenerations can't leave it
+    // showing another answer than the one kept in the session.
+    let current = record.session.session.clone();
+    let mut session = current.lock().await;
+
+    // Only the most recent reply of a session can be regenerated.
+    if session.exchanged() != record.exchanged {
+        return Ok(());
+    }
+
+    data.usage.record_prompt(record.guild, record.author);
+    data.experiment.record_prompt(record.session.arm);
+    data.experiment.record_regeneration(record.session.arm);
+
+    let response = match session.regenerate_last_interaction().await {
+        Ok(Some(response)) => response,
+        Ok(None) => return Ok(()),
+        Err(chat::Error::Vetoed(reason)) => {
+            log::info!("regenerated reply was rejected by script: {reason}");
+
+            return Ok(());
+        }
+        Err(err) => {
+            data.usage.record_error(record.guild);
+            data.experiment.record_error(record.session.arm);
+
+            return Err(Box::from(err));
+        }
+    };
+
+    let model = session
+        .model()
+        .map(str::to_string)
+        .unwrap_or_else(|| data.sbuilder.model());
+    let persona = session.instructions().map(str::to_string);
+
+    data.usage.record_tokens(
+        record.guild,
+        &response.model,
+        Some(record.session.arm),
+        response.usage,
+    );
+    data.experiment
+        .record_tokens(record.session.arm, response.usage);
+
+    // Laid out as delivered, except that pages meant for a thread are posted below it, as the
+    // thread off the message may be taken already.
+    let conf = data.conf();
+    let (max_chars, overflow) = conf.response_limit(record.guild);
+    let pipeline::Fitted {
+        body,
+        attachment,
+        thread_parts,
+    } = pipeline::fit_reply(
+        data,
+        record.guild,
+        &record.session,
+        &response,
+        max_chars,
+        overflow,
+        true,
+    )
+    .await;
+    let mut pages = thread_parts.into_iter();
+    let body = pages.next().unwrap_or(body);
+    let (content, embed, mut parts) = pipeline::compose_reply(
+        &conf,
+        record.guild,
+        &model,
+        response.usage,
+        String::new(),
+        &body,
+        "",
+    );
+    parts.extend(pages);
+
+    let message = reaction.message_id;
+    // Its button stays, telling it's gone when the new reply had no reasoning.
+    let components = if data.reasoning.contains_key(&message.get()) {
+        vec![reasoning::button_row(data)]
+    } else {
+        Vec::new()
+    };
+    let embeds: Vec<_> = embed.into_iter().collect();
+    match &record.webhook {
+        Some(webhook) => {
+            let mut builder = serenity::EditWebhookMessage::new()
+                .content(&content)
+                .embeds(embeds)
+                .components(components)
+                .clear_attachments();
+            if let Some(attachment) = attachment {
+                builder = builder.new_attachment(attachment);
+            }
+            webhook.edit_message(ctx, message, builder).await?;
+        }
+        None => {
+            let mut builder = serenity::EditMessage::new()
+                .content(&content)
+                .embeds(embeds)
+                .components(components)
+                .remove_all_attachments();
+            if let Some(attachment) = attachment {
+                builder = builder.new_attachment(attachment);
+            }
+            reaction
+                .channel_id
+                .edit_message(ctx, message, builder)
+                .await?;
+        }
+    }
+
+    match &response.reasoning {
+        Some(reasoning) => {
+            if let Some(mut shown) = data.reasoning.get_mut(&message.get()) {
+                shown.reasoning = reasoning.clone();
+            }
+        }
+        None => {
+            data.reasoning.remove(&message.get());
+        }
+    }
+
+    for part in &record.parts {
+        if let Err(err) = delete_message(ctx, reaction.channel_id, &record, *part).await {
+            log::warn!("failed to delete part of a regenerated reply: {err}");
+        }
+    }
+
+    let speaker = conf
+        .webhook(record.guild)
+        .map(|webhook_conf| webhook_conf.speaker(persona.as_deref()));
+    let mentions = super::mentions_policy(conf.mentions(record.guild));
+    let mut posted = Vec::with_capacity(parts.len());
+    for part in parts {
+        let sent = post_part(
+            ctx,
+            reaction.channel_id,
+            &record,
+            speaker,
+            mentions.clone(),
+            part,
+        );
+        match sent.await {
+            Ok(Some(part)) => posted.push(part),
+            Ok(None) => (),
+            Err(err) => {
+                log::warn!("failed to post part of a regenerated reply: {err}");
+
+                break;
+            }
+        }
+    }
+
+    data.replies.record(
+        message.get(),
+        ReplyRecord {
+            response: response.content,
+            parts: posted,
+            ..record
+        },
+    );
+    drop(session);
+
+    Ok(())
+}
+
+/// Posts part of a regenerated reply below it, through the webhook it was posted with if any.
+async fn post_part(
+    ctx: &serenity::Context,
+    channel: serenity::ChannelId,
+    record: &ReplyRecord,
+    speaker: Option<(&str, Option<&str>)>,
+    mentions: serenity::CreateAllowedMentions,
+    part: String,
+) -> Result<Option<serenity::MessageId>, serenity::Error> {
+    match &record.webhook {
+        Some(webhook) => {
+            let mut builder = serenity::ExecuteWebhook::new()
+                .content(part)
+                .allowed_mentions(mentions);
+            if let Some((name, avatar_url)) = speaker {
+                builder = builder.username(name);
+                if let Some(avatar_url) = avatar_url {
+                    builder = builder.avatar_url(avatar_url);
+                }
+            }
+            let posted = webhook.execute(ctx, true, builder).await?;
+
+            Ok(posted.map(|message| message.id))
+        }
+        None => {
+            let builder = serenity::CreateMessage::new()
+                .content(part)
+                .allowed_mentions(mentions);
+            let posted = channel.send_message(ctx, builder).await?;
+
+            Ok(Some(posted.id))
+        }
+    }
+}
+
+async fn export(
+    ctx: &serenity::Context,
+    data: &BotData,
+    user: serenity::UserId,
+    record: &ReplyRecord,
+) -> Result<(), InternalError> {
+    let messages = &data.conf().messages.reactions;
+    let embed = serenity::CreateEmbed::new()
+        .title(&messages.export_title)
+        .field(
+            &messages.export_prompt,
+            super::truncate_field_value(&record.prompt),
+            false,
+        )
+        .timestamp(serenity::Timestamp::now());
+    let embed = super::apply_theme(&data.conf().appearance, embed);
+    let mut message = serenity::CreateMessage::new().embed(embed);
+    // Longer replies went through an overflow mode, so they're sent whole as a file instead.
+    message = if record.response.chars().count() <= config::DISCORD_MESSAGE_LIMIT as usize {
+        message.content(&record.response)
+    } else {
+        message.add_file(serenity::CreateAttachment::bytes(
+            record.response.as_bytes(),
+            pipeline::RESPONSE_FILE,
+        ))
+    };
+
+    user.direct_message(ctx, message).await?;
+
+    Ok(())
+}
+
+/// Runs the action mapped to a reaction added on one of the bot replies.
+pub(super) async fn handle_reaction(
+    ctx: &serenity::Context,
+    data: &BotData,
+    reaction: &serenity::Reaction,
+) -> Result<(), InternalError> {
+    let Some(user) = reaction.user_id else {
+        return Ok(());
+    };
+
+    if user == ctx.cache.current_user().id {
+        return Ok(());
+    }
+
+    let Some(action) = parse_action(data, &reaction.emoji) else {
+        return Ok(());
+    };
+
+    let Some(record) = data.replies.get(reaction.message_id.get()) else {
+        return Ok(());
+    };
+
+    let is_author = user.get() == record.author;
+
+    match action {
+        Action::Regenerate if is_author => {
+            regenerate(ctx, data, reaction, record).await?;
+
+            // Allows the author to react again, if the bot is able to remove it.
+            let _ = reaction.delete(ctx).await;
+        }
+        Action::Delete if is_author || can_manage_messages(ctx, reaction) => {
+            for message in record.parts.iter().chain([&reaction.message_id]) {
+                delete_message(ctx, reaction.channel_id, &record, *message).await?;
+            }
+            data.replies.remove(reaction.message_id.get());
+            data.experiment.record_deletion(record.session.arm);
+        }
+        Action::Export => export(ctx, data, user, &record).await?,
+        _ => (),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,56 @@
+use poise::serenity_prelude as serenity;
+
+use super::{
+    apply_theme, truncate_chars, BotData, GuildId, InternalError, UserId, EMBED_DESCRIPTION_LIMIT,
+};
+
+pub(super) const CUSTOM_ID: &str = "reasoning:show";
+
+#[derive(Clone, Debug)]
+pub(super) struct Record {
+    pub guild: GuildId,
+    pub author: UserId,
+    pub reasoning: String,
+}
+
+/// Row with the button showing the reasoning behind a reply.
+pub(super) fn button_row(data: &BotData) -> serenity::CreateActionRow {
+    let button = serenity::CreateButton::new(CUSTOM_ID)
+        .label(&data.conf().messages.reasoning.button)
+        .emoji('🧠')
+        .style(serenity::ButtonStyle::Secondary);
+
+    serenity::CreateActionRow::Buttons(vec![button])
+}
+
+/// Shows the reasoning behind a reply to the author of its prompt, only.
+pub(super) async fn handle_press(
+    ctx: &serenity::Context,
+    data: &BotData,
+    press: &serenity::ComponentInteraction,
+) -> Result<(), InternalError> {
+    let conf = data.conf();
+    let messages = &conf.messages.reasoning;
+
+    // Records are dropped on flush and restart, along with the sessions they refer to.
+    let record = data
+        .reasoning
+        .get(&press.message.id.get())
+        .map(|record| record.clone());
+    let embed = match record {
+        Some(record) if press.user.id.get() == record.author => serenity::CreateEmbed::new()
+            .title(&messages.title)
+            .description(truncate_chars(&record.reasoning, EMBED_DESCRIPTION_LIMIT)),
+        Some(_) => serenity::CreateEmbed::new().title(&messages.not_author),
+        None => serenity::CreateEmbed::new().title(&messages.expired),
+    };
+
+    let response = serenity::CreateInteractionResponse::Message(
+        serenity::CreateInteractionResponseMessage::new()
+            .embed(apply_theme(&conf.appearance, embed))
+            .ephemeral(true),
+    );
+    press.create_response(ctx, response).await?;
+
+    Ok(())
+}
@@ -0,0 +1,91 @@
+use std::{sync::Arc, time::Duration};
+
+use crate::{chat, config, secrets};
+
+use super::{lanes, GuildId};
+
+/// Configured route with the provider built for its key.
+struct Route {
+    conf: config::Route,
+    provider: Arc<dyn chat::ChatProvider>,
+}
+
+/// Routes read on startup, picking the provider and model prompts are answered with.
+pub(super) struct Routes {
+    routes: Vec<Route>,
+    /// Lanes of the routes with a key of their own, which the provider limits apart.
+    keyed_lanes: Vec<Arc<lanes::Lanes>>,
+}
+
+impl Routes {
+    /// Builds the provider of every route, those without a key of their own sharing the default
+    /// one and its lanes.
+    pub async fn build(
+        conf: &config::App,
+        resolvers: &Arc<secrets::Resolvers>,
+        api_key: &secrets::Secret,
+        lanes: &Arc<lanes::Lanes>,
+    ) -> Result<Self, secrets::Error> {
+        let mut routes = Vec::with_capacity(conf.routes.len());
+        let mut keyed_lanes = Vec::new();
+
+        for route in &conf.routes {
+            let mut route_lanes = lanes.clone();
+            let key = match (route.provider, &route.api_key) {
+                // Mock replies don't need a key, as with the default provider.
+                (config::Provider::Mock, _) => secrets::Secret::new(String::new()),
+                (config::Provider::Genai, None) => api_key.clone(),
+                (config::Provider::Genai, Some(reference)) => {
+                    route_lanes = Arc::new(lanes::Lanes::new(&conf.lanes));
+                    keyed_lanes.push(route_lanes.clone());
+
+                    let key = secrets::Secret::new(resolvers.resolve(reference).await?);
+                    if conf.secrets.refresh_secs > 0 {
+                        secrets::spawn_refresher(
+                            resolvers.clone(),
+                            reference.clone(),
+                            key.clone(),
+                            Duration::from_secs(conf.secrets.refresh_secs),
+                        );
+                    }
+
+                    key
+                }
+            };
+
+            let provider = chat::provider(route.provider, key);
+            routes.push(Route {
+                conf: route.clone(),
+                provider: Arc::new(lanes::Paced::new(provider, route_lanes)),
+            });
+        }
+
+        Ok(Self {
+            routes,
+            keyed_lanes,
+        })
+    }
+
+    /// Applies the bounds of the config to the lanes of the routes with a key of their own.
+    pub fn configure_lanes(&self, conf: &config::Lanes) {
+        for lanes in &self.keyed_lanes {
+            lanes.configure(conf);
+        }
+    }
+
+    /// Provider and model of the first route the prompt takes, if any.
+    pub fn pick(
+        &self,
+        guild: GuildId,
+        instructions: Option<&str>,
+        prompt: &str,
+    ) -> Option<chat::Target> {
+        self.routes
+            .iter()
+            .find(|route| route.conf.matches(guild, instructions, prompt))
+            .map(|route| chat::Target {
+                provider: route.provider.clone(),
+                model: route.conf.model.clone(),
+            })
+    }
+}
@@ -0,0 +1,178 @@
+use std::fmt::Display;
+
+use poise::serenity_prelude as serenity;
+
+use crate::{chat, code, config, messages};
+
+use super::{
+    allowed_mentions, handle_command_error, pipeline, send_embedded_reply,
+    send_ephemeral_embedded_reply, truncate_chars, Context, InternalError,
+};
+
+const MAX_CHOICES: usize = 25;
+/// Longest name Discord takes for an autocomplete choice.
+const CHOICE_NAME_LIMIT: usize = 100;
+
+async fn autocomplete_pipeline(
+    ctx: Context<'_>,
+    partial: &str,
+) -> Vec<serenity::AutocompleteChoice> {
+    let conf = ctx.data().conf();
+    let mut pipelines: Vec<_> = conf
+        .pipelines
+        .iter()
+        .filter(|(name, _)| name.contains(partial))
+        .collect();
+    pipelines.sort_unstable_by_key(|(name, _)| name.as_str());
+
+    pipelines
+        .into_iter()
+        .take(MAX_CHOICES)
+        .map(|(name, pipeline)| {
+            let label = if pipeline.description.is_empty() {
+                name.clone()
+            } else {
+                truncate_chars(
+                    &format!("{name}: {}", pipeline.description),
+                    CHOICE_NAME_LIMIT,
+                )
+            };
+
+            serenity::AutocompleteChoice::new(label, name.as_str())
+        })
+        .collect()
+}
+
+/// Fills the step prompt with the input and the outputs of the steps before it.
+fn render_step(step: &config::PipelineStep, input: &str, outputs: &[(String, String)]) -> String {
+    let previous = outputs.last().map_or(input, |(_, output)| output.as_str());
+
+    let mut values: Vec<(&str, &dyn Display)> = vec![("input", &input), ("previous", &previous)];
+    values.extend(
+        outputs
+            .iter()
+            .map(|(name, output)| (name.as_str(), output as &dyn Display)),
+    );
+
+    messages::render(&step.prompt, &values)
+}
+
+/// Runs a pipeline of model calls set up by the bot owners over your input
+#[poise::command(
+    slash_command,
+    prefix_command,
+    rename = "run-pipeline",
+    guild_only,
+    user_cooldown = 30,
+    required_permissions = "SEND_MESSAGES",
+    on_error = "handle_command_error"
+)]
+pub async fn run_pipeline(
+    ctx: Context<'_>,
+    #[description = "pipeline to run"]
+    #[autocomplete = "autocomplete_pipeline"]
+    name: String,
+    #[description = "text the first step starts from"]
+    #[rest]
+    input: String,
+) -> Result<(), InternalError> {
+    let data = ctx.data();
+    let conf = data.conf();
+    let messages = &conf.messages.run_pipeline;
+    let guild = ctx.guild_id().unwrap().get();
+    let user = ctx.author().id.get();
+
+    let Some(named) = conf.pipelines.get(&name) else {
+        let embed = serenity::CreateEmbed::new()
+            .title(messages::render(&messages.unknown, &[("name", &name)]));
+        send_ephemeral_embedded_reply(ctx, embed).await?;
+
+        return Ok(());
+    };
+
+    let input = input.trim();
+    let max = data.prompt_size(guild);
+    if input.chars().count() > max as usize {
+        let embed = serenity::CreateEmbed::new()
+            .title(messages::render(&messages.too_long, &[("max", &max)]));
+        send_ephemeral_embedded_reply(ctx, embed).await?;
+
+        return Ok(());
+    }
+
+    // Held in flight by the exchange until answered.
+    let mut exchange = pipeline::Exchange::new(ctx, input.to_string());
+    if !pipeline::Pipeline::guards()
+        .admit(ctx, &mut exchange)
+        .await?
+    {
+        return Ok(());
+    }
+    let policy = exchange.policy.take();
+
+    ctx.defer().await?;
+
+    let limit = config::DISCORD_MESSAGE_LIMIT as usize;
+    let count = named.steps.len();
+    let mut outputs: Vec<(String, String)> = Vec::with_capacity(count);
+    for (index, step) in named.steps.iter().enumerate() {
+        let prompt = render_step(step, input, &outputs);
+
+        // Steps are answered outside any conversation, each on its own.
+        let mut session = data.sbuilder.create_chat(1);
+        session.set_policy(policy.clone());
+        data.usage.record_prompt(guild, user);
+
+        let response = match session.send_message(prompt, step.model.as_deref()).await {
+            Ok(response) => response,
+            Err(err) => {
+                match err {
+                    chat::Error::Vetoed(reason) => {
+                        log::info!(
+                            "step {} of pipeline {name} was rejected by script: {reason}",
+                            step.name
+                        );
+                    }
+                    err => {
+                        log::warn!("failed to run step {} of pipeline {name}: {err}", step.name);
+                        data.usage.record_error(guild);
+                    }
+                }
+
+                let embed = serenity::CreateEmbed::new()
+                    .title(messages::render(&messages.failed, &[("step", &step.name)]));
+                send_embedded_reply(ctx, embed).await?;
+
+                return Ok(());
+            }
+        };
+        data.usage
+            .record_tokens(guild, &response.model, None, response.usage);
+
+        if step.show || index + 1 == count {
+            let heading = messages::render(
+                &messages.step,
+                &[
+                    ("index", &(index + 1)),
+                    ("count", &count),
+                    ("step", &step.name),
+                ],
+            );
+            let output = if conf.code.format_fences {
+                code::format_fences(&response.content)
+            } else {
+                response.content.clone()
+            };
+            let content = truncate_chars(&format!("{heading}\n{output}"), limit);
+
+            let reply = poise::CreateReply::default()
+                .content(content)
+                .allowed_mentions(allowed_mentions(ctx));
+            ctx.send(reply).await?;
+        }
+
+        outputs.push((step.name.clone(), response.content));
+    }
+
+    Ok(())
+}
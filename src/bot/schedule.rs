@@ -0,0 +1,548 @@
+use std::{
+    io,
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use chrono::{DateTime, NaiveTime, TimeDelta, Utc};
+use dashmap::DashMap;
+use poise::serenity_prelude::{self as serenity, Mentionable};
+
+use crate::{
+    chat, config,
+    messages::{self, plural},
+};
+
+use super::{
+    failure_alert, guard, handle_command_error, send_ephemeral_embedded_reply, truncate_chars,
+    truncate_field_value, BotData, BotDataInner, ChannelId, Context, GuildId, InternalError,
+    UserId,
+};
+
+const JOBS_FILE: &str = "schedule.json";
+const DISPATCH_POLL: Duration = Duration::from_secs(20);
+const MAX_DELAY_DAYS: i64 = 30;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("failed to access scheduled prompts file")]
+    Io(#[from] io::Error),
+    #[error("failed to (de)serialize scheduled prompts")]
+    Json(#[from] serde_json::Error),
+}
+
+/// Prompt answered in a channel once due.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub(super) struct Job {
+    pub id: u64,
+    pub guild: GuildId,
+    pub channel: ChannelId,
+    pub user: UserId,
+    pub prompt: String,
+    pub due: DateTime<Utc>,
+    /// Scheduled again a day later once answered.
+    pub daily: bool,
+}
+
+/// Scheduled prompts, written to the snapshot directory on every change when there's one.
+#[derive(Debug, Default)]
+pub(super) struct Scheduler {
+    jobs: DashMap<u64, Job>,
+    last_id: AtomicU64,
+    /// Held while writing the jobs file, so writes don't interleave.
+    saving: tokio::sync::Mutex<()>,
+}
+
+impl Scheduler {
+    fn add(&self, mut job: Job) -> u64 {
+        job.id = self.last_id.fetch_add(1, Ordering::AcqRel) + 1;
+        self.jobs.insert(job.id, job.clone());
+
+        job.id
+    }
+
+    /// Jobs scheduled by the user in the guild, soonest first.
+    fn user_jobs(&self, guild: GuildId, user: UserId) -> Vec<Job> {
+        let mut jobs: Vec<_> = self
+            .jobs
+            .iter()
+            .filter(|job| job.guild == guild && job.user == user)
+            .map(|job| job.value().clone())
+            .collect();
+        jobs.sort_unstable_by_key(|job| job.due);
+
+        jobs
+    }
+
+    fn remove(&self, guild: GuildId, user: UserId, id: u64) -> bool {
+        self.jobs
+            .remove_if(&id, |_, job| job.guild == guild && job.user == user)
+            .is_some()
+    }
+
+    fn due(&self, now: DateTime<Utc>) -> Vec<Job> {
+        self.jobs
+            .iter()
+            .filter(|job| job.due <= now)
+            .map(|job| job.value().clone())
+            .collect()
+    }
+
+    /// Reschedules daily jobs after the given time, dropping the others.
+    fn answered(&self, job: &Job, now: DateTime<Utc>) {
+        if !job.daily {
+            self.jobs.remove(&job.id);
+
+            return;
+        }
+
+        if let Some(mut job) = self.jobs.get_mut(&job.id) {
+            while job.due <= now {
+                job.due += TimeDelta::days(1);
+            }
+        }
+    }
+
+    async fn save(&self, dir: &Path, file: &str) -> Result<(), Error> {
+        let _saving = self.saving.lock().await;
+
+        let mut jobs: Vec<_> = self.jobs.iter().map(|job| job.value().clone()).collect();
+        jobs.sort_unstable_by_key(|job| job.id);
+        let contents = serde_json::to_vec(&jobs)?;
+
+        tokio::fs::create_dir_all(dir).await?;
+        let path = dir.join(file);
+        let tmp_path = path.with_extension("json.tmp");
+        tokio::fs::write(&tmp_path, contents).await?;
+        tokio::fs::rename(tmp_path, path).await?;
+
+        Ok(())
+    }
+
+    /// Loads the jobs kept in the file, returning how many there were.
+    async fn load(&self, path: &Path) -> Result<usize, Error> {
+        let contents = match tokio::fs::read(path).await {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(err) => return Err(err.into()),
+        };
+        let jobs: Vec<Job> = serde_json::from_slice(&contents)?;
+
+        let loaded = jobs.len();
+        for job in jobs {
+            self.last_id.fetch_max(job.id, Ordering::AcqRel);
+            self.jobs.insert(job.id, job);
+        }
+
+        Ok(loaded)
+    }
+}
+
+/// Loads the jobs this process kept in the directory, returning how many there were.
+pub(super) async fn load(data: &BotDataInner, dir: &Path) -> Result<usize, Error> {
+    let path = dir.join(data.conf().bot.shard_file(JOBS_FILE));
+
+    data.scheduler.load(&path).await
+}
+
+/// Writes the jobs down, if there's somewhere to keep them.
+async fn persist(data: &BotDataInner) {
+    let conf = data.conf();
+    let Some(dir) = &conf.persistence.snapshot_dir else {
+        return;
+    };
+
+    if let Err(err) = data
+        .scheduler
+        .save(dir, &conf.bot.shard_file(JOBS_FILE))
+        .await
+    {
+        log::error!("failed to save scheduled prompts: {err}");
+    }
+}
+
+/// Next time the clock reads the given time, strictly after now.
+fn next_time(time: NaiveTime, now: DateTime<Utc>) -> DateTime<Utc> {
+    let next = now.date_naive().and_time(time).and_utc();
+
+    if next <= now {
+        next + TimeDelta::days(1)
+    } else {
+        next
+    }
+}
+
+/// Reads `in 30m` (or `2h`, `1d`), `at 18:00` (or `18:00`) and `daily 09:00`, in UTC.
+///
+/// Returns when the job is first due and whether it repeats every day.
+fn parse_when(when: &str, now: DateTime<Utc>) -> Option<(DateTime<Utc>, bool)> {
+    let when = when.trim().to_lowercase();
+    let parse_time = |time: &str| NaiveTime::parse_from_str(time.trim(), "%H:%M").ok();
+
+    if let Some(time) = when.strip_prefix("daily ") {
+        return Some((next_time(parse_time(time)?, now), true));
+    }
+
+    if let Some(time) = parse_time(when.strip_prefix("at ").unwrap_or(&when)) {
+        return Some((next_time(time, now), false));
+    }
+
+    let delay = when.strip_prefix("in ").unwrap_or(&when).trim();
+    let unit = delay.chars().last()?;
+    let amount: i64 = delay[..delay.len() - unit.len_utf8()]
+        .trim()
+        .parse()
+        .ok()
+        .filter(|amount| *amount > 0)?;
+    let delay = match unit {
+        'm' => TimeDelta::try_minutes(amount),
+        'h' => TimeDelta::try_hours(amount),
+        'd' => TimeDelta::try_days(amount),
+        _ => None,
+    }?;
+
+    (delay <= TimeDelta::days(MAX_DELAY_DAYS)).then(|| (now + delay, false))
+}
+
+fn render_due(messages: &messages::Schedule, job: &Job) -> String {
+    if job.daily {
+        let time = job.due.format("%R");
+        messages::render(&messages.daily, &[("id", &job.id), ("time", &time)])
+    } else {
+        let date = format!("<t:{}:f>", job.due.timestamp());
+        messages::render(&messages.once, &[("id", &job.id), ("date", &date)])
+    }
+}
+
+/// Asks the model in a conversation of its own and posts the answer in the job channel.
+async fn answer(
+    http: &serenity::Http,
+    data: &BotDataInner,
+    job: &Job,
+    policy: Option<String>,
+) -> Result<(), InternalError> {
+    let conf = data.conf();
+
+    data.usage.record_prompt(job.guild, job.user);
+    let mut session = data
+        .sbuilder
+        .create_chat(data.history_size(job.guild) as usize);
+    session.set_policy(policy);
+    session.set_max_tokens(Some(chat::max_tokens_for(
+        config::DISCORD_MESSAGE_LIMIT as usize,
+    )));
+    let response = match session.send_message(job.prompt.clone(), None).await {
+        Ok(response) => response,
+        Err(err) => {
+            data.usage.record_error(job.guild);
+
+            return Err(Box::from(err));
+        }
+    };
+    data.usage
+        .record_tokens(job.guild, &response.model, None, response.usage);
+
+    let user = serenity::UserId::new(job.user).mention().to_string();
+    let prompt = truncate_chars(&job.prompt, config::DISCORD_MESSAGE_LIMIT as usize / 4);
+    let header = messages::render(
+        &conf.messages.schedule.posted,
+        &[("user", &user), ("prompt", &prompt)],
+    );
+    let budget =
+        (config::DISCORD_MESSAGE_LIMIT as usize).saturating_sub(header.chars().count() + 2);
+    let content = format!("{header}\n\n{}", truncate_chars(&response.content, budget));
+
+    let message = serenity::CreateMessage::new()
+        .content(content)
+        .allowed_mentions(serenity::CreateAllowedMentions::new().users([job.user]));
+    serenity::ChannelId::new(job.channel)
+        .send_message(http, message)
+        .await?;
+
+    Ok(())
+}
+
+/// Answers the job through the guards of the `prompt` command, as if its author sent it.
+async fn dispatch(
+    ctx: &serenity::Context,
+    data: &BotDataInner,
+    job: &Job,
+) -> Result<(), guard::Refusal> {
+    let prompter = guard::Prompter::cached(
+        &ctx.cache,
+        job.guild,
+        serenity::UserId::new(job.user),
+        serenity::ChannelId::new(job.channel),
+        "schedule",
+    );
+    let admitted = guard::admit(ctx, data, &prompter, &job.prompt, false).await?;
+
+    answer(&ctx.http, data, job, admitted.policy)
+        .await
+        .map_err(|err| {
+            log::warn!("failed to answer scheduled prompt #{}: {err}", job.id);
+
+            let alerts = &data.conf().messages.alerts;
+            guard::Refusal::Alert(failure_alert(alerts, err.as_ref()).to_string())
+        })
+}
+
+/// Tells the author of the job in its channel why it wasn't answered.
+async fn report_failure(http: &serenity::Http, data: &BotDataInner, job: &Job, reason: &str) {
+    let user = serenity::UserId::new(job.user).mention().to_string();
+    let content = messages::render(
+        &data.conf().messages.schedule.failed,
+        &[("user", &user), ("id", &job.id), ("reason", &reason)],
+    );
+    let message = serenity::CreateMessage::new()
+        .content(content)
+        .allowed_mentions(serenity::CreateAllowedMentions::new().users([job.user]));
+
+    let channel = serenity::ChannelId::new(job.channel);
+    if let Err(err) = channel.send_message(http, message).await {
+        log::warn!(
+            "failed to report unanswered scheduled prompt #{} in channel {channel}: {err}",
+            job.id
+        );
+    }
+}
+
+/// Answers due jobs of the guilds served by this process in the background, while enabled
+/// in config.
+pub(super) fn start(ctx: serenity::Context, data: BotData) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(DISPATCH_POLL).await;
+
+            let conf = data.conf();
+            if !conf.schedule.enabled || data.is_under_maintenance() || data.is_flushing() {
+                continue;
+            }
+
+            // Jobs are only answered by the process running the shard of their guild.
+            let now = Utc::now();
+            let due: Vec<_> = data
+                .scheduler
+                .due(now)
+                .into_iter()
+                .filter(|job| conf.bot.shards.is_none_or(|shards| shards.owns(job.guild)))
+                .collect();
+            if due.is_empty() {
+                continue;
+            }
+
+            for job in &due {
+                match dispatch(&ctx, &data, job).await {
+                    Ok(()) => (),
+                    Err(guard::Refusal::Unanswerable) => {
+                        log::info!("scheduled prompt #{} can't be answered", job.id);
+                    }
+                    Err(guard::Refusal::Alert(reason)) => {
+                        report_failure(&ctx.http, &data, job, &reason).await;
+                    }
+                }
+                data.scheduler.answered(job, now);
+            }
+
+            persist(&data).await;
+        }
+    });
+}
+
+/// Has the model answer a prompt in this channel later on
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    user_cooldown = 4,
+    required_permissions = "SEND_MESSAGES",
+    on_error = "handle_command_error"
+)]
+pub async fn schedule(
+    ctx: Context<'_>,
+    #[description = "in 30m, at 18:00 or daily 09:00 (UTC)"] when: String,
+    #[description = "message to send"]
+    #[rest]
+    prompt: String,
+) -> Result<(), InternalError> {
+    let data = ctx.data();
+    let conf = data.conf();
+    let messages = &conf.messages.schedule;
+    let guild = ctx.guild_id().unwrap().get();
+    let user = ctx.author().id.get();
+
+    let title = if !conf.schedule.enabled {
+        messages.disabled.clone()
+    } else if let Some((due, daily)) = parse_when(&when, Utc::now()) {
+        let prompt = prompt.trim();
+        let max = data.prompt_size(guild);
+        if prompt.is_empty() || prompt.chars().count() > max as usize {
+            messages::render(&messages.too_long, &[("max", &max)])
+        } else if data.scheduler.user_jobs(guild, user).len() >= conf.schedule.max_jobs as usize {
+            messages::render(&messages.limit_reached, &[("max", &conf.schedule.max_jobs)])
+        } else {
+            let mut job = Job {
+                id: 0,
+                guild,
+                channel: ctx.channel_id().get(),
+                user,
+                prompt: prompt.to_string(),
+                due,
+                daily,
+            };
+            job.id = data.scheduler.add(job.clone());
+            persist(data).await;
+
+            let due = render_due(messages, &job);
+            messages::render(&messages.scheduled, &[("due", &due)])
+        }
+    } else {
+        messages::render(&messages.invalid_when, &[("max", &MAX_DELAY_DAYS)])
+    };
+
+    let embed = serenity::CreateEmbed::new().title(title);
+    send_ephemeral_embedded_reply(ctx, embed).await?;
+
+    Ok(())
+}
+
+/// Lists your scheduled prompts
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    user_cooldown = 2,
+    required_permissions = "SEND_MESSAGES",
+    on_error = "handle_command_error"
+)]
+pub async fn schedules(ctx: Context<'_>) -> Result<(), InternalError> {
+    let data = ctx.data();
+    let conf = data.conf();
+    let messages = &conf.messages.schedule;
+
+    let jobs = data
+        .scheduler
+        .user_jobs(ctx.guild_id().unwrap().get(), ctx.author().id.get());
+
+    if jobs.is_empty() {
+        let embed = serenity::CreateEmbed::new().title(&messages.empty);
+        send_ephemeral_embedded_reply(ctx, embed).await?;
+
+        return Ok(());
+    }
+
+    let embed = serenity::CreateEmbed::new()
+        .title(messages::render(
+            &messages.list_title,
+            &[
+                ("count", &jobs.len()),
+                ("plural", &plural(jobs.len() as u64)),
+                ("max", &conf.schedule.max_jobs),
+            ],
+        ))
+        .fields(jobs.iter().map(|job| {
+            (
+                render_due(messages, job),
+                truncate_field_value(&job.prompt),
+                false,
+            )
+        }));
+    send_ephemeral_embedded_reply(ctx, embed).await?;
+
+    Ok(())
+}
+
+/// Removes one of your scheduled prompts
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    user_cooldown = 2,
+    required_permissions = "SEND_MESSAGES",
+    on_error = "handle_command_error"
+)]
+pub async fn unschedule(
+    ctx: Context<'_>,
+    #[description = "number of the scheduled prompt, as listed by /schedules"] id: u64,
+) -> Result<(), InternalError> {
+    let data = ctx.data();
+    let messages = &data.conf().messages.schedule;
+    let guild = ctx.guild_id().unwrap().get();
+    let user = ctx.author().id.get();
+
+    let title = if data.scheduler.remove(guild, user, id) {
+        persist(data).await;
+
+        messages::render(&messages.removed, &[("id", &id)])
+    } else {
+        messages::render(&messages.missing, &[("id", &id)])
+    };
+    let embed = serenity::CreateEmbed::new().title(title);
+    send_ephemeral_embedded_reply(ctx, embed).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> DateTime<Utc> {
+        "2024-05-01T12:00:00Z".parse().unwrap()
+    }
+
+    #[test]
+    fn parses_delays() {
+        let now = now();
+
+        assert_eq!(
+            parse_when("in 30m", now),
+            Some((now + TimeDelta::minutes(30), false))
+        );
+        assert_eq!(
+            parse_when("2h", now),
+            Some((now + TimeDelta::hours(2), false))
+        );
+        assert_eq!(
+            parse_when(" IN 1D ", now),
+            Some((now + TimeDelta::days(1), false))
+        );
+    }
+
+    #[test]
+    fn parses_times_of_day() {
+        let now = now();
+        let later: DateTime<Utc> = "2024-05-01T18:00:00Z".parse().unwrap();
+        let tomorrow: DateTime<Utc> = "2024-05-02T09:00:00Z".parse().unwrap();
+
+        assert_eq!(parse_when("at 18:00", now), Some((later, false)));
+        assert_eq!(parse_when("18:00", now), Some((later, false)));
+        assert_eq!(parse_when("daily 09:00", now), Some((tomorrow, true)));
+        // The current time is already gone.
+        assert_eq!(
+            parse_when("12:00", now),
+            Some((now + TimeDelta::days(1), false))
+        );
+    }
+
+    #[test]
+    fn refuses_invalid_input() {
+        let now = now();
+
+        for when in [
+            "", "in", "in m", "in 0m", "in -5m", "in 5s", "in 31d", "at 25:00", "daily", "tomorrow",
+        ] {
+            assert_eq!(parse_when(when, now), None, "{when:?}");
+        }
+    }
+
+    #[test]
+    fn refuses_multibyte_input() {
+        let now = now();
+
+        for when in ["é", "in 5é", "in 5ñm", "at 1８:00", "daily 🕘"] {
+            assert_eq!(parse_when(when, now), None, "{when:?}");
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use std::collections::HashSet;
+
+use poise::serenity_prelude as serenity;
+
+use crate::{chat, messages};
+
+use super::{
+    handle_command_error, send_ephemeral_embedded_reply, send_paginated_embeds, snapshot, Context,
+    InternalError, SessionName,
+};
+
+const QUERY_MAX_LEN: usize = 100;
+const MATCHES_PER_PAGE: usize = 5;
+const MAX_MATCHES: usize = 50;
+
+/// Looks for text in the interactions of your sessions, including the snapshotted ones
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    user_cooldown = 4,
+    required_permissions = "SEND_MESSAGES",
+    on_error = "handle_command_error"
+)]
+pub async fn search(
+    ctx: Context<'_>,
+    #[description = "text to look for"]
+    #[rest]
+    query: String,
+) -> Result<(), InternalError> {
+    let data = ctx.data();
+    let conf = data.conf();
+    let messages = &conf.messages.search;
+
+    let query = query.trim();
+    if query.is_empty() || query.chars().count() > QUERY_MAX_LEN {
+        let embed = serenity::CreateEmbed::new().title(messages::render(
+            &messages.invalid_query,
+            &[("max", &QUERY_MAX_LEN)],
+        ));
+        send_ephemeral_embedded_reply(ctx, embed).await?;
+
+        return Ok(());
+    }
+
+    let guild = ctx.guild_id().unwrap().get();
+    let user = ctx.author().id.get();
+
+    let sessions = data.owned_sessions(guild, user);
+
+    let mut matches: Vec<(SessionName, chat::Match)> = Vec::new();
+    for (name, session) in sessions {
+        let found = session.session.lock().await.search(query);
+        matches.extend(found.into_iter().map(|found| (name.clone(), found)));
+    }
+
+    if let Some(dir) = &conf.persistence.snapshot_dir {
+        match snapshot::user_sessions(data, dir, guild, user).await {
+            Ok(snapshotted) => {
+                for (name, session) in snapshotted {
+                    let found = session.search(query);
+                    matches.extend(found.into_iter().map(|found| (name.clone(), found)));
+                }
+            }
+            Err(err) => log::warn!("failed to search snapshotted sessions: {err}"),
+        }
+    }
+
+    // Restored sessions are both in memory and in the snapshot.
+    let mut seen = HashSet::new();
+    matches.retain(|(name, found)| seen.insert((name.clone(), found.at, found.in_prompt)));
+    matches.sort_by_key(|(_, found)| std::cmp::Reverse(found.at));
+    matches.truncate(MAX_MATCHES);
+
+    if matches.is_empty() {
+        let embed = serenity::CreateEmbed::new()
+            .title(messages::render(&messages.empty, &[("query", &query)]));
+        send_ephemeral_embedded_reply(ctx, embed).await?;
+
+        return Ok(());
+    }
+
+    let title = messages::render(
+        &messages.title,
+        &[("query", &query), ("count", &matches.len())],
+    );
+    let pages = matches
+        .chunks(MATCHES_PER_PAGE)
+        .map(|chunk| {
+            let fields = chunk.iter().map(|(name, found)| {
+                let template = if found.in_prompt {
+                    &messages.prompt_match
+                } else {
+                    &messages.response_match
+                };
+                let date = format!("<t:{}:f>", found.at.timestamp());
+
+                (
+                    messages::render(template, &[("name", name), ("date", &date)]),
+                    found.excerpt.clone(),
+                    false,
+                )
+            });
+
+            serenity::CreateEmbed::new().title(&title).fields(fields)
+        })
+        .collect();
+
+    send_paginated_embeds(ctx, pages, true).await?;
+
+    Ok(())
+}
@@ -0,0 +1,308 @@
+use poise::serenity_prelude as serenity;
+
+use crate::messages;
+
+use super::{
+    handle_command_error, send_ephemeral_embedded_reply, tombstones, Context, InternalError,
+    SessionCreation, SessionName, SessionPin,
+};
+
+pub(super) const SESSION_NAME_MAX_LEN: usize = 32;
+
+/// Validates the name, also used for macros.
+pub(super) fn parse_session_name(name: &str) -> Option<SessionName> {
+    let name = name.trim();
+
+    let valid = !name.is_empty()
+        && name.chars().count() <= SESSION_NAME_MAX_LEN
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+
+    valid.then(|| name.to_lowercase())
+}
+
+async fn send_invalid_name_alert(ctx: Context<'_>) -> Result<(), serenity::Error> {
+    let embed = serenity::CreateEmbed::new().title(messages::render(
+        &ctx.data().conf().messages.sessions.invalid_name,
+        &[("max", &SESSION_NAME_MAX_LEN)],
+    ));
+    send_ephemeral_embedded_reply(ctx, embed).await?;
+
+    Ok(())
+}
+
+/// Manages your conversations
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    subcommands("new", "switch", "list", "delete"),
+    subcommand_required,
+    on_error = "handle_command_error"
+)]
+pub async fn sessions(_ctx: Context<'_>) -> Result<(), InternalError> {
+    Ok(())
+}
+
+/// Starts a new conversation and switches to it
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    user_cooldown = 2,
+    required_permissions = "SEND_MESSAGES",
+    on_error = "handle_command_error"
+)]
+async fn new(
+    ctx: Context<'_>,
+    #[description = "name of the conversation"] name: String,
+) -> Result<(), InternalError> {
+    let Some(name) = parse_session_name(&name) else {
+        send_invalid_name_alert(ctx).await?;
+
+        return Ok(());
+    };
+
+    let data = ctx.data();
+    let guild = ctx.guild_id().unwrap().get();
+    let user = ctx.author().id.get();
+
+    let messages = &data.conf().messages.sessions;
+    let title = match data.create_session(guild, user, name.clone()) {
+        SessionCreation::Created => messages::render(&messages.created, &[("name", &name)]),
+        SessionCreation::AlreadyExists => {
+            messages::render(&messages.already_exists, &[("name", &name)])
+        }
+        SessionCreation::LimitReached => messages::render(
+            &messages.limit_reached,
+            &[("max", &data.conf().chat.max_sessions)],
+        ),
+    };
+    let embed = serenity::CreateEmbed::new().title(title);
+    send_ephemeral_embedded_reply(ctx, embed).await?;
+
+    Ok(())
+}
+
+/// Switches to another conversation
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    user_cooldown = 2,
+    required_permissions = "SEND_MESSAGES",
+    on_error = "handle_command_error"
+)]
+async fn switch(
+    ctx: Context<'_>,
+    #[description = "name of the conversation"] name: String,
+) -> Result<(), InternalError> {
+    let Some(name) = parse_session_name(&name) else {
+        send_invalid_name_alert(ctx).await?;
+
+        return Ok(());
+    };
+
+    let guild = ctx.guild_id().unwrap().get();
+    let user = ctx.author().id.get();
+
+    let data = ctx.data();
+    let messages = &data.conf().messages.sessions;
+    let title = if data.switch_session(guild, user, name.clone()) {
+        messages::render(&messages.switched, &[("name", &name)])
+    } else {
+        messages::render(&messages.missing, &[("name", &name)])
+    };
+    let embed = serenity::CreateEmbed::new().title(title);
+    send_ephemeral_embedded_reply(ctx, embed).await?;
+
+    Ok(())
+}
+
+/// Lists your conversations
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    user_cooldown = 2,
+    required_permissions = "SEND_MESSAGES",
+    on_error = "handle_command_error"
+)]
+async fn list(ctx: Context<'_>) -> Result<(), InternalError> {
+    let data = ctx.data();
+    let guild = ctx.guild_id().unwrap().get();
+    let user = ctx.author().id.get();
+
+    let (sessions, selected) = data.user_sessions(guild, user);
+    let description = sessions
+        .iter()
+        .map(|(name, title)| {
+            let title = title
+                .as_deref()
+                .map(|title| format!(" - *{title}*"))
+                .unwrap_or_default();
+
+            if *name == selected {
+                format!(":arrow_forward: **{name}**{title}")
+            } else {
+                format!(":white_small_square: {name}{title}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let embed = serenity::CreateEmbed::new()
+        .title(messages::render(
+            &data.conf().messages.sessions.list_title,
+            &[
+                ("count", &sessions.len()),
+                ("max", &data.conf().chat.max_sessions),
+            ],
+        ))
+        .description(description);
+    send_ephemeral_embedded_reply(ctx, embed).await?;
+
+    Ok(())
+}
+
+/// Deletes a conversation
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    user_cooldown = 2,
+    required_permissions = "SEND_MESSAGES",
+    on_error = "handle_command_error"
+)]
+async fn delete(
+    ctx: Context<'_>,
+    #[description = "name of the conversation"] name: String,
+) -> Result<(), InternalError> {
+    let Some(name) = parse_session_name(&name) else {
+        send_invalid_name_alert(ctx).await?;
+
+        return Ok(());
+    };
+
+    let guild = ctx.guild_id().unwrap().get();
+    let user = ctx.author().id.get();
+
+    let data = ctx.data();
+    let messages = &data.conf().messages.sessions;
+    let grace = data.undo_grace();
+    let embed = if data.delete_session(guild, user, name.clone()) {
+        let embed = serenity::CreateEmbed::new()
+            .title(messages::render(&messages.deleted, &[("name", &name)]));
+        if grace.is_zero() {
+            embed
+        } else {
+            embed.description(messages::render(
+                &messages.undo_hint,
+                &[("minutes", &grace.as_secs().div_ceil(60))],
+            ))
+        }
+    } else {
+        serenity::CreateEmbed::new().title(messages::render(&messages.missing, &[("name", &name)]))
+    };
+    send_ephemeral_embedded_reply(ctx, embed).await?;
+
+    Ok(())
+}
+
+/// Brings back the conversations you deleted in the last few minutes
+#[poise::command(
+    slash_command,
+    prefix_command,
+    rename = "undo-reset",
+    guild_only,
+    user_cooldown = 2,
+    required_permissions = "SEND_MESSAGES",
+    on_error = "handle_command_error"
+)]
+pub async fn undo_reset(ctx: Context<'_>) -> Result<(), InternalError> {
+    let data = ctx.data();
+    let messages = &data.conf().messages.sessions;
+    let guild = ctx.guild_id().unwrap().get();
+    let user = ctx.author().id.get();
+
+    let title = match data.undo_deletion(guild, user, tombstones::Cause::Reset) {
+        Some(restored) => messages::render(
+            &messages.restored,
+            &[
+                ("count", &restored),
+                ("plural", &messages::plural(restored as u64)),
+            ],
+        ),
+        None => messages.nothing_to_restore.clone(),
+    };
+    let embed = serenity::CreateEmbed::new().title(title);
+    send_ephemeral_embedded_reply(ctx, embed).await?;
+
+    Ok(())
+}
+
+/// Keeps your current conversation through the next history resets
+#[poise::command(
+    slash_command,
+    prefix_command,
+    rename = "pin-session",
+    guild_only,
+    user_cooldown = 2,
+    required_permissions = "SEND_MESSAGES",
+    on_error = "handle_command_error"
+)]
+pub async fn pin_session(ctx: Context<'_>) -> Result<(), InternalError> {
+    let data = ctx.data();
+    let conf = data.conf();
+    let messages = &conf.messages.sessions;
+    let guild = ctx.guild_id().unwrap().get();
+    let user = ctx.author().id.get();
+
+    let title = if conf.chat.max_pins == 0 {
+        messages.pins_disabled.clone()
+    } else {
+        match data.pin_session(guild, user) {
+            (name, SessionPin::Pinned) => messages::render(
+                &messages.pinned,
+                &[("name", &name), ("cycles", &conf.chat.pin_cycles)],
+            ),
+            (name, SessionPin::AlreadyPinned) => {
+                messages::render(&messages.already_pinned, &[("name", &name)])
+            }
+            (_, SessionPin::LimitReached) => {
+                messages::render(&messages.pin_limit_reached, &[("max", &conf.chat.max_pins)])
+            }
+        }
+    };
+    let embed = serenity::CreateEmbed::new().title(title);
+    send_ephemeral_embedded_reply(ctx, embed).await?;
+
+    Ok(())
+}
+
+/// Lets your current conversation be reset along with the others
+#[poise::command(
+    slash_command,
+    prefix_command,
+    rename = "unpin-session",
+    guild_only,
+    user_cooldown = 2,
+    required_permissions = "SEND_MESSAGES",
+    on_error = "handle_command_error"
+)]
+pub async fn unpin_session(ctx: Context<'_>) -> Result<(), InternalError> {
+    let data = ctx.data();
+    let guild = ctx.guild_id().unwrap().get();
+    let user = ctx.author().id.get();
+
+    let messages = &data.conf().messages.sessions;
+    let title = match data.unpin_session(guild, user) {
+        (name, true) => messages::render(&messages.unpinned, &[("name", &name)]),
+        (name, false) => messages::render(&messages.not_pinned, &[("name", &name)]),
+    };
+    let embed = serenity::CreateEmbed::new().title(title);
+    send_ephemeral_embedded_reply(ctx, embed).await?;
+
+    Ok(())
+}
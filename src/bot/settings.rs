@@ -0,0 +1,487 @@
+use std::{collections::HashMap, io, path::Path};
+
+use poise::serenity_prelude::{self as serenity, Mentionable};
+
+use crate::{
+    chat,
+    messages::{self, plural},
+};
+
+use super::{
+    command_set, handle_command_error, send_ephemeral_embedded_reply, truncate_field_value,
+    BotDataInner, ChannelId, Context, GuildId, InternalError,
+};
+
+/// Examples a persona may have, each one sent along every prompt.
+pub(super) const MAX_EXAMPLES: usize = 5;
+
+const SETTINGS_FILE: &str = "settings.json";
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("failed to access settings file")]
+    Io(#[from] io::Error),
+    #[error("failed to (de)serialize settings")]
+    Json(#[from] serde_json::Error),
+}
+
+/// Settings guild admins tune at runtime, merged over the config file.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub(super) struct Settings {
+    /// Instructions new sessions start with.
+    pub persona: Option<String>,
+    /// Exchanges new sessions with the persona are shown before their history.
+    pub examples: Vec<chat::Example>,
+    /// Interactions new sessions keep, capped by the configured history size.
+    pub history_size: Option<u8>,
+    /// Answers slash command prompts with replies only their author sees.
+    pub private_replies: bool,
+    /// Channels the bot may be used in, any when empty.
+    pub allowed_channels: Vec<ChannelId>,
+    /// Top-level commands turned on or off, over the config file.
+    pub commands: HashMap<String, bool>,
+}
+
+impl Settings {
+    /// Whether the bot may be used in the channel, or the one the thread belongs to.
+    pub fn allows(&self, channel: ChannelId, parent: Option<ChannelId>) -> bool {
+        self.allowed_channels.is_empty()
+            || self.allowed_channels.contains(&channel)
+            || parent.is_some_and(|parent| self.allowed_channels.contains(&parent))
+    }
+}
+
+async fn save(data: &BotDataInner, dir: &Path) -> Result<(), Error> {
+    let _saving = data.settings_saving.lock().await;
+
+    let settings: HashMap<GuildId, Settings> = data
+        .settings
+        .iter()
+        .map(|entry| (*entry.key(), entry.value().clone()))
+        .collect();
+    let contents = serde_json::to_vec(&settings)?;
+
+    tokio::fs::create_dir_all(dir).await?;
+    let path = dir.join(data.conf().bot.shard_file(SETTINGS_FILE));
+    let tmp_path = path.with_extension("json.tmp");
+    tokio::fs::write(&tmp_path, contents).await?;
+    tokio::fs::rename(tmp_path, path).await?;
+
+    Ok(())
+}
+
+/// Loads the settings kept in the directory, returning how many guilds had some.
+///
+/// They're written on every change, so they replace the ones of an older snapshot.
+pub(super) async fn load(data: &BotDataInner, dir: &Path) -> Result<usize, Error> {
+    let path = dir.join(data.conf().bot.shard_file(SETTINGS_FILE));
+    let contents = match tokio::fs::read(path).await {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err.into()),
+    };
+    let settings: HashMap<GuildId, Settings> = serde_json::from_slice(&contents)?;
+
+    let loaded = settings.len();
+    data.settings.clear();
+    for (guild, settings) in settings {
+        data.settings.insert(guild, settings);
+    }
+
+    Ok(loaded)
+}
+
+/// Writes the settings down, if there's somewhere to keep them.
+pub(super) async fn persist(data: &BotDataInner) {
+    let Some(dir) = &data.conf().persistence.snapshot_dir else {
+        return;
+    };
+
+    if let Err(err) = save(data, dir).await {
+        log::error!("failed to save settings: {err}");
+    }
+}
+
+/// Views and adjusts the settings of this server
+#[poise::command(
+    slash_command,
+    prefix_command,
+    rename = "config",
+    guild_only,
+    default_member_permissions = "MANAGE_GUILD",
+    subcommands(
+        "show",
+        "persona",
+        "example",
+        "history_size",
+        "private_replies",
+        "allow_channel",
+        "disallow_channel",
+        "commands"
+    ),
+    subcommand_required,
+    on_error = "handle_command_error"
+)]
+pub async fn guild_config(_ctx: Context<'_>) -> Result<(), InternalError> {
+    Ok(())
+}
+
+async fn send_updated(ctx: Context<'_>) -> Result<(), InternalError> {
+    let embed = serenity::CreateEmbed::new().title(&ctx.data().conf().messages.settings.updated);
+    send_ephemeral_embedded_reply(ctx, embed).await?;
+
+    Ok(())
+}
+
+/// Shows the current settings of this server
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    user_cooldown = 2,
+    required_permissions = "MANAGE_GUILD",
+    on_error = "handle_command_error"
+)]
+async fn show(ctx: Context<'_>) -> Result<(), InternalError> {
+    let data = ctx.data();
+    let conf = data.conf();
+    let messages = &conf.messages.settings;
+    let guild = ctx.guild_id().unwrap().get();
+    let settings = data.settings(guild);
+
+    let persona = settings
+        .persona
+        .as_deref()
+        .map_or_else(|| messages.unset.clone(), truncate_field_value);
+    let examples = messages::render(
+        &messages.examples_value,
+        &[
+            ("count", &settings.examples.len()),
+            ("plural", &plural(settings.examples.len() as u64)),
+        ],
+    );
+    let history_size = data.history_size(guild);
+    let history_size = messages::render(
+        &messages.history_size_value,
+        &[
+            ("count", &history_size),
+            ("plural", &plural(history_size as u64)),
+        ],
+    );
+    let private_replies = if settings.private_replies {
+        &messages.enabled
+    } else {
+        &messages.disabled
+    };
+    let allowed_channels = if settings.allowed_channels.is_empty() {
+        messages.everywhere.clone()
+    } else {
+        settings
+            .allowed_channels
+            .iter()
+            .map(|&channel| serenity::ChannelId::new(channel).mention().to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let commands = &ctx.framework().options().commands;
+    let disabled_commands: Vec<_> = command_set::toggleable(commands)
+        .into_iter()
+        .filter(|command| !command_set::is_enabled(data, guild, command))
+        .map(|command| format!("`/{command}`"))
+        .collect();
+    let disabled_commands = if disabled_commands.is_empty() {
+        messages.none.clone()
+    } else {
+        disabled_commands.join(", ")
+    };
+
+    let embed = serenity::CreateEmbed::new()
+        .title(&messages.title)
+        .field(&messages.persona, persona, false)
+        .field(&messages.examples, examples, true)
+        .field(&messages.history_size, history_size, true)
+        .field(&messages.private_replies, private_replies, true)
+        .field(
+            &messages.allowed_channels,
+            truncate_field_value(&allowed_channels),
+            false,
+        )
+        .field(
+            &messages.disabled_commands,
+            truncate_field_value(&disabled_commands),
+            false,
+        );
+    send_ephemeral_embedded_reply(ctx, embed).await?;
+
+    Ok(())
+}
+
+/// Sets the instructions new sessions start with, or clears them when empty
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    user_cooldown = 2,
+    required_permissions = "MANAGE_GUILD",
+    on_error = "handle_command_error"
+)]
+async fn persona(
+    ctx: Context<'_>,
+    #[description = "e.g. you're a friendly pirate"]
+    #[rest]
+    instructions: Option<String>,
+) -> Result<(), InternalError> {
+    let data = ctx.data();
+    let guild = ctx.guild_id().unwrap().get();
+
+    let instructions = instructions.unwrap_or_default().trim().to_string();
+    let max = data.prompt_size(guild);
+    if instructions.chars().count() > max as usize {
+        let embed = serenity::CreateEmbed::new().title(messages::render(
+            &data.conf().messages.settings.persona_too_long,
+            &[("max", &max)],
+        ));
+        send_ephemeral_embedded_reply(ctx, embed).await?;
+
+        return Ok(());
+    }
+
+    data.update_settings(guild, |settings| {
+        settings.persona = (!instructions.is_empty()).then_some(instructions);
+    })
+    .await;
+
+    send_updated(ctx).await
+}
+
+/// Adds an exchange for the persona to follow, or removes every one when both are left out
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    user_cooldown = 2,
+    required_permissions = "MANAGE_GUILD",
+    on_error = "handle_command_error"
+)]
+async fn example(
+    ctx: Context<'_>,
+    #[description = "e.g. how do I list every table?"] prompt: Option<String>,
+    #[description = "what the persona would reply"] reply: Option<String>,
+) -> Result<(), InternalError> {
+    let data = ctx.data();
+    let conf = data.conf();
+    let messages = &conf.messages.settings;
+    let guild = ctx.guild_id().unwrap().get();
+
+    let example = match (prompt, reply) {
+        (None, None) => None,
+        (Some(prompt), Some(reply)) if !prompt.trim().is_empty() && !reply.trim().is_empty() => {
+            Some(chat::Example {
+                prompt: prompt.trim().to_string(),
+                response: reply.trim().to_string(),
+            })
+        }
+        _ => {
+            let embed = serenity::CreateEmbed::new().title(&messages.example_incomplete);
+            send_ephemeral_embedded_reply(ctx, embed).await?;
+
+            return Ok(());
+        }
+    };
+
+    let Some(example) = example else {
+        data.update_settings(guild, |settings| settings.examples.clear())
+            .await;
+
+        return send_updated(ctx).await;
+    };
+
+    let max = data.prompt_size(guild) as usize;
+    let settings = data.settings(guild);
+    let title = if settings.persona.is_none() {
+        Some(messages.example_without_persona.clone())
+    } else if example.prompt.chars().count() > max || example.response.chars().count() > max {
+        Some(messages::render(
+            &messages.example_too_long,
+            &[("max", &max)],
+        ))
+    } else if settings.examples.len() >= MAX_EXAMPLES {
+        Some(messages::render(
+            &messages.examples_limit,
+            &[("max", &MAX_EXAMPLES)],
+        ))
+    } else {
+        None
+    };
+    if let Some(title) = title {
+        let embed = serenity::CreateEmbed::new().title(title);
+        send_ephemeral_embedded_reply(ctx, embed).await?;
+
+        return Ok(());
+    }
+
+    data.update_settings(guild, |settings| settings.examples.push(example))
+        .await;
+
+    send_updated(ctx).await
+}
+
+/// Caps the interactions new sessions keep, or resets it when left out
+#[poise::command(
+    slash_command,
+    prefix_command,
+    rename = "history-size",
+    guild_only,
+    user_cooldown = 2,
+    required_permissions = "MANAGE_GUILD",
+    on_error = "handle_command_error"
+)]
+async fn history_size(
+    ctx: Context<'_>,
+    #[description = "interactions kept per session"] size: Option<u8>,
+) -> Result<(), InternalError> {
+    let data = ctx.data();
+    let guild = ctx.guild_id().unwrap().get();
+
+    let max = data.max_history_size(guild);
+    if size.is_some_and(|size| !(1..=max).contains(&size)) {
+        let embed = serenity::CreateEmbed::new().title(messages::render(
+            &data.conf().messages.settings.history_size_invalid,
+            &[("max", &max)],
+        ));
+        send_ephemeral_embedded_reply(ctx, embed).await?;
+
+        return Ok(());
+    }
+
+    data.update_settings(guild, |settings| {
+        settings.history_size = size;
+    })
+    .await;
+
+    send_updated(ctx).await
+}
+
+/// Makes slash command replies visible to their author only, or to everyone
+#[poise::command(
+    slash_command,
+    prefix_command,
+    rename = "private-replies",
+    guild_only,
+    user_cooldown = 2,
+    required_permissions = "MANAGE_GUILD",
+    on_error = "handle_command_error"
+)]
+async fn private_replies(
+    ctx: Context<'_>,
+    #[description = "whether replies are private"] enabled: bool,
+) -> Result<(), InternalError> {
+    ctx.data()
+        .update_settings(ctx.guild_id().unwrap().get(), |settings| {
+            settings.private_replies = enabled;
+        })
+        .await;
+
+    send_updated(ctx).await
+}
+
+/// Lets the bot be used in a channel, restricting it to the allowed ones
+#[poise::command(
+    slash_command,
+    prefix_command,
+    rename = "allow-channel",
+    guild_only,
+    user_cooldown = 2,
+    required_permissions = "MANAGE_GUILD",
+    on_error = "handle_command_error"
+)]
+async fn allow_channel(
+    ctx: Context<'_>,
+    #[description = "channel to allow"] channel: serenity::GuildChannel,
+) -> Result<(), InternalError> {
+    ctx.data()
+        .update_settings(ctx.guild_id().unwrap().get(), |settings| {
+            if !settings.allowed_channels.contains(&channel.id.get()) {
+                settings.allowed_channels.push(channel.id.get());
+            }
+        })
+        .await;
+
+    send_updated(ctx).await
+}
+
+/// Stops allowing a channel, lifting the restriction once none is left
+#[poise::command(
+    slash_command,
+    prefix_command,
+    rename = "disallow-channel",
+    guild_only,
+    user_cooldown = 2,
+    required_permissions = "MANAGE_GUILD",
+    on_error = "handle_command_error"
+)]
+async fn disallow_channel(
+    ctx: Context<'_>,
+    #[description = "channel to disallow"] channel: serenity::GuildChannel,
+) -> Result<(), InternalError> {
+    ctx.data()
+        .update_settings(ctx.guild_id().unwrap().get(), |settings| {
+            settings
+                .allowed_channels
+                .retain(|&allowed| allowed != channel.id.get());
+        })
+        .await;
+
+    send_updated(ctx).await
+}
+
+async fn autocomplete_command(
+    ctx: Context<'_>,
+    partial: &str,
+) -> Vec<serenity::AutocompleteChoice> {
+    command_set::toggleable(&ctx.framework().options().commands)
+        .into_iter()
+        .filter(|command| command.contains(partial))
+        .map(|command| serenity::AutocompleteChoice::new(command, command))
+        .collect()
+}
+
+/// Turns a command on or off in this server
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    user_cooldown = 2,
+    required_permissions = "MANAGE_GUILD",
+    on_error = "handle_command_error"
+)]
+async fn commands(
+    ctx: Context<'_>,
+    #[description = "command to turn on or off"]
+    #[autocomplete = "autocomplete_command"]
+    command: String,
+    #[description = "whether members may use it"] enabled: bool,
+) -> Result<(), InternalError> {
+    let data = ctx.data();
+    let guild = ctx.guild_id().unwrap().get();
+
+    let command = command.trim().trim_start_matches('/');
+    if !command_set::toggleable(&ctx.framework().options().commands).contains(&command) {
+        let embed = serenity::CreateEmbed::new().title(messages::render(
+            &data.conf().messages.settings.unknown_command,
+            &[("command", &command)],
+        ));
+        send_ephemeral_embedded_reply(ctx, embed).await?;
+
+        return Ok(());
+    }
+
+    data.update_settings(guild, |settings| {
+        settings.commands.insert(command.to_string(), enabled);
+    })
+    .await;
+    command_set::refresh(ctx, guild).await;
+
+    send_updated(ctx).await
+}
@@ -0,0 +1,133 @@
+use std::{fmt::Write, sync::OnceLock};
+
+use poise::serenity_prelude as serenity;
+
+use crate::{chat, messages};
+
+use super::{
+    apply_theme, handle_command_error, send_ephemeral_embedded_reply, Context, InternalError,
+};
+
+const TRANSCRIPT_FILE: &str = "conversation.md";
+
+fn paste_client() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+    CLIENT.get_or_init(reqwest::Client::new)
+}
+
+/// Renders the session as a Markdown document.
+fn render_transcript(title: &str, author: &str, session: &chat::Session) -> String {
+    let mut transcript = format!("# {title}\n\n_{author}_\n");
+
+    if let Some(instructions) = session.instructions() {
+        let _ = write!(transcript, "\n> **Instructions:** {instructions}\n");
+    }
+
+    for interaction in session.history() {
+        let _ = write!(
+            transcript,
+            "\n## You · {}\n\n{}\n\n## Model\n\n{}\n",
+            interaction.at.format("%F %R UTC"),
+            interaction.prompt,
+            interaction.response
+        );
+    }
+
+    transcript
+}
+
+/// Uploads the transcript, returning the link replied by the paste service.
+async fn upload(paste_url: &str, transcript: String) -> Result<String, reqwest::Error> {
+    let link = paste_client()
+        .post(paste_url)
+        .header(
+            reqwest::header::CONTENT_TYPE,
+            "text/markdown; charset=utf-8",
+        )
+        .body(transcript)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+
+    Ok(link.trim().to_string())
+}
+
+/// Shares your current conversation as a Markdown transcript
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    user_cooldown = 10,
+    required_permissions = "SEND_MESSAGES",
+    on_error = "handle_command_error"
+)]
+pub async fn share(ctx: Context<'_>) -> Result<(), InternalError> {
+    let data = ctx.data();
+    let conf = data.conf();
+    let messages = &conf.messages.share;
+    let guild = ctx.guild_id().unwrap().get();
+    let author = ctx.author();
+
+    let name = data.selected_session_name(guild, author.id.get());
+    let session = data.session(guild, author.id.get());
+
+    let transcript = {
+        let chat = session.session.lock().await;
+        if chat.history().next().is_none() {
+            let embed = serenity::CreateEmbed::new()
+                .title(messages::render(&messages.empty, &[("name", &name)]));
+            send_ephemeral_embedded_reply(ctx, embed).await?;
+
+            return Ok(());
+        }
+
+        let title = session.title().unwrap_or(&name).to_string();
+        let byline = messages::render(
+            &messages.transcript_author,
+            &[("user", &author.name), ("name", &name)],
+        );
+        render_transcript(&title, &byline, &chat)
+    };
+
+    let mut reply = poise::CreateReply::default().reply(true);
+
+    let link = match &conf.share.paste_url {
+        Some(paste_url) => match upload(paste_url, transcript.clone()).await {
+            Ok(link) => Some(link),
+            Err(err) => {
+                log::warn!("failed to upload shared session, attaching it instead: {err}");
+
+                None
+            }
+        },
+        None => None,
+    };
+
+    let embed = match link {
+        Some(link) => serenity::CreateEmbed::new()
+            .title(messages::render(
+                &messages.linked,
+                &[("user", &author.name), ("name", &name)],
+            ))
+            .description(link),
+        None => {
+            reply = reply.attachment(serenity::CreateAttachment::bytes(
+                transcript.into_bytes(),
+                TRANSCRIPT_FILE,
+            ));
+
+            serenity::CreateEmbed::new().title(messages::render(
+                &messages.attached,
+                &[("user", &author.name), ("name", &name)],
+            ))
+        }
+    };
+
+    ctx.send(reply.embed(apply_theme(&conf.appearance, embed)))
+        .await?;
+
+    Ok(())
+}
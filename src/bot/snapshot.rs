@@ -0,0 +1,211 @@
+use std::{collections::HashMap, io, path::Path};
+
+use crate::chat;
+
+use super::{
+    experiment, limits, settings, BotDataInner, ChannelId, ChatSession, Epoched, GuildId,
+    SessionKey, SessionName, UserId,
+};
+
+const SNAPSHOT_FILE: &str = "sessions.json";
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("failed to access snapshot file")]
+    Io(#[from] io::Error),
+    #[error("failed to (de)serialize snapshot")]
+    Json(#[from] serde_json::Error),
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+struct SessionEntry {
+    title: Option<String>,
+    session: chat::Snapshot,
+    #[serde(default)]
+    arm: experiment::Arm,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Default)]
+struct GuildSnapshot {
+    sessions: Vec<(UserId, SessionName, SessionEntry)>,
+    selected: Vec<(UserId, SessionName)>,
+    shared: Vec<(ChannelId, SessionEntry)>,
+    /// ISO 639-3 codes of the reply languages picked by members.
+    #[serde(default)]
+    languages: Vec<(UserId, String)>,
+    #[serde(default)]
+    macros: Vec<(UserId, String, String)>,
+    #[serde(default)]
+    settings: Option<settings::Settings>,
+    #[serde(default)]
+    limits: Option<limits::Limits>,
+}
+
+async fn session_entry(session: &ChatSession) -> SessionEntry {
+    SessionEntry {
+        title: session.title().map(str::to_string),
+        session: session.session.lock().await.snapshot(),
+        arm: session.arm,
+    }
+}
+
+fn restore_session(data: &BotDataInner, guild: GuildId, entry: SessionEntry) -> ChatSession {
+    let history_size = data.history_size(guild) as usize;
+    let mut session = data.sbuilder.restore_chat(entry.session, history_size);
+    session.set_model(data.arm_model(entry.arm));
+    let session = ChatSession::new(session, entry.arm);
+    if let Some(title) = entry.title {
+        let _ = session.title.set(title);
+    }
+
+    session
+}
+
+/// Writes every session into the snapshot directory, replacing the previous snapshot.
+pub(super) async fn save(data: &BotDataInner, dir: &Path) -> Result<(), Error> {
+    let mut snapshot: HashMap<GuildId, GuildSnapshot> = HashMap::new();
+
+    // Sessions are collected first, so their locks aren't awaited while holding map shards.
+    let sessions: Vec<_> = data.live_sessions().collect();
+
+    for (guild, key, session) in sessions {
+        let guild_snapshot = snapshot.entry(guild).or_default();
+        match key {
+            SessionKey::User(user, name) => {
+                guild_snapshot
+                    .sessions
+                    .push((user, name, session_entry(&session).await));
+            }
+            SessionKey::Channel(channel) => {
+                guild_snapshot
+                    .shared
+                    .push((channel, session_entry(&session).await));
+            }
+        }
+    }
+
+    let epoch = data.epoch();
+    for entry in data.selected.iter().filter(|entry| entry.is_live(epoch)) {
+        let (guild, user) = *entry.key();
+        snapshot
+            .entry(guild)
+            .or_default()
+            .selected
+            .push((user, entry.value.clone()));
+    }
+
+    for entry in data.languages.iter() {
+        let (guild, user) = *entry.key();
+        snapshot
+            .entry(guild)
+            .or_default()
+            .languages
+            .push((user, entry.value().code().to_string()));
+    }
+
+    for entry in data.macros.iter() {
+        let (guild, user, name) = entry.key().clone();
+        snapshot
+            .entry(guild)
+            .or_default()
+            .macros
+            .push((user, name, entry.value().clone()));
+    }
+
+    for entry in data.settings.iter() {
+        snapshot.entry(*entry.key()).or_default().settings = Some(entry.value().clone());
+    }
+
+    for entry in data.limits.iter() {
+        snapshot.entry(*entry.key()).or_default().limits = Some(*entry.value());
+    }
+
+    let contents = serde_json::to_vec(&snapshot)?;
+
+    tokio::fs::create_dir_all(dir).await?;
+    let path = dir.join(data.conf().bot.shard_file(SNAPSHOT_FILE));
+    let tmp_path = path.with_extension("json.tmp");
+    tokio::fs::write(&tmp_path, contents).await?;
+    tokio::fs::rename(tmp_path, path).await?;
+
+    Ok(())
+}
+
+/// Reads the sessions a user had in a guild when the last snapshot was taken, if any.
+pub(super) async fn user_sessions(
+    data: &BotDataInner,
+    dir: &Path,
+    guild: GuildId,
+    user: UserId,
+) -> Result<Vec<(SessionName, chat::Snapshot)>, Error> {
+    let path = dir.join(data.conf().bot.shard_file(SNAPSHOT_FILE));
+    let contents = match tokio::fs::read(path).await {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut snapshot: HashMap<GuildId, GuildSnapshot> = serde_json::from_slice(&contents)?;
+
+    let sessions = snapshot
+        .remove(&guild)
+        .map(|guild_snapshot| {
+            guild_snapshot
+                .sessions
+                .into_iter()
+                .filter(|(owner, _, _)| *owner == user)
+                .map(|(_, name, entry)| (name, entry.session))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok(sessions)
+}
+
+/// Loads the sessions kept in the snapshot directory, returning how many were restored.
+pub(super) async fn restore(data: &BotDataInner, dir: &Path) -> Result<usize, Error> {
+    let contents = tokio::fs::read(dir.join(data.conf().bot.shard_file(SNAPSHOT_FILE))).await?;
+    let snapshot: HashMap<GuildId, GuildSnapshot> = serde_json::from_slice(&contents)?;
+
+    let mut restored = 0;
+    for (guild, guild_snapshot) in snapshot {
+        // Restored sessions keep their own, but later ones start from the settings.
+        if let Some(settings) = guild_snapshot.settings {
+            data.settings.insert(guild, settings);
+        }
+        if let Some(limits) = guild_snapshot.limits {
+            data.limits.insert(guild, limits);
+        }
+
+        let epoch = data.epoch();
+        let user_sessions = guild_snapshot
+            .sessions
+            .into_iter()
+            .map(|(user, name, entry)| (SessionKey::User(user, name), entry));
+        let shared_sessions = guild_snapshot
+            .shared
+            .into_iter()
+            .map(|(channel, entry)| (SessionKey::Channel(channel), entry));
+
+        for (key, entry) in user_sessions.chain(shared_sessions) {
+            let stored = Epoched {
+                epoch,
+                value: restore_session(data, guild, entry),
+            };
+            data.store_session(guild, key, stored);
+            restored += 1;
+        }
+        for (user, name) in guild_snapshot.selected {
+            data.select_session(guild, user, name);
+        }
+        for (user, code) in guild_snapshot.languages {
+            if let Some(lang) = whatlang::Lang::from_code(code) {
+                data.languages.insert((guild, user), lang);
+            }
+        }
+        for (user, name, text) in guild_snapshot.macros {
+            data.macros.insert((guild, user, name), text);
+        }
+    }
+
+    Ok(restored)
+}
@@ -0,0 +1,42 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+
+use crate::usage;
+
+use super::{BotData, BotDataInner, GuildId};
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Read-only view of the global state, so informational commands never wait on the
+/// session map while it's being written to.
+#[derive(Debug, Default)]
+pub(super) struct BotStats {
+    pub next_flush: DateTime<Utc>,
+    pub model: String,
+    pub active_sessions: HashMap<GuildId, usize>,
+    pub guild_usage: HashMap<GuildId, usage::Summary>,
+}
+
+/// Replaces the stats with the current state.
+pub(super) async fn refresh(data: &BotDataInner) {
+    let stats = BotStats {
+        next_flush: data.next_flush(),
+        model: data.sbuilder.model(),
+        active_sessions: data.active_sessions(),
+        guild_usage: data.usage.guilds().into_iter().collect(),
+    };
+
+    *data.stats.write().unwrap() = Arc::new(stats);
+}
+
+/// Keeps the stats fresh, besides the refreshes after flushes and model changes.
+pub(super) fn start(data: BotData) {
+    tokio::spawn(async move {
+        loop {
+            refresh(&data).await;
+
+            tokio::time::sleep(REFRESH_INTERVAL).await;
+        }
+    });
+}
@@ -0,0 +1,144 @@
+use std::sync::Arc;
+
+use poise::serenity_prelude as serenity;
+use tokio::sync::Notify;
+
+use crate::messages::{self, plural};
+
+use super::{
+    apply_theme, handle_command_error, send_ephemeral_embedded_reply, truncate_field_value,
+    ChatSession, Context, GuildId, InternalError, UserId, PAGINATION_TIMEOUT,
+};
+
+/// Discord fits up to five buttons in a row, one per listed prompt.
+const MAX_LISTED: usize = 5;
+
+/// Prompt holding a place in a session queue, until it leaves the pipeline.
+#[derive(Debug)]
+pub(super) struct Prompt {
+    pub guild: GuildId,
+    pub user: UserId,
+    pub content: String,
+    pub started: chrono::DateTime<chrono::Utc>,
+    pub session: ChatSession,
+    /// Notified once the author asks to cancel the prompt.
+    pub cancel: Arc<Notify>,
+}
+
+/// Shows your requests in progress, letting you cancel them
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    user_cooldown = 2,
+    required_permissions = "SEND_MESSAGES",
+    on_error = "handle_command_error"
+)]
+pub async fn status(ctx: Context<'_>) -> Result<(), InternalError> {
+    let data = ctx.data();
+    let conf = data.conf();
+    let messages = &conf.messages.status;
+    let guild = ctx.guild_id().unwrap().get();
+    let user = ctx.author().id.get();
+
+    let mut prompts: Vec<_> = data
+        .prompts
+        .iter()
+        .filter(|prompt| prompt.guild == guild && prompt.user == user)
+        .map(|prompt| {
+            let position = prompt.session.queue_position(*prompt.key());
+            (
+                *prompt.key(),
+                prompt.started,
+                position,
+                prompt.content.clone(),
+            )
+        })
+        .collect();
+
+    if prompts.is_empty() {
+        let embed = serenity::CreateEmbed::new().title(&messages.idle);
+        send_ephemeral_embedded_reply(ctx, embed).await?;
+
+        return Ok(());
+    }
+
+    prompts.sort_unstable_by_key(|(_, started, _, _)| *started);
+    prompts.truncate(MAX_LISTED);
+
+    let now = chrono::Utc::now();
+    let ctx_id = ctx.id();
+    let mut embed = serenity::CreateEmbed::new().title(&messages.title);
+    let mut buttons = Vec::with_capacity(prompts.len());
+    for (index, (prompt, started, position, content)) in prompts.iter().enumerate() {
+        let elapsed = (now - *started).num_seconds();
+        let state = match position {
+            Some(ahead) if *ahead > 0 => messages::render(
+                &messages.queued,
+                &[
+                    ("position", ahead),
+                    ("plural", &plural(*ahead as u64)),
+                    ("elapsed", &elapsed),
+                ],
+            ),
+            _ => messages::render(&messages.generating, &[("elapsed", &elapsed)]),
+        };
+        embed = embed.field(
+            format!("{}. {state}", index + 1),
+            truncate_field_value(content),
+            false,
+        );
+
+        let label = messages::render(&messages.cancel, &[("index", &(index + 1))]);
+        buttons.push(
+            serenity::CreateButton::new(format!("{ctx_id}cancel{prompt}"))
+                .label(label)
+                .style(serenity::ButtonStyle::Danger),
+        );
+    }
+
+    let reply = poise::CreateReply::default()
+        .embed(apply_theme(&conf.appearance, embed))
+        .components(vec![serenity::CreateActionRow::Buttons(buttons)])
+        .ephemeral(true);
+    let handle = ctx.send(reply).await?;
+
+    let prefix = format!("{ctx_id}cancel");
+    if let Some(press) = serenity::ComponentInteractionCollector::new(ctx)
+        .author_id(ctx.author().id)
+        .filter(move |press| press.data.custom_id.starts_with(&prefix))
+        .timeout(PAGINATION_TIMEOUT)
+        .await
+    {
+        let cancelled = press
+            .data
+            .custom_id
+            .trim_start_matches(&format!("{ctx_id}cancel"))
+            .parse::<u64>()
+            .ok()
+            .and_then(|prompt| data.prompts.get(&prompt))
+            .map(|prompt| prompt.cancel.notify_one())
+            .is_some();
+        let title = if cancelled {
+            &messages.cancelled
+        } else {
+            &messages.finished
+        };
+
+        let embed = apply_theme(&conf.appearance, serenity::CreateEmbed::new().title(title));
+        let response = serenity::CreateInteractionResponse::UpdateMessage(
+            serenity::CreateInteractionResponseMessage::new()
+                .embed(embed)
+                .components(vec![]),
+        );
+        press.create_response(ctx, response).await?;
+
+        return Ok(());
+    }
+
+    handle
+        .edit(ctx, poise::CreateReply::default().components(vec![]))
+        .await?;
+
+    Ok(())
+}
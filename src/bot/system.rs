@@ -0,0 +1,132 @@
+use poise::serenity_prelude as serenity;
+
+use crate::messages;
+
+use super::{handle_command_error, send_ephemeral_embedded_reply, Context, InternalError};
+
+/// Manages the instructions of your current conversation
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    subcommands("set", "clear", "show"),
+    subcommand_required,
+    on_error = "handle_command_error"
+)]
+pub async fn system(_ctx: Context<'_>) -> Result<(), InternalError> {
+    Ok(())
+}
+
+/// Sets instructions the model follows in your current conversation
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    user_cooldown = 2,
+    required_permissions = "SEND_MESSAGES",
+    on_error = "handle_command_error"
+)]
+async fn set(
+    ctx: Context<'_>,
+    #[description = "e.g. answer concisely"]
+    #[rest]
+    instructions: String,
+) -> Result<(), InternalError> {
+    let data = ctx.data();
+    let messages = &data.conf().messages.system;
+
+    let guild = ctx.guild_id().unwrap().get();
+
+    let instructions = instructions.trim();
+    if instructions.is_empty() {
+        let embed = serenity::CreateEmbed::new().title(&messages.blank);
+        send_ephemeral_embedded_reply(ctx, embed).await?;
+
+        return Ok(());
+    }
+
+    let max = data.prompt_size(guild);
+    if instructions.chars().count() > max as usize {
+        let embed = serenity::CreateEmbed::new()
+            .title(messages::render(&messages.too_long, &[("max", &max)]));
+        send_ephemeral_embedded_reply(ctx, embed).await?;
+
+        return Ok(());
+    }
+
+    let user = ctx.author().id.get();
+
+    let name = data.selected_session_name(guild, user);
+    let session = data.session(guild, user);
+    session
+        .session
+        .lock()
+        .await
+        .set_instructions(Some(instructions.to_string()));
+
+    let embed =
+        serenity::CreateEmbed::new().title(messages::render(&messages.set, &[("name", &name)]));
+    send_ephemeral_embedded_reply(ctx, embed).await?;
+
+    Ok(())
+}
+
+/// Removes the instructions of your current conversation
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    user_cooldown = 2,
+    required_permissions = "SEND_MESSAGES",
+    on_error = "handle_command_error"
+)]
+async fn clear(ctx: Context<'_>) -> Result<(), InternalError> {
+    let data = ctx.data();
+    let guild = ctx.guild_id().unwrap().get();
+    let user = ctx.author().id.get();
+
+    let name = data.selected_session_name(guild, user);
+    let session = data.session(guild, user);
+    session.session.lock().await.set_instructions(None);
+
+    let embed = serenity::CreateEmbed::new().title(messages::render(
+        &data.conf().messages.system.cleared,
+        &[("name", &name)],
+    ));
+    send_ephemeral_embedded_reply(ctx, embed).await?;
+
+    Ok(())
+}
+
+/// Shows the instructions of your current conversation
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    user_cooldown = 2,
+    required_permissions = "SEND_MESSAGES",
+    on_error = "handle_command_error"
+)]
+async fn show(ctx: Context<'_>) -> Result<(), InternalError> {
+    let data = ctx.data();
+    let messages = &data.conf().messages.system;
+    let guild = ctx.guild_id().unwrap().get();
+    let user = ctx.author().id.get();
+
+    let name = data.selected_session_name(guild, user);
+    let session = data.session(guild, user);
+    let instructions = session
+        .session
+        .lock()
+        .await
+        .instructions()
+        .map(str::to_string)
+        .unwrap_or_else(|| messages.empty.clone());
+
+    let embed = serenity::CreateEmbed::new()
+        .title(messages::render(&messages.show_title, &[("name", &name)]))
+        .description(instructions);
+    send_ephemeral_embedded_reply(ctx, embed).await?;
+
+    Ok(())
+}
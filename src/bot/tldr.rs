@@ -0,0 +1,193 @@
+use poise::serenity_prelude as serenity;
+
+use crate::{
+    chat,
+    messages::{self, plural},
+};
+
+use super::{
+    apply_theme, channel_context, handle_command_error, pipeline, send_ephemeral_embedded_reply,
+    truncate_chars, BotData, Context, GuildId, InternalError, EMBED_DESCRIPTION_LIMIT,
+};
+
+/// Most messages Discord returns per history request.
+const PAGE_SIZE: usize = 100;
+
+/// Reads up to `max` of the most recent messages of the channel, oldest first.
+async fn fetch_history(
+    ctx: Context<'_>,
+    max: usize,
+) -> Result<Vec<serenity::Message>, serenity::Error> {
+    let channel = ctx.channel_id();
+    let mut messages: Vec<serenity::Message> = Vec::new();
+
+    while messages.len() < max {
+        let limit = (max - messages.len()).min(PAGE_SIZE);
+        let mut request = serenity::GetMessages::new().limit(limit as u8);
+        if let Some(oldest) = messages.last() {
+            request = request.before(oldest.id);
+        }
+
+        // Pages come newest first.
+        let page = channel.messages(ctx, request).await?;
+        let exhausted = page.len() < limit;
+        messages.extend(page);
+        if exhausted {
+            break;
+        }
+    }
+    messages.reverse();
+
+    Ok(messages)
+}
+
+/// Splits the parts into consecutive groups of at most `max_chars`.
+///
+/// Groups take at least `min_parts`, however long, except for the last one.
+fn group(parts: Vec<String>, max_chars: usize, min_parts: usize) -> Vec<Vec<String>> {
+    let mut groups = Vec::new();
+    let mut current = Vec::new();
+    let mut chars = 0;
+
+    for part in parts {
+        let len = part.chars().count() + 1;
+        if current.len() >= min_parts && chars + len > max_chars {
+            groups.push(std::mem::take(&mut current));
+            chars = 0;
+        }
+        chars += len;
+        current.push(part);
+    }
+    if !current.is_empty() {
+        groups.push(current);
+    }
+
+    groups
+}
+
+/// Summarizes the lines in chunks, then merges their summaries in rounds until one is left.
+///
+/// Merges take two summaries at least, so each round halves them at most.
+async fn summarize(
+    data: &BotData,
+    guild: GuildId,
+    lines: Vec<String>,
+    chunk_chars: usize,
+    policy: Option<String>,
+) -> Result<String, chat::Error> {
+    let mut session = data.sbuilder.create_chat(data.history_size(guild) as usize);
+    session.set_policy(policy);
+
+    let mut digests = Vec::new();
+    for chunk in group(lines, chunk_chars, 1) {
+        let excerpt = truncate_chars(&chunk.join("\n"), chunk_chars);
+        let response = session.digest_excerpt(&excerpt).await?;
+        data.usage
+            .record_tokens(guild, &response.model, None, response.usage);
+        digests.push(response.content.trim().to_string());
+    }
+
+    while digests.len() > 1 {
+        let mut merged = Vec::new();
+        for batch in group(digests, chunk_chars, 2) {
+            if batch.len() == 1 {
+                merged.extend(batch);
+                continue;
+            }
+
+            let response = session.merge_digests(&batch).await?;
+            data.usage
+                .record_tokens(guild, &response.model, None, response.usage);
+            merged.push(response.content.trim().to_string());
+        }
+        digests = merged;
+    }
+
+    Ok(digests.pop().unwrap_or_default())
+}
+
+/// Summarizes the discussion of this thread
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    user_cooldown = 30,
+    required_permissions = "SEND_MESSAGES",
+    on_error = "handle_command_error"
+)]
+pub async fn tldr(ctx: Context<'_>) -> Result<(), InternalError> {
+    let data = ctx.data();
+    let conf = data.conf();
+    let messages = &conf.messages.tldr;
+    let guild = ctx.guild_id().unwrap().get();
+
+    let in_thread = ctx
+        .guild_channel()
+        .await
+        .is_some_and(|channel| channel.thread_metadata.is_some());
+    if !in_thread {
+        let embed = serenity::CreateEmbed::new().title(&messages.not_thread);
+        send_ephemeral_embedded_reply(ctx, embed).await?;
+
+        return Ok(());
+    }
+
+    // Long threads take a while to go through.
+    ctx.defer().await?;
+
+    let history = fetch_history(ctx, conf.tldr.max_messages).await?;
+    let prefix = conf.bot.prefix.as_deref();
+    let lines: Vec<_> = history
+        .iter()
+        .filter(|message| channel_context::is_conversation(message, prefix))
+        .map(|message| {
+            format!(
+                "{}: {}",
+                message.author.display_name(),
+                message.content.trim()
+            )
+        })
+        .collect();
+
+    if lines.is_empty() {
+        let embed = serenity::CreateEmbed::new().title(&messages.empty);
+        send_ephemeral_embedded_reply(ctx, embed).await?;
+
+        return Ok(());
+    }
+
+    // Held in flight by the exchange until summarized.
+    let mut exchange = pipeline::Exchange::new(ctx, lines.join("\n"));
+    if !pipeline::Pipeline::guards()
+        .admit(ctx, &mut exchange)
+        .await?
+    {
+        return Ok(());
+    }
+    let policy = exchange.policy.take();
+
+    data.usage.record_prompt(guild, ctx.author().id.get());
+
+    let count = lines.len();
+    let summary = match summarize(data, guild, lines, conf.tldr.chunk_chars, policy).await {
+        Ok(summary) => summary,
+        Err(err) => {
+            data.usage.record_error(guild);
+
+            return Err(Box::from(err));
+        }
+    };
+
+    let footer = messages::render(
+        &messages.footer,
+        &[("count", &count), ("plural", &plural(count as u64))],
+    );
+    let embed = serenity::CreateEmbed::new()
+        .title(&messages.title)
+        .description(truncate_chars(&summary, EMBED_DESCRIPTION_LIMIT));
+    let embed =
+        apply_theme(&conf.appearance, embed).footer(serenity::CreateEmbedFooter::new(footer));
+    ctx.send(poise::CreateReply::default().embed(embed)).await?;
+
+    Ok(())
+}
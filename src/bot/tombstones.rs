@@ -0,0 +1,118 @@
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+
+use super::{BotData, ChatSession, GuildId, SessionName, UserId};
+
+/// How often expired tombstones are dropped.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Who deleted the sessions, as each one undoes its own deletions only.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(super) enum Cause {
+    Reset,
+    Purge,
+}
+
+/// Sessions deleted within the grace period, still restorable.
+#[derive(Debug)]
+pub(super) struct Tombstone {
+    pub sessions: Vec<(SessionName, ChatSession)>,
+    /// The session that was selected, if it was deleted.
+    pub selected: Option<SessionName>,
+    pub pins: Vec<(SessionName, u8)>,
+    epoch: u64,
+    expires: Instant,
+}
+
+impl Tombstone {
+    pub fn new(epoch: u64, grace: Duration) -> Self {
+        Self {
+            sessions: Vec::new(),
+            selected: None,
+            pins: Vec::new(),
+            epoch,
+            expires: Instant::now() + grace,
+        }
+    }
+
+    fn is_expired(&self) -> bool {
+        Instant::now() >= self.expires
+    }
+
+    /// Adds the sessions of a newer tombstone, which also takes over its expiration.
+    fn merge(&mut self, newer: Tombstone) {
+        for (name, session) in newer.sessions {
+            self.sessions.retain(|(buried, _)| *buried != name);
+            self.sessions.push((name, session));
+        }
+        for (name, cycles) in newer.pins {
+            self.pins.retain(|(buried, _)| *buried != name);
+            self.pins.push((name, cycles));
+        }
+        self.selected = newer.selected.or(self.selected.take());
+        self.expires = newer.expires;
+    }
+}
+
+/// Deleted sessions kept for a while, so that deleting them by mistake can be undone.
+#[derive(Debug, Default)]
+pub(super) struct Graveyard {
+    tombstones: DashMap<(GuildId, UserId, Cause), Tombstone>,
+}
+
+impl Graveyard {
+    pub fn bury(&self, guild: GuildId, user: UserId, cause: Cause, tombstone: Tombstone) {
+        if tombstone.sessions.is_empty() || tombstone.is_expired() {
+            return;
+        }
+
+        match self.tombstones.entry((guild, user, cause)) {
+            dashmap::Entry::Occupied(mut entry)
+                if !entry.get().is_expired() && entry.get().epoch == tombstone.epoch =>
+            {
+                entry.get_mut().merge(tombstone);
+            }
+            dashmap::Entry::Occupied(mut entry) => {
+                entry.insert(tombstone);
+            }
+            dashmap::Entry::Vacant(entry) => {
+                entry.insert(tombstone);
+            }
+        }
+    }
+
+    /// Takes the sessions deleted for the given cause, unless they expired or were flushed since.
+    pub fn dig_up(
+        &self,
+        guild: GuildId,
+        user: UserId,
+        cause: Cause,
+        epoch: u64,
+    ) -> Option<Tombstone> {
+        self.tombstones
+            .remove(&(guild, user, cause))
+            .map(|(_, tombstone)| tombstone)
+            .filter(|tombstone| !tombstone.is_expired() && tombstone.epoch == epoch)
+    }
+
+    fn sweep(&self) {
+        self.tombstones
+            .retain(|_, tombstone| !tombstone.is_expired());
+    }
+
+    pub fn clear(&self) {
+        self.tombstones.clear();
+    }
+}
+
+/// Drops the tombstones once their grace period is over.
+pub(super) fn start(data: BotData) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(SWEEP_INTERVAL).await;
+
+            data.tombstones.sweep();
+        }
+    });
+}
@@ -0,0 +1,184 @@
+use poise::serenity_prelude as serenity;
+
+use crate::{
+    chat,
+    messages::{self, plural},
+    pdf,
+};
+
+use super::{
+    apply_theme, handle_command_error, send_ephemeral_embedded_reply, Context, InternalError,
+};
+
+const TRANSCRIPT_FILE: &str = "conversation.json";
+const DOCUMENT_FILE: &str = "conversation.pdf";
+const TRANSCRIPT_MAX_KIB: u32 = 512;
+
+#[derive(poise::ChoiceParameter, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Transcript /import takes back.
+    #[name = "json"]
+    Json,
+    /// Document to read or share, which can't be imported.
+    #[name = "pdf"]
+    Pdf,
+}
+
+/// Exports your current conversation as a file you can /import later, or as a PDF
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    user_cooldown = 10,
+    required_permissions = "SEND_MESSAGES",
+    on_error = "handle_command_error"
+)]
+pub async fn export(
+    ctx: Context<'_>,
+    #[description = "json to /import it later (default) or pdf to read it"] format: Option<Format>,
+) -> Result<(), InternalError> {
+    let data = ctx.data();
+    let conf = data.conf();
+    let messages = &conf.messages.transfer;
+    let guild = ctx.guild_id().unwrap().get();
+    let user = ctx.author().id.get();
+
+    let name = data.selected_session_name(guild, user);
+    let transcript = data.session(guild, user).session.lock().await.transcript();
+
+    if transcript.interactions.is_empty() {
+        let embed = serenity::CreateEmbed::new()
+            .title(messages::render(&messages.empty, &[("name", &name)]));
+        send_ephemeral_embedded_reply(ctx, embed).await?;
+
+        return Ok(());
+    }
+
+    let (title, attachment) = match format.unwrap_or(Format::Json) {
+        Format::Json => {
+            let contents = serde_json::to_vec_pretty(&transcript)?;
+
+            (
+                &messages.exported,
+                serenity::CreateAttachment::bytes(contents, TRANSCRIPT_FILE),
+            )
+        }
+        Format::Pdf => {
+            let title = messages::render(&messages.pdf_title, &[("name", &name)]);
+            let labels = pdf::Labels {
+                title: &title,
+                exported: &messages.pdf_exported,
+                persona: &messages.pdf_persona,
+                user: &messages.pdf_user,
+                assistant: &messages.pdf_assistant,
+                page: &messages.pdf_page,
+            };
+            let contents = pdf::conversation(&transcript, &labels, chrono::Utc::now());
+
+            (
+                &messages.exported_pdf,
+                serenity::CreateAttachment::bytes(contents, DOCUMENT_FILE),
+            )
+        }
+    };
+    let embed = serenity::CreateEmbed::new().title(messages::render(title, &[("name", &name)]));
+    let reply = poise::CreateReply::default()
+        .embed(apply_theme(&conf.appearance, embed))
+        .attachment(attachment)
+        .ephemeral(true);
+    ctx.send(reply).await?;
+
+    Ok(())
+}
+
+/// Replaces your current conversation with one exported by /export
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    user_cooldown = 10,
+    required_permissions = "SEND_MESSAGES",
+    on_error = "handle_command_error"
+)]
+pub async fn import(
+    ctx: Context<'_>,
+    #[description = "file sent by /export"] file: serenity::Attachment,
+) -> Result<(), InternalError> {
+    let data = ctx.data();
+    let conf = data.conf();
+    let messages = &conf.messages.transfer;
+
+    if file.size > TRANSCRIPT_MAX_KIB * 1024 {
+        let embed = serenity::CreateEmbed::new().title(messages::render(
+            &messages.too_large,
+            &[("max", &TRANSCRIPT_MAX_KIB)],
+        ));
+        send_ephemeral_embedded_reply(ctx, embed).await?;
+
+        return Ok(());
+    }
+
+    ctx.defer_ephemeral().await?;
+
+    let transcript = serde_json::from_slice::<chat::Transcript>(&file.download().await?)
+        .ok()
+        .filter(|transcript| transcript.version == chat::Transcript::VERSION)
+        // Exported interactions always have both a prompt and a reply.
+        .filter(|transcript| {
+            transcript.interactions.iter().all(|interaction| {
+                !interaction.prompt.trim().is_empty() && !interaction.response.trim().is_empty()
+            })
+        });
+    let Some(transcript) = transcript else {
+        let embed = serenity::CreateEmbed::new().title(&messages.invalid);
+        send_ephemeral_embedded_reply(ctx, embed).await?;
+
+        return Ok(());
+    };
+
+    let guild = ctx.guild_id().unwrap().get();
+    let user = ctx.author().id.get();
+
+    // Same limits as prompts and instructions sent through the other commands.
+    let max = data.prompt_size(guild) as usize;
+    let too_long = transcript
+        .instructions
+        .iter()
+        .chain(
+            transcript
+                .interactions
+                .iter()
+                .map(|interaction| &interaction.prompt),
+        )
+        .any(|content| content.chars().count() > max);
+    if too_long {
+        let embed = serenity::CreateEmbed::new()
+            .title(messages::render(&messages.too_long, &[("max", &max)]));
+        send_ephemeral_embedded_reply(ctx, embed).await?;
+
+        return Ok(());
+    }
+
+    let name = data.selected_session_name(guild, user);
+    let count = transcript
+        .interactions
+        .len()
+        .min(data.history_size(guild) as usize);
+    let session = data.session(guild, user);
+    session.session.lock().await.import(transcript);
+    // Interactions that didn't fit are folded into the summary meanwhile.
+    let fold_data = data.clone();
+    tokio::spawn(async move { session.fold_summary(&fold_data, guild).await });
+
+    let embed = serenity::CreateEmbed::new().title(messages::render(
+        &messages.imported,
+        &[
+            ("count", &count),
+            ("plural", &plural(count as u64)),
+            ("name", &name),
+        ],
+    ));
+    send_ephemeral_embedded_reply(ctx, embed).await?;
+
+    Ok(())
+}
@@ -0,0 +1,53 @@
+use poise::serenity_prelude as serenity;
+
+use super::{handle_prompt_error, pipeline, send_ephemeral_embedded_reply, Context, InternalError};
+
+/// Sends a message and reads the model's response out in your voice channel
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    user_cooldown = 10,
+    required_permissions = "SEND_MESSAGES",
+    on_error = "handle_prompt_error"
+)]
+pub async fn speak(
+    ctx: Context<'_>,
+    #[description = "message to send"]
+    #[rest]
+    content: String,
+) -> Result<(), InternalError> {
+    let data = ctx.data();
+    let guild = ctx.guild_id().unwrap();
+
+    let channel = ctx.guild().and_then(|guild| {
+        guild
+            .voice_states
+            .get(&ctx.author().id)
+            .and_then(|state| state.channel_id)
+    });
+    let (Some(channel), Some(tts)) = (channel, data.tts.get()) else {
+        let embed = serenity::CreateEmbed::new().title(&data.conf().messages.alerts.not_in_voice);
+        send_ephemeral_embedded_reply(ctx, embed).await?;
+
+        return Ok(());
+    };
+
+    let mut exchange = pipeline::Exchange::new(ctx, content);
+    data.pipeline.run(ctx, &mut exchange).await?;
+
+    // The pipeline already replied when it stopped early.
+    let Some(response) = exchange.response else {
+        return Ok(());
+    };
+
+    let audio = tts.synthesize(&response.content).await?;
+
+    let manager = songbird::get(ctx.serenity_context())
+        .await
+        .ok_or("voice client isn't registered")?;
+    let call = manager.join(guild, channel).await?;
+    call.lock().await.play_input(audio.into());
+
+    Ok(())
+}
@@ -0,0 +1,44 @@
+use std::{io, path::Path, time::Duration};
+
+use crate::chat;
+
+use super::BotDataInner;
+
+/// Longest the provider may take to answer the warm-up request.
+const PROVIDER_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("failed to warm up the model provider, check the key and model")]
+    Provider(#[source] chat::Error),
+    #[error("model provider didn't answer the warm-up within {0:?}")]
+    TimedOut(Duration),
+    #[error("failed to write to the snapshot directory")]
+    Storage(#[source] io::Error),
+}
+
+/// Writes and removes a file in the directory, creating it if needed.
+///
+/// Each process probes with a file of its own, as shards may share the directory.
+async fn probe(dir: &Path) -> Result<(), io::Error> {
+    let path = dir.join(format!(".warmup-{}", std::process::id()));
+    tokio::fs::create_dir_all(dir).await?;
+    tokio::fs::write(&path, []).await?;
+    tokio::fs::remove_file(path).await
+}
+
+/// Makes the first requests to the provider and storage, so the first prompt doesn't wait on
+/// setting up their connections and misconfigurations show before connecting.
+pub(super) async fn run(data: &BotDataInner) -> Result<(), Error> {
+    if let Some(dir) = &data.conf().persistence.snapshot_dir {
+        probe(dir).await.map_err(Error::Storage)?;
+    }
+
+    let elapsed = tokio::time::timeout(PROVIDER_TIMEOUT, data.sbuilder.warm_up())
+        .await
+        .map_err(|_| Error::TimedOut(PROVIDER_TIMEOUT))?
+        .map_err(Error::Provider)?;
+    log::info!("model provider warmed up in {}ms", elapsed.as_millis());
+
+    Ok(())
+}
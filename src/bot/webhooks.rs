@@ -0,0 +1,72 @@
+use poise::serenity_prelude as serenity;
+
+use super::Context;
+
+/// Returns the webhook the bot posts replies with in the channel, creating it if needed.
+///
+/// Threads have no webhooks of their own, so they get none.
+pub(super) async fn channel_webhook(ctx: Context<'_>) -> Option<serenity::Webhook> {
+    let data = ctx.data();
+    let channel = ctx.channel_id();
+
+    if let Some(webhook) = data.webhooks.get(&channel.get()) {
+        return Some(webhook.clone());
+    }
+
+    let in_thread = ctx
+        .guild()
+        .is_some_and(|guild| guild.threads.iter().any(|thread| thread.id == channel));
+    if in_thread {
+        return None;
+    }
+
+    let bot = ctx.cache().current_user().id;
+    let existing = match channel.webhooks(ctx).await {
+        Ok(webhooks) => webhooks.into_iter().find(|webhook| {
+            webhook.token.is_some() && webhook.user.as_ref().is_some_and(|user| user.id == bot)
+        }),
+        Err(err) => {
+            log::warn!("failed to list webhooks of channel {channel}: {err}");
+
+            return None;
+        }
+    };
+
+    let webhook = match existing {
+        Some(webhook) => webhook,
+        None => {
+            let name = ctx.cache().current_user().name.clone();
+            match channel
+                .create_webhook(ctx, serenity::CreateWebhook::new(name))
+                .await
+            {
+                Ok(webhook) => webhook,
+                Err(err) => {
+                    log::warn!("failed to create webhook in channel {channel}: {err}");
+
+                    return None;
+                }
+            }
+        }
+    };
+    data.webhooks.insert(channel.get(), webhook.clone());
+
+    Some(webhook)
+}
+
+/// Whether the webhook a request went through no longer exists, e.g. deleted by a moderator.
+pub(super) fn is_unknown(err: &serenity::Error) -> bool {
+    match err {
+        serenity::Error::Http(err) => err
+            .status_code()
+            .is_some_and(|status| status.as_u16() == 404),
+        _ => false,
+    }
+}
+
+/// Drops the webhook of the channel, so the next reply gets a working one.
+pub(super) fn forget(ctx: Context<'_>, webhook: &serenity::Webhook) {
+    ctx.data()
+        .webhooks
+        .remove_if(&ctx.channel_id().get(), |_, cached| cached.id == webhook.id);
+}
@@ -1,111 +1,1846 @@
-use std::{collections::VecDeque, iter::once, sync::Arc};
+use std::{
+    collections::VecDeque,
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
 
+use chrono::{DateTime, Utc};
 use genai::{
-    chat::{ChatMessage, ChatRequest},
+    chat::{
+        ChatMessage, ChatOptions, ChatRequest, ChatResponse, ChatStreamEvent, ContentPart,
+        MessageContent, MetaUsage,
+    },
     resolver::AuthData,
 };
+use poise::futures_util::StreamExt;
+use tokio::sync::mpsc;
 
-type Response = String;
+use crate::{config, hooks, secrets::Secret};
 
-#[derive(Debug)]
-struct User {
+const TITLE_INSTRUCTIONS: &str = "Give the conversation above a short title of at most five \
+    words. Reply only with the title, without quotes or punctuation at the end.";
+const TITLE_MAX_TOKENS: u32 = 16;
+const TITLE_MAX_CHARS: usize = 48;
+const SUMMARY_INSTRUCTIONS: &str = "Summarize the conversation above in one short paragraph, \
+    keeping the facts and preferences worth remembering in a later conversation.";
+const SUMMARY_MAX_TOKENS: u32 = 256;
+const FOLLOWUPS_INSTRUCTIONS: &str = "Suggest {count} short follow-up questions the user could \
+    ask next about the conversation above. Reply only with the questions, one per line.";
+const FOLLOWUPS_MAX_TOKENS: u32 = 128;
+const FOLLOWUP_MAX_CHARS: usize = 80;
+const SHORTEN_INSTRUCTIONS: &str = "Shorten the text given by the user to at most {max} \
+    characters, keeping its meaning, language and formatting. Reply only with the shortened text.";
+const EXCERPT_DIGEST_INSTRUCTIONS: &str = "Summarize the part of a discussion given by the user \
+    in a few bullet points, keeping who said what when it matters. Reply only with the summary.";
+const DIGESTS_MERGE_INSTRUCTIONS: &str = "The user gives summaries of consecutive parts of a \
+    discussion, separated by blank lines. Merge them into a single summary of a few bullet points, \
+    in order. Reply only with the summary.";
+const DIGEST_MAX_TOKENS: u32 = 512;
+const EXCERPT_CONTEXT_CHARS: usize = 60;
+const SUMMARY_FOLD_INSTRUCTIONS: &str = "Update the summary of the earlier conversation with \
+    the messages above, in one short paragraph keeping the facts and preferences worth \
+    remembering. Reply only with the summary.";
+const CHARS_PER_TOKEN: usize = 4;
+const WARMUP_PROMPT: &str = "Reply with OK.";
+const WARMUP_MAX_TOKENS: u32 = 1;
+const THINK_OPEN: &str = "<think>";
+const THINK_CLOSE: &str = "</think>";
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("failed to exchange messages with the model")]
+    Provider(#[from] genai::Error),
+    #[error("interaction was rejected by script: {0}")]
+    Vetoed(String),
+    #[error("failed to run script hook")]
+    Hook(#[from] hooks::Error),
+    #[error("model replied without any text")]
+    EmptyResponse,
+}
+
+/// Kind of failure, as told to the users who ran into it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Failure {
+    RateLimited,
+    Outage,
+    ContentFiltered,
+    /// The request didn't fit in the context window of the model.
+    ContextExceeded,
+    Timeout,
+    EmptyResponse,
+    Internal,
+}
+
+impl Failure {
+    /// Whether asking again may get a reply, unlike when the prompt itself was refused.
+    pub fn is_retryable(self) -> bool {
+        !matches!(self, Self::ContentFiltered | Self::ContextExceeded)
+    }
+}
+
+/// Precedes the wait in the error bodies of rate limited requests, e.g. `try again in 1.5s`.
+const RETRY_HINT: &str = "try again in ";
+/// Fragments of the error bodies providers send back when refusing content.
+const CONTENT_FILTER_MARKERS: [&str; 4] = [
+    "content_filter",
+    "content_policy",
+    "content policy",
+    "moderation",
+];
+
+/// Fragments of the error bodies providers send back when the request doesn't fit the model.
+const CONTEXT_EXCEEDED_MARKERS: [&str; 4] = [
+    "context_length_exceeded",
+    "context length",
+    "context window",
+    "reduce the length",
+];
+
+/// Parses waits as written by providers, e.g. `1m2.5s` or `250ms`.
+fn parse_wait(text: &str) -> Option<Duration> {
+    let mut total = 0.;
+    let mut number = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_ascii_digit() || c == '.' {
+            number.push(c);
+            continue;
+        }
+
+        let value: f64 = number.parse().ok()?;
+        number.clear();
+        total += match c {
+            'h' => value * 3600.,
+            'm' if chars.next_if_eq(&'s').is_some() => value / 1000.,
+            'm' => value * 60.,
+            's' => value,
+            _ => return None,
+        };
+    }
+
+    (number.is_empty() && total > 0.).then(|| Duration::from_secs_f64(total))
+}
+
+impl Error {
+    /// Time the provider asked to wait before calling it again, when it refused for going over
+    /// its rate limits and said so.
+    pub fn retry_after(&self) -> Option<Duration> {
+        let Self::Provider(
+            genai::Error::WebModelCall { webc_error, .. }
+            | genai::Error::WebAdapterCall { webc_error, .. },
+        ) = self
+        else {
+            return None;
+        };
+        let genai::webc::Error::ResponseFailedStatus { status, body } = webc_error else {
+            return None;
+        };
+        if status.as_u16() != 429 {
+            return None;
+        }
+
+        let (_, rest) = body.split_once(RETRY_HINT)?;
+        let wait = rest.split_whitespace().next()?.trim_end_matches(['.', ',']);
+
+        parse_wait(wait)
+    }
+
+    pub fn failure(&self) -> Failure {
+        if let Self::EmptyResponse = self {
+            return Failure::EmptyResponse;
+        }
+        let Self::Provider(
+            genai::Error::WebModelCall { webc_error, .. }
+            | genai::Error::WebAdapterCall { webc_error, .. },
+        ) = self
+        else {
+            return Failure::Internal;
+        };
+
+        match webc_error {
+            genai::webc::Error::ResponseFailedStatus { status, body } => {
+                let body = body.to_lowercase();
+                match status.as_u16() {
+                    429 => Failure::RateLimited,
+                    408 | 504 => Failure::Timeout,
+                    500.. => Failure::Outage,
+                    _ if CONTEXT_EXCEEDED_MARKERS
+                        .iter()
+                        .any(|marker| body.contains(marker)) =>
+                    {
+                        Failure::ContextExceeded
+                    }
+                    _ if CONTENT_FILTER_MARKERS
+                        .iter()
+                        .any(|marker| body.contains(marker)) =>
+                    {
+                        Failure::ContentFiltered
+                    }
+                    _ => Failure::Internal,
+                }
+            }
+            genai::webc::Error::Reqwest(err) if err.is_timeout() => Failure::Timeout,
+            // The provider couldn't be reached at all.
+            genai::webc::Error::Reqwest(err) if err.is_connect() => Failure::Outage,
+            _ => Failure::Internal,
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, Default)]
+pub struct Usage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+impl From<MetaUsage> for Usage {
+    fn from(usage: MetaUsage) -> Self {
+        let tokens = |count: Option<i32>| count.map_or(0, |c| c.max(0) as u64);
+
+        Self {
+            input_tokens: tokens(usage.input_tokens),
+            output_tokens: tokens(usage.output_tokens),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Response {
+    /// Model that replied.
+    pub model: String,
+    pub content: String,
+    /// Thinking of reasoning models, left out of the content and the history.
+    pub reasoning: Option<String>,
+    pub usage: Usage,
+    /// Time the provider took to generate it.
+    pub elapsed: Duration,
+    /// Reply as the provider sent it, when there's one.
+    pub raw: Option<Arc<ChatResponse>>,
+    /// Set when the session was asked to capture the exchange, see [`Session::set_capture`].
+    pub capture: Option<Capture>,
+}
+
+/// Request sent to the provider and its raw reply, to troubleshoot an answer.
+#[derive(Clone, Debug)]
+pub struct Capture {
+    pub model: String,
+    pub request: String,
+    pub response: String,
+}
+
+/// Splits the `<think>` block reasoning models start their replies with off the answer.
+///
+/// A block left open means the model ran out of tokens while thinking, so it's kept as is.
+fn split_reasoning(content: String) -> (String, Option<String>) {
+    let Some(rest) = content.trim_start().strip_prefix(THINK_OPEN) else {
+        return (content, None);
+    };
+    let Some((reasoning, answer)) = rest.split_once(THINK_CLOSE) else {
+        return (content, None);
+    };
+
+    let reasoning = Some(reasoning.trim().to_string()).filter(|reasoning| !reasoning.is_empty());
+
+    (answer.trim_start().to_string(), reasoning)
+}
+
+/// Answer written so far in a streamed reply, none while the model is still thinking.
+pub fn streamed_answer(partial: &str) -> Option<&str> {
+    let answer = match partial.trim_start().strip_prefix(THINK_OPEN) {
+        Some(rest) => rest.split_once(THINK_CLOSE)?.1,
+        None => partial,
+    };
+
+    Some(answer.trim()).filter(|answer| !answer.is_empty())
+}
+
+/// Text of a reply, with the text parts of multi-part ones joined.
+///
+/// Replies made only of tool calls have none.
+fn response_text(content: MessageContent) -> Option<String> {
+    match content {
+        MessageContent::Text(text) => Some(text),
+        MessageContent::Parts(parts) => {
+            let texts: Vec<_> = parts
+                .into_iter()
+                .filter_map(|part| match part {
+                    ContentPart::Text(text) => Some(text),
+                    _ => None,
+                })
+                .collect();
+
+            (!texts.is_empty()).then(|| texts.join("\n"))
+        }
+        _ => None,
+    }
+}
+
+/// Model shared by every session, so it can be switched while they're in use.
+type SharedModel = Arc<RwLock<String>>;
+
+type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Source of model replies, shared by every session.
+pub trait ChatProvider: Send + Sync {
+    fn exec_chat<'a>(
+        &'a self,
+        model: &'a str,
+        request: ChatRequest,
+        options: Option<&'a ChatOptions>,
+    ) -> BoxFuture<'a, Result<Response, Error>>;
+
+    /// Like [`ChatProvider::exec_chat`], also sending the reply text through `chunks` as it's
+    /// generated. Providers without streaming send it whole once done.
+    fn exec_chat_stream<'a>(
+        &'a self,
+        model: &'a str,
+        request: ChatRequest,
+        options: Option<&'a ChatOptions>,
+        chunks: mpsc::UnboundedSender<String>,
+    ) -> BoxFuture<'a, Result<Response, Error>> {
+        Box::pin(async move {
+            let response = self.exec_chat(model, request, options).await?;
+            let _ = chunks.send(response.content.clone());
+
+            Ok(response)
+        })
+    }
+}
+
+/// Reply sent as it's generated once the provider took longer than the budget, see
+/// [`Session::set_stream`].
+#[derive(Clone, Debug)]
+pub struct Stream {
+    pub budget: Duration,
+    /// Gets an empty chunk once the reply is asked again as a stream, then its text.
+    pub chunks: mpsc::UnboundedSender<String>,
+}
+
+/// Provider and model answering a prompt instead of the session ones, see
+/// [`Session::set_target`].
+#[derive(Clone)]
+pub struct Target {
+    pub provider: Arc<dyn ChatProvider>,
+    pub model: String,
+}
+
+impl fmt::Debug for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Target")
+            .field("model", &self.model)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Calls the provider picked by genai from the model name.
+pub struct Genai {
     client: genai::Client,
-    model: Arc<String>,
 }
 
-impl User {
-    fn new(key: String, model: Arc<String>) -> Self {
+impl Genai {
+    pub fn new(key: Secret) -> Self {
         Self {
             client: genai::Client::builder()
-                .with_auth_resolver_fn(|_| Ok(Some(AuthData::from_single(key))))
+                .with_auth_resolver_fn(move |_| Ok(Some(AuthData::from_single(key.get()))))
                 .build(),
-            model,
         }
     }
+}
+
+impl ChatProvider for Genai {
+    fn exec_chat<'a>(
+        &'a self,
+        model: &'a str,
+        request: ChatRequest,
+        options: Option<&'a ChatOptions>,
+    ) -> BoxFuture<'a, Result<Response, Error>> {
+        Box::pin(async move {
+            let started = Instant::now();
+
+            let cr = self.client.exec_chat(model, request, options).await?;
+            let (content, reasoning) = cr
+                .content
+                .clone()
+                .and_then(response_text)
+                .map(split_reasoning)
+                .filter(|(content, _)| !content.trim().is_empty())
+                .ok_or(Error::EmptyResponse)?;
+
+            Ok(Response {
+                model: model.to_string(),
+                content,
+                reasoning,
+                usage: cr.usage.clone().into(),
+                elapsed: started.elapsed(),
+                raw: Some(Arc::new(cr)),
+                capture: None,
+            })
+        })
+    }
+
+    fn exec_chat_stream<'a>(
+        &'a self,
+        model: &'a str,
+        request: ChatRequest,
+        options: Option<&'a ChatOptions>,
+        chunks: mpsc::UnboundedSender<String>,
+    ) -> BoxFuture<'a, Result<Response, Error>> {
+        Box::pin(async move {
+            let started = Instant::now();
+
+            let options = options
+                .cloned()
+                .unwrap_or_default()
+                .with_capture_usage(true);
+            let mut stream = self
+                .client
+                .exec_chat_stream(model, request, Some(&options))
+                .await?
+                .stream;
+
+            let mut content = String::new();
+            let mut usage = Usage::default();
+            while let Some(event) = stream.next().await {
+                match event? {
+                    ChatStreamEvent::Chunk(chunk) => {
+                        content.push_str(&chunk.content);
+                        let _ = chunks.send(chunk.content);
+                    }
+                    ChatStreamEvent::End(end) => {
+                        usage = end.captured_usage.map(Usage::from).unwrap_or_default();
+                    }
+                    _ => (),
+                }
+            }
+
+            let (content, reasoning) = Some(split_reasoning(content))
+                .filter(|(content, _)| !content.trim().is_empty())
+                .ok_or(Error::EmptyResponse)?;
+
+            Ok(Response {
+                model: model.to_string(),
+                content,
+                reasoning,
+                usage,
+                elapsed: started.elapsed(),
+                raw: None,
+                capture: None,
+            })
+        })
+    }
+}
+
+/// Rough token count of the text, for when there's no real usage.
+fn estimate_tokens(text: &str) -> u64 {
+    text.chars().count().div_ceil(CHARS_PER_TOKEN) as u64
+}
+
+/// Rough token count of the text messages of the request.
+fn estimate_request_tokens(request: &ChatRequest) -> u64 {
+    request
+        .messages
+        .iter()
+        .filter_map(|message| message.content.text_as_str())
+        .map(estimate_tokens)
+        .sum()
+}
+
+/// Echoes the last message back without calling any provider.
+pub struct Mock;
+
+impl ChatProvider for Mock {
+    fn exec_chat<'a>(
+        &'a self,
+        model: &'a str,
+        request: ChatRequest,
+        _options: Option<&'a ChatOptions>,
+    ) -> BoxFuture<'a, Result<Response, Error>> {
+        Box::pin(async move {
+            let input_tokens = estimate_request_tokens(&request);
+            let prompt = request
+                .messages
+                .iter()
+                .filter_map(|message| message.content.text_as_str())
+                .next_back()
+                .unwrap_or_default();
+
+            let content = format!("[mock {model}] {prompt}");
+
+            Ok(Response {
+                model: model.to_string(),
+                usage: Usage {
+                    input_tokens,
+                    output_tokens: estimate_tokens(&content),
+                },
+                content,
+                reasoning: None,
+                elapsed: Duration::ZERO,
+                raw: None,
+                capture: None,
+            })
+        })
+    }
+}
+
+/// Builds the provider picked in config.
+pub fn provider(provider: config::Provider, key: Secret) -> Arc<dyn ChatProvider> {
+    match provider {
+        config::Provider::Genai => Arc::new(Genai::new(key)),
+        config::Provider::Mock => Arc::new(Mock),
+    }
+}
+
+#[derive(Clone)]
+struct User {
+    provider: Arc<dyn ChatProvider>,
+    model: SharedModel,
+    title_model: Arc<String>,
+}
+
+impl fmt::Debug for User {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("User")
+            .field("model", &self.model)
+            .field("title_model", &self.title_model)
+            .finish_non_exhaustive()
+    }
+}
+
+impl User {
+    fn model(&self) -> String {
+        self.model.read().unwrap().clone()
+    }
+
+    async fn send_message(
+        &self,
+        request: ChatRequest,
+        model: Option<&str>,
+        max_tokens: Option<u32>,
+        stream: Option<Stream>,
+        provider: Option<&Arc<dyn ChatProvider>>,
+    ) -> Result<Response, Error> {
+        let options =
+            max_tokens.map(|max_tokens| ChatOptions::default().with_max_tokens(max_tokens));
+        let model = model.map_or_else(|| self.model(), str::to_string);
+        let provider = provider.unwrap_or(&self.provider);
+
+        let Some(stream) = stream else {
+            return provider.exec_chat(&model, request, options.as_ref()).await;
+        };
+
+        // Providers can't turn a call into a stream, so the slow one is dropped and asked again.
+        let call = provider.exec_chat(&model, request.clone(), options.as_ref());
+        match tokio::time::timeout(stream.budget, call).await {
+            Ok(sent) => sent,
+            Err(_) => {
+                // The provider still bills the prompt of the dropped call, so it's counted too.
+                let dropped = estimate_request_tokens(&request);
+                let _ = stream.chunks.send(String::new());
+
+                let mut response = provider
+                    .exec_chat_stream(&model, request, options.as_ref(), stream.chunks)
+                    .await?;
+                response.usage.input_tokens += dropped;
+
+                Ok(response)
+            }
+        }
+    }
+
+    async fn request_title(&self, request: ChatRequest) -> Result<Response, Error> {
+        let options = ChatOptions::default().with_max_tokens(TITLE_MAX_TOKENS);
+
+        self.provider
+            .exec_chat(&self.title_model, request, Some(&options))
+            .await
+    }
+
+    async fn request_followups(&self, request: ChatRequest) -> Result<Response, Error> {
+        let options = ChatOptions::default().with_max_tokens(FOLLOWUPS_MAX_TOKENS);
+
+        self.provider
+            .exec_chat(&self.title_model, request, Some(&options))
+            .await
+    }
+
+    async fn request_summary(&self, request: ChatRequest) -> Result<Response, Error> {
+        let options = ChatOptions::default().with_max_tokens(SUMMARY_MAX_TOKENS);
 
-    async fn send_message(&self, request: ChatRequest) -> Result<Response, genai::Error> {
-        self.client
-            .exec_chat(&self.model, request, None)
+        self.provider
+            .exec_chat(&self.model(), request, Some(&options))
             .await
-            .map(|cr| cr.content.unwrap().text_into_string().unwrap())
+    }
+
+    async fn request_digest(&self, request: ChatRequest) -> Result<Response, Error> {
+        let options = ChatOptions::default().with_max_tokens(DIGEST_MAX_TOKENS);
+
+        self.provider
+            .exec_chat(&self.model(), request, Some(&options))
+            .await
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct Interaction {
+    /// Number of the interaction in its session, starting at 1 and kept as older ones are evicted.
+    #[serde(default)]
+    pub id: u64,
+    pub prompt: String,
+    pub response: String,
+    /// When the model replied, the Unix epoch for snapshots that didn't keep it.
+    #[serde(default)]
+    pub at: DateTime<Utc>,
+    /// Model that replied, unknown for snapshots that didn't keep it.
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub usage: Usage,
+}
+
+/// Exchange shown to the model ahead of the history, so replies follow its lead.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Example {
+    pub prompt: String,
+    pub response: String,
+}
+
+/// Conversation as exported by members, to be imported into another session.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct Transcript {
+    pub version: u8,
+    pub instructions: Option<String>,
+    pub interactions: Vec<Interaction>,
+}
+
+impl Transcript {
+    pub const VERSION: u8 = 1;
+}
+
+/// Part of an interaction matching a search query.
+#[derive(Clone, Debug)]
+pub struct Match {
+    pub at: DateTime<Utc>,
+    /// Whether the match is in the prompt rather than in the response.
+    pub in_prompt: bool,
+    pub excerpt: String,
+}
+
+/// Cuts the text around the first case-insensitive occurrence of the query, if any.
+fn excerpt(text: &str, query: &[char]) -> Option<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let start = chars.windows(query.len()).position(|window| {
+        window
+            .iter()
+            .zip(query)
+            .all(|(a, b)| a.to_lowercase().eq(b.to_lowercase()))
+    })?;
+
+    let from = start.saturating_sub(EXCERPT_CONTEXT_CHARS);
+    let to = (start + query.len() + EXCERPT_CONTEXT_CHARS).min(chars.len());
+
+    let mut excerpt = String::new();
+    if from > 0 {
+        excerpt.push('…');
+    }
+    excerpt.extend(&chars[from..to]);
+    if to < chars.len() {
+        excerpt.push('…');
+    }
+
+    Some(excerpt)
+}
+
+fn search_history<'a>(history: impl Iterator<Item = &'a Interaction>, query: &str) -> Vec<Match> {
+    let query: Vec<char> = query.chars().collect();
+    if query.is_empty() {
+        return Vec::new();
+    }
+
+    history
+        .flat_map(|interaction| {
+            [(&interaction.prompt, true), (&interaction.response, false)]
+                .into_iter()
+                .filter_map(|(text, in_prompt)| {
+                    excerpt(text, &query).map(|excerpt| Match {
+                        at: interaction.at,
+                        in_prompt,
+                        excerpt,
+                    })
+                })
+        })
+        .collect()
+}
+
+/// What the last exchange replaced in history, so it can be undone.
+#[derive(Debug)]
+struct Undo {
+    /// Interactions dropped by a branch.
+    dropped: Vec<Interaction>,
+    /// Oldest interactions pushed out of history to make room.
+    evicted: Vec<Interaction>,
+    /// Interactions exchanged once it took place.
+    exchanged: usize,
+}
+
+/// Decides which interactions leave history to make room for new ones.
+pub trait HistoryPolicy: fmt::Debug + Send + Sync {
+    /// Drops the oldest interactions that don't fit anymore, returning them oldest first.
+    ///
+    /// History never keeps more than `size` interactions, and the last one is always kept.
+    fn evict(&self, history: &mut VecDeque<Interaction>, size: usize) -> Vec<Interaction>;
+
+    /// Whether the evicted interactions are folded into the summary of the session.
+    fn summarizes(&self) -> bool {
+        false
+    }
+}
+
+/// Drops the oldest interactions beyond the given size.
+fn evict_over_size(history: &mut VecDeque<Interaction>, size: usize) -> Vec<Interaction> {
+    let over = history.len().saturating_sub(size.max(1));
+
+    history.drain(..over).collect()
+}
+
+/// Keeps the latest interactions that fit.
+#[derive(Debug)]
+pub struct Window;
+
+impl HistoryPolicy for Window {
+    fn evict(&self, history: &mut VecDeque<Interaction>, size: usize) -> Vec<Interaction> {
+        evict_over_size(history, size)
+    }
+}
+
+/// Same as [`Window`], summarizing the interactions it drops.
+#[derive(Debug)]
+pub struct Summarize;
+
+impl HistoryPolicy for Summarize {
+    fn evict(&self, history: &mut VecDeque<Interaction>, size: usize) -> Vec<Interaction> {
+        evict_over_size(history, size)
+    }
+
+    fn summarizes(&self) -> bool {
+        true
     }
 }
 
+/// Same as [`Window`], also dropping the oldest interactions while over a rough token count.
 #[derive(Debug)]
-struct Interaction {
-    user_message: ChatMessage,
-    assistant_message: ChatMessage,
+pub struct TokenBudget {
+    pub max_tokens: u64,
+}
+
+impl HistoryPolicy for TokenBudget {
+    fn evict(&self, history: &mut VecDeque<Interaction>, size: usize) -> Vec<Interaction> {
+        let mut evicted = evict_over_size(history, size);
+
+        let tokens = |i: &Interaction| estimate_tokens(&i.prompt) + estimate_tokens(&i.response);
+        let mut total: u64 = history.iter().map(tokens).sum();
+        while total > self.max_tokens && history.len() > 1 {
+            let Some(oldest) = history.pop_front() else {
+                break;
+            };
+            total -= tokens(&oldest);
+            evicted.push(oldest);
+        }
+
+        evicted
+    }
+}
+
+/// Builds the history policy picked in config.
+pub fn history_policy(chat: &config::Chat) -> Arc<dyn HistoryPolicy> {
+    match chat.strategy {
+        config::HistoryStrategy::Window => Arc::new(Window),
+        config::HistoryStrategy::Summarize => Arc::new(Summarize),
+        config::HistoryStrategy::TokenBudget => Arc::new(TokenBudget {
+            max_tokens: chat.history_tokens.into(),
+        }),
+    }
+}
+
+/// Tokens needed to write up to the given characters.
+///
+/// Tokens tend to be longer than a character, this leaves some room.
+pub fn max_tokens_for(max_chars: usize) -> u32 {
+    (max_chars / 2).max(1) as u32
 }
 
 #[derive(Debug)]
 pub struct Session {
     user: User,
+    script: Option<Arc<hooks::Script>>,
+    postprocess: Arc<config::PostProcess>,
+    /// Model replacing the default one for every message of the session.
+    model: Option<String>,
+    policy: Option<String>,
+    max_tokens: Option<u32>,
+    instructions: Option<String>,
+    /// Sent between the instructions and the history, never evicted.
+    examples: Vec<Example>,
+    summary: Option<String>,
+    /// Evicted interactions waiting to be folded into the summary, oldest first.
+    unfolded: Vec<Interaction>,
+    /// Whether a fold is underway, so folds don't overwrite each other.
+    folding: bool,
     history: VecDeque<Interaction>,
+    history_size: usize,
+    history_policy: Arc<dyn HistoryPolicy>,
+    exchanged: usize,
+    undo: Option<Undo>,
+    capture: bool,
+    stream: Option<Stream>,
+    target: Option<Target>,
 }
 
 impl Session {
-    fn new(user: User, history_size: usize) -> Self {
+    fn new(
+        user: User,
+        script: Option<Arc<hooks::Script>>,
+        postprocess: Arc<config::PostProcess>,
+        history_policy: Arc<dyn HistoryPolicy>,
+        history_size: usize,
+    ) -> Self {
         Self {
             user,
-            history: VecDeque::with_capacity(history_size),
+            script,
+            postprocess,
+            model: None,
+            policy: None,
+            max_tokens: None,
+            instructions: None,
+            examples: Vec::new(),
+            summary: None,
+            unfolded: Vec::new(),
+            folding: false,
+            history: VecDeque::with_capacity(history_size + 1),
+            history_size,
+            history_policy,
+            exchanged: 0,
+            undo: None,
+            capture: false,
+            stream: None,
+            target: None,
+        }
+    }
+
+    fn history_messages(&self) -> impl Iterator<Item = ChatMessage> + '_ {
+        self.history.iter().flat_map(|i| {
+            [
+                ChatMessage::user(i.prompt.clone()),
+                ChatMessage::assistant(i.response.clone()),
+            ]
+        })
+    }
+
+    /// Instructions given by the session owner, sent before the history.
+    pub fn instructions(&self) -> Option<&str> {
+        self.instructions.as_deref()
+    }
+
+    pub fn set_instructions(&mut self, instructions: Option<String>) {
+        self.instructions = instructions;
+    }
+
+    /// Exchanges the model is shown before the history, e.g. those of a persona.
+    pub fn examples(&self) -> &[Example] {
+        &self.examples
+    }
+
+    pub fn set_examples(&mut self, examples: Vec<Example>) {
+        self.examples = examples;
+    }
+
+    /// Model replacing the default one for every message, if any.
+    pub fn model(&self) -> Option<&str> {
+        self.model.as_deref()
+    }
+
+    /// Replaces the default model for every message, unless one is given along it.
+    pub fn set_model(&mut self, model: Option<String>) {
+        self.model = model;
+    }
+
+    /// Content policy of the channel last prompted from, sent before the instructions.
+    pub fn set_policy(&mut self, policy: Option<String>) {
+        self.policy = policy;
+    }
+
+    /// Captures the next exchange with the model in its response.
+    pub fn set_capture(&mut self, capture: bool) {
+        self.capture = capture;
+    }
+
+    /// Streams the next reply once it takes longer than the budget.
+    pub fn set_stream(&mut self, stream: Option<Stream>) {
+        self.stream = stream;
+    }
+
+    /// Answers the next prompt with the target, unless it picks a model itself.
+    pub fn set_target(&mut self, target: Option<Target>) {
+        self.target = target;
+    }
+
+    /// Bounds the replies to what's delivered of them, unbounded when none.
+    pub fn set_max_tokens(&mut self, max_tokens: Option<u32>) {
+        self.max_tokens = max_tokens;
+    }
+
+    /// Seeds the session with the summary of a previous conversation.
+    pub fn seed_summary(&mut self, summary: String) {
+        self.summary = Some(summary);
+    }
+
+    /// Interactions kept in history, oldest first.
+    pub fn history(&self) -> impl Iterator<Item = &Interaction> {
+        self.history.iter()
+    }
+
+    /// Same as [`Session::history`], along with the index of each interaction.
+    pub fn indexed_history(&self) -> impl Iterator<Item = (usize, &Interaction)> {
+        self.history.iter().enumerate()
+    }
+
+    /// Index in history of the interaction with the id, unless it was evicted or dropped.
+    pub fn position(&self, id: u64) -> Option<usize> {
+        self.history
+            .iter()
+            .position(|interaction| interaction.id == id)
+    }
+
+    pub fn transcript(&self) -> Transcript {
+        Transcript {
+            version: Transcript::VERSION,
+            instructions: self.instructions.clone(),
+            interactions: self.history.iter().cloned().collect(),
         }
     }
 
-    fn append_to_history(&mut self, interaction: Interaction) {
-        if self.history.len() == self.history.capacity() {
-            self.history.pop_front();
+    /// Replaces the instructions and history with the transcript ones.
+    ///
+    /// Only the latest interactions fit in history, the imported ones still count as exchanged.
+    pub fn import(&mut self, transcript: Transcript) {
+        self.instructions = transcript.instructions;
+        self.history.clear();
+        self.exchanged += transcript.interactions.len();
+        transcript
+            .interactions
+            .into_iter()
+            .for_each(|mut interaction| {
+                // Ids of the exported session mean nothing here.
+                interaction.id = 0;
+                let evicted = self.append_to_history(interaction);
+                self.keep_unfolded(evicted);
+            });
+        self.assign_missing_ids();
+    }
+
+    /// Interaction kept in history at the index, oldest first.
+    pub fn interaction(&self, index: usize) -> Option<&Interaction> {
+        self.history.get(index)
+    }
+
+    /// Number of interactions since the session was created, including evicted ones.
+    pub fn exchanged(&self) -> usize {
+        self.exchanged
+    }
+
+    /// Numbers the interactions without an id as the last ones exchanged.
+    fn assign_missing_ids(&mut self) {
+        let first = (self.exchanged + 1).saturating_sub(self.history.len()) as u64;
+        for (offset, interaction) in self.history.iter_mut().enumerate() {
+            if interaction.id == 0 {
+                interaction.id = first + offset as u64;
+            }
         }
+    }
 
+    /// Appends the interaction, returning the oldest ones that didn't fit anymore.
+    fn append_to_history(&mut self, interaction: Interaction) -> Vec<Interaction> {
         self.history.push_back(interaction);
+
+        self.history_policy
+            .evict(&mut self.history, self.history_size)
     }
 
-    pub async fn send_message(&mut self, content: String) -> Result<Response, genai::Error> {
-        let user_message = ChatMessage::user(content);
+    /// Keeps the evicted interactions to be folded into the summary, if the policy summarizes.
+    fn keep_unfolded(&mut self, evicted: Vec<Interaction>) {
+        if self.history_policy.summarizes() {
+            self.unfolded.extend(evicted);
+        }
+    }
+
+    /// Takes the interactions evicted since the last fold, so they're folded into the summary
+    /// without holding the session, unless another fold is underway.
+    ///
+    /// [`Session::finish_fold`] must follow, whether the fold went through or not.
+    pub fn take_fold(&mut self) -> Option<Fold> {
+        if self.folding || self.unfolded.is_empty() {
+            return None;
+        }
+        self.folding = true;
+
+        Some(Fold {
+            user: self.user.clone(),
+            summary: self.summary.clone(),
+            evicted: std::mem::take(&mut self.unfolded),
+        })
+    }
 
+    /// Replaces the summary with the folded one, if the fold went through.
+    pub fn finish_fold(&mut self, summary: Option<String>) {
+        self.folding = false;
+        if summary.is_some() {
+            self.summary = summary;
+        }
+    }
+
+    /// Sends the message, passing it and the model reply through the script hooks.
+    ///
+    /// The model, when given, replaces the default one for this message only.
+    pub async fn send_message(
+        &mut self,
+        content: String,
+        model: Option<&str>,
+    ) -> Result<Response, Error> {
+        let kept = self.history.len();
+
+        self.send_message_after(content, model, kept).await
+    }
+
+    /// Sends the message following only the first `kept` interactions of the history.
+    async fn send_message_after(
+        &mut self,
+        content: String,
+        model: Option<&str>,
+        kept: usize,
+    ) -> Result<Response, Error> {
+        let content = match &self.script {
+            Some(script) => match script.clone().on_prompt(content.clone()).await? {
+                hooks::Verdict::Keep => content,
+                hooks::Verdict::Rewrite(content) => content,
+                hooks::Verdict::Veto(reason) => return Err(Error::Vetoed(reason)),
+            },
+            None => content,
+        };
+
+        self.exchange(content, model, kept).await
+    }
+
+    /// Discards the last interaction and asks the model to answer it again.
+    ///
+    /// The discarded interaction is restored if the model fails to reply.
+    pub async fn regenerate_last_interaction(&mut self) -> Result<Option<Response>, Error> {
+        let Some(last) = self.history.pop_back() else {
+            return Ok(None);
+        };
+        self.exchanged -= 1;
+
+        let kept = self.history.len();
+        match self.exchange(last.prompt.clone(), None, kept).await {
+            Ok(response) => {
+                // Replies edited in place aren't undone.
+                self.undo = None;
+
+                Ok(Some(response))
+            }
+            Err(err) => {
+                self.history.push_back(last);
+                self.exchanged += 1;
+
+                Err(err)
+            }
+        }
+    }
+
+    /// Drops the interaction at the index and the ones after it, sending the content in its place.
+    ///
+    /// The interactions are only dropped once the model replied, so they're kept if it fails
+    /// or the request is cancelled. They still count as exchanged, so replies to them can't
+    /// be regenerated afterwards.
+    pub async fn branch(
+        &mut self,
+        index: usize,
+        content: String,
+        model: Option<&str>,
+    ) -> Result<Option<Response>, Error> {
+        if index >= self.history.len() {
+            return Ok(None);
+        }
+
+        self.send_message_after(content, model, index)
+            .await
+            .map(Some)
+    }
+
+    /// Whether replies of the model may be rewritten or vetoed before they're kept.
+    fn filters_replies(&self, model: &str) -> bool {
+        self.postprocess.rewrites(model)
+            || self
+                .script
+                .as_ref()
+                .is_some_and(|script| script.has_response_hook())
+    }
+
+    /// Request asking the model to follow the interactions of the history between `skipped` and
+    /// `kept` with the prompt.
+    fn chat_request(&self, prompt: &str, skipped: usize, kept: usize) -> ChatRequest {
         let mut chat_request = ChatRequest::default();
-        chat_request.messages.reserve_exact(self.history.len() + 1);
-        let history = self
-            .history
-            .iter()
-            .flat_map(|p| once(&p.user_message).chain(once(&p.assistant_message)))
-            .cloned();
-        chat_request.messages.extend(history);
-        chat_request.messages.push(user_message.clone());
+        chat_request
+            .messages
+            .reserve_exact((self.examples.len() + kept - skipped) * 2 + 4);
+        chat_request
+            .messages
+            .extend(self.policy.clone().map(ChatMessage::system));
+        chat_request
+            .messages
+            .extend(self.instructions.clone().map(ChatMessage::system));
+        chat_request
+            .messages
+            .extend(self.summary.as_ref().map(|summary| {
+                ChatMessage::system(format!(
+                    "Summary of a previous conversation with the user: {summary}"
+                ))
+            }));
+        chat_request
+            .messages
+            .extend(self.examples.iter().flat_map(|example| {
+                [
+                    ChatMessage::user(example.prompt.clone()),
+                    ChatMessage::assistant(example.response.clone()),
+                ]
+            }));
+        chat_request
+            .messages
+            .extend(self.history_messages().take(kept * 2).skip(skipped * 2));
+        chat_request
+            .messages
+            .push(ChatMessage::user(prompt.to_string()));
+
+        chat_request
+    }
 
-        let response = self.user.send_message(chat_request).await?;
-        let assistant_message = ChatMessage::assistant(response.clone());
+    /// Asks the model to follow the first `kept` interactions of the history with the prompt,
+    /// replacing the rest with the new interaction once answered.
+    ///
+    /// Requests going over the context window of the model are sent once more without the
+    /// oldest half of those interactions, which then leave history as if evicted.
+    async fn exchange(
+        &mut self,
+        prompt: String,
+        model: Option<&str>,
+        kept: usize,
+    ) -> Result<Response, Error> {
+        let chat_request = self.chat_request(&prompt, 0, kept);
 
-        self.append_to_history(Interaction {
-            user_message,
-            assistant_message,
+        let capture = std::mem::take(&mut self.capture);
+        let mut captured_request = capture.then(|| format!("{chat_request:#?}"));
+        let target = self.target.take().filter(|_| model.is_none());
+        let model = model
+            .map(str::to_string)
+            .or_else(|| target.as_ref().map(|target| target.model.clone()))
+            .or_else(|| self.model.clone());
+        let model = model.as_deref();
+
+        let resolved = model.map_or_else(|| self.user.model(), str::to_string);
+        // Streamed text is shown before the reply is rewritten or vetoed, so replies only
+        // stream when nothing would change them.
+        let stream = self
+            .stream
+            .take()
+            .filter(|_| !self.filters_replies(&resolved));
+        let provider = target.as_ref().map(|target| &target.provider);
+        let sent = self
+            .user
+            .send_message(
+                chat_request,
+                model,
+                self.max_tokens,
+                stream.clone(),
+                provider,
+            )
+            .await;
+        let (mut response, trimmed) = match sent {
+            Err(err) if err.failure() == Failure::ContextExceeded && kept > 0 => {
+                let trimmed = kept.div_ceil(2);
+                log::info!(
+                    "request went over the context window, sending it again without the oldest \
+                     {trimmed} of {kept} interaction(s)"
+                );
+
+                let chat_request = self.chat_request(&prompt, trimmed, kept);
+                if capture {
+                    captured_request = Some(format!("{chat_request:#?}"));
+                }
+                let response = self
+                    .user
+                    .send_message(chat_request, model, self.max_tokens, stream, provider)
+                    .await?;
+
+                (response, trimmed)
+            }
+            sent => (sent?, 0),
+        };
+
+        let model = resolved;
+        if let Some(request) = captured_request {
+            let raw = match &response.raw {
+                Some(raw) => format!("{raw:#?}"),
+                // Streamed replies come in chunks, leaving only their text and usage.
+                None => format!("{:#?}\n{:#?}", response.content, response.usage),
+            };
+            response.capture = Some(Capture {
+                model: model.clone(),
+                request,
+                response: raw,
+            });
+        }
+
+        if let Some(content) = self.postprocess.apply(&model, &response.content) {
+            response.content = content;
+        }
+
+        if let Some(script) = &self.script {
+            let hooked = script
+                .clone()
+                .on_response(prompt.clone(), response.content.clone());
+            match hooked.await? {
+                hooks::Verdict::Keep => (),
+                hooks::Verdict::Rewrite(content) => response.content = content,
+                hooks::Verdict::Veto(reason) => return Err(Error::Vetoed(reason)),
+            }
+        }
+
+        let dropped = self.history.drain(kept..).collect();
+        let mut evicted: Vec<_> = self.history.drain(..trimmed).collect();
+        self.exchanged += 1;
+        evicted.extend(self.append_to_history(Interaction {
+            id: self.exchanged as u64,
+            prompt,
+            response: response.content.clone(),
+            at: Utc::now(),
+            model: Some(model),
+            usage: response.usage,
+        }));
+        if self.history_policy.summarizes() {
+            self.unfolded.extend(evicted.iter().cloned());
+        }
+        self.undo = Some(Undo {
+            dropped,
+            evicted,
+            exchanged: self.exchanged,
         });
 
         Ok(response)
     }
 
-    pub fn pop_last_interaction(&mut self) {
+    /// Takes the conversation kept in history, for the title model to name it.
+    pub fn title_request(&self) -> TitleRequest {
+        let mut chat_request = ChatRequest::default();
+        chat_request
+            .messages
+            .reserve_exact(self.history.len() * 2 + 1);
+        chat_request.messages.extend(self.history_messages());
+        chat_request
+            .messages
+            .push(ChatMessage::user(TITLE_INSTRUCTIONS));
+
+        TitleRequest {
+            user: self.user.clone(),
+            chat_request,
+        }
+    }
+
+    /// Asks the model to summarize the conversation kept in history, if any.
+    pub async fn summarize(&self) -> Result<Option<String>, Error> {
+        if self.history.is_empty() {
+            return Ok(None);
+        }
+
+        let mut chat_request = ChatRequest::default();
+        chat_request
+            .messages
+            .reserve_exact(self.history.len() * 2 + 1);
+        chat_request.messages.extend(self.history_messages());
+        chat_request
+            .messages
+            .push(ChatMessage::user(SUMMARY_INSTRUCTIONS));
+
+        let response = self.user.request_summary(chat_request).await?;
+        let summary = response.content.trim();
+
+        Ok((!summary.is_empty()).then(|| summary.to_string()))
+    }
+
+    /// Takes the conversation kept in history, for the title model to suggest how the user
+    /// could follow it.
+    pub fn followups_request(&self, count: usize) -> FollowupsRequest {
+        let instructions = FOLLOWUPS_INSTRUCTIONS.replace("{count}", &count.to_string());
+
+        let mut chat_request = ChatRequest::default();
+        chat_request
+            .messages
+            .reserve_exact(self.history.len() * 2 + 1);
+        chat_request.messages.extend(self.history_messages());
+        chat_request.messages.push(ChatMessage::user(instructions));
+
+        FollowupsRequest {
+            user: self.user.clone(),
+            chat_request,
+            count,
+        }
+    }
+
+    /// Asks the model to summarize part of a discussion, without touching history.
+    pub async fn digest_excerpt(&self, excerpt: &str) -> Result<Response, Error> {
+        self.digest(EXCERPT_DIGEST_INSTRUCTIONS, excerpt).await
+    }
+
+    /// Asks the model to merge the summaries of consecutive parts of a discussion.
+    pub async fn merge_digests(&self, digests: &[String]) -> Result<Response, Error> {
+        self.digest(DIGESTS_MERGE_INSTRUCTIONS, &digests.join("\n\n"))
+            .await
+    }
+
+    async fn digest(&self, instructions: &str, text: &str) -> Result<Response, Error> {
+        let mut chat_request = ChatRequest::default();
+        chat_request.messages.reserve_exact(3);
+        chat_request
+            .messages
+            .push(ChatMessage::system(instructions));
+        chat_request
+            .messages
+            .extend(self.policy.clone().map(ChatMessage::system));
+        chat_request.messages.push(ChatMessage::user(text));
+
+        self.user.request_digest(chat_request).await
+    }
+
+    /// Captures everything but the provider client, so the session can be restored later.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            instructions: self.instructions.clone(),
+            examples: self.examples.clone(),
+            summary: self.summary.clone(),
+            unfolded: self.unfolded.clone(),
+            history: self.history.iter().cloned().collect(),
+            exchanged: self.exchanged,
+        }
+    }
+
+    /// Finds the interactions kept in history that mention the query, ignoring case.
+    pub fn search(&self, query: &str) -> Vec<Match> {
+        search_history(self.history.iter(), query)
+    }
+
+    /// Puts history back as it was before the interaction answered when `exchanged` was reached,
+    /// along with the interactions a branch dropped for it.
+    ///
+    /// Does nothing once something else was exchanged, so a late undo doesn't drop the
+    /// wrong interaction.
+    pub fn undo_last_interaction(&mut self, exchanged: usize) -> bool {
+        if self.exchanged != exchanged {
+            return false;
+        }
+        let Some(undo) = self.undo.take_if(|undo| undo.exchanged == exchanged) else {
+            return false;
+        };
+
         self.history.pop_back();
+        self.history.extend(undo.dropped);
+        // Unless a fold took them already, they're no longer waiting for one.
+        self.unfolded
+            .retain(|unfolded| undo.evicted.iter().all(|evicted| evicted.id != unfolded.id));
+        for evicted in undo.evicted.into_iter().rev() {
+            self.history.push_front(evicted);
+        }
+        self.exchanged -= 1;
+
+        true
+    }
+}
+
+/// Evicted interactions taken from a session, to be folded into its summary.
+pub struct Fold {
+    user: User,
+    summary: Option<String>,
+    evicted: Vec<Interaction>,
+}
+
+impl Fold {
+    /// Asks the model to merge the evicted interactions into the summary.
+    pub async fn summarize(&self) -> Result<Response, Error> {
+        let mut chat_request = ChatRequest::default();
+        chat_request
+            .messages
+            .reserve_exact(self.evicted.len() * 2 + 2);
+        chat_request
+            .messages
+            .extend(self.summary.as_ref().map(|summary| {
+                ChatMessage::system(format!(
+                    "Summary of the earlier conversation with the user: {summary}"
+                ))
+            }));
+        chat_request
+            .messages
+            .extend(self.evicted.iter().flat_map(|i| {
+                [
+                    ChatMessage::user(i.prompt.clone()),
+                    ChatMessage::assistant(i.response.clone()),
+                ]
+            }));
+        chat_request
+            .messages
+            .push(ChatMessage::user(SUMMARY_FOLD_INSTRUCTIONS));
+
+        self.user.request_summary(chat_request).await
+    }
+}
+
+/// Conversation taken from a session, to be named by the title model.
+pub struct TitleRequest {
+    user: User,
+    chat_request: ChatRequest,
+}
+
+impl TitleRequest {
+    /// Asks the title model to name the conversation.
+    pub async fn generate(self) -> Result<String, Error> {
+        let response = self.user.request_title(self.chat_request).await?;
+        let title = response
+            .content
+            .trim()
+            .trim_matches(|c: char| c == '"' || c == '\'' || c == '.')
+            .chars()
+            .take(TITLE_MAX_CHARS)
+            .collect();
+
+        Ok(title)
+    }
+}
+
+/// Conversation taken from a session, for the title model to suggest follow-up questions.
+pub struct FollowupsRequest {
+    user: User,
+    chat_request: ChatRequest,
+    count: usize,
+}
+
+impl FollowupsRequest {
+    /// Asks the title model for questions the user could follow the conversation with, along
+    /// with its reply.
+    pub async fn suggest(self) -> Result<(Vec<String>, Response), Error> {
+        let response = self.user.request_followups(self.chat_request).await?;
+        let followups = response
+            .content
+            .lines()
+            .map(|line| {
+                line.trim()
+                    .trim_start_matches(|c: char| {
+                        c.is_ascii_digit() || matches!(c, '.' | ')' | '-' | '*' | ' ')
+                    })
+                    .trim_matches('"')
+            })
+            .filter(|line| !line.is_empty() && line.chars().count() <= FOLLOWUP_MAX_CHARS)
+            .take(self.count)
+            .map(str::to_string)
+            .collect();
+
+        Ok((followups, response))
+    }
+}
+
+/// Serializable state of a session.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct Snapshot {
+    instructions: Option<String>,
+    #[serde(default)]
+    examples: Vec<Example>,
+    summary: Option<String>,
+    /// Evicted interactions not folded into the summary yet.
+    #[serde(default)]
+    unfolded: Vec<Interaction>,
+    history: Vec<Interaction>,
+    exchanged: usize,
+}
+
+impl Snapshot {
+    /// Same as [`Session::search`], over the snapshotted history.
+    pub fn search(&self, query: &str) -> Vec<Match> {
+        search_history(self.history.iter(), query)
     }
 }
 
 pub struct SessionBuilder {
-    key: String,
-    model: Arc<String>,
-    history_size: usize,
+    provider: Arc<dyn ChatProvider>,
+    model: SharedModel,
+    title_model: Arc<String>,
+    script: Option<Arc<hooks::Script>>,
+    postprocess: Arc<config::PostProcess>,
+    history_policy: Arc<dyn HistoryPolicy>,
 }
 
 impl SessionBuilder {
-    pub fn new(key: String, model: String, history_size: usize) -> Self {
+    pub fn new(
+        provider: Arc<dyn ChatProvider>,
+        model: String,
+        title_model: String,
+        script: Option<hooks::Script>,
+        postprocess: config::PostProcess,
+        history_policy: Arc<dyn HistoryPolicy>,
+    ) -> Self {
         Self {
-            key,
-            model: Arc::new(model),
+            provider,
+            model: Arc::new(RwLock::new(model)),
+            title_model: Arc::new(title_model),
+            script: script.map(Arc::new),
+            postprocess: Arc::new(postprocess),
+            history_policy,
+        }
+    }
+
+    pub fn model(&self) -> String {
+        self.model.read().unwrap().clone()
+    }
+
+    /// Switches the model of every session, including those already created.
+    ///
+    /// The title model is kept as is.
+    pub fn set_model(&self, model: String) {
+        *self.model.write().unwrap() = model;
+    }
+
+    /// Sends a trivial request to the default model, setting up the connection to the
+    /// provider and making sure it takes the key, and tells how long it took.
+    pub async fn warm_up(&self) -> Result<Duration, Error> {
+        let mut chat_request = ChatRequest::default();
+        chat_request.messages.push(ChatMessage::user(WARMUP_PROMPT));
+        let options = ChatOptions::default().with_max_tokens(WARMUP_MAX_TOKENS);
+
+        let started = Instant::now();
+        let sent = self
+            .provider
+            .exec_chat(&self.model(), chat_request, Some(&options))
+            .await;
+        match sent {
+            // A single token may be all reasoning, leaving no text.
+            Ok(_) | Err(Error::EmptyResponse) => Ok(started.elapsed()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Asks the default model to shorten the text to the given characters, outside of any session.
+    pub async fn shorten(&self, text: &str, max_chars: usize) -> Result<Response, Error> {
+        let instructions = SHORTEN_INSTRUCTIONS.replace("{max}", &max_chars.to_string());
+
+        let mut chat_request = ChatRequest::default();
+        chat_request.messages.reserve_exact(2);
+        chat_request
+            .messages
+            .push(ChatMessage::system(instructions));
+        chat_request.messages.push(ChatMessage::user(text));
+        let options = ChatOptions::default().with_max_tokens(max_tokens_for(max_chars));
+
+        self.provider
+            .exec_chat(&self.model(), chat_request, Some(&options))
+            .await
+    }
+
+    /// Creates a session keeping up to the given interactions.
+    pub fn create_chat(&self, history_size: usize) -> Session {
+        let user = User {
+            provider: self.provider.clone(),
+            model: self.model.clone(),
+            title_model: self.title_model.clone(),
+        };
+
+        Session::new(
+            user,
+            self.script.clone(),
+            self.postprocess.clone(),
+            self.history_policy.clone(),
             history_size,
+        )
+    }
+
+    /// Creates a session from a snapshot, keeping only the most recent interactions that fit.
+    pub fn restore_chat(&self, snapshot: Snapshot, history_size: usize) -> Session {
+        let mut session = self.create_chat(history_size);
+        session.instructions = snapshot.instructions;
+        session.examples = snapshot.examples;
+        session.summary = snapshot.summary;
+        session.unfolded = snapshot.unfolded;
+        snapshot.history.into_iter().for_each(|interaction| {
+            let evicted = session.append_to_history(interaction);
+            session.keep_unfolded(evicted);
+        });
+        session.exchanged = snapshot.exchanged;
+        session.assign_missing_ids();
+
+        session
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    /// Replies with the scripted answers in order, keeping the requests it got.
+    #[derive(Default)]
+    struct Scripted {
+        replies: Mutex<VecDeque<Result<String, Error>>>,
+        requests: Mutex<Vec<ChatRequest>>,
+    }
+
+    impl Scripted {
+        fn new(replies: impl IntoIterator<Item = Result<&'static str, Error>>) -> Arc<Self> {
+            let replies = replies
+                .into_iter()
+                .map(|reply| reply.map(str::to_string))
+                .collect();
+
+            Arc::new(Self {
+                replies: Mutex::new(replies),
+                requests: Mutex::default(),
+            })
+        }
+
+        /// Texts of the messages sent in each request.
+        fn sent(&self) -> Vec<Vec<String>> {
+            self.requests
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|request| {
+                    request
+                        .messages
+                        .iter()
+                        .filter_map(|message| message.content.text_as_str())
+                        .map(str::to_string)
+                        .collect()
+                })
+                .collect()
         }
     }
 
-    pub fn create_chat(&self) -> Session {
-        let user = User::new(self.key.clone(), self.model.clone());
+    impl ChatProvider for Scripted {
+        fn exec_chat<'a>(
+            &'a self,
+            model: &'a str,
+            request: ChatRequest,
+            _options: Option<&'a ChatOptions>,
+        ) -> BoxFuture<'a, Result<Response, Error>> {
+            self.requests.lock().unwrap().push(request);
+            let reply = self
+                .replies
+                .lock()
+                .unwrap()
+                .pop_front()
+                .expect("no reply scripted");
+
+            Box::pin(async move {
+                Ok(Response {
+                    model: model.to_string(),
+                    content: reply?,
+                    reasoning: None,
+                    usage: Usage::default(),
+                    elapsed: Duration::ZERO,
+                    raw: None,
+                    capture: None,
+                })
+            })
+        }
+    }
+
+    fn session(provider: Arc<Scripted>, history_size: usize) -> Session {
+        SessionBuilder::new(
+            provider,
+            "model".to_string(),
+            "title".to_string(),
+            None,
+            config::PostProcess::default(),
+            Arc::new(Window),
+        )
+        .create_chat(history_size)
+    }
+
+    fn prompts(session: &Session) -> Vec<(&str, &str)> {
+        session
+            .history()
+            .map(|interaction| (interaction.prompt.as_str(), interaction.response.as_str()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn exchanges_follow_the_history() {
+        let provider = Scripted::new([Ok("a1"), Ok("a2")]);
+        let mut session = session(provider.clone(), 5);
+
+        session.send_message("q1".to_string(), None).await.unwrap();
+        let response = session.send_message("q2".to_string(), None).await.unwrap();
+
+        assert_eq!(response.content, "a2");
+        assert_eq!(prompts(&session), [("q1", "a1"), ("q2", "a2")]);
+        assert_eq!(session.exchanged(), 2);
+        assert_eq!(provider.sent()[1], ["q1", "a1", "q2"]);
+    }
+
+    #[tokio::test]
+    async fn failed_exchanges_leave_history_as_is() {
+        let provider = Scripted::new([Ok("a1"), Err(Error::EmptyResponse)]);
+        let mut session = session(provider, 5);
+
+        session.send_message("q1".to_string(), None).await.unwrap();
+        let failed = session.send_message("q2".to_string(), None).await;
+
+        assert!(matches!(failed, Err(Error::EmptyResponse)));
+        assert_eq!(prompts(&session), [("q1", "a1")]);
+        assert_eq!(session.exchanged(), 1);
+    }
+
+    #[tokio::test]
+    async fn branches_drop_later_interactions_once_answered() {
+        let provider = Scripted::new([Ok("a1"), Ok("a2"), Ok("a3"), Err(Error::EmptyResponse)]);
+        let mut session = session(provider.clone(), 5);
+
+        for prompt in ["q1", "q2", "q3"] {
+            session
+                .send_message(prompt.to_string(), None)
+                .await
+                .unwrap();
+        }
+
+        let failed = session.branch(1, "b2".to_string(), None).await;
+        assert!(failed.is_err());
+        assert_eq!(session.history().count(), 3);
+
+        let provider = Scripted::new([Ok("c2")]);
+        session.user.provider = provider.clone();
+        let response = session.branch(1, "b2".to_string(), None).await.unwrap();
+
+        assert_eq!(response.unwrap().content, "c2");
+        assert_eq!(prompts(&session), [("q1", "a1"), ("b2", "c2")]);
+        assert_eq!(provider.sent()[0], ["q1", "a1", "b2"]);
+        assert_eq!(session.exchanged(), 4);
+
+        let out_of_range = session.branch(5, "b3".to_string(), None).await;
+        assert!(out_of_range.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn undo_restores_dropped_and_evicted_interactions() {
+        let provider = Scripted::new([Ok("a1"), Ok("a2"), Ok("c2"), Ok("a3")]);
+        let mut session = session(provider, 2);
+
+        session.send_message("q1".to_string(), None).await.unwrap();
+        session.send_message("q2".to_string(), None).await.unwrap();
+        session.branch(1, "b2".to_string(), None).await.unwrap();
+
+        assert!(session.undo_last_interaction(3));
+        assert_eq!(prompts(&session), [("q1", "a1"), ("q2", "a2")]);
+        assert_eq!(session.exchanged(), 2);
+        assert!(!session.undo_last_interaction(2));
+
+        session.send_message("q3".to_string(), None).await.unwrap();
+        assert_eq!(prompts(&session), [("q2", "a2"), ("q3", "a3")]);
+
+        // Late undos don't drop interactions exchanged since.
+        assert!(!session.undo_last_interaction(2));
+        assert!(session.undo_last_interaction(3));
+        assert_eq!(prompts(&session), [("q1", "a1"), ("q2", "a2")]);
+    }
+
+    #[tokio::test]
+    async fn regenerating_keeps_the_interaction_on_failure() {
+        let provider = Scripted::new([Ok("a1"), Err(Error::EmptyResponse), Ok("r1")]);
+        let mut session = session(provider, 5);
+
+        session.send_message("q1".to_string(), None).await.unwrap();
+        assert!(session.regenerate_last_interaction().await.is_err());
+        assert_eq!(prompts(&session), [("q1", "a1")]);
+
+        let response = session.regenerate_last_interaction().await.unwrap();
+        assert_eq!(response.unwrap().content, "r1");
+        assert_eq!(prompts(&session), [("q1", "r1")]);
+        assert_eq!(session.exchanged(), 1);
+    }
+
+    fn status_error(status: u16, body: &str) -> Error {
+        Error::Provider(genai::Error::WebAdapterCall {
+            adapter_kind: genai::adapter::AdapterKind::Groq,
+            webc_error: genai::webc::Error::ResponseFailedStatus {
+                status: reqwest::StatusCode::from_u16(status).unwrap(),
+                body: body.to_string(),
+            },
+        })
+    }
+
+    #[test]
+    fn failures_follow_the_status() {
+        assert_eq!(status_error(429, "").failure(), Failure::RateLimited);
+        assert_eq!(status_error(408, "").failure(), Failure::Timeout);
+        assert_eq!(status_error(504, "").failure(), Failure::Timeout);
+        assert_eq!(status_error(503, "moderation").failure(), Failure::Outage);
+        assert_eq!(
+            status_error(401, "invalid key").failure(),
+            Failure::Internal
+        );
+        assert_eq!(Error::EmptyResponse.failure(), Failure::EmptyResponse);
+    }
+
+    #[test]
+    fn failures_follow_the_body_markers() {
+        let context = r#"{"error":{"code":"context_length_exceeded"}}"#;
+        assert_eq!(
+            status_error(400, context).failure(),
+            Failure::ContextExceeded
+        );
+        let filtered = "Request blocked by Content Policy";
+        assert_eq!(
+            status_error(400, filtered).failure(),
+            Failure::ContentFiltered
+        );
+
+        // Partial successes carry the markers too.
+        assert_eq!(
+            status_error(207, "moderation").failure(),
+            Failure::ContentFiltered
+        );
+        assert_eq!(
+            status_error(207, "reduce the length of the messages").failure(),
+            Failure::ContextExceeded
+        );
+        assert_eq!(status_error(207, "").failure(), Failure::Internal);
+    }
+
+    #[test]
+    fn retry_after_reads_the_rate_limit_hint() {
+        let limited = status_error(429, "Rate limit reached, please try again in 1m2.5s.");
+        assert_eq!(limited.retry_after(), Some(Duration::from_secs_f64(62.5)));
+        assert_eq!(status_error(429, "slow down").retry_after(), None);
+        assert_eq!(status_error(500, "try again in 2s").retry_after(), None);
+    }
+
+    #[test]
+    fn parse_wait_adds_up_units() {
+        assert_eq!(parse_wait("1m2.5s"), Some(Duration::from_secs_f64(62.5)));
+        assert_eq!(parse_wait("250ms"), Some(Duration::from_millis(250)));
+        assert_eq!(parse_wait("1h"), Some(Duration::from_secs(3600)));
+    }
 
-        Session::new(user, self.history_size)
+    #[test]
+    fn parse_wait_refuses_missing_or_unknown_units() {
+        assert_eq!(parse_wait("12"), None);
+        assert_eq!(parse_wait("1m2"), None);
+        assert_eq!(parse_wait("3d"), None);
+        assert_eq!(parse_wait("0s"), None);
+        assert_eq!(parse_wait(""), None);
     }
 }
@@ -0,0 +1,434 @@
+const FENCE: &str = "```";
+
+/// Markers hinting at the language of a snippet, compared ignoring case.
+///
+/// Ties go to the language listed first.
+const LANGUAGE_MARKERS: &[(&str, &[&str])] = &[
+    (
+        "rust",
+        &[
+            "fn ", "let mut ", "impl ", "pub fn ", "println!", "&str", "::new(",
+        ],
+    ),
+    (
+        "python",
+        &["def ", "elif ", "self.", "print(", "__init__", "import "],
+    ),
+    (
+        "typescript",
+        &[
+            "interface ",
+            ": string",
+            ": number",
+            "export type ",
+            "readonly ",
+        ],
+    ),
+    (
+        "javascript",
+        &[
+            "const ",
+            "function ",
+            "=> ",
+            "console.log",
+            "require(",
+            "===",
+            "document.",
+        ],
+    ),
+    ("go", &["package ", "func ", ":= ", "fmt.", "err != nil"]),
+    (
+        "java",
+        &[
+            "public class ",
+            "public static void",
+            "system.out",
+            "private ",
+            "@override",
+        ],
+    ),
+    (
+        "cpp",
+        &[
+            "std::",
+            "#include <iostream>",
+            "cout <<",
+            "template<",
+            "nullptr",
+        ],
+    ),
+    ("c", &["#include", "printf(", "int main", "malloc(", "->"]),
+    (
+        "csharp",
+        &[
+            "using system",
+            "namespace ",
+            "console.writeline",
+            "public void ",
+        ],
+    ),
+    (
+        "sql",
+        &[
+            "select ",
+            " from ",
+            "insert into",
+            "create table",
+            "where ",
+            "join ",
+        ],
+    ),
+    (
+        "bash",
+        &["#!/bin/", "echo ", "sudo ", "apt ", "export ", "fi\n", "$ "],
+    ),
+    ("html", &["<!doctype", "<html", "<div", "</", "<body"]),
+    ("css", &["color:", "margin:", "padding:", "px;", "display:"]),
+    ("yaml", &["---\n", ": |", "- name:", "version:"]),
+];
+
+/// Best guess of the language a snippet is written in, if any marker shows up.
+pub fn detect_language(code: &str) -> Option<&'static str> {
+    let trimmed = code.trim();
+    if (trimmed.starts_with('{') || trimmed.starts_with('['))
+        && serde_json::from_str::<serde_json::Value>(trimmed).is_ok()
+    {
+        return Some("json");
+    }
+
+    let code = code.to_lowercase();
+    let mut best = None;
+    let mut best_score = 0;
+    for (language, markers) in LANGUAGE_MARKERS {
+        let score = markers
+            .iter()
+            .filter(|marker| code.contains(*marker))
+            .count();
+        if score > best_score {
+            best = Some(*language);
+            best_score = score;
+        }
+    }
+
+    best
+}
+
+/// Whether the line opens or closes a code block, unlike one-line blocks.
+fn is_fence(line: &str) -> bool {
+    let line = line.trim_start();
+
+    line.starts_with(FENCE) && line.matches(FENCE).count() == 1
+}
+
+/// Whether the text has a code block, even one left open.
+pub fn has_code_block(text: &str) -> bool {
+    text.lines().any(is_fence)
+}
+
+/// Whether a code block is left open at the end of the text.
+pub fn has_open_fence(text: &str) -> bool {
+    text.lines().filter(|line| is_fence(line)).count() % 2 == 1
+}
+
+/// Language tag and first code line of a fence opening line, without the backticks.
+///
+/// Models sometimes start the code right after the backticks, which isn't a tag.
+fn parse_opening(rest: &str) -> (String, Option<&str>) {
+    let rest = rest.trim();
+    let is_tag = !rest.is_empty()
+        && rest
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '+' | '#' | '-' | '_' | '.'));
+
+    if is_tag {
+        (rest.to_lowercase(), None)
+    } else if rest.is_empty() {
+        (String::new(), None)
+    } else {
+        (String::new(), Some(rest))
+    }
+}
+
+/// Rewrites the code blocks of a reply so they render well.
+///
+/// Untagged blocks are tagged with their detected language, tags are lowercased and a block left
+/// open is closed.
+pub fn format_fences(text: &str) -> String {
+    let mut formatted = String::with_capacity(text.len());
+    let mut lines = text.lines();
+
+    while let Some(line) = lines.next() {
+        if !is_fence(line) {
+            formatted.push_str(line);
+            formatted.push('\n');
+            continue;
+        }
+
+        let (mut tag, first) = parse_opening(&line.trim_start()[FENCE.len()..]);
+        let mut code: Vec<&str> = first.into_iter().collect();
+        for line in lines.by_ref() {
+            if is_fence(line) {
+                break;
+            }
+            code.push(line);
+        }
+
+        let code = code.join("\n");
+        if tag.is_empty() {
+            tag = detect_language(&code).unwrap_or_default().to_string();
+        }
+
+        formatted.push_str(FENCE);
+        formatted.push_str(&tag);
+        formatted.push('\n');
+        if !code.is_empty() {
+            formatted.push_str(&code);
+            formatted.push('\n');
+        }
+        formatted.push_str(FENCE);
+        formatted.push('\n');
+    }
+
+    if !text.ends_with('\n') {
+        formatted.pop();
+    }
+
+    formatted
+}
+
+/// Splits a reply into its prose and code blocks, in order, dropping blank parts.
+pub fn split_code(text: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut in_code = false;
+
+    for line in text.lines() {
+        let fence = is_fence(line);
+
+        if fence && !in_code {
+            parts.push(std::mem::take(&mut current));
+        }
+
+        current.push_str(line);
+        current.push('\n');
+
+        if fence && in_code {
+            parts.push(std::mem::take(&mut current));
+        }
+        if fence {
+            in_code = !in_code;
+        }
+    }
+    parts.push(current);
+
+    parts
+        .into_iter()
+        .map(|part| part.trim().to_string())
+        .filter(|part| !part.is_empty())
+        .collect()
+}
+
+/// Splits a reply into messages of up to `limit` characters, at the edges of its prose and code
+/// blocks when they fit, and at line ends otherwise.
+///
+/// Code blocks cut in between are closed and opened again, with their tag, in the next message.
+pub fn split_reply(text: &str, limit: usize) -> Vec<String> {
+    let mut messages = Vec::new();
+    let mut current = String::new();
+
+    for part in split_code(text) {
+        let len = part.chars().count();
+        if current.is_empty() && len <= limit {
+            current = part;
+            continue;
+        }
+        if !current.is_empty() && current.chars().count() + 2 + len <= limit {
+            current.push_str("\n\n");
+            current.push_str(&part);
+            continue;
+        }
+
+        if !current.is_empty() {
+            messages.push(std::mem::take(&mut current));
+        }
+        if len <= limit {
+            current = part;
+        } else {
+            let mut pieces = split_lines(&part, limit);
+            current = pieces.pop().unwrap_or_default();
+            messages.extend(pieces);
+        }
+    }
+    if !current.is_empty() {
+        messages.push(current);
+    }
+
+    messages
+}
+
+/// Splits prose or a code block too long for a message at line ends, cutting lines too long
+/// themselves.
+fn split_lines(part: &str, limit: usize) -> Vec<String> {
+    let opening = part.lines().next().filter(|line| is_fence(line));
+    // Room to close the block in each piece and open it again in the next.
+    let reserved = opening.map_or(0, |opening| opening.chars().count() + FENCE.len() + 2);
+    let budget = limit.saturating_sub(reserved).max(1);
+
+    let lines = part.lines().flat_map(|line| {
+        let chars: Vec<char> = line.chars().collect();
+        let mut cut: Vec<String> = chars
+            .chunks(budget)
+            .map(|chunk| chunk.iter().collect())
+            .collect();
+        if cut.is_empty() {
+            cut.push(String::new());
+        }
+
+        cut
+    });
+
+    let mut pieces: Vec<String> = Vec::new();
+    for line in lines {
+        match pieces.last_mut() {
+            Some(current) if current.chars().count() + 1 + line.chars().count() <= budget => {
+                current.push('\n');
+                current.push_str(&line);
+            }
+            _ => pieces.push(line),
+        }
+    }
+
+    if let Some(opening) = opening {
+        let last = pieces.len().saturating_sub(1);
+        for (index, piece) in pieces.iter_mut().enumerate() {
+            if index > 0 {
+                *piece = format!("{opening}\n{piece}");
+            }
+            if index < last {
+                piece.push('\n');
+                piece.push_str(FENCE);
+            }
+        }
+    }
+
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn has_open_fence_counts_block_fences_only() {
+        assert!(!has_open_fence("no code"));
+        assert!(!has_open_fence("```rust\nfn main() {}\n```"));
+        assert!(has_open_fence("text\n```rust\nfn main() {}"));
+        // One-line blocks neither open nor close one.
+        assert!(!has_open_fence("run ```ls``` here\n```ls```"));
+        assert!(has_open_fence("```\n```\n  ```py"));
+    }
+
+    #[test]
+    fn format_fences_tags_and_closes_blocks() {
+        let formatted = format_fences("Here:\n```\nlet mut x = String::new();\nprintln!(\"{x}\");");
+
+        assert_eq!(
+            formatted,
+            "Here:\n```rust\nlet mut x = String::new();\nprintln!(\"{x}\");\n```"
+        );
+    }
+
+    #[test]
+    fn format_fences_keeps_tags_lowercased() {
+        assert_eq!(
+            format_fences("```Python\nx = 1\n```\n"),
+            "```python\nx = 1\n```\n"
+        );
+    }
+
+    #[test]
+    fn format_fences_moves_code_off_the_opening_line() {
+        assert_eq!(
+            format_fences("```SELECT * FROM users;\n```"),
+            "```sql\nSELECT * FROM users;\n```"
+        );
+    }
+
+    #[test]
+    fn format_fences_leaves_prose_alone() {
+        let text = "Just prose, with `inline` code.\nAnd a second line.";
+
+        assert_eq!(format_fences(text), text);
+    }
+
+    #[test]
+    fn split_code_separates_blocks_from_prose() {
+        let parts = split_code("Intro\n\n```sh\necho hi\n```\n\nMiddle\n```\nls\n```");
+
+        assert_eq!(
+            parts,
+            ["Intro", "```sh\necho hi\n```", "Middle", "```\nls\n```"]
+        );
+    }
+
+    #[test]
+    fn split_code_keeps_an_open_block_whole() {
+        let parts = split_code("Intro\n```py\nprint(1)\n\nprint(2)");
+
+        assert_eq!(parts, ["Intro", "```py\nprint(1)\n\nprint(2)"]);
+    }
+
+    #[test]
+    fn split_code_drops_blank_parts() {
+        assert_eq!(split_code("```\nx\n```\n\n"), ["```\nx\n```"]);
+        assert!(split_code("  \n\n").is_empty());
+    }
+
+    #[test]
+    fn split_reply_keeps_a_short_reply_whole() {
+        let reply = "Intro\n\n```sh\necho hi\n```\n\nOutro";
+
+        assert_eq!(split_reply(reply, 100), [reply]);
+    }
+
+    #[test]
+    fn split_reply_cuts_between_prose_and_code() {
+        let messages = split_reply("First paragraph.\n```sh\necho hi\n```\nLast one.", 30);
+
+        assert_eq!(
+            messages,
+            ["First paragraph.", "```sh\necho hi\n```\n\nLast one."]
+        );
+    }
+
+    #[test]
+    fn split_reply_reopens_cut_code_blocks_with_their_tag() {
+        let code: Vec<String> = (0..20).map(|i| format!("let x{i} = {i};")).collect();
+        let reply = format!("```rust\n{}\n```", code.join("\n"));
+
+        let messages = split_reply(&reply, 80);
+
+        assert!(messages.len() > 1);
+        for message in &messages {
+            assert!(message.chars().count() <= 80, "{message:?} is too long");
+            assert!(message.starts_with("```rust\n"));
+            assert!(message.ends_with("\n```"));
+            assert!(!has_open_fence(message));
+        }
+        let lines: Vec<&str> = messages
+            .iter()
+            .flat_map(|message| message.lines())
+            .filter(|line| !is_fence(line))
+            .collect();
+        assert_eq!(lines, code);
+    }
+
+    #[test]
+    fn split_reply_cuts_lines_longer_than_a_message() {
+        let line = "a".repeat(25);
+
+        let messages = split_reply(&line, 10);
+
+        assert_eq!(messages, ["a".repeat(10), "a".repeat(10), "a".repeat(5)]);
+    }
+}
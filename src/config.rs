@@ -1,30 +1,250 @@
-use std::path::Path;
+use std::{
+    collections::{HashMap, HashSet},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
 
 use config::{Config, ConfigError};
 
+use crate::{chat, code, messages::Messages};
+
+/// Commented example with every section and its defaults.
+pub const EXAMPLE: &str = include_str!("../config/sample.yaml");
+
+/// Longest message Discord accepts, in characters.
+pub const DISCORD_MESSAGE_LIMIT: u16 = 2000;
+
+/// Prompt sizes accepted, in characters.
+pub const PROMPT_SIZE_RANGE: RangeInclusive<u16> = 255..=4096;
+
+/// Longest name Discord accepts for webhook messages, in characters.
+const WEBHOOK_NAME_LIMIT: usize = 80;
+/// Most messages Discord returns per history request.
+const CHANNEL_CONTEXT_LIMIT: u8 = 100;
+/// Most questions asked at once, keeping the answers within a few messages.
+const ASK_MANY_LIMIT: u8 = 10;
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("failed to read config")]
     ReadedError(#[source] ConfigError),
     #[error("failed to to parse config")]
     ParserError(#[source] ConfigError),
-    #[error("prompt_size must be between 255 and 4096 characters")]
+    #[error("invalid include pattern")]
+    InvalidInclude(#[source] glob::PatternError),
+    #[error("failed to read included files")]
+    ReadedInclude(#[source] glob::GlobError),
+    #[error("failed to read messages file")]
+    ReadedMessagesError(#[source] ConfigError),
+    #[error("failed to parse messages file")]
+    ParserMessagesError(#[source] ConfigError),
+    #[error("prompt_size must be between {} and {} characters", PROMPT_SIZE_RANGE.start(), PROMPT_SIZE_RANGE.end())]
     InvalidPromptSize,
     #[error("flush_days must be greater than zero")]
     InvalidFlushDays,
     #[error("history_size must be greater than zero")]
     InvalidHistorySize,
+    #[error("history_tokens must be greater than zero")]
+    InvalidHistoryTokens,
+    #[error("priority_burst must be greater than zero")]
+    InvalidPriorityBurst,
+    #[error("max_sessions must be greater than zero")]
+    InvalidMaxSessions,
+    #[error("pin_cycles must be greater than zero")]
+    InvalidPinCycles,
+    #[error("max_response_chars must be between 1 and {DISCORD_MESSAGE_LIMIT}")]
+    InvalidMaxResponseChars,
+    #[error("sentry_dsn is not a valid DSN")]
+    InvalidSentryDsn,
+    #[error("alert_lifetime_secs must be greater than zero")]
+    InvalidAlertLifetime,
+    #[error("min_tokens_per_sec and slow_requests must be greater than zero")]
+    InvalidThroughput,
+    #[error("tldr chunk_chars and max_messages must be greater than zero")]
+    InvalidTldr,
+    #[error("ask_many max_questions must be between 1 and {ASK_MANY_LIMIT} and concurrency greater than zero")]
+    InvalidAskMany,
+    #[error("color must be a RGB value between 0x000000 and 0xFFFFFF")]
+    InvalidColor,
+    #[error("pricing must not be negative")]
+    InvalidPricing,
+    #[error("shards must be greater than zero and ranges must satisfy first <= last < total")]
+    InvalidShards,
+    #[error("spam window, max similar and block must be greater than zero and similarity between 0 and 1")]
+    InvalidSpam,
+    #[error("command prefix must not be blank")]
+    InvalidPrefix,
+    #[error("voice needs a bot built with the voice feature, a TTS model and a TTS voice")]
+    InvalidVoice,
+    #[error("transcription needs an STT model and a max duration greater than zero")]
+    InvalidTranscription,
+    #[error("image generation needs an image model")]
+    InvalidImagine,
+    #[error("followups count must be between 1 and 5")]
+    InvalidFollowups,
+    #[error("schedule max_jobs must be greater than zero")]
+    InvalidSchedule,
+    #[error("webhook names must have between 1 and {WEBHOOK_NAME_LIMIT} characters and persona keywords can't be blank")]
+    InvalidWebhook,
+    #[error("channel context must take between 1 and {CHANNEL_CONTEXT_LIMIT} messages and some characters")]
+    InvalidChannelContext,
+    #[error("digest hour must be between 0 and 23")]
+    InvalidDigestHour,
+    #[error(
+        "experiment share must be at most 100 and a running experiment needs a model or persona"
+    )]
+    InvalidExperiment,
+    #[error("route {0} needs a model and min_prompt_chars at most max_prompt_chars")]
+    InvalidRoute(usize),
+    #[error("pipeline {0} needs a name without spaces and steps with a prompt and a unique name other than input and previous")]
+    InvalidPipeline(String),
+    #[error("intent {0:?} is required by the enabled features")]
+    MissingIntent(Intent),
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ShardingMode {
+    Auto,
+}
+
+/// Shards run by this process, either picked by Discord, a total count or a range of it.
+#[derive(serde::Deserialize, Debug, Clone, Copy)]
+#[serde(untagged)]
+pub enum Shards {
+    Auto(ShardingMode),
+    Count(u32),
+    Range { first: u32, last: u32, total: u32 },
+}
+
+impl Shards {
+    fn is_valid(&self) -> bool {
+        match *self {
+            Shards::Auto(_) => true,
+            Shards::Count(count) => count > 0,
+            Shards::Range { first, last, total } => first <= last && last < total,
+        }
+    }
+
+    /// Whether the guild is served by one of these shards.
+    pub fn owns(&self, guild: u64) -> bool {
+        match *self {
+            Shards::Range { first, last, total } => {
+                let shard = (guild >> 22) % total as u64;
+
+                (first as u64..=last as u64).contains(&shard)
+            }
+            // Every shard runs in this process.
+            Shards::Auto(_) | Shards::Count(_) => true,
+        }
+    }
+}
+
+/// Gateway intents that may be requested from Discord.
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Intent {
+    Guilds,
+    GuildMembers,
+    GuildModeration,
+    GuildPresences,
+    GuildMessages,
+    GuildMessageReactions,
+    MessageContent,
+    GuildVoiceStates,
+    DirectMessages,
+    DirectMessageReactions,
 }
 
 #[derive(serde::Deserialize, Debug, Clone)]
 pub struct Bot {
     pub discord_token: String,
+    #[serde(default)]
+    pub owners: Vec<u64>,
+    pub shards: Option<Shards>,
+    #[serde(default)]
+    pub maintenance: bool,
+    pub prefix: Option<String>,
+    /// Replaces the intents picked from the enabled features when set.
+    pub intents: Option<Vec<Intent>>,
+    #[serde(default)]
+    pub mentions: Mentions,
+    /// Answers prefix prompts again when their message is edited, replacing the reply.
+    #[serde(default)]
+    pub regenerate_on_edit: bool,
+    #[serde(default)]
+    pub startup_checks: StartupChecks,
+    /// Registers commands per guild, leaving out the ones it turned off, instead of globally.
+    #[serde(default)]
+    pub guild_commands: bool,
+    /// Calls the provider and the snapshot directory once before connecting.
+    #[serde(default)]
+    pub warmup: bool,
+}
+
+impl Bot {
+    /// Name of a file this process keeps in the snapshot directory.
+    ///
+    /// Processes running a range of shards may share the directory, so their files are named
+    /// after the range, e.g. `sessions.0-3of8.json`.
+    pub fn shard_file(&self, file: &str) -> String {
+        let Some(Shards::Range { first, last, total }) = self.shards else {
+            return file.to_string();
+        };
+
+        match file.rsplit_once('.') {
+            Some((stem, extension)) => format!("{stem}.{first}-{last}of{total}.{extension}"),
+            None => format!("{file}.{first}-{last}of{total}"),
+        }
+    }
+}
+
+/// What happens on startup when Discord doesn't grant the application the privileged
+/// intents requested.
+#[derive(serde::Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StartupChecks {
+    /// Skips the checks, e.g. when the application info can't be fetched.
+    Off,
+    /// Logs what's missing and starts anyway.
+    Warn,
+    #[default]
+    Fail,
+}
+
+/// Mentions in bot messages that ping, the author of a replied message aside.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Mentions {
+    None,
+    #[default]
+    Users,
+    /// Users and roles, never @everyone nor @here.
+    Roles,
 }
 
 #[derive(serde::Deserialize, Debug, Clone)]
 pub struct AiProvider {
+    #[serde(default)]
+    pub provider: Provider,
+    #[serde(default)]
     pub api_key: String,
     pub model: String,
+    pub title_model: Option<String>,
+    /// Models that privileged members may pick for a single prompt.
+    #[serde(default)]
+    pub override_models: Vec<String>,
+}
+
+/// Where model replies come from.
+#[derive(serde::Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    /// The provider picked by genai from the model name.
+    #[default]
+    Genai,
+    /// Echoes prompts back without calling any provider, e.g. to test the bot.
+    Mock,
 }
 
 #[derive(serde::Deserialize, Debug, Clone)]
@@ -32,6 +252,966 @@ pub struct Chat {
     pub prompt_size: u16,
     pub flush_days: u8,
     pub history_size: u8,
+    #[serde(default = "default_max_sessions")]
+    pub max_sessions: u8,
+    #[serde(default = "default_title_after")]
+    pub title_after: u8,
+    #[serde(default)]
+    pub carry_summary: bool,
+    #[serde(default)]
+    pub match_language: bool,
+    /// Sessions each guild may pin at once, zero disables pinning.
+    #[serde(default = "default_max_pins")]
+    pub max_pins: u8,
+    /// Flushes a pinned session survives before it's unpinned.
+    #[serde(default = "default_pin_cycles")]
+    pub pin_cycles: u8,
+    #[serde(default = "default_max_response_chars")]
+    pub max_response_chars: u16,
+    #[serde(default)]
+    pub overflow: Overflow,
+    /// Prompt snippets each member may save with /macro, zero disables saving them.
+    #[serde(default = "default_max_macros")]
+    pub max_macros: u8,
+    /// Prompts that may wait behind the one a session is answering.
+    #[serde(default = "default_queue_depth")]
+    pub queue_depth: u8,
+    /// Lets prompt authors read the thinking of reasoning models behind a button.
+    #[serde(default)]
+    pub show_reasoning: bool,
+    /// Notes under replies that their session is about to be flushed.
+    #[serde(default)]
+    pub flush_warning: bool,
+    /// Seconds deleted sessions may still be restored, zero deletes them right away.
+    #[serde(default = "default_undo_grace_secs")]
+    pub undo_grace_secs: u32,
+    #[serde(default)]
+    pub strategy: HistoryStrategy,
+    /// Rough tokens the history of a session may take with the token-budget strategy.
+    #[serde(default = "default_history_tokens")]
+    pub history_tokens: u32,
+    /// Seconds a reply may take before it's asked again as a stream, zero never streams.
+    #[serde(default)]
+    pub latency_budget_secs: u32,
+}
+
+/// How sessions make room in history for new interactions, besides keeping up to `history_size`.
+#[derive(serde::Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum HistoryStrategy {
+    /// Drops the oldest interactions.
+    #[default]
+    Window,
+    /// Drops the oldest interactions, asking the model to fold them into the session summary.
+    Summarize,
+    /// Also drops the oldest interactions while history takes more than `history_tokens`.
+    TokenBudget,
+}
+
+/// What's done with replies longer than `max_response_chars`.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Overflow {
+    #[default]
+    Truncate,
+    /// Asks the model to shorten the reply, truncating it if still too long.
+    Summarize,
+    /// Sends the truncated reply along with the whole one as a file.
+    AttachFile,
+    /// Posts the whole reply in a thread started off a pointer to it, over as many messages as
+    /// it takes. Private replies are truncated instead.
+    Thread,
+}
+
+fn default_max_pins() -> u8 {
+    3
+}
+
+fn default_pin_cycles() -> u8 {
+    1
+}
+
+fn default_history_tokens() -> u32 {
+    4096
+}
+
+fn default_undo_grace_secs() -> u32 {
+    300
+}
+
+fn default_max_macros() -> u8 {
+    10
+}
+
+fn default_queue_depth() -> u8 {
+    1
+}
+
+fn default_max_response_chars() -> u16 {
+    DISCORD_MESSAGE_LIMIT
+}
+
+fn default_max_sessions() -> u8 {
+    5
+}
+
+fn default_title_after() -> u8 {
+    2
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default)]
+pub struct Guild {
+    pub log_channel: Option<u64>,
+    #[serde(default)]
+    pub log_arguments: bool,
+    #[serde(default)]
+    pub shared_channels: Vec<u64>,
+    #[serde(default)]
+    pub required_roles: Vec<u64>,
+    #[serde(default)]
+    pub model_override_roles: Vec<u64>,
+    /// Members with one of these roles may run /compare.
+    #[serde(default)]
+    pub compare_roles: Vec<u64>,
+    /// Members with one of these roles are queued first when the provider is busy.
+    #[serde(default)]
+    pub priority_roles: Vec<u64>,
+    /// Commands turned off in the guild by name, unless turned back on with /config commands.
+    #[serde(default)]
+    pub disabled_commands: Vec<String>,
+    pub max_response_chars: Option<u16>,
+    pub overflow: Option<Overflow>,
+    /// Longest prompt of the guild, over the chat one, e.g. for premium servers.
+    pub prompt_size: Option<u16>,
+    /// Interactions sessions of the guild keep, over the chat one.
+    pub history_size: Option<u8>,
+    /// Wraps replies in an embed labelling them as AI-generated.
+    #[serde(default)]
+    pub embed_replies: bool,
+    /// Posts replies through a channel webhook instead of the bot user.
+    pub webhook: Option<Webhook>,
+    /// Lets members send the recent messages of the channel along their prompts.
+    pub channel_context: Option<ChannelContext>,
+    /// Mentions that ping in the guild, over the bot one.
+    pub mentions: Option<Mentions>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct ChannelContext {
+    /// Recent messages read, before leaving out the bot and command ones.
+    #[serde(default = "default_context_messages")]
+    pub messages: u8,
+    /// Most characters sent along, dropping the oldest messages past it.
+    #[serde(default = "default_context_chars")]
+    pub max_chars: usize,
+}
+
+fn default_context_messages() -> u8 {
+    30
+}
+
+fn default_context_chars() -> usize {
+    3000
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct Webhook {
+    #[serde(default = "default_webhook_name")]
+    pub name: String,
+    pub avatar_url: Option<String>,
+    /// Speakers picked by the session instructions, first match wins.
+    #[serde(default)]
+    pub personas: Vec<Persona>,
+}
+
+fn default_webhook_name() -> String {
+    "Assistant".to_string()
+}
+
+impl Webhook {
+    /// Name and avatar replies are posted with, given the instructions of the session.
+    pub fn speaker(&self, instructions: Option<&str>) -> (&str, Option<&str>) {
+        let instructions = instructions.unwrap_or_default().to_lowercase();
+        let persona = self
+            .personas
+            .iter()
+            .find(|persona| instructions.contains(&persona.keyword.to_lowercase()));
+
+        match persona {
+            Some(persona) => (&persona.name, persona.avatar_url.as_deref()),
+            None => (&self.name, self.avatar_url.as_deref()),
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct Persona {
+    /// Looked up in the session instructions, ignoring case.
+    pub keyword: String,
+    pub name: String,
+    pub avatar_url: Option<String>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Default)]
+pub struct Access {
+    #[serde(default)]
+    pub min_account_age_days: u64,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct Reactions {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_regenerate_reaction")]
+    pub regenerate: Option<String>,
+    #[serde(default = "default_delete_reaction")]
+    pub delete: Option<String>,
+    #[serde(default = "default_export_reaction")]
+    pub export: Option<String>,
+}
+
+fn default_regenerate_reaction() -> Option<String> {
+    Some("🔁".to_string())
+}
+
+fn default_delete_reaction() -> Option<String> {
+    Some("❌".to_string())
+}
+
+fn default_export_reaction() -> Option<String> {
+    Some("📌".to_string())
+}
+
+impl Default for Reactions {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            regenerate: default_regenerate_reaction(),
+            delete: default_delete_reaction(),
+            export: default_export_reaction(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct Spam {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_spam_window_secs")]
+    pub window_secs: u64,
+    #[serde(default = "default_spam_max_similar")]
+    pub max_similar: u32,
+    #[serde(default = "default_spam_similarity")]
+    pub similarity: f64,
+    #[serde(default = "default_spam_warnings")]
+    pub warnings: u32,
+    #[serde(default = "default_spam_block_secs")]
+    pub block_secs: u64,
+}
+
+fn default_spam_window_secs() -> u64 {
+    60
+}
+
+fn default_spam_max_similar() -> u32 {
+    3
+}
+
+fn default_spam_similarity() -> f64 {
+    0.9
+}
+
+fn default_spam_warnings() -> u32 {
+    2
+}
+
+fn default_spam_block_secs() -> u64 {
+    600
+}
+
+impl Default for Spam {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            window_secs: default_spam_window_secs(),
+            max_similar: default_spam_max_similar(),
+            similarity: default_spam_similarity(),
+            warnings: default_spam_warnings(),
+            block_secs: default_spam_block_secs(),
+        }
+    }
+}
+
+/// Error texts from before the messages file, used when there isn't one.
+#[derive(serde::Deserialize, Debug, Clone, Default)]
+pub struct ErrorMessages {
+    pub cooldown: Option<String>,
+    pub unexpected: Option<String>,
+    pub prompt_failure: Option<String>,
+}
+
+impl ErrorMessages {
+    fn apply(&self, messages: &mut Messages) {
+        if let Some(cooldown) = &self.cooldown {
+            messages.alerts.cooldown.clone_from(cooldown);
+        }
+        if let Some(unexpected) = &self.unexpected {
+            messages.alerts.unexpected_error.clone_from(unexpected);
+        }
+        if let Some(prompt_failure) = &self.prompt_failure {
+            messages.alerts.prompt_failure.clone_from(prompt_failure);
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct Appearance {
+    #[serde(default = "default_alert_lifetime_secs")]
+    pub alert_lifetime_secs: u64,
+    pub color: Option<u32>,
+    pub footer_text: Option<String>,
+    pub footer_icon_url: Option<String>,
+    pub messages_file: Option<PathBuf>,
+    /// Deprecated in favour of `messages_file`.
+    #[serde(default)]
+    pub errors: ErrorMessages,
+    #[serde(default = "default_welcome_message")]
+    pub welcome_message: bool,
+}
+
+fn default_alert_lifetime_secs() -> u64 {
+    10
+}
+
+fn default_welcome_message() -> bool {
+    true
+}
+
+impl Default for Appearance {
+    fn default() -> Self {
+        Self {
+            alert_lifetime_secs: default_alert_lifetime_secs(),
+            color: None,
+            footer_text: None,
+            footer_icon_url: None,
+            messages_file: None,
+            errors: ErrorMessages::default(),
+            welcome_message: default_welcome_message(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct Observability {
+    pub sentry_dsn: Option<String>,
+    pub environment: Option<String>,
+    /// Reply generation speed below which the provider counts as degraded.
+    pub min_tokens_per_sec: Option<f64>,
+    /// Slow replies in a row before raising it.
+    #[serde(default = "default_slow_requests")]
+    pub slow_requests: u32,
+    /// Directory where requests captured by owners are written to.
+    pub capture_dir: Option<PathBuf>,
+}
+
+fn default_slow_requests() -> u32 {
+    3
+}
+
+impl Default for Observability {
+    fn default() -> Self {
+        Self {
+            sentry_dsn: None,
+            environment: None,
+            min_tokens_per_sec: None,
+            slow_requests: default_slow_requests(),
+            capture_dir: None,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct ModelPricing {
+    pub input_per_million: f64,
+    pub output_per_million: f64,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct Pricing {
+    pub input_per_million: f64,
+    pub output_per_million: f64,
+    #[serde(default = "default_currency")]
+    pub currency: String,
+    /// Prices of specific models, overriding the ones above.
+    #[serde(default)]
+    pub models: HashMap<String, ModelPricing>,
+}
+
+fn default_currency() -> String {
+    "USD".to_string()
+}
+
+impl Pricing {
+    /// Estimated cost of the tokens spent on each model, each priced as its own.
+    pub fn estimate(&self, spent: &HashMap<String, chat::Usage>) -> f64 {
+        spent
+            .iter()
+            .map(|(model, usage)| {
+                let (input_per_million, output_per_million) = match self.models.get(model) {
+                    Some(prices) => (prices.input_per_million, prices.output_per_million),
+                    None => (self.input_per_million, self.output_per_million),
+                };
+
+                (usage.input_tokens as f64 * input_per_million
+                    + usage.output_tokens as f64 * output_per_million)
+                    / 1_000_000.
+            })
+            .sum()
+    }
+
+    fn is_valid(&self) -> bool {
+        self.input_per_million >= 0.
+            && self.output_per_million >= 0.
+            && self
+                .models
+                .values()
+                .all(|prices| prices.input_per_million >= 0. && prices.output_per_million >= 0.)
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct Vault {
+    pub address: String,
+    pub token: Option<String>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Default)]
+pub struct Secrets {
+    pub vault: Option<Vault>,
+    #[serde(default)]
+    pub refresh_secs: u64,
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Default)]
+pub struct Persistence {
+    pub snapshot_dir: Option<PathBuf>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Default)]
+pub struct Hooks {
+    pub script: Option<PathBuf>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct Voice {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_tts_url")]
+    pub tts_url: String,
+    #[serde(default)]
+    pub tts_api_key: String,
+    #[serde(default)]
+    pub tts_model: String,
+    #[serde(default)]
+    pub tts_voice: String,
+}
+
+fn default_tts_url() -> String {
+    "https://api.groq.com/openai/v1/audio/speech".to_string()
+}
+
+impl Default for Voice {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            tts_url: default_tts_url(),
+            tts_api_key: String::new(),
+            tts_model: String::new(),
+            tts_voice: String::new(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct Transcription {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_stt_url")]
+    pub stt_url: String,
+    #[serde(default)]
+    pub stt_api_key: String,
+    #[serde(default = "default_stt_model")]
+    pub stt_model: String,
+    #[serde(default = "default_max_duration_secs")]
+    pub max_duration_secs: u64,
+}
+
+fn default_stt_url() -> String {
+    "https://api.groq.com/openai/v1/audio/transcriptions".to_string()
+}
+
+fn default_stt_model() -> String {
+    "whisper-large-v3-turbo".to_string()
+}
+
+fn default_max_duration_secs() -> u64 {
+    60
+}
+
+impl Default for Transcription {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            stt_url: default_stt_url(),
+            stt_api_key: String::new(),
+            stt_model: default_stt_model(),
+            max_duration_secs: default_max_duration_secs(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct Imagine {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_image_url")]
+    pub image_url: String,
+    #[serde(default)]
+    pub image_api_key: String,
+    #[serde(default)]
+    pub image_model: String,
+    #[serde(default = "default_image_size")]
+    pub size: String,
+    /// Images a member may generate per day, unlimited when zero.
+    #[serde(default)]
+    pub daily_quota: u64,
+    #[serde(default)]
+    pub nsfw_only: bool,
+}
+
+fn default_image_url() -> String {
+    "https://api.openai.com/v1/images/generations".to_string()
+}
+
+fn default_image_size() -> String {
+    "1024x1024".to_string()
+}
+
+impl Default for Imagine {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            image_url: default_image_url(),
+            image_api_key: String::new(),
+            image_model: String::new(),
+            size: default_image_size(),
+            daily_quota: 0,
+            nsfw_only: false,
+        }
+    }
+}
+
+/// How permissive the model may be in a channel.
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentLevel {
+    /// Refuses prompts with blocked terms and keeps replies family friendly.
+    Strict,
+    Moderate,
+    Relaxed,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct Content {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Level of channels that aren't age-restricted.
+    #[serde(default = "default_sfw_level")]
+    pub sfw_level: ContentLevel,
+    /// Level of age-restricted channels.
+    #[serde(default = "default_nsfw_level")]
+    pub nsfw_level: ContentLevel,
+    #[serde(default = "default_strict_instructions")]
+    pub strict_instructions: String,
+    #[serde(default = "default_moderate_instructions")]
+    pub moderate_instructions: String,
+    #[serde(default)]
+    pub relaxed_instructions: String,
+    /// Terms refused in strict channels, matched ignoring case.
+    #[serde(default)]
+    pub blocked_terms: Vec<String>,
+}
+
+fn default_sfw_level() -> ContentLevel {
+    ContentLevel::Strict
+}
+
+fn default_nsfw_level() -> ContentLevel {
+    ContentLevel::Moderate
+}
+
+fn default_strict_instructions() -> String {
+    "Keep replies suitable for all ages. Politely decline sexual, graphic or otherwise \
+    mature requests."
+        .to_string()
+}
+
+fn default_moderate_instructions() -> String {
+    "Mature themes are allowed, but avoid explicit content.".to_string()
+}
+
+impl Content {
+    pub fn level(&self, age_restricted: bool) -> ContentLevel {
+        if age_restricted {
+            self.nsfw_level
+        } else {
+            self.sfw_level
+        }
+    }
+
+    pub fn instructions(&self, level: ContentLevel) -> &str {
+        match level {
+            ContentLevel::Strict => &self.strict_instructions,
+            ContentLevel::Moderate => &self.moderate_instructions,
+            ContentLevel::Relaxed => &self.relaxed_instructions,
+        }
+    }
+}
+
+impl Default for Content {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            sfw_level: default_sfw_level(),
+            nsfw_level: default_nsfw_level(),
+            strict_instructions: default_strict_instructions(),
+            moderate_instructions: default_moderate_instructions(),
+            relaxed_instructions: String::new(),
+            blocked_terms: Vec::new(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct Followups {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_followups_count")]
+    pub count: u8,
+}
+
+fn default_followups_count() -> u8 {
+    3
+}
+
+impl Default for Followups {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            count: default_followups_count(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Default)]
+pub struct Code {
+    /// Tags untagged code blocks with their detected language and closes open ones.
+    #[serde(default)]
+    pub format_fences: bool,
+    /// Posts code blocks and the prose around them as separate messages.
+    #[serde(default)]
+    pub split_replies: bool,
+}
+
+/// Rules rewriting model replies, applied in order.
+#[derive(serde::Deserialize, Debug, Clone, Default)]
+pub struct PostProcess {
+    #[serde(default)]
+    pub rules: Vec<Rewrite>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct Rewrite {
+    #[serde(deserialize_with = "deserialize_regex")]
+    pub pattern: regex::Regex,
+    /// Replaces every match, may refer to groups like `$1`.
+    #[serde(default)]
+    pub replacement: String,
+    /// Models the rule applies to, every one when empty.
+    #[serde(default)]
+    pub models: Vec<String>,
+}
+
+fn deserialize_regex<'de, D>(deserializer: D) -> Result<regex::Regex, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let pattern = <String as serde::Deserialize>::deserialize(deserializer)?;
+
+    regex::Regex::new(&pattern).map_err(serde::de::Error::custom)
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct Tldr {
+    /// Most characters of the thread summarized at once, also bounding merged summaries.
+    #[serde(default = "default_tldr_chunk_chars")]
+    pub chunk_chars: usize,
+    /// Most recent messages of the thread read.
+    #[serde(default = "default_tldr_max_messages")]
+    pub max_messages: usize,
+}
+
+fn default_tldr_chunk_chars() -> usize {
+    12000
+}
+
+fn default_tldr_max_messages() -> usize {
+    1000
+}
+
+impl Default for Tldr {
+    fn default() -> Self {
+        Self {
+            chunk_chars: default_tldr_chunk_chars(),
+            max_messages: default_tldr_max_messages(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct AskMany {
+    /// Most questions asked at once.
+    #[serde(default = "default_ask_many_max_questions")]
+    pub max_questions: u8,
+    /// Questions answered at the same time.
+    #[serde(default = "default_ask_many_concurrency")]
+    pub concurrency: u8,
+}
+
+fn default_ask_many_max_questions() -> u8 {
+    5
+}
+
+fn default_ask_many_concurrency() -> u8 {
+    2
+}
+
+impl Default for AskMany {
+    fn default() -> Self {
+        Self {
+            max_questions: default_ask_many_max_questions(),
+            concurrency: default_ask_many_concurrency(),
+        }
+    }
+}
+
+/// Model calls /run-pipeline chains, each step prompting with the output of the previous ones.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct NamedPipeline {
+    #[serde(default)]
+    pub description: String,
+    pub steps: Vec<PipelineStep>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct PipelineStep {
+    /// Placeholder later steps take its output from.
+    pub name: String,
+    /// Template replacing `{input}`, `{previous}` and `{<step name>}` with their text.
+    pub prompt: String,
+    /// Model answering the step, the default one when none.
+    pub model: Option<String>,
+    /// Posts the output of the step, as the one of the last step always is.
+    #[serde(default)]
+    pub show: bool,
+}
+
+impl NamedPipeline {
+    fn is_valid(&self, name: &str) -> bool {
+        let mut names = HashSet::new();
+        let valid_step = |step: &PipelineStep| {
+            !step.name.trim().is_empty()
+                && !matches!(step.name.as_str(), "input" | "previous")
+                && !step.prompt.trim().is_empty()
+        };
+
+        !name.is_empty()
+            && !name.contains(char::is_whitespace)
+            && !self.steps.is_empty()
+            && self
+                .steps
+                .iter()
+                .all(|step| valid_step(step) && names.insert(step.name.as_str()))
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct Schedule {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Prompts each member may have scheduled at once.
+    #[serde(default = "default_schedule_max_jobs")]
+    pub max_jobs: u8,
+}
+
+fn default_schedule_max_jobs() -> u8 {
+    5
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_jobs: default_schedule_max_jobs(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct Digest {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_digest_weekday")]
+    pub weekday: chrono::Weekday,
+    #[serde(default = "default_digest_hour")]
+    pub hour: u32,
+}
+
+fn default_digest_weekday() -> chrono::Weekday {
+    chrono::Weekday::Mon
+}
+
+fn default_digest_hour() -> u32 {
+    9
+}
+
+impl Default for Digest {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            weekday: default_digest_weekday(),
+            hour: default_digest_hour(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Default)]
+pub struct Share {
+    pub paste_url: Option<String>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Default)]
+pub struct Console {
+    #[serde(default)]
+    pub stdin: bool,
+    pub socket: Option<PathBuf>,
+}
+
+/// Alternative model or persona a share of new sessions are created with.
+#[derive(serde::Deserialize, Debug, Clone, Default)]
+pub struct Experiment {
+    /// Percentage of new sessions in the variant arm, zero disables the experiment.
+    #[serde(default)]
+    pub share: u8,
+    /// Model of the variant arm, the default one when none.
+    pub model: Option<String>,
+    /// Instructions variant sessions start with, unless the guild set a persona.
+    pub persona: Option<String>,
+}
+
+/// Models /compare answers with, each standing for an arm of the experiment.
+#[derive(serde::Deserialize, Debug, Clone, Default)]
+pub struct Compare {
+    /// Model answering for the control arm, the default one when none.
+    pub control: Option<String>,
+    /// Model answering for the variant arm, the experiment one when none.
+    pub variant: Option<String>,
+}
+
+/// Provider, key and model answering the prompts that meet every condition set.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct Route {
+    /// Guilds the route applies to, every guild when empty.
+    #[serde(default)]
+    pub guilds: Vec<u64>,
+    /// Looked up in the session instructions, ignoring case.
+    pub persona: Option<String>,
+    /// Shortest prompt taken, in characters.
+    #[serde(default)]
+    pub min_prompt_chars: usize,
+    /// Longest prompt taken, in characters, unbounded when none.
+    pub max_prompt_chars: Option<usize>,
+    /// Only takes prompts with a code block.
+    #[serde(default)]
+    pub code: bool,
+    #[serde(default)]
+    pub provider: Provider,
+    /// Key of the provider, the ai_provider one when none.
+    pub api_key: Option<String>,
+    pub model: String,
+}
+
+impl Route {
+    /// Whether the prompt sent in the guild, to a session with the instructions, takes the route.
+    pub fn matches(&self, guild: u64, instructions: Option<&str>, prompt: &str) -> bool {
+        let chars = prompt.chars().count();
+        let persona = self.persona.as_ref().is_none_or(|keyword| {
+            instructions.is_some_and(|instructions| {
+                instructions
+                    .to_lowercase()
+                    .contains(&keyword.to_lowercase())
+            })
+        });
+
+        (self.guilds.is_empty() || self.guilds.contains(&guild))
+            && persona
+            && chars >= self.min_prompt_chars
+            && self.max_prompt_chars.is_none_or(|max| chars <= max)
+            && (!self.code || code::has_code_block(prompt))
+    }
+
+    fn is_valid(&self) -> bool {
+        !self.model.trim().is_empty()
+            && self
+                .max_prompt_chars
+                .is_none_or(|max| self.min_prompt_chars <= max)
+    }
+}
+
+/// Bound on the provider calls running at once, queueing supporters' prompts first.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct Lanes {
+    /// Provider calls running at once across every guild, zero leaves them unbounded.
+    #[serde(default)]
+    pub max_concurrent: u16,
+    /// Prompts of supporters let through in a row while others wait, before one of those.
+    #[serde(default = "default_priority_burst")]
+    pub priority_burst: u8,
+    /// Treats members boosting the guild as supporters.
+    #[serde(default)]
+    pub boosters: bool,
+    /// Provider calls let through per minute, zero when the provider doesn't bound them.
+    #[serde(default)]
+    pub requests_per_minute: u32,
+    /// Tokens provider calls may take per minute, zero when the provider doesn't bound them.
+    #[serde(default)]
+    pub tokens_per_minute: u32,
+}
+
+fn default_priority_burst() -> u8 {
+    3
+}
+
+impl Default for Lanes {
+    fn default() -> Self {
+        Self {
+            max_concurrent: 0,
+            priority_burst: default_priority_burst(),
+            boosters: false,
+            requests_per_minute: 0,
+            tokens_per_minute: 0,
+        }
+    }
 }
 
 #[derive(serde::Deserialize, Debug, Clone)]
@@ -39,31 +1219,430 @@ pub struct App {
     pub bot: Bot,
     pub chat: Chat,
     pub ai_provider: AiProvider,
+    #[serde(default)]
+    pub reactions: Reactions,
+    #[serde(default)]
+    pub spam: Spam,
+    #[serde(default)]
+    pub access: Access,
+    #[serde(default)]
+    pub appearance: Appearance,
+    #[serde(default)]
+    pub observability: Observability,
+    pub pricing: Option<Pricing>,
+    #[serde(default)]
+    pub secrets: Secrets,
+    #[serde(default)]
+    pub persistence: Persistence,
+    #[serde(default)]
+    pub hooks: Hooks,
+    #[serde(default)]
+    pub voice: Voice,
+    #[serde(default)]
+    pub transcription: Transcription,
+    #[serde(default)]
+    pub imagine: Imagine,
+    #[serde(default)]
+    pub content: Content,
+    #[serde(default)]
+    pub followups: Followups,
+    #[serde(default)]
+    pub code: Code,
+    #[serde(default)]
+    pub postprocess: PostProcess,
+    #[serde(default)]
+    pub tldr: Tldr,
+    #[serde(default)]
+    pub ask_many: AskMany,
+    #[serde(default)]
+    pub schedule: Schedule,
+    #[serde(default)]
+    pub digest: Digest,
+    #[serde(default)]
+    pub share: Share,
+    #[serde(default)]
+    pub console: Console,
+    #[serde(default)]
+    pub experiment: Experiment,
+    #[serde(default)]
+    pub compare: Compare,
+    #[serde(default)]
+    pub lanes: Lanes,
+    /// Picked for each prompt in order, first match wins.
+    #[serde(default)]
+    pub routes: Vec<Route>,
+    #[serde(default)]
+    pub pipelines: HashMap<String, NamedPipeline>,
+    #[serde(default)]
+    pub guilds: HashMap<u64, Guild>,
+    /// Files merged over this one in order, relative to its directory.
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(skip)]
+    pub messages: Messages,
+    /// File this config was parsed from.
+    #[serde(skip)]
+    pub path: PathBuf,
+}
+
+/// Expands the include patterns, sorting the files matched by each one.
+fn included_files(base: &Path, patterns: &[String]) -> Result<Vec<PathBuf>, Error> {
+    let dir = base.parent().unwrap_or(Path::new(""));
+    let mut files = Vec::new();
+
+    for pattern in patterns {
+        let path = dir.join(pattern);
+        let mut matches = glob::glob(&path.to_string_lossy())
+            .map_err(Error::InvalidInclude)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(Error::ReadedInclude)?;
+
+        // Plain paths are kept when missing, so the error points at them.
+        if matches.is_empty() && glob::Pattern::escape(pattern) == *pattern {
+            matches.push(path);
+        }
+
+        matches.sort();
+        files.extend(matches);
+    }
+
+    Ok(files)
 }
 
 impl App {
+    /// Longest reply of the guild and what's done with longer ones.
+    pub fn response_limit(&self, guild: u64) -> (usize, Overflow) {
+        let guild = self.guilds.get(&guild);
+        let max_chars = guild
+            .and_then(|guild| guild.max_response_chars)
+            .unwrap_or(self.chat.max_response_chars);
+        let overflow = guild
+            .and_then(|guild| guild.overflow)
+            .unwrap_or(self.chat.overflow);
+
+        (max_chars as usize, overflow)
+    }
+
+    /// Longest prompt of the guild, in characters.
+    pub fn prompt_size(&self, guild: u64) -> u16 {
+        self.guilds
+            .get(&guild)
+            .and_then(|guild| guild.prompt_size)
+            .unwrap_or(self.chat.prompt_size)
+    }
+
+    /// Interactions sessions of the guild keep.
+    pub fn history_size(&self, guild: u64) -> u8 {
+        self.guilds
+            .get(&guild)
+            .and_then(|guild| guild.history_size)
+            .unwrap_or(self.chat.history_size)
+    }
+
+    pub fn mentions(&self, guild: u64) -> Mentions {
+        self.guilds
+            .get(&guild)
+            .and_then(|guild| guild.mentions)
+            .unwrap_or(self.bot.mentions)
+    }
+
+    pub fn embed_replies(&self, guild: u64) -> bool {
+        self.guilds
+            .get(&guild)
+            .is_some_and(|guild| guild.embed_replies)
+    }
+
+    pub fn webhook(&self, guild: u64) -> Option<&Webhook> {
+        self.guilds
+            .get(&guild)
+            .and_then(|guild| guild.webhook.as_ref())
+    }
+
+    pub fn channel_context(&self, guild: u64) -> Option<&ChannelContext> {
+        self.guilds
+            .get(&guild)
+            .and_then(|guild| guild.channel_context.as_ref())
+    }
+
     pub fn parse(path: &Path) -> Result<Self, Error> {
-        let file = config::File::from(path);
+        let base = Config::builder()
+            .add_source(config::File::from(path))
+            .build()
+            .map_err(Error::ReadedError)?;
+
+        let include = match base.get::<Vec<String>>("include") {
+            Ok(include) => include,
+            Err(ConfigError::NotFound(_)) => Vec::new(),
+            Err(err) => return Err(Error::ParserError(err)),
+        };
 
-        let config = Config::builder()
-            .add_source(file)
+        let builder = included_files(path, &include)?
+            .into_iter()
+            .fold(Config::builder().add_source(base), |builder, file| {
+                builder.add_source(config::File::from(file))
+            });
+
+        let mut config = builder
             .build()
             .map_err(Error::ReadedError)?
             .try_deserialize::<App>()
             .map_err(Error::ParserError)?;
+        config.path = path.to_path_buf();
 
-        if !(255..=4096).contains(&config.chat.prompt_size) {
-            return Err(Error::InvalidFlushDays);
+        if let Some(path) = &config.appearance.messages_file {
+            config.messages = Config::builder()
+                .add_source(config::File::from(path.as_path()))
+                .build()
+                .map_err(Error::ReadedMessagesError)?
+                .try_deserialize::<Messages>()
+                .map_err(Error::ParserMessagesError)?;
+        } else {
+            config.appearance.errors.apply(&mut config.messages);
+        }
+
+        if !PROMPT_SIZE_RANGE.contains(&config.chat.prompt_size)
+            || config
+                .guilds
+                .values()
+                .filter_map(|guild| guild.prompt_size)
+                .any(|size| !PROMPT_SIZE_RANGE.contains(&size))
+        {
+            return Err(Error::InvalidPromptSize);
         }
 
         if config.chat.flush_days == 0 {
             return Err(Error::InvalidFlushDays);
         }
 
-        if config.chat.history_size == 0 {
+        if config.chat.history_size == 0
+            || config
+                .guilds
+                .values()
+                .any(|guild| guild.history_size == Some(0))
+        {
             return Err(Error::InvalidHistorySize);
         }
 
+        if config.chat.history_tokens == 0 {
+            return Err(Error::InvalidHistoryTokens);
+        }
+
+        if config.lanes.priority_burst == 0 {
+            return Err(Error::InvalidPriorityBurst);
+        }
+
+        if config.chat.max_sessions == 0 {
+            return Err(Error::InvalidMaxSessions);
+        }
+
+        if config.chat.pin_cycles == 0 {
+            return Err(Error::InvalidPinCycles);
+        }
+
+        let valid_response_chars = |chars: u16| (1..=DISCORD_MESSAGE_LIMIT).contains(&chars);
+        if !valid_response_chars(config.chat.max_response_chars)
+            || config
+                .guilds
+                .values()
+                .filter_map(|guild| guild.max_response_chars)
+                .any(|chars| !valid_response_chars(chars))
+        {
+            return Err(Error::InvalidMaxResponseChars);
+        }
+
+        if config.appearance.alert_lifetime_secs == 0 {
+            return Err(Error::InvalidAlertLifetime);
+        }
+
+        if config
+            .appearance
+            .color
+            .is_some_and(|color| color > 0xFFFFFF)
+        {
+            return Err(Error::InvalidColor);
+        }
+
+        if config.spam.window_secs == 0
+            || config.spam.max_similar == 0
+            || config.spam.block_secs == 0
+            || !(0. ..=1.).contains(&config.spam.similarity)
+        {
+            return Err(Error::InvalidSpam);
+        }
+
+        if config
+            .bot
+            .prefix
+            .as_ref()
+            .is_some_and(|prefix| prefix.trim().is_empty())
+        {
+            return Err(Error::InvalidPrefix);
+        }
+
+        config.check_intents()?;
+
+        if config.voice.enabled
+            && (!cfg!(feature = "voice")
+                || config.voice.tts_model.is_empty()
+                || config.voice.tts_voice.is_empty())
+        {
+            return Err(Error::InvalidVoice);
+        }
+
+        if config.transcription.enabled
+            && (config.transcription.stt_model.is_empty()
+                || config.transcription.max_duration_secs == 0)
+        {
+            return Err(Error::InvalidTranscription);
+        }
+
+        if config.imagine.enabled && config.imagine.image_model.is_empty() {
+            return Err(Error::InvalidImagine);
+        }
+
+        if !(1..=5).contains(&config.followups.count) {
+            return Err(Error::InvalidFollowups);
+        }
+
+        if config.schedule.enabled && config.schedule.max_jobs == 0 {
+            return Err(Error::InvalidSchedule);
+        }
+
+        let experiment = &config.experiment;
+        if experiment.share > 100
+            || (experiment.share > 0 && experiment.model.is_none() && experiment.persona.is_none())
+        {
+            return Err(Error::InvalidExperiment);
+        }
+
+        if let Some(index) = config.routes.iter().position(|route| !route.is_valid()) {
+            return Err(Error::InvalidRoute(index));
+        }
+
+        let valid_name = |name: &str| (1..=WEBHOOK_NAME_LIMIT).contains(&name.chars().count());
+        let invalid_webhook = config
+            .guilds
+            .values()
+            .filter_map(|guild| guild.webhook.as_ref())
+            .any(|webhook| {
+                !valid_name(&webhook.name)
+                    || webhook.personas.iter().any(|persona| {
+                        !valid_name(&persona.name) || persona.keyword.trim().is_empty()
+                    })
+            });
+        if invalid_webhook {
+            return Err(Error::InvalidWebhook);
+        }
+
+        let invalid_channel_context = config
+            .guilds
+            .values()
+            .filter_map(|guild| guild.channel_context.as_ref())
+            .any(|context| {
+                !(1..=CHANNEL_CONTEXT_LIMIT).contains(&context.messages) || context.max_chars == 0
+            });
+        if invalid_channel_context {
+            return Err(Error::InvalidChannelContext);
+        }
+
+        if config.tldr.chunk_chars == 0 || config.tldr.max_messages == 0 {
+            return Err(Error::InvalidTldr);
+        }
+
+        if !(1..=ASK_MANY_LIMIT).contains(&config.ask_many.max_questions)
+            || config.ask_many.concurrency == 0
+        {
+            return Err(Error::InvalidAskMany);
+        }
+
+        if config.digest.hour > 23 {
+            return Err(Error::InvalidDigestHour);
+        }
+
+        if let Some((name, _)) = config
+            .pipelines
+            .iter()
+            .find(|(name, pipeline)| !pipeline.is_valid(name))
+        {
+            return Err(Error::InvalidPipeline(name.clone()));
+        }
+
+        if config.bot.shards.is_some_and(|shards| !shards.is_valid()) {
+            return Err(Error::InvalidShards);
+        }
+
+        if config
+            .pricing
+            .as_ref()
+            .is_some_and(|pricing| !pricing.is_valid())
+        {
+            return Err(Error::InvalidPricing);
+        }
+
+        if let Some(dsn) = &config.observability.sentry_dsn {
+            if dsn.parse::<sentry::types::Dsn>().is_err() {
+                return Err(Error::InvalidSentryDsn);
+            }
+        }
+
+        if config
+            .observability
+            .min_tokens_per_sec
+            .is_some_and(|rate| rate <= 0.)
+            || config.observability.slow_requests == 0
+        {
+            return Err(Error::InvalidThroughput);
+        }
+
         Ok(config)
     }
+
+    /// Makes sure the configured intents cover the enabled features.
+    fn check_intents(&self) -> Result<(), Error> {
+        let Some(intents) = &self.bot.intents else {
+            return Ok(());
+        };
+
+        let mut required = vec![Intent::Guilds];
+        if self.reactions.enabled {
+            required.push(Intent::GuildMessageReactions);
+        }
+        if self.bot.prefix.is_some() {
+            required.extend([Intent::GuildMessages, Intent::MessageContent]);
+        }
+        // Fetched messages come without their content otherwise.
+        if self
+            .guilds
+            .values()
+            .any(|guild| guild.channel_context.is_some())
+        {
+            required.push(Intent::MessageContent);
+        }
+        if self.voice.enabled {
+            required.push(Intent::GuildVoiceStates);
+        }
+
+        match required
+            .into_iter()
+            .find(|intent| !intents.contains(intent))
+        {
+            Some(intent) => Err(Error::MissingIntent(intent)),
+            None => Ok(()),
+        }
+    }
+
+    /// Overrides the configured shards, e.g. to run a range of them per process.
+    ///
+    /// Sessions are kept per guild and every guild is served by a single shard, so
+    /// processes don't need to share them, each keeping files of its own, see
+    /// [`Bot::shard_file`]. Usage stats only cover the local shards.
+    pub fn set_shards(&mut self, shards: Shards) -> Result<(), Error> {
+        if !shards.is_valid() {
+            return Err(Error::InvalidShards);
+        }
+
+        self.bot.shards = Some(shards);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,126 @@
+use std::{
+    fs, io,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use mlua::{Function, HookTriggers, Lua, LuaOptions, StdLib, Value, Variadic};
+
+const PROMPT_HOOK: &str = "on_prompt";
+const RESPONSE_HOOK: &str = "on_response";
+const MEMORY_LIMIT: usize = 16 * 1024 * 1024;
+/// Longest a hook may run, so a script stuck in a loop doesn't hold the prompt forever.
+const HOOK_TIMEOUT: Duration = Duration::from_secs(2);
+/// VM instructions run between checks of the timeout.
+const INSTRUCTIONS_PER_CHECK: u32 = 10_000;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("failed to read script")]
+    Read(#[source] io::Error),
+    #[error("script error")]
+    Lua(#[from] mlua::Error),
+    #[error("hook '{0}' returned a {1} instead of nil, a string or false")]
+    InvalidVerdict(&'static str, &'static str),
+    #[error("hook '{0}' ran for longer than {1:?}")]
+    TimedOut(&'static str, Duration),
+    #[error("script state was lost to an earlier panic")]
+    Poisoned,
+    #[error("hook task failed")]
+    Task(#[from] tokio::task::JoinError),
+}
+
+/// Outcome of a hook call.
+#[derive(Debug)]
+pub enum Verdict {
+    Keep,
+    Rewrite(String),
+    Veto(String),
+}
+
+/// Lua script defining the optional `on_prompt(prompt)` and
+/// `on_response(prompt, response)` hooks.
+///
+/// A hook returns nil to keep the text, a string to replace it or
+/// `false, reason` to reject the interaction.
+#[derive(Debug)]
+pub struct Script {
+    lua: Mutex<Lua>,
+}
+
+impl Script {
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let source = fs::read_to_string(path).map_err(Error::Read)?;
+
+        // Scripts only get pure libraries, so they can't reach the filesystem or the OS.
+        let libs = StdLib::STRING | StdLib::TABLE | StdLib::MATH | StdLib::UTF8;
+        let lua = Lua::new_with(libs, LuaOptions::default())?;
+        lua.set_memory_limit(MEMORY_LIMIT)?;
+        lua.load(source).set_name(path.to_string_lossy()).exec()?;
+
+        Ok(Self {
+            lua: Mutex::new(lua),
+        })
+    }
+
+    /// Runs `on_prompt` on a blocking thread, as scripts may take a while.
+    pub async fn on_prompt(self: Arc<Self>, prompt: String) -> Result<Verdict, Error> {
+        tokio::task::spawn_blocking(move || self.call(PROMPT_HOOK, &[&prompt])).await?
+    }
+
+    /// Runs `on_response` on a blocking thread, as scripts may take a while.
+    pub async fn on_response(
+        self: Arc<Self>,
+        prompt: String,
+        response: String,
+    ) -> Result<Verdict, Error> {
+        tokio::task::spawn_blocking(move || self.call(RESPONSE_HOOK, &[&prompt, &response])).await?
+    }
+
+    /// Whether the script defines `on_response`, which may rewrite or reject replies.
+    pub fn has_response_hook(&self) -> bool {
+        // Hooks of a poisoned script fail, which rejects the replies.
+        let Ok(lua) = self.lua.lock() else {
+            return true;
+        };
+        let hook = lua.globals().get::<_, Option<Function>>(RESPONSE_HOOK);
+
+        matches!(hook, Ok(Some(_)))
+    }
+
+    fn call(&self, hook: &'static str, args: &[&str]) -> Result<Verdict, Error> {
+        let lua = self.lua.lock().map_err(|_| Error::Poisoned)?;
+
+        let Some(function) = lua.globals().get::<_, Option<Function>>(hook)? else {
+            return Ok(Verdict::Keep);
+        };
+
+        let deadline = Instant::now() + HOOK_TIMEOUT;
+        let triggers = HookTriggers::new().every_nth_instruction(INSTRUCTIONS_PER_CHECK);
+        lua.set_hook(triggers, move |_, _| {
+            if Instant::now() < deadline {
+                Ok(())
+            } else {
+                Err(mlua::Error::runtime("hook timed out"))
+            }
+        });
+        let args = args.iter().copied().collect::<Variadic<_>>();
+        let called = function.call::<_, (Value, Option<String>)>(args);
+        lua.remove_hook();
+
+        let (verdict, reason) = match called {
+            Err(_) if Instant::now() >= deadline => {
+                return Err(Error::TimedOut(hook, HOOK_TIMEOUT));
+            }
+            called => called?,
+        };
+
+        match verdict {
+            Value::Nil | Value::Boolean(true) => Ok(Verdict::Keep),
+            Value::Boolean(false) => Ok(Verdict::Veto(reason.unwrap_or_default())),
+            Value::String(text) => Ok(Verdict::Rewrite(text.to_str()?.to_string())),
+            other => Err(Error::InvalidVerdict(hook, other.type_name())),
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use crate::{config, secrets::Secret};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("failed to generate image")]
+    Request(#[from] reqwest::Error),
+    #[error("image provider returned no image")]
+    Empty,
+}
+
+#[derive(serde::Deserialize)]
+struct Generated {
+    url: String,
+}
+
+#[derive(serde::Deserialize)]
+struct Generation {
+    data: Vec<Generated>,
+}
+
+/// Client of an OpenAI compatible image generation endpoint.
+#[derive(Debug)]
+pub struct Generator {
+    client: reqwest::Client,
+    url: String,
+    key: Secret,
+    model: String,
+    size: String,
+}
+
+impl Generator {
+    pub fn new(conf: &config::Imagine, key: Secret) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: conf.image_url.clone(),
+            key,
+            model: conf.image_model.clone(),
+            size: conf.size.clone(),
+        }
+    }
+
+    /// Draws the prompt, returning the image file.
+    pub async fn generate(&self, prompt: &str) -> Result<Vec<u8>, Error> {
+        let generation: Generation = self
+            .client
+            .post(&self.url)
+            .bearer_auth(self.key.get())
+            .json(&serde_json::json!({
+                "model": self.model,
+                "prompt": prompt,
+                "size": self.size,
+                "n": 1,
+                "response_format": "url",
+            }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let generated = generation.data.into_iter().next().ok_or(Error::Empty)?;
+        let image = self
+            .client
+            .get(generated.url)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+
+        Ok(image.to_vec())
+    }
+}
@@ -1,4 +1,18 @@
 pub mod bot;
 pub mod chat;
+pub mod code;
 pub mod config;
+pub mod hooks;
+pub mod image;
 pub mod log;
+pub mod messages;
+pub mod pdf;
+pub mod postprocess;
+pub mod report;
+pub mod secrets;
+pub mod spam;
+pub mod stt;
+pub mod throughput;
+#[cfg(feature = "voice")]
+pub mod tts;
+pub mod usage;
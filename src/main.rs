@@ -1,24 +1,102 @@
 use std::path::PathBuf;
 
 use anyhow::Context;
-use clap::Parser;
-use groqddbot::{bot, config, log};
+use clap::{Parser, Subcommand};
+use groqddbot::{bot, config, log, report, secrets};
 
 /// LLM chat bot
 #[derive(Parser, Debug)]
+#[command(subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Config file
+    #[arg(short, long, required_unless_present = "store_secret")]
+    config: Option<PathBuf>,
+
+    /// Answer prompts with a mock provider, without spending provider credits
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Restore sessions from the last snapshot
     #[arg(short, long)]
-    config: PathBuf,
+    restore: bool,
+
+    /// Run only the given shards, as <first>-<last>/<total>, overriding the config
+    #[arg(long, value_name = "RANGE", value_parser = parse_shard_range)]
+    shards: Option<config::Shards>,
+
+    /// Store a secret read from stdin in the OS keyring under the given entry and exit
+    #[arg(long, value_name = "ENTRY")]
+    store_secret: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print a commented example config
+    GenerateConfig {
+        /// Write the example into this file instead
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+fn parse_shard_range(range: &str) -> Result<config::Shards, String> {
+    let invalid = || format!("'{range}' isn't in the form <first>-<last>/<total>");
+
+    let (shards, total) = range.split_once('/').ok_or_else(invalid)?;
+    let (first, last) = shards.split_once('-').ok_or_else(invalid)?;
+    let parse = |n: &str| n.trim().parse::<u32>().map_err(|_| invalid());
+
+    Ok(config::Shards::Range {
+        first: parse(first)?,
+        last: parse(last)?,
+        total: parse(total)?,
+    })
 }
 
 #[tokio::main()]
 async fn main() -> anyhow::Result<()> {
     let args = Args::parse();
 
-    let conf = config::App::parse(&args.config).context("Failed to parse config")?;
+    if let Some(Command::GenerateConfig { output }) = &args.command {
+        match output {
+            Some(path) => {
+                std::fs::write(path, config::EXAMPLE).context("Failed to write config")?
+            }
+            None => print!("{}", config::EXAMPLE),
+        }
+
+        return Ok(());
+    }
+
+    if let Some(entry) = &args.store_secret {
+        let mut secret = String::new();
+        std::io::stdin()
+            .read_line(&mut secret)
+            .context("Failed to read secret")?;
+        secrets::store(entry, secret.trim_end()).context("Failed to store secret")?;
+
+        return Ok(());
+    }
+
+    let mut conf =
+        config::App::parse(args.config.as_deref().unwrap()).context("Failed to parse config")?;
+
+    if let Some(shards) = args.shards {
+        conf.set_shards(shards).context("Invalid shard range")?;
+    }
+
+    if args.dry_run {
+        conf.ai_provider.provider = config::Provider::Mock;
+    }
 
     log::init();
 
-    bot::run(conf).await.context("Unexpected error on bot")
+    let _reporter = report::init(&conf.observability);
+
+    bot::run(conf, args.restore)
+        .await
+        .context("Unexpected error on bot")
 }
@@ -0,0 +1,930 @@
+use std::fmt::{Display, Write};
+
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Alerts {
+    pub cooldown: String,
+    pub unexpected_error: String,
+    pub prompt_failure: String,
+    pub rate_limited: String,
+    pub provider_outage: String,
+    pub content_filtered: String,
+    pub context_exceeded: String,
+    pub prompt_timeout: String,
+    pub empty_response: String,
+    pub prompt_too_long: String,
+    pub prompt_vetoed: String,
+    pub flushing: String,
+    pub maintenance: String,
+    pub owner_only: String,
+    pub spam_warning: String,
+    pub spam_blocked: String,
+    pub access_denied: String,
+    pub access_account_age: String,
+    pub access_roles: String,
+    pub not_in_voice: String,
+    pub prompt_empty: String,
+    pub transcription_disabled: String,
+    pub voice_invalid: String,
+    pub voice_transcript: String,
+    pub content_refused: String,
+    pub model_unknown: String,
+    pub model_override_denied: String,
+    pub request_in_progress: String,
+    pub prompt_cancelled: String,
+    pub delivery_failed: String,
+    pub delivery_retry: String,
+    pub channel_context_disabled: String,
+    pub channel_context_denied: String,
+    pub access_channels: String,
+    pub access_command: String,
+    pub flush_warning: String,
+    pub late_reply: String,
+    pub thread_pointer: String,
+    pub streaming: String,
+}
+
+impl Default for Alerts {
+    fn default() -> Self {
+        Self {
+            cooldown: ":hotsprings: Hold on, I'm not that fast!".to_string(),
+            unexpected_error: ":man_shrugging: Something went wrong and Idk why...".to_string(),
+            prompt_failure: ":skull: Failed to send message. Something went realy bad..."
+                .to_string(),
+            rate_limited: ":hourglass: Too many requests are being made right now, try again in a \
+                minute"
+                .to_string(),
+            provider_outage: ":satellite: The AI provider is unavailable right now, try again \
+                later"
+                .to_string(),
+            content_filtered: ":no_entry_sign: The AI provider refused to answer that message"
+                .to_string(),
+            context_exceeded: ":scroll: The conversation got too long for the model, start a new \
+                session with /sessions new or shorten your message"
+                .to_string(),
+            prompt_timeout: ":stopwatch: The AI provider took too long to answer, try again"
+                .to_string(),
+            empty_response: ":speech_balloon: The model replied with nothing, try rephrasing \
+                your message"
+                .to_string(),
+            prompt_too_long: ":red_circle: Message must be {max} tokens max".to_string(),
+            prompt_vetoed: ":no_entry_sign: Message was rejected: {reason}".to_string(),
+            flushing: ":yellow_circle: History is being flushed, wait a little more".to_string(),
+            maintenance: ":construction: Under maintenance, try again later".to_string(),
+            owner_only: ":no_entry: This command is reserved to the bot owners".to_string(),
+            spam_warning: ":warning: Slow down with the repeated prompts or you'll be blocked \
+                ({strikes}/{max} warnings)"
+                .to_string(),
+            spam_blocked: ":no_entry: You're blocked from prompting until {date}".to_string(),
+            access_denied: ":lock: Sorry, you can't use me here yet".to_string(),
+            access_account_age: "Your account must be at least {days} day(s) old.".to_string(),
+            access_roles: "You need one of these roles: {roles}".to_string(),
+            not_in_voice: ":mute: Join a voice channel first".to_string(),
+            prompt_empty: ":red_circle: Write a message or attach a voice message".to_string(),
+            transcription_disabled: ":mute: Voice messages aren't accepted here".to_string(),
+            voice_invalid: ":red_circle: Only voice messages up to {max} seconds are accepted"
+                .to_string(),
+            voice_transcript: ":microphone2: *{transcript}*".to_string(),
+            content_refused: ":underage: That's not allowed in this channel".to_string(),
+            model_unknown: ":red_circle: Model `{model}` isn't available".to_string(),
+            model_override_denied: ":no_entry: You aren't allowed to pick the model".to_string(),
+            request_in_progress: ":hourglass: You already have a request in progress, wait for \
+                the reply"
+                .to_string(),
+            prompt_cancelled: ":stop_button: Request was cancelled".to_string(),
+            delivery_failed: ":warning: Couldn't send the reply, so it was left out of the \
+                conversation"
+                .to_string(),
+            delivery_retry: "Retry".to_string(),
+            channel_context_disabled: ":speech_balloon: Channel messages can't be sent along \
+                prompts here"
+                .to_string(),
+            channel_context_denied: ":lock: You need to be able to read the channel history to \
+                send it along"
+                .to_string(),
+            access_channels: "I can only be used in {channels}.".to_string(),
+            access_command: "/{command} is turned off on this server.".to_string(),
+            flush_warning: "-# :hourglass: This conversation is cleared {time}".to_string(),
+            late_reply:
+                ":hourglass: {user}, here's the answer that took too long to reply to your command"
+                    .to_string(),
+            thread_pointer: ":thread: That's a long one, the whole answer is in the thread below"
+                .to_string(),
+            streaming:
+                ":hourglass_flowing_sand: Taking longer than usual, writing it as it comes..."
+                    .to_string(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Info {
+    pub title: String,
+    pub description: String,
+    pub reset_date: String,
+    pub history_size: String,
+    pub history_size_value: String,
+    pub sessions_limit: String,
+    pub sessions_limit_value: String,
+    pub model: String,
+    pub prompt_size: String,
+    pub prompt_size_value: String,
+    pub cost: String,
+    pub cost_value: String,
+}
+
+impl Default for Info {
+    fn default() -> Self {
+        Self {
+            title: "Characteristics".to_string(),
+            description: "**Note:** older interactions are removed when session limit is reached"
+                .to_string(),
+            reset_date: ":wastebasket: | Sessions Reset Date:".to_string(),
+            history_size: ":notepad_spiral: | Session History Size:".to_string(),
+            history_size_value: "{count} interaction{plural} per user".to_string(),
+            sessions_limit: ":card_index_dividers: | Sessions Limit:".to_string(),
+            sessions_limit_value: "{count} per user".to_string(),
+            model: ":brain: | LLM's Name:".to_string(),
+            prompt_size: ":pencil: | Prompt Message Size Limit:".to_string(),
+            prompt_size_value: "{count} tokens (aka characters)".to_string(),
+            cost: ":coin: | Estimated Cost:".to_string(),
+            cost_value: "~{per_prompt} {currency} per prompt, ~{spend} {currency} in this \
+                server since the last reset"
+                .to_string(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Leaderboard {
+    pub title: String,
+    pub empty: String,
+    pub entry: String,
+    pub resets: String,
+}
+
+impl Default for Leaderboard {
+    fn default() -> Self {
+        Self {
+            title: ":trophy: Leaderboard".to_string(),
+            empty: "Nobody has sent a prompt yet. Be the first one!".to_string(),
+            entry: "{medal} {user} - {count} prompt{plural}".to_string(),
+            resets: "*Resets on {date}*".to_string(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Sessions {
+    pub invalid_name: String,
+    pub created: String,
+    pub already_exists: String,
+    pub limit_reached: String,
+    pub switched: String,
+    pub missing: String,
+    pub deleted: String,
+    pub list_title: String,
+    pub pinned: String,
+    pub already_pinned: String,
+    pub pin_limit_reached: String,
+    pub pins_disabled: String,
+    pub unpinned: String,
+    pub not_pinned: String,
+    pub undo_hint: String,
+    pub restored: String,
+    pub nothing_to_restore: String,
+}
+
+impl Default for Sessions {
+    fn default() -> Self {
+        Self {
+            invalid_name:
+                ":red_circle: Session names must have up to {max} letters, digits, '-' or '_'"
+                    .to_string(),
+            created: ":green_circle: Switched to new session `{name}`".to_string(),
+            already_exists: ":yellow_circle: Session `{name}` already exists".to_string(),
+            limit_reached: ":red_circle: You can't have more than {max} sessions".to_string(),
+            switched: ":green_circle: Switched to session `{name}`".to_string(),
+            missing: ":red_circle: Session `{name}` doesn't exist".to_string(),
+            deleted: ":wastebasket: Session `{name}` was deleted".to_string(),
+            list_title: ":card_index_dividers: Your Sessions ({count}/{max})".to_string(),
+            pinned: ":pushpin: Session `{name}` will be kept through the next {cycles} reset(s)"
+                .to_string(),
+            already_pinned: ":yellow_circle: Session `{name}` is already pinned".to_string(),
+            pin_limit_reached: ":red_circle: This server can't pin more than {max} sessions"
+                .to_string(),
+            pins_disabled: ":no_entry: Sessions can't be pinned here".to_string(),
+            unpinned: ":wastebasket: Session `{name}` will be reset with the others".to_string(),
+            not_pinned: ":yellow_circle: Session `{name}` isn't pinned".to_string(),
+            undo_hint: "Use /undo-reset within {minutes} minute(s) to bring it back".to_string(),
+            restored: ":recycle: Restored {count} deleted session{plural}".to_string(),
+            nothing_to_restore: ":yellow_circle: There's no deleted session left to restore"
+                .to_string(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Reactions {
+    pub export_title: String,
+    pub export_prompt: String,
+}
+
+impl Default for Reactions {
+    fn default() -> Self {
+        Self {
+            export_title: ":pushpin: Pinned Interaction".to_string(),
+            export_prompt: ":speech_balloon: | Prompt:".to_string(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct System {
+    pub blank: String,
+    pub too_long: String,
+    pub set: String,
+    pub cleared: String,
+    pub show_title: String,
+    pub empty: String,
+}
+
+impl Default for System {
+    fn default() -> Self {
+        Self {
+            blank: ":red_circle: Instructions can't be blank, use `/system clear` to remove them"
+                .to_string(),
+            too_long: ":red_circle: Instructions must be {max} tokens max".to_string(),
+            set: ":green_circle: Instructions of session `{name}` were updated".to_string(),
+            cleared: ":wastebasket: Instructions of session `{name}` were cleared".to_string(),
+            show_title: ":scroll: Instructions of session `{name}`".to_string(),
+            empty: "No instructions were given yet.".to_string(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Welcome {
+    pub title: String,
+    pub description: String,
+    pub prompt: String,
+    pub prompt_value: String,
+    pub info: String,
+    pub info_value: String,
+    pub limits: String,
+    pub limits_value: String,
+    pub admin: String,
+    pub admin_value: String,
+}
+
+impl Default for Welcome {
+    fn default() -> Self {
+        Self {
+            title: ":wave: Thanks for inviting me!".to_string(),
+            description: "Here's how to get started.".to_string(),
+            prompt: ":speech_balloon: | Chatting:".to_string(),
+            prompt_value: "Use `/prompt` to talk with the model and `/sessions` to keep \
+                separate conversations."
+                .to_string(),
+            info: ":information_source: | Details:".to_string(),
+            info_value: "Use `/info` to see the model and the current limits.".to_string(),
+            limits: ":straight_ruler: | Limits:".to_string(),
+            limits_value: "Prompts up to {prompt_size} tokens, {history_size} \
+                interaction{plural} kept per session, reset every {flush_days} day(s)."
+                .to_string(),
+            admin: ":tools: | Admins:".to_string(),
+            admin_value: "Log channels and shared channels are set per guild in the bot \
+                config. Bot owners also get the `/admin` tools."
+                .to_string(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Search {
+    pub invalid_query: String,
+    pub title: String,
+    pub empty: String,
+    pub prompt_match: String,
+    pub response_match: String,
+}
+
+impl Default for Search {
+    fn default() -> Self {
+        Self {
+            invalid_query: ":red_circle: Search queries must have between 1 and {max} characters"
+                .to_string(),
+            title: ":mag: Matches for \"{query}\" ({count})".to_string(),
+            empty: ":mag: Nothing in your sessions matches \"{query}\"".to_string(),
+            prompt_match: ":speech_balloon: You in `{name}`, {date}".to_string(),
+            response_match: ":robot: Model in `{name}`, {date}".to_string(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Share {
+    pub empty: String,
+    pub linked: String,
+    pub attached: String,
+    pub transcript_author: String,
+}
+
+impl Default for Share {
+    fn default() -> Self {
+        Self {
+            empty: ":yellow_circle: Session `{name}` has nothing to share yet".to_string(),
+            linked: ":link: {user} shared session `{name}`".to_string(),
+            attached: ":page_facing_up: {user} shared session `{name}`".to_string(),
+            transcript_author: "Shared by {user} from session `{name}`".to_string(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Imagine {
+    pub nsfw_only: String,
+    pub quota_reached: String,
+    pub caption: String,
+}
+
+impl Default for Imagine {
+    fn default() -> Self {
+        Self {
+            nsfw_only: ":underage: Images can only be generated in age-restricted channels"
+                .to_string(),
+            quota_reached: ":hourglass: You've reached today's limit of {max} images".to_string(),
+            caption: ":art: Imagined for {user}".to_string(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct History {
+    pub title: String,
+    pub empty: String,
+    pub entry: String,
+    pub missing: String,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            title: ":scroll: Conversation history ({count})".to_string(),
+            empty: ":yellow_circle: Nothing was said in this conversation yet".to_string(),
+            entry: "#{index} · {date}".to_string(),
+            missing: ":red_circle: There's no interaction #{index}, see /history".to_string(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Language {
+    pub set: String,
+    pub automatic: String,
+    pub unknown: String,
+}
+
+impl Default for Language {
+    fn default() -> Self {
+        Self {
+            set: ":globe_with_meridians: Replies will be in {language}".to_string(),
+            automatic: ":globe_with_meridians: Replies will follow the language of your messages"
+                .to_string(),
+            unknown: ":red_circle: Unknown language `{language}`".to_string(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Followups {
+    pub asked: String,
+    pub not_author: String,
+    pub expired: String,
+}
+
+impl Default for Followups {
+    fn default() -> Self {
+        Self {
+            asked: ":speech_balloon: **{user}** asked: *{question}*".to_string(),
+            not_author: ":no_entry: Only the author of the prompt can pick a follow-up".to_string(),
+            expired: ":hourglass: This conversation was reset, ask away with /prompt".to_string(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Status {
+    pub title: String,
+    pub idle: String,
+    pub generating: String,
+    pub queued: String,
+    pub cancel: String,
+    pub cancelled: String,
+    pub finished: String,
+}
+
+impl Default for Status {
+    fn default() -> Self {
+        Self {
+            title: ":satellite: Your requests in progress".to_string(),
+            idle: ":zzz: You have no request in progress".to_string(),
+            generating: ":gear: Generating for {elapsed}s".to_string(),
+            queued: ":hourglass: Waiting behind {position} request{plural} for {elapsed}s"
+                .to_string(),
+            cancel: "Cancel {index}".to_string(),
+            cancelled: ":stop_button: Request cancelled".to_string(),
+            finished: ":white_check_mark: Request already finished".to_string(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Transfer {
+    pub exported: String,
+    pub exported_pdf: String,
+    pub empty: String,
+    pub imported: String,
+    pub invalid: String,
+    pub too_large: String,
+    pub too_long: String,
+    pub pdf_title: String,
+    pub pdf_exported: String,
+    pub pdf_persona: String,
+    pub pdf_user: String,
+    pub pdf_assistant: String,
+    pub pdf_page: String,
+}
+
+impl Default for Transfer {
+    fn default() -> Self {
+        Self {
+            exported: ":outbox_tray: Session `{name}` exported, /import it to pick it up again"
+                .to_string(),
+            exported_pdf: ":page_facing_up: Session `{name}` exported as a document".to_string(),
+            empty: ":yellow_circle: There's nothing to export in session `{name}`".to_string(),
+            imported: ":inbox_tray: Imported {count} interaction{plural} into session `{name}`"
+                .to_string(),
+            invalid: ":red_circle: That's not a conversation exported with /export".to_string(),
+            too_large: ":red_circle: Exported conversations must be {max} KiB max".to_string(),
+            too_long: ":red_circle: Messages and instructions must be {max} characters max"
+                .to_string(),
+            pdf_title: "Conversation {name}".to_string(),
+            pdf_exported: "Exported on".to_string(),
+            pdf_persona: "Persona:".to_string(),
+            pdf_user: "You".to_string(),
+            pdf_assistant: "Assistant".to_string(),
+            pdf_page: "Page".to_string(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Macros {
+    pub disabled: String,
+    pub invalid_name: String,
+    pub saved: String,
+    pub too_long: String,
+    pub limit_reached: String,
+    pub missing: String,
+    pub deleted: String,
+    pub list_title: String,
+    pub empty: String,
+}
+
+impl Default for Macros {
+    fn default() -> Self {
+        Self {
+            disabled: ":no_entry: Macros are disabled".to_string(),
+            invalid_name:
+                ":red_circle: Macro names must have up to {max} letters, digits, '-' or '_'"
+                    .to_string(),
+            saved: ":floppy_disk: Macro `{name}` saved, send it with /macro run".to_string(),
+            too_long: ":red_circle: Macros must have between 1 and {max} characters".to_string(),
+            limit_reached: ":red_circle: You can't have more than {max} macros".to_string(),
+            missing: ":red_circle: Macro `{name}` doesn't exist".to_string(),
+            deleted: ":wastebasket: Macro `{name}` was deleted".to_string(),
+            list_title: ":scroll: Your Macros ({count}/{max})".to_string(),
+            empty: ":yellow_circle: You have no macros, save one with /macro save".to_string(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Schedule {
+    pub disabled: String,
+    pub invalid_when: String,
+    pub too_long: String,
+    pub limit_reached: String,
+    pub scheduled: String,
+    pub once: String,
+    pub daily: String,
+    pub list_title: String,
+    pub empty: String,
+    pub missing: String,
+    pub removed: String,
+    pub posted: String,
+    pub failed: String,
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Self {
+            disabled: ":no_entry: Prompts can't be scheduled here".to_string(),
+            invalid_when: ":red_circle: Pick when as `in 30m`, `at 18:00` or `daily 09:00` (UTC), \
+                up to {max} days ahead"
+                .to_string(),
+            too_long: ":red_circle: Message must be between 1 and {max} characters".to_string(),
+            limit_reached: ":red_circle: You can't have more than {max} scheduled prompts"
+                .to_string(),
+            scheduled: ":alarm_clock: Scheduled {due}".to_string(),
+            once: "#{id} · {date}".to_string(),
+            daily: "#{id} · every day at {time} UTC".to_string(),
+            list_title: ":alarm_clock: Your Scheduled Prompt{plural} ({count}/{max})".to_string(),
+            empty: ":yellow_circle: You have no scheduled prompts, add one with /schedule"
+                .to_string(),
+            missing: ":red_circle: There's no scheduled prompt #{id}, see /schedules".to_string(),
+            removed: ":wastebasket: Scheduled prompt #{id} was removed".to_string(),
+            posted: ":alarm_clock: Scheduled by {user}: *{prompt}*".to_string(),
+            failed: ":warning: {user}, your scheduled prompt #{id} wasn't answered\n{reason}"
+                .to_string(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ReplyEmbed {
+    pub model: String,
+    pub tokens: String,
+    pub tokens_value: String,
+    pub disclaimer: String,
+}
+
+impl Default for ReplyEmbed {
+    fn default() -> Self {
+        Self {
+            model: ":robot: | Model:".to_string(),
+            tokens: ":coin: | Tokens:".to_string(),
+            tokens_value: "{input} in, {output} out".to_string(),
+            disclaimer: "AI-generated content, it may be inaccurate".to_string(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Moderation {
+    pub history_title: String,
+    pub session_entry: String,
+    pub with_instructions: String,
+    pub pinned: String,
+    pub no_sessions: String,
+    pub purged: String,
+    pub undo_hint: String,
+    pub restored: String,
+    pub nothing_to_restore: String,
+}
+
+impl Default for Moderation {
+    fn default() -> Self {
+        Self {
+            history_title: ":shield: Sessions of {user} ({count})".to_string(),
+            session_entry: "{kept} interaction(s) kept, {exchanged} exchanged, last {last}"
+                .to_string(),
+            with_instructions: ":scroll: Has custom instructions".to_string(),
+            pinned: ":pushpin: Pinned".to_string(),
+            no_sessions: ":yellow_circle: {user} has no sessions".to_string(),
+            purged: ":wastebasket: Deleted {count} session{plural} of {user}".to_string(),
+            undo_hint: "Use /mod undo-purge within {minutes} minute(s) to bring them back"
+                .to_string(),
+            restored: ":recycle: Restored {count} session{plural} of {user}".to_string(),
+            nothing_to_restore: ":yellow_circle: There's no purge of {user} left to undo"
+                .to_string(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Webhook {
+    pub delivered: String,
+}
+
+impl Default for Webhook {
+    fn default() -> Self {
+        Self {
+            delivered: ":speech_balloon: {name} answered [here]({link})".to_string(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct LongPrompt {
+    pub title: String,
+    pub label: String,
+    pub placeholder: String,
+}
+
+impl Default for LongPrompt {
+    fn default() -> Self {
+        Self {
+            title: "Long Prompt".to_string(),
+            label: "Message".to_string(),
+            placeholder: "Paste code, lists or anything spanning several lines".to_string(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Reasoning {
+    pub button: String,
+    pub title: String,
+    pub not_author: String,
+    pub expired: String,
+}
+
+impl Default for Reasoning {
+    fn default() -> Self {
+        Self {
+            button: "Show reasoning".to_string(),
+            title: ":brain: Reasoning".to_string(),
+            not_author: ":no_entry: Only the author of the prompt can read its reasoning"
+                .to_string(),
+            expired: ":hourglass: This reasoning is no longer available".to_string(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Tldr {
+    pub not_thread: String,
+    pub empty: String,
+    pub title: String,
+    pub footer: String,
+}
+
+impl Default for Tldr {
+    fn default() -> Self {
+        Self {
+            not_thread: ":thread: This command only works inside threads".to_string(),
+            empty: ":yellow_circle: There's nothing to summarize yet".to_string(),
+            title: ":scroll: TL;DR".to_string(),
+            footer: "Summary of {count} message{plural}".to_string(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Settings {
+    pub title: String,
+    pub persona: String,
+    pub history_size: String,
+    pub history_size_value: String,
+    pub private_replies: String,
+    pub allowed_channels: String,
+    pub disabled_commands: String,
+    pub none: String,
+    pub unset: String,
+    pub everywhere: String,
+    pub enabled: String,
+    pub disabled: String,
+    pub updated: String,
+    pub persona_too_long: String,
+    pub history_size_invalid: String,
+    pub examples: String,
+    pub examples_value: String,
+    pub example_incomplete: String,
+    pub example_too_long: String,
+    pub examples_limit: String,
+    pub example_without_persona: String,
+    pub unknown_command: String,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            title: ":gear: Guild Settings".to_string(),
+            persona: ":performing_arts: | Persona:".to_string(),
+            history_size: ":notepad_spiral: | Session History Size:".to_string(),
+            history_size_value: "{count} interaction{plural}".to_string(),
+            private_replies: ":spy: | Private Replies:".to_string(),
+            allowed_channels: ":hash: | Allowed Channels:".to_string(),
+            disabled_commands: ":no_entry: | Turned Off Commands:".to_string(),
+            none: "None".to_string(),
+            unset: "Default".to_string(),
+            everywhere: "Everywhere".to_string(),
+            enabled: "Enabled".to_string(),
+            disabled: "Disabled".to_string(),
+            updated: ":white_check_mark: Settings updated, new sessions pick them up".to_string(),
+            persona_too_long: ":red_circle: Persona must be {max} tokens max".to_string(),
+            history_size_invalid: ":red_circle: History size must be between 1 and {max}"
+                .to_string(),
+            examples: ":books: | Persona Examples:".to_string(),
+            examples_value: "{count} example{plural}".to_string(),
+            example_incomplete: ":red_circle: Examples need both a prompt and a reply".to_string(),
+            example_too_long: ":red_circle: Example prompts and replies must be {max} tokens max"
+                .to_string(),
+            examples_limit: ":red_circle: The persona can't have more than {max} examples"
+                .to_string(),
+            example_without_persona:
+                ":red_circle: Set a persona with `/config persona` before giving it examples"
+                    .to_string(),
+            unknown_command: ":red_circle: There's no `/{command}` command to turn on or off"
+                .to_string(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Degraded {
+    pub missing_permissions: String,
+}
+
+impl Default for Degraded {
+    fn default() -> Self {
+        Self {
+            missing_permissions: ":warning: I'm missing the {permissions} permission(s) in \
+                {channel} of **{guild}**, which `/{command}` needs. Grant them to my role or in \
+                the channel settings."
+                .to_string(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct AskMany {
+    pub title: String,
+    pub label: String,
+    pub placeholder: String,
+    pub empty: String,
+    pub too_many: String,
+    pub too_long: String,
+    pub failed: String,
+}
+
+impl Default for AskMany {
+    fn default() -> Self {
+        Self {
+            title: "Ask Many".to_string(),
+            label: "Questions".to_string(),
+            placeholder: "One question per line".to_string(),
+            empty: ":yellow_circle: Write one question per line".to_string(),
+            too_many: ":red_circle: Up to {max} questions can be asked at once".to_string(),
+            too_long: ":red_circle: Question {index} must be {max} tokens max".to_string(),
+            failed: "*No answer, try asking it again*".to_string(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RunPipeline {
+    pub unknown: String,
+    pub too_long: String,
+    pub step: String,
+    pub failed: String,
+}
+
+impl Default for RunPipeline {
+    fn default() -> Self {
+        Self {
+            unknown: ":red_circle: There's no pipeline named `{name}`".to_string(),
+            too_long: ":red_circle: Input must be {max} tokens max".to_string(),
+            step: "-# :link: Step {index} of {count}, {step}".to_string(),
+            failed: ":red_circle: Step `{step}` failed, the pipeline was stopped".to_string(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Compare {
+    pub unavailable: String,
+    pub denied: String,
+    pub too_long: String,
+    pub answer: String,
+    pub failed: String,
+    pub pick: String,
+    pub picked: String,
+    pub not_author: String,
+    pub already_picked: String,
+    pub expired: String,
+}
+
+impl Default for Compare {
+    fn default() -> Self {
+        Self {
+            unavailable: ":red_circle: There's no pair of models to compare".to_string(),
+            denied: ":no_entry: You aren't allowed to compare models".to_string(),
+            too_long: ":red_circle: Message must be {max} tokens max".to_string(),
+            answer: "{label} · `{model}`".to_string(),
+            failed: "*No answer, this model failed to reply*".to_string(),
+            pick: "{label} is better".to_string(),
+            picked: ":ballot_box: Thanks, your pick was recorded".to_string(),
+            not_author: ":no_entry: Only the author of the prompt can pick an answer".to_string(),
+            already_picked: ":ballot_box: You already picked an answer".to_string(),
+            expired: ":hourglass: This comparison is no longer open".to_string(),
+        }
+    }
+}
+
+/// User-facing texts, optionally overridden by a messages file.
+#[derive(serde::Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Messages {
+    pub alerts: Alerts,
+    pub info: Info,
+    pub leaderboard: Leaderboard,
+    pub sessions: Sessions,
+    pub reactions: Reactions,
+    pub system: System,
+    pub welcome: Welcome,
+    pub search: Search,
+    pub share: Share,
+    pub imagine: Imagine,
+    pub history: History,
+    pub language: Language,
+    pub followups: Followups,
+    pub status: Status,
+    pub transfer: Transfer,
+    pub macros: Macros,
+    pub schedule: Schedule,
+    pub reply_embed: ReplyEmbed,
+    pub moderation: Moderation,
+    pub webhook: Webhook,
+    pub long_prompt: LongPrompt,
+    pub reasoning: Reasoning,
+    pub tldr: Tldr,
+    pub settings: Settings,
+    pub degraded: Degraded,
+    pub ask_many: AskMany,
+    pub run_pipeline: RunPipeline,
+    pub compare: Compare,
+}
+
+/// Replaces every `{name}` placeholder of the template with its value.
+///
+/// Unknown placeholders are kept as they are.
+pub fn render(template: &str, values: &[(&str, &dyn Display)]) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+
+        let after = &rest[start + 1..];
+        let Some(end) = after.find('}') else {
+            rest = &rest[start..];
+            break;
+        };
+
+        let name = &after[..end];
+        match values.iter().find(|(key, _)| *key == name) {
+            Some((_, value)) => {
+                let _ = write!(rendered, "{value}");
+            }
+            None => {
+                rendered.push('{');
+                rendered.push_str(name);
+                rendered.push('}');
+            }
+        }
+
+        rest = &after[end + 1..];
+    }
+
+    rendered.push_str(rest);
+
+    rendered
+}
+
+pub fn plural(count: u64) -> &'static str {
+    if count == 1 {
+        ""
+    } else {
+        "s"
+    }
+}
@@ -0,0 +1,473 @@
+use std::io::Write;
+
+use chrono::{DateTime, Utc};
+
+use crate::chat;
+
+/// A4, in points.
+const PAGE_WIDTH: f32 = 595.;
+const PAGE_HEIGHT: f32 = 842.;
+const MARGIN: f32 = 56.;
+const FOOTER_Y: f32 = 32.;
+
+const TITLE_SIZE: f32 = 16.;
+const HEADING_SIZE: f32 = 11.;
+const BODY_SIZE: f32 = 10.;
+const META_SIZE: f32 = 8.5;
+/// Space between lines, relative to the font size.
+const LEADING: f32 = 1.35;
+
+/// Widths of the printable ASCII characters in thousandths of the font size, from the
+/// standard font metrics.
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667,
+    611, 778, 722, 278, 500, 667, 556, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667,
+    667, 611, 278, 278, 278, 469, 556, 333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500,
+    222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+const HELVETICA_BOLD_WIDTHS: [u16; 95] = [
+    278, 333, 474, 556, 556, 889, 722, 238, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 556, 333, 333, 584, 584, 584, 611, 975, 722, 722, 722, 722, 667,
+    611, 778, 722, 278, 556, 722, 611, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667,
+    667, 611, 333, 278, 333, 584, 556, 333, 556, 611, 556, 611, 556, 333, 611, 611, 278, 278, 556,
+    278, 889, 611, 611, 611, 611, 389, 556, 333, 611, 556, 778, 556, 556, 500, 389, 280, 389, 584,
+];
+/// Width of the characters outside printable ASCII, close to the average letter.
+const FALLBACK_WIDTH: u16 = 556;
+
+/// Characters outside Latin-1 that the WinAnsi encoding of the standard fonts still has.
+const WIN_ANSI_EXTRAS: &[(char, u8)] = &[
+    ('€', 0x80),
+    ('…', 0x85),
+    ('‘', 0x91),
+    ('’', 0x92),
+    ('“', 0x93),
+    ('”', 0x94),
+    ('•', 0x95),
+    ('–', 0x96),
+    ('—', 0x97),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Font {
+    Regular,
+    Bold,
+    Italic,
+    Mono,
+}
+
+impl Font {
+    const ALL: [Font; 4] = [Font::Regular, Font::Bold, Font::Italic, Font::Mono];
+
+    fn resource(self) -> &'static str {
+        match self {
+            Font::Regular => "F1",
+            Font::Bold => "F2",
+            Font::Italic => "F3",
+            Font::Mono => "F4",
+        }
+    }
+
+    fn base(self) -> &'static str {
+        match self {
+            Font::Regular => "Helvetica",
+            Font::Bold => "Helvetica-Bold",
+            Font::Italic => "Helvetica-Oblique",
+            Font::Mono => "Courier",
+        }
+    }
+
+    fn char_width(self, c: char) -> u16 {
+        let ascii = (c as u32).wrapping_sub(32) as usize;
+        match self {
+            Font::Mono => 600,
+            Font::Bold => HELVETICA_BOLD_WIDTHS
+                .get(ascii)
+                .copied()
+                .unwrap_or(FALLBACK_WIDTH),
+            Font::Regular | Font::Italic => HELVETICA_WIDTHS
+                .get(ascii)
+                .copied()
+                .unwrap_or(FALLBACK_WIDTH),
+        }
+    }
+
+    fn width(self, text: &str, size: f32) -> f32 {
+        text.chars().map(|c| self.char_width(c) as f32).sum::<f32>() * size / 1000.
+    }
+}
+
+/// Byte of the character in the WinAnsi encoding, `?` when it has none.
+fn win_ansi(c: char) -> u8 {
+    match c as u32 {
+        0x20..=0x7E | 0xA0..=0xFF => c as u8,
+        _ => WIN_ANSI_EXTRAS
+            .iter()
+            .find(|(extra, _)| *extra == c)
+            .map_or(b'?', |(_, byte)| *byte),
+    }
+}
+
+/// Text as a PDF string literal.
+fn pdf_string(text: &str) -> Vec<u8> {
+    let mut encoded = vec![b'('];
+    for c in text.chars() {
+        let byte = if c == '\t' { b' ' } else { win_ansi(c) };
+        if matches!(byte, b'(' | b')' | b'\\') {
+            encoded.push(b'\\');
+        }
+        encoded.push(byte);
+    }
+    encoded.push(b')');
+
+    encoded
+}
+
+/// Splits the text into lines fitting the width, breaking words only when they don't fit alone.
+fn wrap(text: &str, font: Font, size: f32, width: f32) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+
+    for word in text.split(' ') {
+        let candidate = if line.is_empty() {
+            word.to_string()
+        } else {
+            format!("{line} {word}")
+        };
+        if font.width(&candidate, size) <= width {
+            line = candidate;
+            continue;
+        }
+
+        if !line.is_empty() {
+            lines.push(std::mem::take(&mut line));
+        }
+        for c in word.chars() {
+            line.push(c);
+            if font.width(&line, size) > width {
+                line.pop();
+                lines.push(std::mem::replace(&mut line, c.to_string()));
+            }
+        }
+    }
+    lines.push(line);
+
+    lines
+}
+
+/// Lays text out top to bottom, starting new pages as they fill up.
+struct Layout {
+    pages: Vec<Vec<u8>>,
+    y: f32,
+}
+
+impl Layout {
+    fn new() -> Self {
+        Self {
+            pages: vec![Vec::new()],
+            y: PAGE_HEIGHT - MARGIN,
+        }
+    }
+
+    fn page(&mut self) -> &mut Vec<u8> {
+        self.pages.last_mut().unwrap()
+    }
+
+    fn space(&mut self, points: f32) {
+        self.y -= points;
+    }
+
+    fn text_at(&mut self, font: Font, size: f32, x: f32, y: f32, text: &str) {
+        let page = self.page();
+        let _ = write!(page, "BT /{} {size} Tf {x} {y} Td ", font.resource());
+        page.extend(pdf_string(text));
+        page.extend(b" Tj ET\n");
+    }
+
+    fn paragraph(&mut self, font: Font, size: f32, indent: f32, text: &str) {
+        let width = PAGE_WIDTH - 2. * MARGIN - indent;
+        for line in wrap(text, font, size, width) {
+            let height = size * LEADING;
+            if self.y - height < MARGIN {
+                self.pages.push(Vec::new());
+                self.y = PAGE_HEIGHT - MARGIN;
+            }
+            self.y -= height;
+            self.text_at(font, size, MARGIN + indent, self.y, &line);
+        }
+    }
+
+    /// Writes the markdown text, keeping headings, lists and code blocks apart.
+    fn markdown(&mut self, text: &str) {
+        let mut in_code = false;
+        for line in text.lines() {
+            if line.trim_start().starts_with("```") {
+                in_code = !in_code;
+                self.space(BODY_SIZE * 0.3);
+                continue;
+            }
+            if in_code {
+                self.paragraph(Font::Mono, BODY_SIZE * 0.9, 12., line);
+                continue;
+            }
+
+            let trimmed = line.trim();
+            let plain = trimmed.replace("**", "").replace("__", "").replace('`', "");
+            if trimmed.is_empty() {
+                self.space(BODY_SIZE * 0.5);
+            } else if trimmed.starts_with('#') {
+                let heading = plain.trim_start_matches('#').trim();
+                self.space(BODY_SIZE * 0.3);
+                self.paragraph(Font::Bold, BODY_SIZE + 1., 0., heading);
+            } else if let Some(item) = plain
+                .strip_prefix("- ")
+                .or_else(|| plain.strip_prefix("* "))
+            {
+                self.paragraph(Font::Regular, BODY_SIZE, 8., &format!("• {item}"));
+            } else {
+                self.paragraph(Font::Regular, BODY_SIZE, 0., &plain);
+            }
+        }
+    }
+}
+
+/// Texts of the document, in the language of the bot.
+pub struct Labels<'a> {
+    pub title: &'a str,
+    pub exported: &'a str,
+    pub persona: &'a str,
+    pub user: &'a str,
+    pub assistant: &'a str,
+    pub page: &'a str,
+}
+
+fn format_date(at: DateTime<Utc>) -> String {
+    at.format("%Y-%m-%d %H:%M UTC").to_string()
+}
+
+/// Renders the conversation as a PDF document, with the standard fonts so nothing is embedded.
+///
+/// Characters the fonts lack are replaced by `?`.
+pub fn conversation(
+    transcript: &chat::Transcript,
+    labels: &Labels<'_>,
+    exported: DateTime<Utc>,
+) -> Vec<u8> {
+    let mut layout = Layout::new();
+
+    layout.paragraph(Font::Bold, TITLE_SIZE, 0., labels.title);
+    layout.paragraph(
+        Font::Italic,
+        META_SIZE,
+        0.,
+        &format!("{} {}", labels.exported, format_date(exported)),
+    );
+    if let Some(persona) = &transcript.instructions {
+        layout.paragraph(
+            Font::Italic,
+            META_SIZE,
+            0.,
+            &format!("{} {persona}", labels.persona),
+        );
+    }
+
+    for interaction in &transcript.interactions {
+        let at = format_date(interaction.at);
+
+        layout.space(HEADING_SIZE);
+        layout.paragraph(
+            Font::Bold,
+            HEADING_SIZE,
+            0.,
+            &format!("{} · {at}", labels.user),
+        );
+        layout.markdown(&interaction.prompt);
+        layout.space(HEADING_SIZE * 0.5);
+        layout.paragraph(Font::Bold, HEADING_SIZE, 0., labels.assistant);
+        layout.markdown(&interaction.response);
+    }
+
+    let total = layout.pages.len();
+    for (index, page) in layout.pages.iter_mut().enumerate() {
+        let footer = format!("{} {} / {total}", labels.page, index + 1);
+        let x = (PAGE_WIDTH - Font::Regular.width(&footer, META_SIZE)) / 2.;
+        let _ = write!(
+            page,
+            "BT /{} {META_SIZE} Tf {x} {FOOTER_Y} Td ",
+            Font::Regular.resource()
+        );
+        page.extend(pdf_string(&footer));
+        page.extend(b" Tj ET\n");
+    }
+
+    write_document(labels.title, &layout.pages)
+}
+
+/// Assembles the pages into a PDF file, fonts first, then each page and its content.
+fn write_document(title: &str, pages: &[Vec<u8>]) -> Vec<u8> {
+    let fonts = Font::ALL.len();
+    // Catalog, page tree, info and fonts come before the pages.
+    let first_page = 4 + fonts;
+    let count = 3 + fonts + pages.len() * 2;
+
+    let mut objects: Vec<Vec<u8>> = Vec::with_capacity(count);
+    let kids = (0..pages.len())
+        .map(|page| format!("{} 0 R", first_page + page * 2))
+        .collect::<Vec<_>>()
+        .join(" ");
+    objects.push(b"<< /Type /Catalog /Pages 2 0 R >>".to_vec());
+    objects.push(format!("<< /Type /Pages /Kids [{kids}] /Count {} >>", pages.len()).into_bytes());
+
+    let mut info = b"<< /Producer (groqddbot) /Title ".to_vec();
+    info.extend(pdf_string(title));
+    info.extend(b" >>");
+    objects.push(info);
+
+    let resources = Font::ALL
+        .iter()
+        .enumerate()
+        .map(|(index, font)| format!("/{} {} 0 R", font.resource(), 4 + index))
+        .collect::<Vec<_>>()
+        .join(" ");
+    for font in Font::ALL {
+        objects.push(
+            format!(
+                "<< /Type /Font /Subtype /Type1 /BaseFont /{} /Encoding /WinAnsiEncoding >>",
+                font.base()
+            )
+            .into_bytes(),
+        );
+    }
+
+    for (index, content) in pages.iter().enumerate() {
+        objects.push(
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {PAGE_WIDTH} {PAGE_HEIGHT}] \
+                /Resources << /Font << {resources} >> >> /Contents {} 0 R >>",
+                first_page + index * 2 + 1
+            )
+            .into_bytes(),
+        );
+
+        let mut stream = format!("<< /Length {} >>\nstream\n", content.len()).into_bytes();
+        stream.extend(content);
+        stream.extend(b"endstream");
+        objects.push(stream);
+    }
+
+    let mut document = b"%PDF-1.4\n".to_vec();
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (index, object) in objects.iter().enumerate() {
+        offsets.push(document.len());
+        let _ = writeln!(document, "{} 0 obj", index + 1);
+        document.extend(object);
+        document.extend(b"\nendobj\n");
+    }
+
+    let xref = document.len();
+    let _ = write!(
+        document,
+        "xref\n0 {}\n0000000000 65535 f \n",
+        objects.len() + 1
+    );
+    for offset in offsets {
+        let _ = writeln!(document, "{offset:010} 00000 n ");
+    }
+    let _ = write!(
+        document,
+        "trailer\n<< /Size {} /Root 1 0 R /Info 3 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+        objects.len() + 1
+    );
+
+    document
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrap_breaks_at_spaces_within_the_width() {
+        let text = "the quick brown fox jumps over the lazy dog";
+        let width = Font::Regular.width("the quick brown", BODY_SIZE);
+
+        let lines = wrap(text, Font::Regular, BODY_SIZE, width);
+
+        assert_eq!(lines, ["the quick brown", "fox jumps over", "the lazy dog"]);
+    }
+
+    #[test]
+    fn wrap_breaks_words_too_long_for_a_line() {
+        let width = Font::Mono.width("abcd", BODY_SIZE);
+
+        let lines = wrap("ab abcdefghij", Font::Mono, BODY_SIZE, width);
+
+        assert_eq!(lines, ["ab", "abcd", "efgh", "ij"]);
+    }
+
+    #[test]
+    fn wrap_keeps_an_empty_line() {
+        assert_eq!(wrap("", Font::Regular, BODY_SIZE, 100.), [""]);
+    }
+
+    #[test]
+    fn win_ansi_maps_latin1_and_extras() {
+        assert_eq!(win_ansi('a'), b'a');
+        assert_eq!(win_ansi('é'), 0xE9);
+        assert_eq!(win_ansi('€'), 0x80);
+        assert_eq!(win_ansi('—'), 0x97);
+    }
+
+    #[test]
+    fn win_ansi_replaces_what_it_lacks() {
+        assert_eq!(win_ansi('日'), b'?');
+        assert_eq!(win_ansi('\n'), b'?');
+        assert_eq!(win_ansi('\u{80}'), b'?');
+    }
+
+    #[test]
+    fn pdf_string_escapes_delimiters() {
+        assert_eq!(pdf_string(r"a (b) \c"), br"(a \(b\) \\c)".to_vec());
+    }
+
+    #[test]
+    fn pdf_string_encodes_as_win_ansi() {
+        assert_eq!(pdf_string("\tcafé €日"), b"( caf\xE9 \x80?)".to_vec());
+    }
+
+    #[test]
+    fn write_document_points_xref_at_each_object() {
+        let pages = [b"BT ET\n".to_vec(), Vec::new()];
+        let document = write_document("Title (1)", &pages);
+        let text = String::from_utf8_lossy(&document);
+
+        let startxref = text.rsplit("startxref\n").next().unwrap();
+        let xref: usize = startxref.lines().next().unwrap().parse().unwrap();
+        assert!(document[xref..].starts_with(b"xref\n"));
+
+        let table = &text[xref..];
+        let mut lines = table.lines().skip(1);
+        let count: usize = lines
+            .next()
+            .unwrap()
+            .strip_prefix("0 ")
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert_eq!(count, 3 + Font::ALL.len() + pages.len() * 2 + 1);
+        assert_eq!(lines.next(), Some("0000000000 65535 f "));
+
+        for object in 1..count {
+            let entry = lines.next().unwrap();
+            assert_eq!(entry.len() + 1, 20, "entries take 20 bytes");
+            let offset: usize = entry[..10].parse().unwrap();
+            let header = format!("{object} 0 obj\n");
+            assert!(
+                document[offset..].starts_with(header.as_bytes()),
+                "object {object} isn't at {offset}"
+            );
+        }
+        assert!(lines.next().unwrap().starts_with("trailer"));
+    }
+}
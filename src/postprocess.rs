@@ -0,0 +1,99 @@
+use std::borrow::Cow;
+
+use crate::config::{PostProcess, Rewrite};
+
+impl Rewrite {
+    fn applies_to(&self, model: &str) -> bool {
+        self.models.is_empty() || self.models.iter().any(|name| name == model)
+    }
+}
+
+impl PostProcess {
+    /// Whether any rule applies to the replies of the model.
+    pub fn rewrites(&self, model: &str) -> bool {
+        self.rules.iter().any(|rule| rule.applies_to(model))
+    }
+
+    /// Applies the rules of the model to the reply, keeping it as is if nothing would be left.
+    pub fn apply(&self, model: &str, content: &str) -> Option<String> {
+        let mut rewritten = None;
+        for rule in &self.rules {
+            if !rule.applies_to(model) {
+                continue;
+            }
+
+            let current = rewritten.as_deref().unwrap_or(content);
+            if let Cow::Owned(replaced) =
+                rule.pattern.replace_all(current, rule.replacement.as_str())
+            {
+                rewritten = Some(replaced);
+            }
+        }
+
+        rewritten
+            .map(|content| content.trim().to_string())
+            .filter(|content| !content.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(pattern: &str, replacement: &str, models: &[&str]) -> Rewrite {
+        Rewrite {
+            pattern: regex::Regex::new(pattern).unwrap(),
+            replacement: replacement.to_string(),
+            models: models.iter().map(|model| model.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn applies_only_rules_of_the_model() {
+        let postprocess = PostProcess {
+            rules: vec![
+                rule(r"(?s)<think>.*?</think>", "", &["deepseek"]),
+                rule(r"\bfoo\b", "bar", &[]),
+            ],
+        };
+
+        assert!(postprocess.rewrites("llama"));
+        assert_eq!(
+            postprocess.apply("deepseek", "<think>hmm</think> foo"),
+            Some("bar".to_string())
+        );
+        assert_eq!(
+            postprocess.apply("llama", "<think>hmm</think> foo"),
+            Some("<think>hmm</think> bar".to_string())
+        );
+    }
+
+    #[test]
+    fn skips_models_without_rules() {
+        let postprocess = PostProcess {
+            rules: vec![rule("a", "b", &["deepseek"])],
+        };
+
+        assert!(!postprocess.rewrites("llama"));
+        assert_eq!(postprocess.apply("llama", "a"), None);
+    }
+
+    #[test]
+    fn chains_rules_in_order() {
+        let postprocess = PostProcess {
+            rules: vec![rule("a", "b", &[]), rule("b", "c", &[])],
+        };
+
+        assert_eq!(postprocess.apply("llama", "ab"), Some("cc".to_string()));
+    }
+
+    #[test]
+    fn keeps_unchanged_or_emptied_replies() {
+        let postprocess = PostProcess {
+            rules: vec![rule(".*", "", &[])],
+        };
+
+        assert_eq!(postprocess.apply("llama", "hello"), None);
+        assert_eq!(PostProcess::default().apply("llama", "hello"), None);
+    }
+}
@@ -0,0 +1,66 @@
+use std::error::Error;
+
+use crate::config;
+
+pub type Guard = Option<sentry::ClientInitGuard>;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Context<'a> {
+    pub guild: Option<u64>,
+    pub command: Option<&'a str>,
+}
+
+impl<'a> Context<'a> {
+    pub fn new(guild: Option<u64>, command: Option<&'a str>) -> Self {
+        Self { guild, command }
+    }
+
+    fn apply(&self, scope: &mut sentry::Scope) {
+        if let Some(guild) = self.guild {
+            scope.set_tag("guild", guild);
+        }
+
+        if let Some(command) = self.command {
+            scope.set_tag("command", command);
+        }
+    }
+}
+
+/// Starts the reporting client if a DSN was configured.
+///
+/// Events are silently discarded when the returned guard is `None` or dropped.
+pub fn init(conf: &config::Observability) -> Guard {
+    let dsn = conf.sentry_dsn.as_deref()?;
+
+    let options = sentry::ClientOptions {
+        dsn: dsn.parse().ok(),
+        environment: conf.environment.clone().map(Into::into),
+        release: sentry::release_name!(),
+        ..Default::default()
+    };
+
+    Some(sentry::init(options))
+}
+
+pub fn error(context: Context<'_>, error: &(dyn Error + 'static)) {
+    sentry::with_scope(
+        |scope| context.apply(scope),
+        || sentry::capture_error(error),
+    );
+}
+
+pub fn panic(context: Context<'_>, payload: Option<&str>) {
+    let message = format!("panicked: {}", payload.unwrap_or("unknown reason"));
+
+    sentry::with_scope(
+        |scope| context.apply(scope),
+        || sentry::capture_message(&message, sentry::Level::Fatal),
+    );
+}
+
+pub fn message(context: Context<'_>, message: &str) {
+    sentry::with_scope(
+        |scope| context.apply(scope),
+        || sentry::capture_message(message, sentry::Level::Error),
+    );
+}
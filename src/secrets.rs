@@ -0,0 +1,202 @@
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use crate::config;
+
+const KEYRING_SCHEME: &str = "keyring";
+const KEYRING_SERVICE: &str = "groqddbot";
+const VAULT_SCHEME: &str = "vault";
+const VAULT_TOKEN_VAR: &str = "VAULT_TOKEN";
+
+type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("failed to access keyring entry '{0}'")]
+    Keyring(String, #[source] keyring::Error),
+    #[error("failed to fetch vault secret '{0}'")]
+    Vault(String, #[source] reqwest::Error),
+    #[error("vault secret '{0}' has no field '{1}'")]
+    MissingField(String, String),
+    #[error("vault secret reference '{0}' must be in the form <path>#<field>")]
+    InvalidReference(String),
+}
+
+/// Source of secrets referenced from config as `<scheme>:<reference>`.
+pub trait Resolver: Send + Sync {
+    fn scheme(&self) -> &'static str;
+
+    fn resolve<'a>(&'a self, reference: &'a str) -> BoxFuture<'a, Result<String, Error>>;
+}
+
+fn keyring_entry(name: &str) -> Result<keyring::Entry, Error> {
+    keyring::Entry::new(KEYRING_SERVICE, name).map_err(|err| Error::Keyring(name.to_string(), err))
+}
+
+/// Stores a secret in the OS keyring, so config can reference it as `keyring:<entry>`.
+pub fn store(name: &str, secret: &str) -> Result<(), Error> {
+    keyring_entry(name)?
+        .set_password(secret)
+        .map_err(|err| Error::Keyring(name.to_string(), err))
+}
+
+/// Reads `keyring:<entry>` secrets from the OS keyring.
+pub struct Keyring;
+
+impl Resolver for Keyring {
+    fn scheme(&self) -> &'static str {
+        KEYRING_SCHEME
+    }
+
+    fn resolve<'a>(&'a self, reference: &'a str) -> BoxFuture<'a, Result<String, Error>> {
+        Box::pin(async move {
+            keyring_entry(reference)?
+                .get_password()
+                .map_err(|err| Error::Keyring(reference.to_string(), err))
+        })
+    }
+}
+
+/// Reads `vault:<path>#<field>` secrets from a HashiCorp Vault KV engine.
+pub struct Vault {
+    client: reqwest::Client,
+    address: String,
+    token: String,
+}
+
+impl Vault {
+    pub fn new(conf: &config::Vault) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            address: conf.address.trim_end_matches('/').to_string(),
+            token: conf
+                .token
+                .clone()
+                .or_else(|| std::env::var(VAULT_TOKEN_VAR).ok())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl Resolver for Vault {
+    fn scheme(&self) -> &'static str {
+        VAULT_SCHEME
+    }
+
+    fn resolve<'a>(&'a self, reference: &'a str) -> BoxFuture<'a, Result<String, Error>> {
+        Box::pin(async move {
+            let Some((path, field)) = reference.split_once('#') else {
+                return Err(Error::InvalidReference(reference.to_string()));
+            };
+            let vault_error = |err| Error::Vault(path.to_string(), err);
+
+            let body: serde_json::Value = self
+                .client
+                .get(format!(
+                    "{}/v1/{}",
+                    self.address,
+                    path.trim_start_matches('/')
+                ))
+                .header("X-Vault-Token", &self.token)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(vault_error)?
+                .json()
+                .await
+                .map_err(vault_error)?;
+
+            // KV v2 nests the secret under another data object.
+            let data = &body["data"];
+            let data = if data["data"].is_object() {
+                &data["data"]
+            } else {
+                data
+            };
+
+            data[field]
+                .as_str()
+                .map(str::to_string)
+                .ok_or_else(|| Error::MissingField(path.to_string(), field.to_string()))
+        })
+    }
+}
+
+/// Registered resolvers, picked by the scheme of each config value.
+pub struct Resolvers {
+    resolvers: Vec<Box<dyn Resolver>>,
+}
+
+impl Resolvers {
+    pub fn new(conf: &config::Secrets) -> Self {
+        let mut resolvers: Vec<Box<dyn Resolver>> = vec![Box::new(Keyring)];
+        if let Some(vault) = &conf.vault {
+            resolvers.push(Box::new(Vault::new(vault)));
+        }
+
+        Self { resolvers }
+    }
+
+    /// Returns the value as is, unless it references one of the registered resolvers.
+    pub async fn resolve(&self, value: &str) -> Result<String, Error> {
+        let resolver = value.split_once(':').and_then(|(scheme, reference)| {
+            self.resolvers
+                .iter()
+                .find(|resolver| resolver.scheme() == scheme)
+                .map(|resolver| (resolver, reference))
+        });
+
+        match resolver {
+            Some((resolver, reference)) => resolver.resolve(reference).await,
+            None => Ok(value.to_string()),
+        }
+    }
+}
+
+/// Secret value that may be replaced while in use.
+#[derive(Clone)]
+pub struct Secret(Arc<RwLock<String>>);
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(..)")
+    }
+}
+
+impl Secret {
+    pub fn new(value: String) -> Self {
+        Self(Arc::new(RwLock::new(value)))
+    }
+
+    pub fn get(&self) -> String {
+        self.0.read().unwrap().clone()
+    }
+
+    fn set(&self, value: String) {
+        *self.0.write().unwrap() = value;
+    }
+}
+
+/// Periodically fetches the referenced secret again, so rotated values are picked up.
+pub fn spawn_refresher(
+    resolvers: Arc<Resolvers>,
+    reference: String,
+    secret: Secret,
+    interval: Duration,
+) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+
+            match resolvers.resolve(&reference).await {
+                Ok(value) => secret.set(value),
+                Err(err) => log::warn!("failed to refresh secret: {err}"),
+            }
+        }
+    });
+}
@@ -0,0 +1,213 @@
+use std::collections::{HashSet, VecDeque};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use dashmap::DashMap;
+
+use crate::config;
+
+type GuildId = u64;
+type UserId = u64;
+type Shingles = HashSet<[char; 3]>;
+
+/// Outcome of checking a prompt against the sender's recent ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Allowed,
+    /// The prompt went past the allowed similar ones, with the strikes so far.
+    Warned {
+        strikes: u32,
+    },
+    /// The sender is blocked, `new` being set only for the prompt that caused it.
+    Blocked {
+        until: DateTime<Utc>,
+        new: bool,
+    },
+}
+
+#[derive(Debug, Default)]
+struct Activity {
+    recent: VecDeque<(DateTime<Utc>, Shingles)>,
+    strikes: u32,
+    blocked_until: Option<DateTime<Utc>>,
+}
+
+/// Character trigrams of the prompt, ignoring case and repeated whitespace.
+fn shingles(prompt: &str) -> Shingles {
+    let mut normalized: Vec<char> = prompt
+        .split_whitespace()
+        .flat_map(|word| word.chars().flat_map(char::to_lowercase).chain([' ']))
+        .collect();
+    normalized.pop();
+
+    while normalized.len() < 3 {
+        normalized.push(' ');
+    }
+
+    normalized
+        .windows(3)
+        .map(|window| [window[0], window[1], window[2]])
+        .collect()
+}
+
+/// Jaccard index of both sets of trigrams.
+fn similarity(a: &Shingles, b: &Shingles) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 1.;
+    }
+
+    a.intersection(b).count() as f64 / union as f64
+}
+
+/// Tracks near-duplicate prompts per guild member over a sliding window.
+#[derive(Debug, Default)]
+pub struct Detector {
+    users: DashMap<(GuildId, UserId), Activity>,
+}
+
+impl Detector {
+    pub fn check(
+        &self,
+        conf: &config::Spam,
+        guild: GuildId,
+        user: UserId,
+        prompt: &str,
+    ) -> Verdict {
+        let now = Utc::now();
+        let mut activity = self.users.entry((guild, user)).or_default();
+
+        if let Some(until) = activity.blocked_until {
+            if until > now {
+                return Verdict::Blocked { until, new: false };
+            }
+
+            activity.blocked_until = None;
+        }
+
+        let window = TimeDelta::seconds(conf.window_secs as i64);
+        while activity
+            .recent
+            .front()
+            .is_some_and(|(at, _)| now - *at > window)
+        {
+            activity.recent.pop_front();
+        }
+
+        // Strikes are forgiven once the sender stays quiet for a whole window.
+        if activity.recent.is_empty() {
+            activity.strikes = 0;
+        }
+
+        let shingles = shingles(prompt);
+        let similar = activity
+            .recent
+            .iter()
+            .filter(|(_, previous)| similarity(previous, &shingles) >= conf.similarity)
+            .count();
+        activity.recent.push_back((now, shingles));
+
+        if similar < conf.max_similar as usize {
+            return Verdict::Allowed;
+        }
+
+        activity.strikes += 1;
+        if activity.strikes <= conf.warnings {
+            return Verdict::Warned {
+                strikes: activity.strikes,
+            };
+        }
+
+        let until = now + TimeDelta::seconds(conf.block_secs as i64);
+        activity.blocked_until = Some(until);
+        activity.recent.clear();
+        activity.strikes = 0;
+
+        Verdict::Blocked { until, new: true }
+    }
+
+    /// Forgets the members that are neither blocked nor sent prompts lately.
+    pub fn prune(&self, conf: &config::Spam) {
+        let now = Utc::now();
+        let window = TimeDelta::seconds(conf.window_secs as i64);
+
+        self.users.retain(|_, activity| {
+            activity.blocked_until.is_some_and(|until| until > now)
+                || activity
+                    .recent
+                    .back()
+                    .is_some_and(|(at, _)| now - *at <= window)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conf() -> config::Spam {
+        config::Spam {
+            enabled: true,
+            window_secs: 60,
+            max_similar: 2,
+            similarity: 0.8,
+            warnings: 1,
+            block_secs: 300,
+        }
+    }
+
+    #[test]
+    fn shingles_ignore_case_and_whitespace() {
+        assert_eq!(shingles("Hello  World"), shingles("hello world\n"));
+        assert_eq!(shingles("ab"), HashSet::from([['a', 'b', ' ']]));
+        assert_eq!(shingles(""), HashSet::from([[' ', ' ', ' ']]));
+    }
+
+    #[test]
+    fn similarity_is_the_jaccard_index() {
+        let a = shingles("abcd");
+        let b = shingles("bcde");
+
+        assert_eq!(similarity(&a, &a), 1.);
+        assert_eq!(similarity(&a, &shingles("wxyz")), 0.);
+        // Shares "bcd" out of "abc", "bcd" and "cde".
+        assert!((similarity(&a, &b) - 1. / 3.).abs() < f64::EPSILON);
+        assert_eq!(similarity(&Shingles::new(), &Shingles::new()), 1.);
+    }
+
+    #[test]
+    fn warns_then_blocks_repeated_prompts() {
+        let conf = conf();
+        let detector = Detector::default();
+        let check = |prompt| detector.check(&conf, 1, 1, prompt);
+
+        assert_eq!(check("what's the weather like?"), Verdict::Allowed);
+        assert_eq!(check("What's the weather like?"), Verdict::Allowed);
+        assert_eq!(
+            check("what's the weather  like?"),
+            Verdict::Warned { strikes: 1 }
+        );
+        assert!(matches!(
+            check("what's the weather like?"),
+            Verdict::Blocked { new: true, .. }
+        ));
+        // Even different prompts wait for the block to end.
+        assert!(matches!(
+            check("tell me a joke"),
+            Verdict::Blocked { new: false, .. }
+        ));
+    }
+
+    #[test]
+    fn allows_different_prompts_and_members() {
+        let conf = conf();
+        let detector = Detector::default();
+
+        for prompt in ["tell me a joke", "what's rust?", "write a haiku", "hi"] {
+            assert_eq!(detector.check(&conf, 1, 1, prompt), Verdict::Allowed);
+        }
+        for user in 2..6 {
+            assert_eq!(detector.check(&conf, 1, user, "hi"), Verdict::Allowed);
+        }
+        assert_eq!(detector.check(&conf, 2, 1, "hi"), Verdict::Allowed);
+    }
+}
@@ -0,0 +1,58 @@
+use crate::{config, secrets::Secret};
+
+#[derive(thiserror::Error, Debug)]
+#[error("failed to transcribe audio")]
+pub struct Error(#[from] reqwest::Error);
+
+#[derive(serde::Deserialize)]
+struct Transcript {
+    text: String,
+}
+
+/// Client of an OpenAI compatible transcription endpoint.
+#[derive(Debug)]
+pub struct Stt {
+    client: reqwest::Client,
+    url: String,
+    key: Secret,
+    model: String,
+}
+
+impl Stt {
+    pub fn new(conf: &config::Transcription, key: Secret) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: conf.stt_url.clone(),
+            key,
+            model: conf.stt_model.clone(),
+        }
+    }
+
+    /// Writes down what's said in the audio file.
+    pub async fn transcribe(
+        &self,
+        audio: Vec<u8>,
+        file_name: String,
+        content_type: &str,
+    ) -> Result<String, Error> {
+        let file = reqwest::multipart::Part::bytes(audio)
+            .file_name(file_name)
+            .mime_str(content_type)?;
+        let form = reqwest::multipart::Form::new()
+            .text("model", self.model.clone())
+            .part("file", file);
+
+        let transcript: Transcript = self
+            .client
+            .post(&self.url)
+            .bearer_auth(self.key.get())
+            .multipart(form)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(transcript.text.trim().to_string())
+    }
+}
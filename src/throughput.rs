@@ -0,0 +1,70 @@
+use std::{
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use crate::config;
+
+/// Replies shorter than this are left out, as their rate is mostly latency.
+const MIN_SAMPLE_TOKENS: u64 = 32;
+
+/// Change in the provider speed worth raising.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Alert {
+    /// The last `requests` replies were generated slower than the threshold.
+    Degraded {
+        rate: f64,
+        requests: u32,
+    },
+    Recovered {
+        rate: f64,
+    },
+}
+
+/// Tracks the tokens per second the provider generates replies at.
+#[derive(Debug, Default)]
+pub struct Monitor {
+    slow_streak: AtomicU32,
+    last_rate: Mutex<Option<f64>>,
+}
+
+impl Monitor {
+    /// Records a reply, returning an alert once the provider gets slow or fast again.
+    pub fn record(
+        &self,
+        conf: &config::Observability,
+        output_tokens: u64,
+        elapsed: Duration,
+    ) -> Option<Alert> {
+        if output_tokens < MIN_SAMPLE_TOKENS || elapsed.is_zero() {
+            return None;
+        }
+
+        let rate = output_tokens as f64 / elapsed.as_secs_f64();
+        *self.last_rate.lock().unwrap() = Some(rate);
+        log::debug!("reply generated at {rate:.1} tokens/s");
+
+        let min_rate = conf.min_tokens_per_sec?;
+        if rate >= min_rate {
+            let streak = self.slow_streak.swap(0, Ordering::Relaxed);
+
+            return (streak >= conf.slow_requests).then_some(Alert::Recovered { rate });
+        }
+
+        let streak = self.slow_streak.fetch_add(1, Ordering::Relaxed) + 1;
+
+        // Raised once per slow streak.
+        (streak == conf.slow_requests).then_some(Alert::Degraded {
+            rate,
+            requests: streak,
+        })
+    }
+
+    /// Rate of the last reply long enough to be measured.
+    pub fn last_rate(&self) -> Option<f64> {
+        *self.last_rate.lock().unwrap()
+    }
+}
@@ -0,0 +1,48 @@
+use crate::{config, secrets::Secret};
+
+#[derive(thiserror::Error, Debug)]
+#[error("failed to synthesize speech")]
+pub struct Error(#[from] reqwest::Error);
+
+/// Client of an OpenAI compatible speech endpoint.
+#[derive(Debug)]
+pub struct Tts {
+    client: reqwest::Client,
+    url: String,
+    key: Secret,
+    model: String,
+    voice: String,
+}
+
+impl Tts {
+    pub fn new(conf: &config::Voice, key: Secret) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: conf.tts_url.clone(),
+            key,
+            model: conf.tts_model.clone(),
+            voice: conf.tts_voice.clone(),
+        }
+    }
+
+    /// Reads the text out, returning mp3 audio.
+    pub async fn synthesize(&self, text: &str) -> Result<Vec<u8>, Error> {
+        let audio = self
+            .client
+            .post(&self.url)
+            .bearer_auth(self.key.get())
+            .json(&serde_json::json!({
+                "model": self.model,
+                "voice": self.voice,
+                "input": text,
+                "response_format": "mp3",
+            }))
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+
+        Ok(audio.to_vec())
+    }
+}
@@ -0,0 +1,533 @@
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    io,
+    path::Path,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+
+use chrono::{Datelike, Days, NaiveDate};
+use dashmap::DashMap;
+
+use crate::chat;
+
+type GuildId = u64;
+type UserId = u64;
+
+/// Days of usage kept for reports, enough to cover the current and previous month.
+const DAYS_RETENTION: u64 = 62;
+pub const USAGE_FILE: &str = "usage.json";
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("failed to access usage file")]
+    Io(#[from] io::Error),
+    #[error("failed to (de)serialize usage")]
+    Json(#[from] serde_json::Error),
+}
+
+/// Group of the session tokens were spent for, see the experiment config.
+#[derive(
+    serde::Serialize, serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Arm {
+    #[default]
+    Control,
+    Variant,
+}
+
+/// Tokens spent on each model.
+pub type Spent = HashMap<String, chat::Usage>;
+
+/// Input and output tokens counted together.
+type Tokens = (AtomicU64, AtomicU64);
+
+fn add_tokens(tokens: &Tokens, usage: chat::Usage) {
+    tokens.0.fetch_add(usage.input_tokens, Ordering::Relaxed);
+    tokens.1.fetch_add(usage.output_tokens, Ordering::Relaxed);
+}
+
+fn load_tokens(tokens: &Tokens) -> chat::Usage {
+    chat::Usage {
+        input_tokens: tokens.0.load(Ordering::Relaxed),
+        output_tokens: tokens.1.load(Ordering::Relaxed),
+    }
+}
+
+/// Adds the tokens spent on each model to the total.
+fn add_spent(total: &mut Spent, spent: impl IntoIterator<Item = (String, chat::Usage)>) {
+    for (model, usage) in spent {
+        let tokens = total.entry(model).or_default();
+        tokens.input_tokens += usage.input_tokens;
+        tokens.output_tokens += usage.output_tokens;
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    users: DashMap<UserId, AtomicU64>,
+    /// Prompts per guild, only counted in daily totals.
+    guilds: DashMap<GuildId, AtomicU64>,
+    /// Images per user, only counted in daily totals.
+    image_users: DashMap<UserId, AtomicU64>,
+    prompts: AtomicU64,
+    images: AtomicU64,
+    errors: AtomicU64,
+    input_tokens: AtomicU64,
+    output_tokens: AtomicU64,
+    /// Tokens spent on each model, which are priced apart.
+    models: DashMap<String, Tokens>,
+    /// Tokens spent on each model for sessions of each experiment arm.
+    arms: DashMap<(Arm, String), Tokens>,
+}
+
+impl Counters {
+    fn add_tokens(&self, model: &str, arm: Option<Arm>, usage: chat::Usage) {
+        self.input_tokens
+            .fetch_add(usage.input_tokens, Ordering::Relaxed);
+        self.output_tokens
+            .fetch_add(usage.output_tokens, Ordering::Relaxed);
+
+        add_tokens(&self.models.entry(model.to_string()).or_default(), usage);
+        if let Some(arm) = arm {
+            add_tokens(
+                &self.arms.entry((arm, model.to_string())).or_default(),
+                usage,
+            );
+        }
+    }
+
+    /// Tokens spent on each model, only for sessions of the arm when given.
+    fn spent(&self, arm: Option<Arm>) -> Vec<(String, chat::Usage)> {
+        match arm {
+            None => self
+                .models
+                .iter()
+                .map(|entry| (entry.key().clone(), load_tokens(entry.value())))
+                .collect(),
+            Some(arm) => self
+                .arms
+                .iter()
+                .filter(|entry| entry.key().0 == arm)
+                .map(|entry| (entry.key().1.clone(), load_tokens(entry.value())))
+                .collect(),
+        }
+    }
+
+    fn store(&self) -> StoredCounters {
+        let load = |counters: &DashMap<u64, AtomicU64>| {
+            counters
+                .iter()
+                .map(|entry| (*entry.key(), entry.load(Ordering::Relaxed)))
+                .collect()
+        };
+
+        StoredCounters {
+            users: load(&self.users),
+            guilds: load(&self.guilds),
+            image_users: load(&self.image_users),
+            summary: self.snapshot(),
+            models: self.spent(None),
+            arms: self
+                .arms
+                .iter()
+                .map(|entry| {
+                    let (arm, model) = entry.key().clone();
+                    (arm, model, load_tokens(entry.value()))
+                })
+                .collect(),
+        }
+    }
+
+    /// Adds the stored counts to the ones counted so far.
+    fn merge(&self, stored: StoredCounters) {
+        let add = |counters: &DashMap<u64, AtomicU64>, stored: Vec<(u64, u64)>| {
+            for (id, count) in stored {
+                counters
+                    .entry(id)
+                    .or_default()
+                    .fetch_add(count, Ordering::Relaxed);
+            }
+        };
+        add(&self.users, stored.users);
+        add(&self.guilds, stored.guilds);
+        add(&self.image_users, stored.image_users);
+
+        let summary = stored.summary;
+        self.prompts.fetch_add(summary.prompts, Ordering::Relaxed);
+        self.images.fetch_add(summary.images, Ordering::Relaxed);
+        self.errors.fetch_add(summary.errors, Ordering::Relaxed);
+        self.input_tokens
+            .fetch_add(summary.input_tokens, Ordering::Relaxed);
+        self.output_tokens
+            .fetch_add(summary.output_tokens, Ordering::Relaxed);
+
+        for (model, usage) in stored.models {
+            add_tokens(&self.models.entry(model).or_default(), usage);
+        }
+        for (arm, model, usage) in stored.arms {
+            add_tokens(&self.arms.entry((arm, model)).or_default(), usage);
+        }
+    }
+
+    fn snapshot(&self) -> Summary {
+        Summary {
+            prompts: self.prompts.load(Ordering::Relaxed),
+            images: self.images.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            input_tokens: self.input_tokens.load(Ordering::Relaxed),
+            output_tokens: self.output_tokens.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, Default)]
+pub struct Summary {
+    pub prompts: u64,
+    pub images: u64,
+    pub errors: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+impl Summary {
+    fn add(mut self, other: Self) -> Self {
+        self.prompts += other.prompts;
+        self.images += other.images;
+        self.errors += other.errors;
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+
+        self
+    }
+
+    pub fn error_rate(&self) -> f64 {
+        if self.prompts == 0 {
+            return 0.;
+        }
+
+        self.errors as f64 / self.prompts as f64
+    }
+
+    pub fn total_tokens(&self) -> u64 {
+        self.input_tokens + self.output_tokens
+    }
+}
+
+/// Serializable counts of [`Counters`].
+#[derive(serde::Serialize, serde::Deserialize, Debug, Default)]
+#[serde(default)]
+struct StoredCounters {
+    users: Vec<(UserId, u64)>,
+    guilds: Vec<(GuildId, u64)>,
+    image_users: Vec<(UserId, u64)>,
+    summary: Summary,
+    models: Vec<(String, chat::Usage)>,
+    arms: Vec<(Arm, String, chat::Usage)>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Default)]
+#[serde(default)]
+struct Stored {
+    guilds: Vec<(GuildId, StoredCounters)>,
+    days: Vec<(NaiveDate, StoredCounters)>,
+}
+
+/// Counts prompts, failures and spent tokens per guild since the last reset.
+///
+/// Totals across guilds are also kept per day and survive resets, for usage reports.
+#[derive(Debug, Default)]
+pub struct Tracker {
+    guilds: DashMap<GuildId, Counters>,
+    days: DashMap<NaiveDate, Counters>,
+    /// Set when counted since the last save.
+    changed: AtomicBool,
+    saving: tokio::sync::Mutex<()>,
+}
+
+pub fn today() -> NaiveDate {
+    chrono::Local::now().date_naive()
+}
+
+/// Monday of the current week.
+pub fn monday() -> NaiveDate {
+    let today = today();
+
+    today - Days::new(today.weekday().num_days_from_monday() as u64)
+}
+
+/// First day of the current month.
+pub fn month_start() -> NaiveDate {
+    let today = today();
+
+    today.with_day(1).unwrap_or(today)
+}
+
+/// First day of the last seven days, today included.
+pub fn week_start() -> NaiveDate {
+    today() - Days::new(6)
+}
+
+impl Tracker {
+    /// Whether anything was counted since the last save.
+    pub fn changed(&self) -> bool {
+        self.changed.load(Ordering::Acquire)
+    }
+
+    fn mark_changed(&self) {
+        self.changed.store(true, Ordering::Release);
+    }
+
+    pub fn record_prompt(&self, guild: GuildId, user: UserId) {
+        self.mark_changed();
+
+        let day = self.days.entry(today()).or_default();
+        day.prompts.fetch_add(1, Ordering::Relaxed);
+        day.guilds
+            .entry(guild)
+            .or_default()
+            .fetch_add(1, Ordering::Relaxed);
+
+        let counters = self.guilds.entry(guild).or_default();
+        counters.prompts.fetch_add(1, Ordering::Relaxed);
+        counters
+            .users
+            .entry(user)
+            .or_default()
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_image(&self, guild: GuildId, user: UserId) {
+        self.mark_changed();
+
+        let day = self.days.entry(today()).or_default();
+        day.images.fetch_add(1, Ordering::Relaxed);
+        day.image_users
+            .entry(user)
+            .or_default()
+            .fetch_add(1, Ordering::Relaxed);
+
+        self.guilds
+            .entry(guild)
+            .or_default()
+            .images
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Images generated by the user today, across guilds.
+    pub fn user_images_today(&self, user: UserId) -> u64 {
+        self.days
+            .get(&today())
+            .and_then(|day| {
+                day.image_users
+                    .get(&user)
+                    .map(|images| images.load(Ordering::Relaxed))
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn record_error(&self, guild: GuildId) {
+        self.mark_changed();
+
+        self.days
+            .entry(today())
+            .or_default()
+            .errors
+            .fetch_add(1, Ordering::Relaxed);
+
+        self.guilds
+            .entry(guild)
+            .or_default()
+            .errors
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts the tokens spent on the model, also for the experiment arm of the session they
+    /// were spent for.
+    pub fn record_tokens(&self, guild: GuildId, model: &str, arm: Option<Arm>, usage: chat::Usage) {
+        self.mark_changed();
+        self.days
+            .entry(today())
+            .or_default()
+            .add_tokens(model, arm, usage);
+        self.guilds
+            .entry(guild)
+            .or_default()
+            .add_tokens(model, arm, usage);
+    }
+
+    pub fn guild(&self, guild: GuildId) -> Summary {
+        self.guilds
+            .get(&guild)
+            .map(|counters| counters.snapshot())
+            .unwrap_or_default()
+    }
+
+    /// Tokens the guild spent on each model.
+    pub fn guild_spent(&self, guild: GuildId) -> Spent {
+        self.guilds
+            .get(&guild)
+            .map(|counters| counters.spent(None).into_iter().collect())
+            .unwrap_or_default()
+    }
+
+    /// Returns every tracked guild, sorted by prompt volume.
+    pub fn guilds(&self) -> Vec<(GuildId, Summary)> {
+        let mut guilds: Vec<_> = self
+            .guilds
+            .iter()
+            .map(|entry| (*entry.key(), entry.snapshot()))
+            .collect();
+        guilds.sort_unstable_by_key(|(_, usage)| Reverse(usage.prompts));
+
+        guilds
+    }
+
+    /// Returns the users of a guild with the most prompts, in descending order.
+    pub fn top_users(&self, guild: GuildId, limit: usize) -> Vec<(UserId, u64)> {
+        let Some(counters) = self.guilds.get(&guild) else {
+            return Vec::new();
+        };
+
+        let mut users: Vec<_> = counters
+            .users
+            .iter()
+            .map(|entry| (*entry.key(), entry.load(Ordering::Relaxed)))
+            .collect();
+        users.sort_unstable_by_key(|(_, prompts)| Reverse(*prompts));
+        users.truncate(limit);
+
+        users
+    }
+
+    /// Sums the usage of every guild from the given day onwards.
+    pub fn since(&self, day: NaiveDate) -> Summary {
+        self.days
+            .iter()
+            .filter(|entry| *entry.key() >= day)
+            .map(|entry| entry.snapshot())
+            .fold(Summary::default(), Summary::add)
+    }
+
+    /// Returns the guilds with the most prompts from the given day onwards, in descending order.
+    pub fn top_guilds_since(&self, day: NaiveDate, limit: usize) -> Vec<(GuildId, u64)> {
+        let mut guilds: HashMap<GuildId, u64> = HashMap::new();
+        for entry in self.days.iter().filter(|entry| *entry.key() >= day) {
+            for guild in entry.guilds.iter() {
+                *guilds.entry(*guild.key()).or_default() += guild.load(Ordering::Relaxed);
+            }
+        }
+
+        let mut guilds: Vec<_> = guilds.into_iter().collect();
+        guilds.sort_unstable_by_key(|(_, prompts)| Reverse(*prompts));
+        guilds.truncate(limit);
+
+        guilds
+    }
+
+    /// Sums the usage of the last seven days, today included.
+    pub fn last_week(&self) -> Summary {
+        self.since(week_start())
+    }
+
+    pub fn today(&self) -> Summary {
+        self.since(today())
+    }
+
+    pub fn this_week(&self) -> Summary {
+        self.since(monday())
+    }
+
+    pub fn this_month(&self) -> Summary {
+        self.since(month_start())
+    }
+
+    /// Tokens spent on each model from the given day onwards, only for sessions of the arm
+    /// when given.
+    pub fn spent_since(&self, day: NaiveDate, arm: Option<Arm>) -> Spent {
+        let mut spent = Spent::new();
+        for entry in self.days.iter().filter(|entry| *entry.key() >= day) {
+            add_spent(&mut spent, entry.spent(arm));
+        }
+
+        spent
+    }
+
+    /// Writes the counters into the file of the directory, replacing the previous ones.
+    pub async fn save(&self, dir: &Path, file: &str) -> Result<(), Error> {
+        let _saving = self.saving.lock().await;
+
+        // Counted while saving or left unsaved, they're saved the next time.
+        self.changed.store(false, Ordering::Release);
+        let saved = self.write(dir, file).await;
+        if saved.is_err() {
+            self.mark_changed();
+        }
+
+        saved
+    }
+
+    async fn write(&self, dir: &Path, file: &str) -> Result<(), Error> {
+        let stored = Stored {
+            guilds: self
+                .guilds
+                .iter()
+                .map(|entry| (*entry.key(), entry.store()))
+                .collect(),
+            days: self
+                .days
+                .iter()
+                .map(|entry| (*entry.key(), entry.store()))
+                .collect(),
+        };
+        let contents = serde_json::to_vec(&stored)?;
+
+        tokio::fs::create_dir_all(dir).await?;
+        let path = dir.join(file);
+        let tmp_path = path.with_extension("json.tmp");
+        tokio::fs::write(&tmp_path, contents).await?;
+        tokio::fs::rename(tmp_path, path).await?;
+
+        Ok(())
+    }
+
+    /// Adds the counters kept in the file of the directory to the current ones, returning the
+    /// days loaded.
+    ///
+    /// Days past retention are left out, as a reset would drop them anyway.
+    pub async fn load(&self, dir: &Path, file: &str) -> Result<usize, Error> {
+        let contents = match tokio::fs::read(dir.join(file)).await {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(err) => return Err(err.into()),
+        };
+        let stored: Stored = serde_json::from_slice(&contents)?;
+
+        for (guild, counters) in stored.guilds {
+            self.guilds.entry(guild).or_default().merge(counters);
+        }
+
+        let cutoff = today().checked_sub_days(Days::new(DAYS_RETENTION));
+        let mut loaded = 0;
+        for (day, counters) in stored.days {
+            if cutoff.is_some_and(|cutoff| day < cutoff) {
+                continue;
+            }
+            self.days.entry(day).or_default().merge(counters);
+            loaded += 1;
+        }
+
+        Ok(loaded)
+    }
+
+    /// Clears the per guild counters, keeping the daily totals still within retention.
+    pub fn reset(&self) {
+        self.mark_changed();
+        self.guilds.clear();
+
+        if let Some(cutoff) = today().checked_sub_days(Days::new(DAYS_RETENTION)) {
+            self.days.retain(|day, _| *day >= cutoff);
+        }
+    }
+}